@@ -1,22 +1,26 @@
-use std::collections::HashMap;
 use std::fmt::Write;
+use std::io::BufRead;
+use std::path::PathBuf;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use clap::Clap;
 use colored_json::{ColorMode, ToColoredJson};
 use dialoguer::theme::{ColorfulTheme, Theme};
-use dialoguer::{Input, Password, Select};
+use dialoguer::{Confirm, Input, MultiSelect, Password, Select};
 use minus::Pager;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use relay_models::models::{
-    BridgeConfigurationView, EthEventVoteDataView, EthTonTransactionView, EthTxStatView,
-    EventConfigurationType, EventConfigurationView, InitData, NewEventConfiguration,
-    Password as PasswordData, RescanEthData, Status, TonEthTransactionView, TonEventVoteDataView,
-    TonTxStatView, Voting,
+    ActivityView, AuditEntryView, BridgeConfigurationView, ChangePassword, DatabaseExportView,
+    EthKeystoreData, EthQueuedEventView, EthTonDeadVoteView, EthTonVoteView, EthTxStatView,
+    EventConfigurationType, EventConfigurationView, EventJournalEntryView, EventVote, InitData,
+    NewEventConfiguration, PageQuery, PageView, Password as PasswordData, PublicKeysView,
+    RescanEthData, RotateEthKey, SortOrder, Status, TonEthDeadVoteView, TonEthVoteView,
+    TonQueuedEventView, TonTxStatView, Voting,
 };
 
 #[derive(Clap)]
@@ -25,16 +29,35 @@ struct Arguments {
     server_addr: Url,
     #[clap(short, long)]
     full_models: bool,
+    /// File with the api token. `RELAY_API_TOKEN` environment variable is used if not specified
+    #[clap(long)]
+    token_file: Option<PathBuf>,
+    /// PEM encoded CA certificate of the relay api with TLS
+    #[clap(long)]
+    ca_cert: Option<PathBuf>,
+    /// PKCS#12 archive with the client certificate and key
+    #[clap(long)]
+    client_identity: Option<PathBuf>,
 }
 
 fn main() -> Result<(), Error> {
     let args: Arguments = Arguments::parse();
     let theme = ColorfulTheme::default();
 
-    Prompt::new(&theme, "Select action", args.server_addr, args.full_models)
+    let client = Client::new(
+        args.server_addr.clone(),
+        args.full_models,
+        build_http_client(&args)?,
+    );
+
+    Prompt::new(&theme, "Select action", client)
         .item("Get status", Client::get_status)
         .item("Init", Client::init_bridge)
         .item("Provide password", Client::unlock_bridge)
+        .item("Change password", Client::change_password)
+        .item("Re-encrypt keystore", Client::reencrypt_keystore)
+        .item("Export public keys", Client::export_public_keys)
+        .item("Rotate ETH key", Client::rotate_eth_key)
         .item("Set ETH block", Client::set_eth_block)
         .item("Retry failed votes", Client::retry_failed_votes)
         .item(
@@ -54,6 +77,10 @@ fn main() -> Result<(), Error> {
             "Get failed transactions ETH->TON",
             Client::get_failed_transactions_eth_to_ton,
         )
+        .item(
+            "Get abandoned transactions ETH->TON",
+            Client::get_dead_transactions_eth_to_ton,
+        )
         .item(
             "Get queued transactions ETH->TON",
             Client::get_queued_transactions_eth_to_ton,
@@ -70,6 +97,10 @@ fn main() -> Result<(), Error> {
             "Get failed transactions TON->ETH",
             Client::get_failed_transactions_ton_to_eth,
         )
+        .item(
+            "Get abandoned transactions TON->ETH",
+            Client::get_dead_transactions_ton_to_eth,
+        )
         .item(
             "Get queued transactions TON->ETH",
             Client::get_queued_transactions_ton_to_eth,
@@ -78,6 +109,11 @@ fn main() -> Result<(), Error> {
             "Get all confirmed transactions from TON",
             Client::get_ton_stats,
         )
+        .item("Get event history", Client::get_event_history)
+        .item("Get audit log", Client::get_audit_log)
+        .item("Watch activity", Client::watch_activity)
+        .item("Backup database", Client::backup_database)
+        .item("Export database as JSON", Client::export_database)
         .item(
             "Update bridge configuration",
             Client::update_bridge_configuration,
@@ -92,8 +128,7 @@ struct Client {
 }
 
 impl Client {
-    pub fn new(url: Url, full_models: bool) -> Self {
-        let client = reqwest::blocking::Client::new();
+    pub fn new(url: Url, full_models: bool, client: reqwest::blocking::Client) -> Self {
         Self {
             url,
             client,
@@ -112,11 +147,45 @@ impl Client {
     }
 
     pub fn init_bridge(&self) -> Result<(), Error> {
-        let language = provide_language()?;
-        let ton_seed = provide_ton_seed()?;
-        let ton_derivation_path = provide_ton_derivation_path()?;
-        let eth_seed = provide_eth_seed()?;
-        let eth_derivation_path = provide_eth_derivation_path()?;
+        let ton_from_file = provide_key_source("TON", "Keypair JSON file")?;
+        let eth_from_file = provide_key_source("ETH", "Keystore v3 JSON file")?;
+        let language = if ton_from_file && eth_from_file {
+            "en".to_string()
+        } else {
+            provide_language()?
+        };
+
+        let (ton_seed, ton_derivation_path, ton_keypair) = if ton_from_file {
+            (
+                String::new(),
+                None,
+                Some(provide_key_file("TON keypair file")?),
+            )
+        } else {
+            (
+                provide_ton_seed()?,
+                Some(provide_ton_derivation_path()?),
+                None,
+            )
+        };
+        let (eth_seed, eth_derivation_path, eth_keystore) = if eth_from_file {
+            let keystore = provide_key_file("ETH keystore file")?;
+            let password = Password::with_theme(&ColorfulTheme::default())
+                .with_prompt("ETH keystore password")
+                .interact()?;
+            (
+                String::new(),
+                None,
+                Some(EthKeystoreData { keystore, password }),
+            )
+        } else {
+            (
+                provide_eth_seed()?,
+                Some(provide_eth_derivation_path()?),
+                None,
+            )
+        };
+
         let password = provide_password()?;
         let _ = self.post_raw(
             "init",
@@ -125,8 +194,10 @@ impl Client {
                 language,
                 eth_seed,
                 ton_seed,
-                ton_derivation_path: Some(ton_derivation_path),
-                eth_derivation_path: Some(eth_derivation_path),
+                ton_derivation_path,
+                eth_derivation_path,
+                eth_keystore,
+                ton_keypair,
             },
         )?;
 
@@ -145,6 +216,90 @@ impl Client {
         Ok(())
     }
 
+    pub fn change_password(&self) -> Result<(), Error> {
+        let old_password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Old password")
+            .interact()?;
+        let new_password = provide_password()?;
+
+        let _ = self.post_raw(
+            "change-password",
+            &ChangePassword {
+                old_password,
+                new_password,
+            },
+        )?;
+
+        println!("Success!");
+        Ok(())
+    }
+
+    pub fn reencrypt_keystore(&self) -> Result<(), Error> {
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Password")
+            .interact()?;
+
+        let response = self.post_raw("keystore/reencrypt", &PasswordData { password })?;
+
+        println!("{}", response);
+        Ok(())
+    }
+
+    pub fn export_public_keys(&self) -> Result<(), Error> {
+        let public_keys: PublicKeysView = self.get("keystore/public-keys")?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&public_keys)?.to_colored_json_auto()?
+        );
+
+        let path = provide_output_path("relay_public_keys.json")?;
+        std::fs::write(&path, serde_json::to_string_pretty(&public_keys)?)?;
+
+        println!("Saved to {}", path);
+        Ok(())
+    }
+
+    pub fn rotate_eth_key(&self) -> Result<(), Error> {
+        let (language, eth_seed, eth_derivation_path, eth_keystore) =
+            if provide_key_source("new ETH", "Keystore v3 JSON file")? {
+                let keystore = provide_key_file("ETH keystore file")?;
+                let password = Password::with_theme(&ColorfulTheme::default())
+                    .with_prompt("ETH keystore password")
+                    .interact()?;
+                (
+                    "en".to_string(),
+                    String::new(),
+                    None,
+                    Some(EthKeystoreData { keystore, password }),
+                )
+            } else {
+                (
+                    provide_language()?,
+                    provide_eth_seed()?,
+                    Some(provide_eth_derivation_path()?),
+                    None,
+                )
+            };
+
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Password")
+            .interact()?;
+
+        let response = self.post_raw(
+            "keystore/rotate-eth-key",
+            &RotateEthKey {
+                password,
+                eth_seed,
+                language,
+                eth_derivation_path,
+                eth_keystore,
+            },
+        )?;
+
+        println!("{}", response);
+        Ok(())
+    }
+
     pub fn set_eth_block(&self) -> Result<(), Error> {
         let block: u64 = Input::with_theme(&ColorfulTheme::default())
             .with_prompt("Enter block number")
@@ -245,63 +400,38 @@ impl Client {
     }
 
     pub fn get_pending_transactions_eth_to_ton(&self) -> Result<(), Error> {
-        let response: Vec<EthTonTransactionView> = self.get("eth-to-ton/pending")?;
-        let mut output = Pager::new().set_prompt("Pending transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<EthTonVoteView>("eth-to-ton/pending", "Pending transactions", query)
     }
 
     pub fn get_pending_transactions_ton_to_eth(&self) -> Result<(), Error> {
-        let response: Vec<TonEthTransactionView> = self.get("ton-to-eth/pending")?;
-        let mut output = Pager::new().set_prompt("Pending transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<TonEthVoteView>("ton-to-eth/pending", "Pending transactions", query)
     }
 
     pub fn get_failed_transactions_eth_to_ton(&self) -> Result<(), Error> {
-        let response: Vec<EthTonTransactionView> = self.get("eth-to-ton/failed")?;
-        let mut output = Pager::new().set_prompt("Failed transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<EthTonVoteView>("eth-to-ton/failed", "Failed transactions", query)
     }
 
     pub fn get_failed_transactions_ton_to_eth(&self) -> Result<(), Error> {
-        let response: Vec<TonEthTransactionView> = self.get("ton-to-eth/failed")?;
-        let mut output = Pager::new().set_prompt("Failed transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<TonEthVoteView>("ton-to-eth/failed", "Failed transactions", query)
+    }
+
+    pub fn get_dead_transactions_eth_to_ton(&self) -> Result<(), Error> {
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<EthTonDeadVoteView>("eth-to-ton/dead", "Abandoned transactions", query)
+    }
+
+    pub fn get_dead_transactions_ton_to_eth(&self) -> Result<(), Error> {
+        let query = provide_page_query(true, true, None)?;
+        self.get_pages::<TonEthDeadVoteView>("ton-to-eth/dead", "Abandoned transactions", query)
     }
 
     pub fn get_queued_transactions_eth_to_ton(&self) -> Result<(), Error> {
-        let response: HashMap<u64, Vec<EthEventVoteDataView>> = self.get("eth-to-ton/queued")?;
-        let mut output = Pager::new().set_prompt("Queued transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(true, false, Some("target block"))?;
+        self.get_pages::<EthQueuedEventView>("eth-to-ton/queued", "Queued transactions", query)
     }
 
     pub fn get_queued_transactions_ton_to_eth(&self) -> Result<(), Error> {
@@ -309,16 +439,12 @@ impl Client {
             .with_prompt("Enter configuration id:")
             .interact()?;
 
-        let response: HashMap<u64, Vec<TonEventVoteDataView>> =
-            self.get(&format!("ton-to-eth/queued/{}", configuration_id))?;
-        let mut output = Pager::new().set_prompt("Queued transactions");
-        writeln!(
-            output.lines,
-            "{}",
-            serde_json::to_string_pretty(&response)?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
-        Ok(())
+        let query = provide_page_query(false, false, Some("logical time"))?;
+        self.get_pages::<TonQueuedEventView>(
+            &format!("ton-to-eth/queued/{}", configuration_id),
+            "Queued transactions",
+            query,
+        )
     }
 
     pub fn get_eth_stats(&self) -> Result<(), Error> {
@@ -337,23 +463,218 @@ impl Client {
             .get::<Status>("status")?
             .ton_relay_address
             .ok_or_else(|| anyhow!("Relay is locked or not initialized"))?;
+        let relay: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Relay key (empty for all):")
+            .with_initial_text(our_key)
+            .allow_empty(true)
+            .interact_text()?;
+
+        let mut query = provide_page_query(true, true, Some("vote timestamp"))?;
+        if !relay.trim().is_empty() {
+            query.relay = Some(relay.trim().to_string());
+        }
+        self.get_pages::<T>(url, "Stats", query)
+    }
+
+    /// Shows pages one by one until there are no more items or the user stops
+    fn get_pages<T>(&self, url: &str, title: &str, mut query: PageQuery) -> Result<(), Error>
+    where
+        for<'a> T: Deserialize<'a> + Serialize,
+    {
+        let url = self.url.join(url)?;
+        loop {
+            let page: PageView<T> = self
+                .client
+                .get(url.clone())
+                .query(&query)
+                .send()?
+                .prepare()?
+                .json()?;
+
+            let mut output = Pager::new().set_prompt(title);
+            writeln!(
+                output.lines,
+                "{}",
+                serde_json::to_string_pretty(&page.items)?.to_colored_json(ColorMode::On)?
+            )?;
+            minus::page_all(output)?;
+
+            match page.next_cursor {
+                Some(cursor)
+                    if Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt("Load next page?")
+                        .default(true)
+                        .interact()? =>
+                {
+                    query.cursor = Some(cursor)
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    pub fn get_event_history(&self) -> Result<(), Error> {
+        let theme = ColorfulTheme::default();
+
+        let selected_network = Select::with_theme(&theme)
+            .with_prompt("Select event source")
+            .item("ETH")
+            .item("TON")
+            .default(0)
+            .interact()?;
+
+        let url = match selected_network {
+            0 => {
+                let tx_hash: String = Input::with_theme(&theme)
+                    .with_prompt("Enter transaction hash:")
+                    .interact_text()?;
+                let event_index: u32 = Input::with_theme(&theme)
+                    .with_prompt("Enter event index:")
+                    .default(0)
+                    .interact_text()?;
+                format!("events/eth/{}/{}", tx_hash.trim(), event_index)
+            }
+            1 => {
+                let address: String = Input::with_theme(&theme)
+                    .with_prompt("Enter event contract address:")
+                    .interact_text()?;
+                format!("events/ton/{}", address.trim())
+            }
+            _ => unreachable!(),
+        };
+
+        let response: Vec<EventJournalEntryView> = self.get(&url)?;
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&response)?.to_colored_json_auto()?
+        );
+        Ok(())
+    }
+
+    pub fn get_audit_log(&self) -> Result<(), Error> {
         let theme = ColorfulTheme::default();
-        let mut selection = Select::with_theme(&theme);
-        selection
-            .with_prompt(format!("Select relay key. Our key is: {}", our_key))
-            .default(0);
-        let response: HashMap<String, Vec<T>> = self.get(url)?;
-        let keys: Vec<_> = response.keys().cloned().collect();
-        selection.items(&keys);
-        let selection = &keys[selection.interact()?];
-
-        let mut output = Pager::new().set_prompt("Stats");
-        writeln!(
-            output.lines,
+
+        let action: String = Input::with_theme(&theme)
+            .with_prompt("Filter by action (empty for all):")
+            .allow_empty(true)
+            .interact_text()?;
+        let caller: String = Input::with_theme(&theme)
+            .with_prompt("Filter by api token name (empty for all):")
+            .allow_empty(true)
+            .interact_text()?;
+        let limit: usize = Input::with_theme(&theme)
+            .with_prompt("Max entries:")
+            .default(100)
+            .interact_text()?;
+
+        let mut url = self.url.join("audit")?;
+        {
+            let mut query = url.query_pairs_mut();
+            if !action.trim().is_empty() {
+                query.append_pair("action", action.trim());
+            }
+            if !caller.trim().is_empty() {
+                query.append_pair("caller", caller.trim());
+            }
+            query.append_pair("limit", &limit.to_string());
+        }
+
+        let response: Vec<AuditEntryView> = self.client.get(url).send()?.prepare()?.json()?;
+        println!(
             "{}",
-            serde_json::to_string_pretty(&response[selection])?.to_colored_json(ColorMode::On)?
-        )?;
-        minus::page_all(output)?;
+            serde_json::to_string_pretty(&response)?.to_colored_json_auto()?
+        );
+        Ok(())
+    }
+
+    /// Prints activity events until the connection is closed
+    pub fn watch_activity(&self) -> Result<(), Error> {
+        const TYPES: [&str; 7] = [
+            "eth_event",
+            "ton_event",
+            "vote_sent",
+            "vote_confirmed",
+            "vote_failed",
+            "configuration_changed",
+            "state_changed",
+        ];
+
+        let theme = ColorfulTheme::default();
+
+        let types = MultiSelect::with_theme(&theme)
+            .with_prompt("Select activity types (none for all)")
+            .items(&TYPES)
+            .interact()?;
+        let configuration_id: Option<u32> =
+            provide_optional("Filter by configuration id (empty for all):")?;
+        let direction = Select::with_theme(&theme)
+            .with_prompt("Filter by direction")
+            .items(&["Any", "ETH->TON", "TON->ETH"])
+            .default(0)
+            .interact()?;
+
+        let mut url = self.url.join("activity")?;
+        {
+            let mut query = url.query_pairs_mut();
+            if !types.is_empty() {
+                let types = types.into_iter().map(|i| TYPES[i]).collect::<Vec<_>>();
+                query.append_pair("types", &types.join(","));
+            }
+            if let Some(configuration_id) = configuration_id {
+                query.append_pair("configuration_id", &configuration_id.to_string());
+            }
+            match direction {
+                1 => {
+                    query.append_pair("direction", "eth_to_ton");
+                }
+                2 => {
+                    query.append_pair("direction", "ton_to_eth");
+                }
+                _ => {}
+            }
+        }
+
+        let response = self.client.get(url).send()?.prepare()?;
+        println!("Watching activity. Press Ctrl+C to stop");
+
+        for line in std::io::BufReader::new(response).lines() {
+            // Only `data` lines are printed, event names and keep-alive comments are skipped
+            let line = line?;
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+            let activity: ActivityView = serde_json::from_str(data)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&activity)?.to_colored_json_auto()?
+            );
+        }
+
+        println!("Activity stream closed");
+        Ok(())
+    }
+
+    pub fn backup_database(&self) -> Result<(), Error> {
+        let path = provide_output_path("relay_backup.bin")?;
+
+        let url = self.url.join("db/backup")?;
+        let mut response = self.client.post(url).send()?.prepare()?;
+        let mut file = std::fs::File::create(&path)?;
+        let len = std::io::copy(&mut response, &mut file)?;
+
+        println!("Saved {} bytes to {}", len, path);
+        println!("Start relay with `--restore {}` to restore it", path);
+        Ok(())
+    }
+
+    pub fn export_database(&self) -> Result<(), Error> {
+        let path = provide_output_path("relay_export.json")?;
+
+        let export: DatabaseExportView = self.get("db/export")?;
+        std::fs::write(&path, serde_json::to_string_pretty(&export)?)?;
+
+        println!("Saved to {}", path);
         Ok(())
     }
 
@@ -432,6 +753,73 @@ fn update_bridge_configuration() -> Result<BridgeConfigurationView, Error> {
     })
 }
 
+/// Asks for the filters of the paged list. `range` is the name of the `from` and `to` bounds
+fn provide_page_query(
+    configuration_id: bool,
+    vote: bool,
+    range: Option<&str>,
+) -> Result<PageQuery, Error> {
+    let theme = ColorfulTheme::default();
+    let mut query = PageQuery::default();
+
+    if configuration_id {
+        query.configuration_id = provide_optional("Filter by configuration id (empty for all):")?;
+    }
+
+    if vote {
+        query.vote = match Select::with_theme(&theme)
+            .with_prompt("Filter by vote")
+            .items(&["Any", "Confirm", "Reject"])
+            .default(0)
+            .interact()?
+        {
+            1 => Some(EventVote::Confirm),
+            2 => Some(EventVote::Reject),
+            _ => None,
+        };
+    }
+
+    if let Some(range) = range {
+        query.from = provide_optional(&format!("Since {} (empty for any):", range))?;
+        query.to = provide_optional(&format!("Until {}, exclusive (empty for any):", range))?;
+    }
+
+    let order = Select::with_theme(&theme)
+        .with_prompt("Order")
+        .items(&["Ascending", "Descending"])
+        .default(0)
+        .interact()?;
+    if order == 1 {
+        query.order = Some(SortOrder::Desc);
+    }
+
+    query.limit = Some(
+        Input::with_theme(&theme)
+            .with_prompt("Page size:")
+            .default(100)
+            .interact_text()?,
+    );
+
+    Ok(query)
+}
+
+fn provide_optional<T>(prompt: &str) -> Result<Option<T>, Error>
+where
+    T: std::str::FromStr,
+{
+    let input: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .allow_empty(true)
+        .interact_text()?;
+    match input.trim() {
+        "" => Ok(None),
+        input => input
+            .parse()
+            .map(Some)
+            .map_err(|_| anyhow!("Invalid value: {}", input)),
+    }
+}
+
 fn provide_language() -> Result<String, Error> {
     let langs = ["en", "zh-hans", "zh-hant", "fr", "it", "ja", "ko", "es"];
     let selection = Select::with_theme(&ColorfulTheme::default())
@@ -442,6 +830,24 @@ fn provide_language() -> Result<String, Error> {
     Ok(langs[selection].to_string())
 }
 
+/// Returns whether the key is imported from the file instead of the seed phrase
+fn provide_key_source(chain: &str, file_kind: &str) -> Result<bool, Error> {
+    let sources = ["Seed phrase", file_kind];
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Choose {} key source", chain))
+        .items(&sources)
+        .default(0)
+        .interact()?;
+    Ok(selection == 1)
+}
+
+fn provide_key_file(prompt: &str) -> Result<String, Error> {
+    let path: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .interact_text()?;
+    Ok(std::fs::read_to_string(path.trim())?)
+}
+
 fn provide_ton_seed() -> Result<String, Error> {
     let input: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Provide TON seed words. 12 words are needed.")
@@ -478,6 +884,14 @@ fn provide_password() -> Result<String, Error> {
     Ok(password)
 }
 
+fn provide_output_path(default: &str) -> Result<String, Error> {
+    let path = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Output file")
+        .with_initial_text(default.to_string())
+        .interact_text()?;
+    Ok(path)
+}
+
 fn provide_eth_derivation_path() -> Result<String, Error> {
     let derivation_path = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Provide derivation path for ETH")
@@ -504,6 +918,39 @@ fn parse_url(url: &str) -> Result<Url, Error> {
     Ok(Url::parse(url)?)
 }
 
+fn build_http_client(args: &Arguments) -> Result<reqwest::blocking::Client, Error> {
+    let mut builder = reqwest::blocking::Client::builder();
+
+    let token = match &args.token_file {
+        Some(path) => Some(std::fs::read_to_string(path)?),
+        None => std::env::var("RELAY_API_TOKEN").ok(),
+    };
+    if let Some(token) = token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token.trim()))?;
+        value.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, value);
+        builder = builder.default_headers(headers);
+    }
+
+    if let Some(path) = &args.ca_cert {
+        let certificate = reqwest::Certificate::from_pem(&std::fs::read(path)?)?;
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    if let Some(path) = &args.client_identity {
+        let password = Password::with_theme(&ColorfulTheme::default())
+            .with_prompt("Client certificate password")
+            .allow_empty_password(true)
+            .interact()?;
+        let identity = reqwest::Identity::from_pkcs12_der(&std::fs::read(path)?, &password)?;
+        builder = builder.identity(identity);
+    }
+
+    Ok(builder.build()?)
+}
+
 type CommandHandler = Box<dyn FnMut(&Client) -> Result<(), Error>>;
 
 struct Prompt<'a> {
@@ -513,8 +960,7 @@ struct Prompt<'a> {
 }
 
 impl<'a> Prompt<'a> {
-    pub fn new(theme: &'a dyn Theme, title: &str, url: Url, full_models: bool) -> Self {
-        let client = Client::new(url, full_models);
+    pub fn new(theme: &'a dyn Theme, title: &str, client: Client) -> Self {
         let mut select = Select::with_theme(theme);
         select.with_prompt(title).default(0);
 
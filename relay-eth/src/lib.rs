@@ -5,9 +5,9 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, Error};
+use relay_storage::{Batch, Db, Tree};
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
-use sled::{Db, Tree};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
 use tokio::sync::RwLock;
 use tokio::sync::Semaphore;
@@ -20,6 +20,8 @@ pub use web3::types::{FilterBuilder, Log, H160};
 use web3::{Transport, Web3};
 const ETH_TREE_NAME: &str = "ethereum_data";
 const ETH_LAST_MET_HEIGHT: &str = "last_met_height";
+const ETH_RELAY_KEYS_HEIGHT: &str = "relay_keys_height";
+const ETH_RELAY_KEY_PREFIX: &str = "relay_key";
 
 #[derive(Copy, Clone)]
 struct Timeouts {
@@ -161,7 +163,6 @@ impl EthListener {
             },
             bridge_address,
         };
-        // dbg!(listener.get_actual_keys().await?); //todo use it
         Ok(listener)
     }
 
@@ -182,6 +183,11 @@ impl EthListener {
         Ok(events_rx)
     }
 
+    /// Latest scanned ETH block
+    pub fn scanned_height(&self) -> u64 {
+        self.current_block.load(Ordering::SeqCst)
+    }
+
     pub fn change_eth_height(&self, height: u64) -> Result<(), Error> {
         self.current_block.store(height, Ordering::SeqCst);
         update_height(&self.db, height)?;
@@ -254,62 +260,50 @@ impl EthListener {
         }
     }
 
-    async fn get_actual_keys(&self) -> Result<(Address, Vec<H160>), Error> {
+    /// Returns the bridge address and relay accounts, which were granted ownership in it.
+    ///
+    /// Ownership changes are stored in the db, so each call requests only the logs
+    /// from the blocks after the previously processed one. Only blocks with at least
+    /// `confirmations` blocks above them are processed, so stored changes are not
+    /// affected by reorgs
+    pub async fn get_actual_keys(&self, confirmations: u64) -> Result<(Address, Vec<H160>), Error> {
         let address = self.bridge_address;
-        async fn get_keys(
-            topic: H256,
-            address: Address,
-            web3: &Web3<Http>,
-        ) -> Result<HashSet<H160>, Error> {
+        let granted_topic = self.relay_keys_function_to_topic_map["OwnershipGranted"];
+        let removed_topic = self.relay_keys_function_to_topic_map["OwnershipRemoved"];
+
+        let from_block = match self.db.get(relay_keys_height_key(&address))? {
+            Some(height) => u64::from_le_bytes(height.as_slice().try_into()?) + 1,
+            None => 0,
+        };
+        let latest_block = self.web3.eth().block_number().await?.as_u64();
+        let to_block = latest_block.checked_sub(confirmations);
+
+        if let Some(to_block) = to_block.filter(|&to_block| from_block <= to_block) {
             let filter = FilterBuilder::default()
                 .address(vec![address])
-                .topics(Some(vec![topic]), None, None, None)
-                .from_block(BlockNumber::Earliest)
-                .to_block(BlockNumber::Latest)
+                .topics(Some(vec![granted_topic, removed_topic]), None, None, None)
+                .from_block(BlockNumber::Number(from_block.into()))
+                .to_block(BlockNumber::Number(to_block.into()))
                 .build();
 
-            Ok(web3
+            let changes = self
+                .web3
                 .eth()
                 .logs(filter)
                 .await?
                 .into_iter()
-                .map(EthListener::log_to_event)
-                .filter_map(|x| match x {
-                    Ok(a) if !a.data.is_empty() => {
-                        match ethabi::decode(&[ethabi::ParamType::Address], &*a.data) {
-                            Ok(a) => {
-                                if a.is_empty() {
-                                    log::error!("No addresses in data");
-                                    None
-                                } else {
-                                    Some(a[0].clone().into_address().map(|x| H160::from(x.0)))
-                                }
-                            }
-                            Err(e) => {
-                                log::error!("Failed decoding data as address: {}", e);
-                                None
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("Failed parsing log as event: {}", e);
-                        None
-                    }
-                    Ok(a) => {
-                        log::error!("Bad data len: {}", a.data.len());
-                        None
-                    }
+                .filter_map(|log| {
+                    let granted = log.topics.first() == Some(&granted_topic);
+                    let position = (log.block_number?.as_u64(), log.log_index?.as_u64());
+                    let account = relay_key_from_log(log)?;
+                    Some((position, account, granted))
                 })
-                .flatten()
-                .collect())
+                .collect();
+            apply_relay_keys_changes(&self.db, &address, changes, to_block)?;
         }
-        let ok_topic = self.relay_keys_function_to_topic_map["OwnershipGranted"];
-        let bad_topic = self.relay_keys_function_to_topic_map["OwnershipRemoved"];
-        // use hex::ToHex;
-        let ok_fut = get_keys(ok_topic, address, &self.web3);
-        let bad_fut = get_keys(bad_topic, address, &self.web3);
-        let (ok, bad): (HashSet<_>, HashSet<_>) = futures::try_join!(ok_fut, bad_fut)?;
-        Ok((address, ok.difference(&bad).cloned().collect()))
+
+        let keys = get_relay_keys(&self.db, &address)?;
+        Ok((address, keys))
     }
 
     fn log_to_event(log: Log) -> Result<Event, Error> {
@@ -598,6 +592,77 @@ pub struct Event {
     pub block_hash: H256,
 }
 
+/// Decodes the account from the `OwnershipGranted` or `OwnershipRemoved` log
+fn relay_key_from_log(log: Log) -> Option<H160> {
+    let event = match EthListener::log_to_event(log) {
+        Ok(event) => event,
+        Err(e) => {
+            log::error!("Failed parsing log as event: {}", e);
+            return None;
+        }
+    };
+    if event.data.is_empty() {
+        log::error!("Bad data len: {}", event.data.len());
+        return None;
+    }
+
+    match ethabi::decode(&[ethabi::ParamType::Address], &*event.data) {
+        Ok(tokens) => match tokens.into_iter().next() {
+            Some(token) => token.into_address().map(|x| H160::from(x.0)),
+            None => {
+                log::error!("No addresses in data");
+                None
+            }
+        },
+        Err(e) => {
+            log::error!("Failed decoding data as address: {}", e);
+            None
+        }
+    }
+}
+
+fn relay_keys_height_key(bridge_address: &Address) -> Vec<u8> {
+    [ETH_RELAY_KEYS_HEIGHT.as_bytes(), bridge_address.as_bytes()].concat()
+}
+
+fn relay_keys_prefix(bridge_address: &Address) -> Vec<u8> {
+    [ETH_RELAY_KEY_PREFIX.as_bytes(), bridge_address.as_bytes()].concat()
+}
+
+/// Applies ownership changes in the order of logs and stores the processed height.
+/// Each change is `((block number, log index), account, granted)`
+fn apply_relay_keys_changes(
+    db: &Tree,
+    bridge_address: &Address,
+    mut changes: Vec<((u64, u64), H160, bool)>,
+    height: u64,
+) -> Result<(), Error> {
+    changes.sort_by_key(|(position, _, _)| *position);
+
+    let prefix = relay_keys_prefix(bridge_address);
+    let mut batch = Batch::default();
+    for (_, account, granted) in changes {
+        let key = [prefix.as_slice(), account.as_bytes()].concat();
+        if granted {
+            batch.insert(key, b"");
+        } else {
+            batch.remove(key);
+        }
+    }
+    batch.insert(relay_keys_height_key(bridge_address), height.to_le_bytes());
+
+    db.apply_batch(batch)?;
+    Ok(())
+}
+
+fn get_relay_keys(db: &Tree, bridge_address: &Address) -> Result<Vec<H160>, Error> {
+    let prefix = relay_keys_prefix(bridge_address);
+    db.scan_prefix(&prefix)
+        .keys()
+        .map(|key| Ok(H160::from_slice(&key?[prefix.len()..])))
+        .collect()
+}
+
 fn update_eth_state(db: &Tree, height: u64, key: &str) -> Result<(), Error> {
     db.insert(key, &height.to_le_bytes())?;
     Ok(())
@@ -607,3 +672,46 @@ pub fn update_height(db: &Tree, height: u64) -> Result<(), Error> {
     update_eth_state(&db, height, ETH_LAST_MET_HEIGHT)?;
     Ok(())
 }
+
+/// Returns the last processed block number, stored by the listener
+pub fn get_last_met_height(db: &Db) -> Result<Option<u64>, Error> {
+    let tree = db.open_tree(ETH_TREE_NAME)?;
+    Ok(match tree.get(ETH_LAST_MET_HEIGHT)? {
+        Some(a) => Some(u64::from_le_bytes(a.as_slice().try_into()?)),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_relay_keys_changes() {
+        let db = Db::in_memory().open_tree(ETH_TREE_NAME).unwrap();
+        let bridge = Address::repeat_byte(1);
+        let (first, second) = (H160::repeat_byte(2), H160::repeat_byte(3));
+
+        // Granted, removed and granted again. Logs are not ordered
+        let changes = vec![
+            ((10, 1), first, true),
+            ((12, 0), first, true),
+            ((10, 2), second, true),
+            ((11, 0), first, false),
+        ];
+        apply_relay_keys_changes(&db, &bridge, changes, 20).unwrap();
+        assert_eq!(get_relay_keys(&db, &bridge).unwrap(), vec![first, second]);
+
+        apply_relay_keys_changes(&db, &bridge, vec![((21, 0), second, false)], 30).unwrap();
+        assert_eq!(get_relay_keys(&db, &bridge).unwrap(), vec![first]);
+        assert_eq!(
+            db.get(relay_keys_height_key(&bridge)).unwrap(),
+            Some(30u64.to_le_bytes().to_vec())
+        );
+
+        // Keys of the other bridge are not affected
+        assert!(get_relay_keys(&db, &Address::repeat_byte(4))
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,21 +1,123 @@
+use std::collections::HashMap;
+
 use opg::OpgModel;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, OpgModel)]
+#[derive(Deserialize, Serialize, OpgModel)]
 pub struct InitData {
+    #[serde(default)]
     pub ton_seed: String,
+    #[serde(default)]
     pub eth_seed: String,
     pub password: String,
     pub language: String,
     pub ton_derivation_path: Option<String>,
     pub eth_derivation_path: Option<String>,
+    #[serde(default)]
+    pub eth_keystore: Option<EthKeystoreData>,
+    #[serde(default)]
+    pub ton_keypair: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct EthKeystoreData {
+    pub keystore: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, OpgModel)]
+pub struct PublicKeysView {
+    pub eth_address: String,
+    pub eth_pubkey: String,
+    pub ton_public_key: String,
 }
 
-#[derive(Deserialize, Debug, OpgModel, Serialize)]
+#[derive(Deserialize, OpgModel, Serialize)]
 pub struct Password {
     pub password: String,
 }
 
+#[derive(Deserialize, OpgModel, Serialize)]
+pub struct ChangePassword {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+/// New ETH key, derived from the seed or imported from the keystore file
+#[derive(Deserialize, Serialize, OpgModel)]
+pub struct RotateEthKey {
+    /// Keystore password
+    pub password: String,
+    #[serde(default)]
+    pub eth_seed: String,
+    pub language: String,
+    pub eth_derivation_path: Option<String>,
+    #[serde(default)]
+    pub eth_keystore: Option<EthKeystoreData>,
+}
+
+/// Replaces secrets in the debug output
+struct Redacted;
+
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("***SECRET***")
+    }
+}
+
+impl std::fmt::Debug for InitData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InitData")
+            .field("ton_seed", &Redacted)
+            .field("eth_seed", &Redacted)
+            .field("password", &Redacted)
+            .field("language", &self.language)
+            .field("ton_derivation_path", &self.ton_derivation_path)
+            .field("eth_derivation_path", &self.eth_derivation_path)
+            .field("eth_keystore", &self.eth_keystore)
+            .field("ton_keypair", &self.ton_keypair.as_ref().map(|_| Redacted))
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for EthKeystoreData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EthKeystoreData")
+            .field("keystore", &self.keystore)
+            .field("password", &Redacted)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Password")
+            .field("password", &Redacted)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for RotateEthKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RotateEthKey")
+            .field("password", &Redacted)
+            .field("eth_seed", &Redacted)
+            .field("language", &self.language)
+            .field("eth_derivation_path", &self.eth_derivation_path)
+            .field("eth_keystore", &self.eth_keystore)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for ChangePassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChangePassword")
+            .field("old_password", &Redacted)
+            .field("new_password", &Redacted)
+            .finish()
+    }
+}
+
 #[derive(Deserialize, Debug, Serialize, OpgModel)]
 pub struct RescanEthData {
     pub block: u64,
@@ -62,6 +164,21 @@ pub struct Status {
     pub is_working: bool,
     pub ton_relay_address: Option<String>,
     pub eth_pubkey: Option<String>,
+    /// `None` if the ETH key is not being rotated
+    #[serde(default)]
+    pub eth_key_rotation: Option<EthKeyRotationView>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, OpgModel)]
+pub struct EthKeyRotationView {
+    /// Address of the new ETH key. The current key is used until both contracts acknowledge it
+    pub next_eth_address: String,
+    /// Whether the TON bridge returns the new address for this relay
+    pub ton_acknowledged: bool,
+    /// Whether the new address is in the relay set of the ETH bridge
+    pub eth_acknowledged: bool,
+    /// Unix timestamp of the last check. `None` until the first check
+    pub checked_at: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
@@ -92,13 +209,62 @@ pub enum TonEthTransactionView {
     Reject(TonEventVoteDataView),
 }
 
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct EthTonDeadVoteView {
+    pub event_address: String,
+    pub transaction: EthTonTransactionView,
+    pub history: VoteRetryHistoryView,
+}
+
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct TonEthDeadVoteView {
+    pub event_address: String,
+    pub transaction: TonEthTransactionView,
+    pub history: VoteRetryHistoryView,
+}
+
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct VoteRetryHistoryView {
+    #[opg("Timestamp in seconds")]
+    pub first_failed_at: i64,
+    pub last_error: Option<String>,
+    pub attempts: Vec<VoteAttemptView>,
+}
+
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct VoteAttemptView {
+    #[opg("Timestamp in seconds")]
+    pub failed_at: i64,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct EventJournalEntryView {
+    pub state: EventStateView,
+    #[opg("Timestamp in seconds")]
+    pub timestamp: i64,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, opg::OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum EventStateView {
+    Seen,
+    Queued,
+    Verified,
+    VoteSent,
+    VoteObserved,
+    Finalized,
+    Failed,
+}
+
 #[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
 pub struct SignedVoteDataView {
     pub signature: String,
     pub data: TonEventVoteDataView,
 }
 
-#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+#[derive(Serialize, Deserialize, Debug, Clone, opg::OpgModel)]
 pub struct TonEventVoteDataView {
     pub configuration_id: u32,
     pub event_transaction: String,
@@ -127,6 +293,8 @@ pub struct EthTxStatView {
     #[opg("Timestamp in seconds")]
     pub met: String,
     pub event_addr: String,
+    #[serde(default)]
+    pub relay_addr: String,
     pub vote: EventVote,
 }
 
@@ -138,9 +306,60 @@ pub struct TonTxStatView {
     #[opg("Timestamp in seconds")]
     pub met: String,
     pub event_addr: String,
+    #[serde(default)]
+    pub relay_addr: String,
     pub vote: EventVote,
 }
 
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct EthQueuedEventView {
+    #[opg("ETH block, after which the event is verified")]
+    pub target_block: u64,
+    #[opg("Whether the event was found in the votes of other relays")]
+    pub external: bool,
+    pub event: EthEventVoteDataView,
+}
+
+#[derive(Serialize, Deserialize, Clone, opg::OpgModel)]
+pub struct TonQueuedEventView {
+    #[opg("Logical time of the event transaction")]
+    pub target_lt: u64,
+    #[opg("Whether the event was found in the votes of other relays")]
+    pub external: bool,
+    pub event: TonEventVoteDataView,
+}
+
+/// Filters and position of the requested page
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PageQuery {
+    /// Hex encoded `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    pub order: Option<SortOrder>,
+    pub configuration_id: Option<u32>,
+    /// TON address of the relay
+    pub relay: Option<String>,
+    pub vote: Option<EventVote>,
+    /// Inclusive lower bound of the block, logical time or timestamp
+    pub from: Option<u64>,
+    /// Exclusive upper bound of the block, logical time or timestamp
+    pub to: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageView<T> {
+    pub items: Vec<T>,
+    /// `None` if there are no more items
+    pub next_cursor: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, OpgModel)]
 #[serde(rename_all = "lowercase")]
 pub enum EventVote {
@@ -148,6 +367,220 @@ pub enum EventVote {
     Reject,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct AuditEntryView {
+    #[opg("Entry id. Grows with time")]
+    pub id: u64,
+    #[opg("Timestamp in seconds")]
+    pub timestamp: i64,
+    #[opg("Name of the api token and the remote address")]
+    pub caller: String,
+    #[opg("Api path of the request")]
+    pub action: String,
+    #[opg("JSON encoded request parameters without secrets")]
+    pub params: String,
+    #[opg("HTTP status of the response")]
+    pub status: u16,
+    #[opg("Response body of the failed request")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub action: Option<String>,
+    pub caller: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct ActivityView {
+    #[opg("Timestamp in seconds")]
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: ActivityEventView,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ActivityEventView {
+    /// New ETH event is waiting for the confirmations
+    EthEvent(EthEventVoteDataView),
+    /// New swapback event in TON
+    TonEvent(TonEventVoteDataView),
+    VoteSent(VoteActivityView),
+    /// Our vote was found in TON
+    VoteConfirmed(VoteActivityView),
+    /// Vote was not delivered after all retries
+    VoteFailed(VoteActivityView),
+    ConfigurationChanged(ConfigurationActivityView),
+    StateChanged(StateChangeView),
+    /// Subscriber is too slow and missed some events
+    Lagged(LaggedActivityView),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, OpgModel)]
+#[serde(rename_all = "snake_case")]
+pub enum EventDirection {
+    EthToTon,
+    TonToEth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct VoteActivityView {
+    pub direction: EventDirection,
+    pub configuration_id: u32,
+    pub event_address: String,
+    pub vote: EventVote,
+    #[opg("Reason of the failure")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct ConfigurationActivityView {
+    pub configuration_id: u32,
+    pub address: String,
+    pub configuration_type: EventConfigurationType,
+    #[opg("Whether the relay is subscribed to the configuration now")]
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, OpgModel)]
+#[serde(rename_all = "lowercase")]
+pub enum RelayStateView {
+    Uninitialized,
+    Locked,
+    Running,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct StateChangeView {
+    pub from: RelayStateView,
+    pub to: RelayStateView,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
+pub struct LaggedActivityView {
+    #[opg("Number of missed events")]
+    pub skipped: u64,
+}
+
+/// Filters of the activity stream
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActivityQuery {
+    /// Comma separated activity types, e.g. `vote_failed,state_changed`
+    pub types: Option<String>,
+    pub configuration_id: Option<u32>,
+    pub direction: Option<EventDirection>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct ReadinessView {
+    #[opg("Whether all components are healthy")]
+    pub ready: bool,
+    pub eth: EthHealthView,
+    pub ton: TonHealthView,
+    pub storage: ComponentHealthView,
+    pub keystore: KeystoreHealthView,
+    pub configurations: ConfigurationsHealthView,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct ComponentHealthView {
+    pub healthy: bool,
+    #[opg("Reason, why the component is unhealthy")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct EthHealthView {
+    pub healthy: bool,
+    #[opg("Reason, why the component is unhealthy")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[opg("Latest block of the ETH node")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_height: Option<u64>,
+    #[opg("Latest scanned ETH block")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanned_height: Option<u64>,
+    #[opg("Number of blocks, which are not scanned yet")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lag: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct TonHealthView {
+    pub healthy: bool,
+    #[opg("Reason, why the component is unhealthy")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[opg("Timestamp of the latest TON block, seen by the transport")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_utime: Option<u32>,
+    #[opg("Age of the latest TON block in seconds")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lag: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct KeystoreHealthView {
+    #[opg("Whether the keystore is unlocked and the relay is running")]
+    pub healthy: bool,
+    #[opg("Reason, why the component is unhealthy")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<RelayStateView>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, OpgModel)]
+pub struct ConfigurationsHealthView {
+    pub healthy: bool,
+    #[opg("Reason, why the component is unhealthy")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[opg("Number of active event configurations in the bridge contract")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<usize>,
+    #[opg("Active event configurations, which the relay is not subscribed to")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, OpgModel)]
+pub struct DatabaseExportView {
+    #[opg("Timestamp in seconds")]
+    pub created_at: i64,
+    pub eth_to_ton: EthToTonExportView,
+    pub ton_to_eth: TonToEthExportView,
+}
+
+#[derive(Serialize, Deserialize, OpgModel)]
+pub struct EthToTonExportView {
+    #[opg("Last processed ETH block")]
+    pub last_scanned_block: Option<u64>,
+    #[opg("Verification queue")]
+    pub queued: HashMap<String, Vec<EthEventVoteDataView>>,
+    #[opg("Pending, failed and abandoned votes")]
+    pub votes: HashMap<String, Vec<EthTonTransactionView>>,
+    #[opg("Known votes for all relays")]
+    pub stats: HashMap<String, Vec<EthTxStatView>>,
+}
+
+#[derive(Serialize, Deserialize, OpgModel)]
+pub struct TonToEthExportView {
+    #[opg("Latest processed logical time for each event configuration contract")]
+    pub latest_scanned_lt: HashMap<String, u64>,
+    #[opg("Verification queue for each configuration")]
+    pub queued: HashMap<u32, HashMap<String, Vec<TonEventVoteDataView>>>,
+    #[opg("Pending, failed and abandoned votes")]
+    pub votes: HashMap<String, Vec<TonEthTransactionView>>,
+    #[opg("Known votes for all relays")]
+    pub stats: HashMap<String, Vec<TonTxStatView>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, OpgModel)]
 pub struct CommonEventConfigurationParamsView {
     pub event_abi: String,
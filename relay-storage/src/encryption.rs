@@ -0,0 +1,263 @@
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+use super::*;
+
+/// Symmetric cipher for the stored values
+pub trait ValueCipher: Send + Sync {
+    fn encrypt(&self, value: &[u8]) -> Vec<u8>;
+
+    fn decrypt(&self, value: &[u8]) -> StorageResult<Vec<u8>>;
+}
+
+/// Returned for all value operations until the data key is set
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+#[error("Storage is locked")]
+pub struct StorageLocked;
+
+/// Shared slot for the data key of [`EncryptedStorage`]
+#[derive(Clone, Default)]
+pub struct CipherSlot(Arc<RwLock<Option<Arc<dyn ValueCipher>>>>);
+
+impl CipherSlot {
+    /// Unlocks all storages, which use this slot
+    pub fn set(&self, cipher: Arc<dyn ValueCipher>) {
+        *self.0.write().unwrap() = Some(cipher);
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.0.read().unwrap().is_none()
+    }
+
+    fn get(&self) -> StorageResult<Arc<dyn ValueCipher>> {
+        match &*self.0.read().unwrap() {
+            Some(cipher) => Ok(cipher.clone()),
+            None => Err(StorageLocked.into()),
+        }
+    }
+}
+
+/// Storage wrapper which encrypts values of all trees.
+///
+/// Keys are stored as is to preserve ordering. System trees are left
+/// unencrypted, remain accessible while the storage is locked and are
+/// hidden from [`Storage::tree_names`], so they don't get into backups
+pub struct EncryptedStorage {
+    inner: Box<dyn Storage>,
+    slot: CipherSlot,
+    system_trees: Vec<Vec<u8>>,
+}
+
+impl EncryptedStorage {
+    pub fn new<S>(storage: S, slot: CipherSlot, system_trees: &[&[u8]]) -> Self
+    where
+        S: Storage + 'static,
+    {
+        Self {
+            inner: Box::new(storage),
+            slot,
+            system_trees: system_trees.iter().map(|name| name.to_vec()).collect(),
+        }
+    }
+
+    fn is_system_tree(&self, name: &[u8]) -> bool {
+        self.system_trees.iter().any(|system| system == name)
+    }
+}
+
+impl Storage for EncryptedStorage {
+    fn open_tree(&self, name: &[u8]) -> StorageResult<Arc<dyn StorageTree>> {
+        let inner = self.inner.open_tree(name)?;
+        if self.is_system_tree(name) {
+            return Ok(inner);
+        }
+
+        Ok(Arc::new(EncryptedTree {
+            inner,
+            slot: self.slot.clone(),
+        }))
+    }
+
+    fn tree_names(&self) -> Vec<Vec<u8>> {
+        let mut names = self.inner.tree_names();
+        names.retain(|name| !self.is_system_tree(name));
+        names
+    }
+
+    fn drop_tree(&self, name: &[u8]) -> StorageResult<bool> {
+        self.inner.drop_tree(name)
+    }
+
+    fn transaction(&self, trees: &[&[u8]], f: &TransactionFn<'_>) -> Result<(), TransactionError> {
+        let cipher = if trees.iter().all(|name| self.is_system_tree(name)) {
+            None
+        } else {
+            Some(self.slot.get().map_err(TransactionError::Storage)?)
+        };
+
+        self.inner.transaction(trees, &|views| {
+            let views: Vec<_> = views
+                .iter()
+                .zip(trees)
+                .map(|(&inner, name)| EncryptedTransactionalTree {
+                    inner,
+                    cipher: match &cipher {
+                        Some(cipher) if !self.is_system_tree(name) => Some(cipher.as_ref()),
+                        _ => None,
+                    },
+                })
+                .collect();
+            let views: Vec<&dyn TransactionalTree> = views
+                .iter()
+                .map(|view| view as &dyn TransactionalTree)
+                .collect();
+
+            f(&views)
+        })
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        self.inner.flush()
+    }
+}
+
+struct EncryptedTree {
+    inner: Arc<dyn StorageTree>,
+    slot: CipherSlot,
+}
+
+impl EncryptedTree {
+    fn decrypt(cipher: &dyn ValueCipher, value: Option<Vec<u8>>) -> StorageResult<Option<Vec<u8>>> {
+        value.map(|value| cipher.decrypt(&value)).transpose()
+    }
+
+    fn decrypt_iter<F>(&self, f: F) -> Iter
+    where
+        F: FnOnce(&dyn StorageTree) -> Iter,
+    {
+        let cipher = match self.slot.get() {
+            Ok(cipher) => cipher,
+            Err(e) => return Iter::new(std::iter::once(Err(e))),
+        };
+
+        Iter::new(f(self.inner.as_ref()).map(move |item| {
+            let (key, value) = item?;
+            Ok((key, cipher.decrypt(&value)?))
+        }))
+    }
+}
+
+impl StorageTree for EncryptedTree {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        let cipher = self.slot.get()?;
+        Self::decrypt(cipher.as_ref(), self.inner.get(key)?)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        let cipher = self.slot.get()?;
+        let old = self.inner.insert(key, &cipher.encrypt(value))?;
+        Self::decrypt(cipher.as_ref(), old)
+    }
+
+    fn remove(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        let cipher = self.slot.get()?;
+        Self::decrypt(cipher.as_ref(), self.inner.remove(key)?)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> StorageResult<bool> {
+        self.inner.contains_key(key)
+    }
+
+    fn fetch_and_update(&self, key: &[u8], f: &mut UpdateFn<'_>) -> StorageResult<Option<Vec<u8>>> {
+        let cipher = self.slot.get()?;
+
+        // Undecryptable value is left untouched and the error is returned afterwards
+        let mut error = None;
+        let old = self.inner.fetch_and_update(key, &mut |encrypted| {
+            let value = match encrypted.map(|value| cipher.decrypt(value)).transpose() {
+                Ok(value) => value,
+                Err(e) => {
+                    error = Some(e);
+                    return encrypted.map(<[u8]>::to_vec);
+                }
+            };
+            error = None;
+            f(value.as_deref()).map(|value| cipher.encrypt(&value))
+        })?;
+
+        match error {
+            Some(e) => Err(e),
+            None => Self::decrypt(cipher.as_ref(), old),
+        }
+    }
+
+    fn range(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        self.decrypt_iter(|inner| inner.range(from, to))
+    }
+
+    fn range_rev(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        self.decrypt_iter(|inner| inner.range_rev(from, to))
+    }
+
+    fn apply_batch(&self, batch: Batch) -> StorageResult<()> {
+        let cipher = self.slot.get()?;
+        let ops = batch
+            .ops
+            .into_iter()
+            .map(|(key, value)| (key, value.map(|value| cipher.encrypt(&value))))
+            .collect();
+        self.inner.apply_batch(Batch { ops })
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn clear(&self) -> StorageResult<()> {
+        self.inner.clear()
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        self.inner.flush()
+    }
+}
+
+struct EncryptedTransactionalTree<'a> {
+    inner: &'a dyn TransactionalTree,
+    /// `None` for system trees
+    cipher: Option<&'a dyn ValueCipher>,
+}
+
+impl EncryptedTransactionalTree<'_> {
+    fn decrypt(&self, value: Option<Vec<u8>>) -> Result<Option<Vec<u8>>, TransactionError> {
+        match (self.cipher, value) {
+            (Some(cipher), Some(value)) => cipher
+                .decrypt(&value)
+                .map(Some)
+                .map_err(TransactionError::Storage),
+            (_, value) => Ok(value),
+        }
+    }
+}
+
+impl TransactionalTree for EncryptedTransactionalTree<'_> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.decrypt(self.inner.get(key)?)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        let old = match self.cipher {
+            Some(cipher) => self.inner.insert(key, &cipher.encrypt(value))?,
+            None => self.inner.insert(key, value)?,
+        };
+        self.decrypt(old)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.decrypt(self.inner.remove(key)?)
+    }
+}
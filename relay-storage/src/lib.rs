@@ -0,0 +1,835 @@
+//! Key-value storage used by the relay.
+//!
+//! All tables are stored in named trees with ordered byte keys. Backends
+//! implement [`Storage`] and [`StorageTree`], while the rest of the relay
+//! works with cheap cloneable [`Db`] and [`Tree`] handles.
+
+use std::cell::{Cell, RefCell};
+use std::io::{Read, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+use anyhow::{anyhow, Error};
+
+pub use self::encryption::{CipherSlot, EncryptedStorage, StorageLocked, ValueCipher};
+pub use self::memory_storage::MemoryStorage;
+pub use self::sled_storage::SledStorage;
+pub use self::snapshot::SnapshotStats;
+
+use self::snapshot::{SnapshotReader, SnapshotWriter};
+
+mod encryption;
+mod memory_storage;
+mod sled_storage;
+mod snapshot;
+
+pub type StorageResult<T> = Result<T, Error>;
+
+/// Max number of entries applied at once during restore
+pub const RESTORE_BATCH_SIZE: usize = 1024;
+
+/// Runs the closure, which waits for the pending snapshot.
+/// See [`Db::set_blocking_hook`]
+pub type BlockingHook = fn(&mut dyn FnMut());
+
+/// Key-value pair
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Body of the [`Storage::transaction`]
+pub type TransactionFn<'a> = dyn Fn(&[&dyn TransactionalTree]) -> Result<(), TransactionError> + 'a;
+
+/// Value update used by [`StorageTree::fetch_and_update`]
+pub type UpdateFn<'a> = dyn FnMut(Option<&[u8]>) -> Option<Vec<u8>> + 'a;
+
+/// Pending changes of the single tree. `None` removes the value
+pub(crate) type Changes = Vec<(Vec<u8>, Option<Vec<u8>>)>;
+
+/// Storage backend
+pub trait Storage: Send + Sync {
+    /// Opens tree with the specified name, creating it if it doesn't exist
+    fn open_tree(&self, name: &[u8]) -> StorageResult<Arc<dyn StorageTree>>;
+
+    /// Returns names of all existing trees
+    fn tree_names(&self) -> Vec<Vec<u8>>;
+
+    /// Removes tree with all its data. Returns `false` if there was no such tree
+    fn drop_tree(&self, name: &[u8]) -> StorageResult<bool>;
+
+    /// Atomically applies changes made in `f` to the specified trees.
+    ///
+    /// `f` can be called several times in case of conflicts
+    fn transaction(&self, trees: &[&[u8]], f: &TransactionFn<'_>) -> Result<(), TransactionError>;
+
+    /// Writes all buffered changes to the disk
+    fn flush(&self) -> StorageResult<()>;
+}
+
+/// Ordered map of byte keys to byte values
+pub trait StorageTree: Send + Sync {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>>;
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> StorageResult<Option<Vec<u8>>>;
+
+    fn remove(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>>;
+
+    fn contains_key(&self, key: &[u8]) -> StorageResult<bool>;
+
+    /// Atomically replaces the value with the result of `f`. `None` removes the value
+    fn fetch_and_update(&self, key: &[u8], f: &mut UpdateFn<'_>) -> StorageResult<Option<Vec<u8>>>;
+
+    /// Iterates over the entries within the range in ascending key order
+    fn range(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter;
+
+    /// Iterates over the entries within the range in descending key order
+    fn range_rev(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter;
+
+    /// Atomically applies all changes from the batch
+    fn apply_batch(&self, batch: Batch) -> StorageResult<()>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool;
+
+    fn clear(&self) -> StorageResult<()>;
+
+    fn flush(&self) -> StorageResult<()>;
+}
+
+/// Tree view inside the transaction
+pub trait TransactionalTree {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError>;
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, TransactionError>;
+
+    fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransactionError {
+    /// Transaction was aborted by the caller
+    #[error("{0}")]
+    Abort(Error),
+    /// Concurrent modification, the transaction will be retried
+    #[error("transaction conflict")]
+    Conflict,
+    #[error("storage error: {0}")]
+    Storage(Error),
+}
+
+impl TransactionError {
+    pub fn abort<E>(error: E) -> Self
+    where
+        E: Into<Error>,
+    {
+        Self::Abort(error.into())
+    }
+}
+
+/// Set of changes for the single tree
+#[derive(Debug, Default, Clone)]
+pub struct Batch {
+    pub(crate) ops: Changes,
+}
+
+impl Batch {
+    pub fn insert<K, V>(&mut self, key: K, value: V)
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        self.ops
+            .push((key.as_ref().to_vec(), Some(value.as_ref().to_vec())));
+    }
+
+    pub fn remove<K>(&mut self, key: K)
+    where
+        K: AsRef<[u8]>,
+    {
+        self.ops.push((key.as_ref().to_vec(), None));
+    }
+}
+
+/// Iterator over tree entries
+pub struct Iter(Box<dyn Iterator<Item = StorageResult<Entry>> + Send>);
+
+impl Iter {
+    pub fn new<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = StorageResult<Entry>> + Send + 'static,
+    {
+        Self(Box::new(iter))
+    }
+
+    pub fn keys(self) -> impl Iterator<Item = StorageResult<Vec<u8>>> + Send {
+        self.map(|item| item.map(|(key, _)| key))
+    }
+
+    pub fn values(self) -> impl Iterator<Item = StorageResult<Vec<u8>>> + Send {
+        self.map(|item| item.map(|(_, value)| value))
+    }
+}
+
+impl Iterator for Iter {
+    type Item = StorageResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// Handle to the storage backend
+#[derive(Clone)]
+pub struct Db {
+    shared: Arc<Shared>,
+}
+
+impl Db {
+    pub fn new<S>(storage: S) -> Self
+    where
+        S: Storage + 'static,
+    {
+        Self {
+            shared: Arc::new(Shared {
+                storage: Box::new(storage),
+                writes: Default::default(),
+                blocking_hook: Default::default(),
+            }),
+        }
+    }
+
+    /// Opens sled database at the specified path
+    pub fn open_sled<P>(path: P) -> StorageResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::new(SledStorage::open(path)?))
+    }
+
+    /// Creates empty volatile storage
+    pub fn in_memory() -> Self {
+        Self::new(MemoryStorage::default())
+    }
+
+    pub fn open_tree<N>(&self, name: N) -> StorageResult<Tree>
+    where
+        N: AsRef<[u8]>,
+    {
+        let name = name.as_ref();
+        Ok(Tree {
+            name: name.into(),
+            inner: self.shared.storage.open_tree(name)?,
+            shared: self.shared.clone(),
+        })
+    }
+
+    pub fn tree_names(&self) -> Vec<Vec<u8>> {
+        self.shared.storage.tree_names()
+    }
+
+    pub fn drop_tree<N>(&self, name: N) -> StorageResult<bool>
+    where
+        N: AsRef<[u8]>,
+    {
+        let _guard = self.shared.write_guard();
+        self.shared.storage.drop_tree(name.as_ref())
+    }
+
+    pub fn flush(&self) -> StorageResult<()> {
+        self.shared.storage.flush()
+    }
+
+    /// Sets the wrapper for modifications, which have to wait for the pending snapshot.
+    ///
+    /// The wait lasts for the whole [`Db::backup`], so async runtimes should move it
+    /// off their worker threads, e.g. with `tokio::task::block_in_place`. Modifications,
+    /// which don't wait, are not affected
+    pub fn set_blocking_hook(&self, hook: BlockingHook) {
+        *self
+            .shared
+            .blocking_hook
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(hook);
+    }
+
+    /// Writes consistent snapshot of all trees.
+    ///
+    /// All modifications are suspended until the snapshot is written, so this method
+    /// blocks and must not be called from the async context. Modifications made during
+    /// the snapshot wait in the [`BlockingHook`]. Entries are streamed into the writer,
+    /// which should be backed by a file rather than memory
+    pub fn backup<W>(&self, writer: W) -> StorageResult<SnapshotStats>
+    where
+        W: Write,
+    {
+        let _guard = self.shared.exclusive_guard()?;
+
+        let mut snapshot = SnapshotWriter::new(writer)?;
+        for name in self.shared.storage.tree_names() {
+            let tree = self.shared.storage.open_tree(&name)?;
+            snapshot.write_tree(
+                &name,
+                tree.len(),
+                tree.range(Bound::Unbounded, Bound::Unbounded),
+            )?;
+        }
+        snapshot.finish()
+    }
+
+    /// Fills storage with the data from the snapshot.
+    ///
+    /// Storage must be empty to prevent accidental data loss. Entries are applied in
+    /// batches of [`RESTORE_BATCH_SIZE`], so a corrupted snapshot leaves the entries
+    /// restored before the error in the storage
+    pub fn restore<R>(&self, reader: R) -> StorageResult<SnapshotStats>
+    where
+        R: Read,
+    {
+        let _guard = self.shared.exclusive_guard()?;
+
+        for name in self.shared.storage.tree_names() {
+            if !self.shared.storage.open_tree(&name)?.is_empty() {
+                return Err(anyhow!(
+                    "Storage is not empty: tree {} contains data",
+                    String::from_utf8_lossy(&name)
+                ));
+            }
+        }
+
+        let mut snapshot = SnapshotReader::new(reader)?;
+        while let Some((name, len)) = snapshot.read_tree()? {
+            let tree = self.shared.storage.open_tree(&name)?;
+            let mut batch = Batch::default();
+            for _ in 0..len {
+                let (key, value) = snapshot.read_entry()?;
+                batch.insert(key, value);
+                if batch.ops.len() >= RESTORE_BATCH_SIZE {
+                    tree.apply_batch(std::mem::take(&mut batch))?;
+                }
+            }
+            if !batch.ops.is_empty() {
+                tree.apply_batch(batch)?;
+            }
+        }
+
+        self.shared.storage.flush()?;
+        Ok(snapshot.stats())
+    }
+}
+
+struct Shared {
+    storage: Box<dyn Storage>,
+    /// Modifications take the shared lock, snapshots take the exclusive one
+    writes: RwLock<()>,
+    blocking_hook: RwLock<Option<BlockingHook>>,
+}
+
+thread_local! {
+    /// Whether the current thread holds the shared modifications lock
+    static HOLDS_WRITE_GUARD: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Shared {
+    /// The lock is never taken twice by the same thread: modifications, nested into
+    /// the transaction or `fetch_and_update`, reuse the outer guard. Otherwise they
+    /// would wait for the pending snapshot, which waits for the outer guard
+    fn write_guard(&self) -> WriteGuard<'_> {
+        if HOLDS_WRITE_GUARD.with(Cell::get) {
+            return WriteGuard(None);
+        }
+        let guard = match self.writes.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => self.wait_snapshot(),
+        };
+        HOLDS_WRITE_GUARD.with(|holds| holds.set(true));
+        WriteGuard(Some(guard))
+    }
+
+    fn wait_snapshot(&self) -> RwLockReadGuard<'_, ()> {
+        let hook = *self
+            .blocking_hook
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut guard = None;
+        let mut wait = || {
+            guard = Some(self.writes.read().unwrap_or_else(PoisonError::into_inner));
+        };
+        match hook {
+            Some(hook) => hook(&mut wait),
+            None => wait(),
+        }
+        guard.expect("Hook must call the closure")
+    }
+
+    fn exclusive_guard(&self) -> StorageResult<RwLockWriteGuard<'_, ()>> {
+        if HOLDS_WRITE_GUARD.with(Cell::get) {
+            return Err(anyhow!("Snapshot can't be used inside of the modification"));
+        }
+        Ok(self.writes.write().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+struct WriteGuard<'a>(Option<RwLockReadGuard<'a, ()>>);
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        if self.0.take().is_some() {
+            HOLDS_WRITE_GUARD.with(|holds| holds.set(false));
+        }
+    }
+}
+
+/// Handle to the single tree
+#[derive(Clone)]
+pub struct Tree {
+    name: Arc<[u8]>,
+    inner: Arc<dyn StorageTree>,
+    shared: Arc<Shared>,
+}
+
+impl Tree {
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    pub fn get<K>(&self, key: K) -> StorageResult<Option<Vec<u8>>>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.inner.get(key.as_ref())
+    }
+
+    pub fn insert<K, V>(&self, key: K, value: V) -> StorageResult<Option<Vec<u8>>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let _guard = self.shared.write_guard();
+        self.inner.insert(key.as_ref(), value.as_ref())
+    }
+
+    pub fn remove<K>(&self, key: K) -> StorageResult<Option<Vec<u8>>>
+    where
+        K: AsRef<[u8]>,
+    {
+        let _guard = self.shared.write_guard();
+        self.inner.remove(key.as_ref())
+    }
+
+    pub fn contains_key<K>(&self, key: K) -> StorageResult<bool>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.inner.contains_key(key.as_ref())
+    }
+
+    pub fn fetch_and_update<K, F>(&self, key: K, mut f: F) -> StorageResult<Option<Vec<u8>>>
+    where
+        K: AsRef<[u8]>,
+        F: FnMut(Option<&[u8]>) -> Option<Vec<u8>>,
+    {
+        let _guard = self.shared.write_guard();
+        self.inner.fetch_and_update(key.as_ref(), &mut f)
+    }
+
+    pub fn iter(&self) -> Iter {
+        self.inner.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    pub fn range<K, R>(&self, range: R) -> Iter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        self.inner.range(
+            convert_bound(range.start_bound()),
+            convert_bound(range.end_bound()),
+        )
+    }
+
+    /// Same as `range`, but in descending key order
+    pub fn range_rev<K, R>(&self, range: R) -> Iter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        self.inner.range_rev(
+            convert_bound(range.start_bound()),
+            convert_bound(range.end_bound()),
+        )
+    }
+
+    pub fn scan_prefix<P>(&self, prefix: P) -> Iter
+    where
+        P: AsRef<[u8]>,
+    {
+        let prefix = prefix.as_ref().to_vec();
+        let iter = self
+            .inner
+            .range(Bound::Included(prefix.clone()), Bound::Unbounded)
+            .take_while(move |item| match item {
+                Ok((key, _)) => key.starts_with(&prefix),
+                Err(_) => true,
+            });
+        Iter::new(iter)
+    }
+
+    pub fn apply_batch(&self, batch: Batch) -> StorageResult<()> {
+        let _guard = self.shared.write_guard();
+        self.inner.apply_batch(batch)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn clear(&self) -> StorageResult<()> {
+        let _guard = self.shared.write_guard();
+        self.inner.clear()
+    }
+
+    pub fn flush(&self) -> StorageResult<()> {
+        self.inner.flush()
+    }
+}
+
+fn convert_bound<K: AsRef<[u8]>>(bound: Bound<&K>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.as_ref().to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.as_ref().to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Atomically applies changes made in `f` to the specified trees.
+/// All trees must belong to the same storage.
+///
+/// `f` can be called several times in case of conflicts
+pub fn transaction<R, F>(trees: &[&Tree], f: F) -> Result<R, TransactionError>
+where
+    F: Fn(&[&dyn TransactionalTree]) -> Result<R, TransactionError>,
+{
+    let shared = match trees.first() {
+        Some(tree) => &tree.shared,
+        None => return f(&[]),
+    };
+    debug_assert!(trees.iter().all(|tree| Arc::ptr_eq(&tree.shared, shared)));
+
+    let names: Vec<&[u8]> = trees.iter().map(|tree| tree.name()).collect();
+    let result = RefCell::new(None);
+
+    let _guard = shared.write_guard();
+    shared.storage.transaction(&names, &|trees| {
+        let value = f(trees)?;
+        *result.borrow_mut() = Some(value);
+        Ok(())
+    })?;
+
+    Ok(result
+        .into_inner()
+        .expect("Transaction result must be set on success"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_storage(db: Db) {
+        let first = db.open_tree("first").unwrap();
+        let second = db.open_tree("second").unwrap();
+
+        first.insert([1, 1], [1]).unwrap();
+        first.insert([1, 2], [2]).unwrap();
+        first.insert([2, 1], [3]).unwrap();
+
+        let keys: Vec<_> = first.scan_prefix([1]).keys().map(Result::unwrap).collect();
+        assert_eq!(keys, vec![vec![1, 1], vec![1, 2]]);
+
+        let keys: Vec<_> = first.range([1, 2]..).keys().map(Result::unwrap).collect();
+        assert_eq!(keys, vec![vec![1, 2], vec![2, 1]]);
+
+        let keys: Vec<_> = first
+            .range_rev(..[2, 1])
+            .keys()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(keys, vec![vec![1, 2], vec![1, 1]]);
+
+        assert!(first.range([2, 1]..[1, 1]).next().is_none());
+
+        // Iteration doesn't block writes to the tree
+        let mut iter = first.range_rev::<[u8; 2], _>(..).keys();
+        assert_eq!(iter.next().unwrap().unwrap(), vec![2, 1]);
+        first.insert([3, 1], [4]).unwrap();
+        assert_eq!(iter.next().unwrap().unwrap(), vec![1, 2]);
+        first.remove([3, 1]).unwrap();
+
+        // Move value between trees
+        transaction(&[&first, &second], |trees| {
+            let value = trees[0].remove(&[1, 1])?.unwrap();
+            trees[1].insert(&[1, 1], &value)?;
+            Ok(())
+        })
+        .unwrap();
+        assert!(!first.contains_key([1, 1]).unwrap());
+        assert_eq!(second.get([1, 1]).unwrap(), Some(vec![1]));
+
+        // Aborted transaction leaves trees untouched
+        let result = transaction(&[&first, &second], |trees| {
+            trees[0].remove(&[1, 2])?;
+            Err::<(), _>(TransactionError::abort(anyhow::anyhow!("abort")))
+        });
+        assert!(matches!(result, Err(TransactionError::Abort(_))));
+        assert!(first.contains_key([1, 2]).unwrap());
+
+        let mut batch = Batch::default();
+        batch.remove([1, 2]);
+        batch.insert([3], [4]);
+        first.apply_batch(batch).unwrap();
+        assert_eq!(first.len(), 2);
+
+        first
+            .fetch_and_update([3], |value| value.map(|value| vec![value[0] + 1]))
+            .unwrap();
+        assert_eq!(first.get([3]).unwrap(), Some(vec![5]));
+
+        assert!(db.tree_names().contains(&b"second".to_vec()));
+        assert!(db.drop_tree("second").unwrap());
+        db.flush().unwrap();
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let db = Db::in_memory();
+        let tree = db.open_tree("tree").unwrap();
+        tree.insert([1], [2]).unwrap();
+        tree.insert([3], []).unwrap();
+        db.open_tree("empty").unwrap();
+        // Restored in several batches
+        let large = db.open_tree("large").unwrap();
+        for i in 0..=RESTORE_BATCH_SIZE as u32 {
+            large.insert(i.to_be_bytes(), []).unwrap();
+        }
+
+        let mut snapshot = Vec::new();
+        let stats = db.backup(&mut snapshot).unwrap();
+        assert_eq!(
+            stats,
+            SnapshotStats {
+                trees: 3,
+                entries: RESTORE_BATCH_SIZE + 3
+            }
+        );
+
+        let restored = Db::new(SledStorage::temporary().unwrap());
+        assert_eq!(restored.restore(snapshot.as_slice()).unwrap(), stats);
+        let tree = restored.open_tree("tree").unwrap();
+        assert_eq!(tree.get([1]).unwrap(), Some(vec![2]));
+        assert_eq!(tree.get([3]).unwrap(), Some(vec![]));
+        assert_eq!(
+            restored.open_tree("large").unwrap().len(),
+            RESTORE_BATCH_SIZE + 1
+        );
+
+        // Restoring into non-empty storage is prohibited
+        assert!(restored.restore(snapshot.as_slice()).is_err());
+        // Truncated snapshot is rejected
+        let truncated = &snapshot[..snapshot.len() - 1];
+        assert!(Db::in_memory().restore(truncated).is_err());
+    }
+
+    #[test]
+    fn nested_modifications() {
+        let db = Db::in_memory();
+        let tree = db.open_tree("tree").unwrap();
+        let other = db.open_tree("other").unwrap();
+
+        tree.fetch_and_update([1], |_| {
+            // Reuses the outer guard
+            other.insert([2], [2]).unwrap();
+            // Would wait for the outer guard forever
+            assert!(db.backup(std::io::sink()).is_err());
+            Some(vec![1])
+        })
+        .unwrap();
+
+        assert_eq!(other.get([2]).unwrap(), Some(vec![2]));
+        assert!(db.backup(std::io::sink()).is_ok());
+    }
+
+    #[test]
+    fn modifications_wait_for_snapshot_in_hook() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::mpsc;
+
+        static WAITS: AtomicUsize = AtomicUsize::new(0);
+
+        fn hook(wait: &mut dyn FnMut()) {
+            WAITS.fetch_add(1, Ordering::SeqCst);
+            wait();
+        }
+
+        /// Pauses the snapshot after the header
+        struct PausedWriter(Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>);
+
+        impl Write for PausedWriter {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if let Some((started, resume)) = self.0.take() {
+                    started.send(()).unwrap();
+                    resume.recv().unwrap();
+                }
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let db = Db::in_memory();
+        db.set_blocking_hook(hook);
+        let tree = db.open_tree("tree").unwrap();
+        tree.insert([1], [1]).unwrap();
+        assert_eq!(WAITS.load(Ordering::SeqCst), 0);
+
+        let (started_tx, started_rx) = mpsc::channel();
+        let (resume_tx, resume_rx) = mpsc::channel();
+        let backup = {
+            let db = db.clone();
+            let writer = PausedWriter(Some((started_tx, resume_rx)));
+            std::thread::spawn(move || db.backup(writer))
+        };
+        started_rx.recv().unwrap();
+
+        let insert = std::thread::spawn(move || tree.insert([2], [2]));
+        while WAITS.load(Ordering::SeqCst) == 0 {
+            std::thread::yield_now();
+        }
+
+        resume_tx.send(()).unwrap();
+        assert_eq!(backup.join().unwrap().unwrap().entries, 1);
+        insert.join().unwrap().unwrap();
+        assert_eq!(WAITS.load(Ordering::SeqCst), 1);
+    }
+
+    /// Toy cipher with the checksum byte to detect wrong keys
+    struct XorCipher(u8);
+
+    impl ValueCipher for XorCipher {
+        fn encrypt(&self, value: &[u8]) -> Vec<u8> {
+            let mut result: Vec<u8> = value.iter().map(|byte| byte ^ self.0).collect();
+            result.push(self.0);
+            result
+        }
+
+        fn decrypt(&self, value: &[u8]) -> StorageResult<Vec<u8>> {
+            match value.split_last() {
+                Some((&key, value)) if key == self.0 => {
+                    Ok(value.iter().map(|byte| byte ^ self.0).collect())
+                }
+                _ => Err(anyhow!("Invalid key")),
+            }
+        }
+    }
+
+    #[test]
+    fn encrypted_storage() {
+        let slot = CipherSlot::default();
+        let raw = Db::in_memory();
+        let db = Db::new(EncryptedStorage::new(
+            ProxyStorage(raw.clone()),
+            slot.clone(),
+            &[b"system"],
+        ));
+
+        // System trees are available while the storage is locked
+        let system = db.open_tree("system").unwrap();
+        system.insert([0], [1]).unwrap();
+        let tree = db.open_tree("tree").unwrap();
+        assert!(tree.insert([1], [2]).is_err());
+        assert!(tree.iter().next().unwrap().is_err());
+
+        slot.set(Arc::new(XorCipher(0xaa)));
+        assert!(!slot.is_locked());
+        tree.insert([1], [2]).unwrap();
+        tree.insert([3], [4]).unwrap();
+        let values: Vec<_> = tree
+            .range_rev::<[u8; 1], _>(..)
+            .values()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(values, vec![vec![4], vec![2]]);
+        tree.remove([3]).unwrap();
+
+        // Only values are encrypted
+        let raw_tree = raw.open_tree("tree").unwrap();
+        assert_eq!(raw_tree.get([1]).unwrap(), Some(vec![0xa8, 0xaa]));
+        assert_eq!(
+            raw.open_tree("system").unwrap().get([0]).unwrap(),
+            Some(vec![1])
+        );
+        assert_eq!(db.tree_names(), vec![b"tree".to_vec()]);
+
+        transaction(&[&tree, &system], |trees| {
+            let value = trees[0].remove(&[1])?.unwrap();
+            trees[1].insert(&[1], &value)?;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(system.get([1]).unwrap(), Some(vec![2]));
+
+        // Values written with another key are not readable
+        raw_tree.insert([2], [0xa8, 0xbb]).unwrap();
+        assert!(tree.get([2]).is_err());
+        assert!(tree.fetch_and_update([2], |_| None).is_err());
+        assert!(tree.contains_key([2]).unwrap());
+
+        raw_tree.remove([2]).unwrap();
+        check_storage(db);
+    }
+
+    /// Exposes raw values written by the encrypted storage
+    struct ProxyStorage(Db);
+
+    impl Storage for ProxyStorage {
+        fn open_tree(&self, name: &[u8]) -> StorageResult<Arc<dyn StorageTree>> {
+            self.0.shared.storage.open_tree(name)
+        }
+
+        fn tree_names(&self) -> Vec<Vec<u8>> {
+            self.0.tree_names()
+        }
+
+        fn drop_tree(&self, name: &[u8]) -> StorageResult<bool> {
+            self.0.drop_tree(name)
+        }
+
+        fn transaction(
+            &self,
+            trees: &[&[u8]],
+            f: &TransactionFn<'_>,
+        ) -> Result<(), TransactionError> {
+            self.0.shared.storage.transaction(trees, f)
+        }
+
+        fn flush(&self) -> StorageResult<()> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn memory_storage() {
+        check_storage(Db::in_memory());
+    }
+
+    #[test]
+    fn sled_storage() {
+        check_storage(Db::new(SledStorage::temporary().unwrap()));
+    }
+}
@@ -0,0 +1,265 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::*;
+
+type Map = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Volatile storage which keeps all trees in memory.
+///
+/// Useful for tests and for relays which don't need to survive restarts
+#[derive(Default)]
+pub struct MemoryStorage {
+    trees: Mutex<HashMap<Vec<u8>, Arc<MemoryTree>>>,
+    /// Serializes transactions to prevent deadlocks on tree locks
+    transaction_lock: Mutex<()>,
+}
+
+impl MemoryStorage {
+    fn get_or_create(&self, name: &[u8]) -> Arc<MemoryTree> {
+        self.trees
+            .lock()
+            .unwrap()
+            .entry(name.to_vec())
+            .or_default()
+            .clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn open_tree(&self, name: &[u8]) -> StorageResult<Arc<dyn StorageTree>> {
+        Ok(self.get_or_create(name))
+    }
+
+    fn tree_names(&self) -> Vec<Vec<u8>> {
+        self.trees.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn drop_tree(&self, name: &[u8]) -> StorageResult<bool> {
+        Ok(match self.trees.lock().unwrap().remove(name) {
+            Some(tree) => {
+                tree.data.write().unwrap().clear();
+                true
+            }
+            None => false,
+        })
+    }
+
+    fn transaction(&self, trees: &[&[u8]], f: &TransactionFn<'_>) -> Result<(), TransactionError> {
+        let _guard = self.transaction_lock.lock().unwrap();
+
+        let trees: Vec<_> = trees.iter().map(|name| self.get_or_create(name)).collect();
+        let mut locks = Vec::with_capacity(trees.len());
+        for tree in &trees {
+            // The same tree can be specified twice
+            if locks.iter().any(|(other, _)| Arc::ptr_eq(other, tree)) {
+                continue;
+            }
+            locks.push((tree.clone(), tree.data.write().unwrap()));
+        }
+
+        let views: Vec<_> = trees
+            .iter()
+            .map(|tree| {
+                let (_, data) = locks
+                    .iter()
+                    .find(|(other, _)| Arc::ptr_eq(other, tree))
+                    .expect("Tree must be locked");
+                OverlayTree {
+                    data,
+                    changes: Default::default(),
+                }
+            })
+            .collect();
+
+        // Changes are applied only if the whole transaction succeeds
+        {
+            let views: Vec<&dyn TransactionalTree> = views
+                .iter()
+                .map(|view| view as &dyn TransactionalTree)
+                .collect();
+            f(&views)?;
+        }
+
+        let changes: Vec<_> = views
+            .into_iter()
+            .map(|view| view.changes.into_inner())
+            .collect();
+        for (tree, changes) in trees.iter().zip(changes) {
+            let (_, data) = locks
+                .iter_mut()
+                .find(|(other, _)| Arc::ptr_eq(other, tree))
+                .expect("Tree must be locked");
+            apply_changes(data, changes);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MemoryTree {
+    data: Arc<RwLock<Map>>,
+}
+
+impl MemoryTree {
+    /// Entries are fetched one by one, so the lock is not held during iteration
+    fn lazy_range(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>, rev: bool) -> Iter {
+        Iter::new(LazyRange {
+            data: self.data.clone(),
+            from,
+            to,
+            rev,
+        })
+    }
+}
+
+/// Range of the tree, which remembers the last returned key instead of
+/// copying the entries beforehand
+struct LazyRange {
+    data: Arc<RwLock<Map>>,
+    from: Bound<Vec<u8>>,
+    to: Bound<Vec<u8>>,
+    rev: bool,
+}
+
+impl Iterator for LazyRange {
+    type Item = StorageResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // BTreeMap panics on such ranges while sled returns nothing
+        let is_empty = match (&self.from, &self.to) {
+            (Bound::Included(from), Bound::Included(to)) => from > to,
+            (Bound::Included(from), Bound::Excluded(to))
+            | (Bound::Excluded(from), Bound::Included(to))
+            | (Bound::Excluded(from), Bound::Excluded(to)) => from >= to,
+            _ => false,
+        };
+        if is_empty {
+            return None;
+        }
+
+        let data = self.data.read().unwrap();
+        let mut range = data.range::<Vec<u8>, _>((self.from.clone(), self.to.clone()));
+        let (key, value) = if self.rev {
+            range.next_back()?
+        } else {
+            range.next()?
+        };
+
+        let bound = Bound::Excluded(key.clone());
+        if self.rev {
+            self.to = bound;
+        } else {
+            self.from = bound;
+        }
+        Some(Ok((key.clone(), value.clone())))
+    }
+}
+
+impl StorageTree for MemoryTree {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.data.read().unwrap().get(key).cloned())
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self
+            .data
+            .write()
+            .unwrap()
+            .insert(key.to_vec(), value.to_vec()))
+    }
+
+    fn remove(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.data.write().unwrap().remove(key))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> StorageResult<bool> {
+        Ok(self.data.read().unwrap().contains_key(key))
+    }
+
+    fn fetch_and_update(&self, key: &[u8], f: &mut UpdateFn<'_>) -> StorageResult<Option<Vec<u8>>> {
+        let mut data = self.data.write().unwrap();
+        let old = data.get(key).cloned();
+        match f(old.as_deref()) {
+            Some(value) => data.insert(key.to_vec(), value),
+            None => data.remove(key),
+        };
+        Ok(old)
+    }
+
+    fn range(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        self.lazy_range(from, to, false)
+    }
+
+    fn range_rev(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        self.lazy_range(from, to, true)
+    }
+
+    fn apply_batch(&self, batch: Batch) -> StorageResult<()> {
+        apply_changes(&mut self.data.write().unwrap(), batch.ops);
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.data.read().unwrap().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.read().unwrap().is_empty()
+    }
+
+    fn clear(&self) -> StorageResult<()> {
+        self.data.write().unwrap().clear();
+        Ok(())
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        Ok(())
+    }
+}
+
+struct OverlayTree<'a> {
+    data: &'a Map,
+    changes: RefCell<Changes>,
+}
+
+impl TransactionalTree for OverlayTree<'_> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        let changes = self.changes.borrow();
+        let value = match changes.iter().rev().find(|(changed, _)| changed == key) {
+            Some((_, value)) => value.clone(),
+            None => self.data.get(key).cloned(),
+        };
+        Ok(value)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        let old = self.get(key)?;
+        self.changes
+            .borrow_mut()
+            .push((key.to_vec(), Some(value.to_vec())));
+        Ok(old)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        let old = self.get(key)?;
+        self.changes.borrow_mut().push((key.to_vec(), None));
+        Ok(old)
+    }
+}
+
+fn apply_changes(data: &mut Map, changes: Changes) {
+    for (key, value) in changes {
+        match value {
+            Some(value) => data.insert(key, value),
+            None => data.remove(&key),
+        };
+    }
+}
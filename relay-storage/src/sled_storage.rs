@@ -0,0 +1,182 @@
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+
+use sled::transaction::{ConflictableTransactionError, Transactional, UnabortableTransactionError};
+
+use super::*;
+
+/// Persistent storage backed by sled
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    pub fn open<P>(path: P) -> StorageResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+
+    /// Creates database which is removed on drop
+    pub fn temporary() -> StorageResult<Self> {
+        Ok(Self {
+            db: sled::Config::new().temporary(true).open()?,
+        })
+    }
+}
+
+impl Storage for SledStorage {
+    fn open_tree(&self, name: &[u8]) -> StorageResult<Arc<dyn StorageTree>> {
+        Ok(Arc::new(SledTree(self.db.open_tree(name)?)))
+    }
+
+    fn tree_names(&self) -> Vec<Vec<u8>> {
+        self.db
+            .tree_names()
+            .into_iter()
+            .map(|name| name.to_vec())
+            .collect()
+    }
+
+    fn drop_tree(&self, name: &[u8]) -> StorageResult<bool> {
+        Ok(self.db.drop_tree(name)?)
+    }
+
+    fn transaction(&self, trees: &[&[u8]], f: &TransactionFn<'_>) -> Result<(), TransactionError> {
+        let trees = trees
+            .iter()
+            .map(|name| self.db.open_tree(name))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| TransactionError::Storage(e.into()))?;
+
+        let result = trees.as_slice().transaction(|views| {
+            let views: Vec<_> = views.iter().map(SledTransactionalTree).collect();
+            let views: Vec<&dyn TransactionalTree> = views
+                .iter()
+                .map(|view| view as &dyn TransactionalTree)
+                .collect();
+
+            f(&views).map_err(|e| match e {
+                TransactionError::Conflict => ConflictableTransactionError::Conflict,
+                e => ConflictableTransactionError::Abort(e),
+            })
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(sled::transaction::TransactionError::Abort(e)) => Err(e),
+            Err(sled::transaction::TransactionError::Storage(e)) => {
+                Err(TransactionError::Storage(e.into()))
+            }
+        }
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+struct SledTree(sled::Tree);
+
+impl StorageTree for SledTree {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.0.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.0.insert(key, value)?.map(|value| value.to_vec()))
+    }
+
+    fn remove(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.0.remove(key)?.map(|value| value.to_vec()))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> StorageResult<bool> {
+        Ok(self.0.contains_key(key)?)
+    }
+
+    fn fetch_and_update(&self, key: &[u8], f: &mut UpdateFn<'_>) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.0.fetch_and_update(key, f)?.map(|value| value.to_vec()))
+    }
+
+    fn range(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        Iter::new(self.0.range((from, to)).map(|item| {
+            item.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(From::from)
+        }))
+    }
+
+    fn range_rev(&self, from: Bound<Vec<u8>>, to: Bound<Vec<u8>>) -> Iter {
+        Iter::new(self.0.range((from, to)).rev().map(|item| {
+            item.map(|(key, value)| (key.to_vec(), value.to_vec()))
+                .map_err(From::from)
+        }))
+    }
+
+    fn apply_batch(&self, batch: Batch) -> StorageResult<()> {
+        let mut sled_batch = sled::Batch::default();
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => sled_batch.insert(key, value),
+                None => sled_batch.remove(key),
+            }
+        }
+        self.0.apply_batch(sled_batch)?;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn clear(&self) -> StorageResult<()> {
+        self.0.clear()?;
+        Ok(())
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+struct SledTransactionalTree<'a>(&'a sled::transaction::TransactionalTree);
+
+impl TransactionalTree for SledTransactionalTree<'_> {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.0
+            .get(key)
+            .map(|value| value.map(|value| value.to_vec()))
+            .map_err(convert_error)
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.0
+            .insert(key, value)
+            .map(|value| value.map(|value| value.to_vec()))
+            .map_err(convert_error)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TransactionError> {
+        self.0
+            .remove(key)
+            .map(|value| value.map(|value| value.to_vec()))
+            .map_err(convert_error)
+    }
+}
+
+fn convert_error(error: UnabortableTransactionError) -> TransactionError {
+    match error {
+        UnabortableTransactionError::Conflict => TransactionError::Conflict,
+        UnabortableTransactionError::Storage(e) => TransactionError::Storage(e.into()),
+    }
+}
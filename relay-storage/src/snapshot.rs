@@ -0,0 +1,174 @@
+//! Binary snapshot format.
+//!
+//! ```text
+//! snapshot := MAGIC tree* END entries_count:u64
+//! tree     := TREE name:bytes entries_count:u64 (key:bytes value:bytes)*
+//! bytes    := len:u32 data
+//! ```
+//!
+//! All integers are little-endian
+
+use std::io::{Read, Write};
+
+use anyhow::{anyhow, Error};
+
+use super::Entry;
+
+const MAGIC: &[u8; 8] = b"RLYSNAP1";
+const TREE: u8 = 1;
+const END: u8 = 0;
+
+/// Amount of data in the snapshot
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct SnapshotStats {
+    pub trees: usize,
+    pub entries: usize,
+}
+
+pub(crate) struct SnapshotWriter<W> {
+    writer: W,
+    stats: SnapshotStats,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            stats: Default::default(),
+        })
+    }
+
+    pub fn write_tree<I>(&mut self, name: &[u8], len: usize, entries: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = Result<(Vec<u8>, Vec<u8>), Error>>,
+    {
+        self.writer.write_all(&[TREE])?;
+        write_bytes(&mut self.writer, name)?;
+        self.writer.write_all(&(len as u64).to_le_bytes())?;
+
+        let mut written = 0;
+        for entry in entries {
+            let (key, value) = entry?;
+            write_bytes(&mut self.writer, &key)?;
+            write_bytes(&mut self.writer, &value)?;
+            written += 1;
+        }
+
+        // Tree must not be modified while the snapshot is being made
+        if written != len {
+            return Err(anyhow!(
+                "Tree {} changed during backup",
+                String::from_utf8_lossy(name)
+            ));
+        }
+
+        self.stats.trees += 1;
+        self.stats.entries += len;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<SnapshotStats, Error> {
+        self.writer.write_all(&[END])?;
+        self.writer
+            .write_all(&(self.stats.entries as u64).to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.stats)
+    }
+}
+
+pub(crate) struct SnapshotReader<R> {
+    reader: R,
+    stats: SnapshotStats,
+    /// Unread entries of the current tree
+    remaining: usize,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, Error> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("Invalid snapshot header"));
+        }
+
+        Ok(Self {
+            reader,
+            stats: Default::default(),
+            remaining: 0,
+        })
+    }
+
+    /// Reads header of the next tree. Returns its name and entries count
+    /// or `None` after the last tree.
+    ///
+    /// All entries of the tree must be read with [`Self::read_entry`] before the next one
+    pub fn read_tree(&mut self) -> Result<Option<(Vec<u8>, usize)>, Error> {
+        if self.remaining != 0 {
+            return Err(anyhow!("Previous tree was not read completely"));
+        }
+
+        match read_u8(&mut self.reader)? {
+            TREE => {}
+            END => {
+                let entries = read_u64(&mut self.reader)? as usize;
+                if entries != self.stats.entries {
+                    return Err(anyhow!("Snapshot is corrupted: entries count mismatch"));
+                }
+                return Ok(None);
+            }
+            tag => return Err(anyhow!("Snapshot is corrupted: unknown tag {}", tag)),
+        }
+
+        let name = read_bytes(&mut self.reader)?;
+        let len = read_u64(&mut self.reader)? as usize;
+
+        self.remaining = len;
+        self.stats.trees += 1;
+        Ok(Some((name, len)))
+    }
+
+    /// Reads next entry of the current tree
+    pub fn read_entry(&mut self) -> Result<Entry, Error> {
+        if self.remaining == 0 {
+            return Err(anyhow!("No entries left in the tree"));
+        }
+
+        let key = read_bytes(&mut self.reader)?;
+        let value = read_bytes(&mut self.reader)?;
+
+        self.remaining -= 1;
+        self.stats.entries += 1;
+        Ok((key, value))
+    }
+
+    pub fn stats(&self) -> SnapshotStats {
+        self.stats
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, data: &[u8]) -> Result<(), Error> {
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, Error> {
+    let mut buffer = [0; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut buffer = [0; 8];
+    reader.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut data = vec![0; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
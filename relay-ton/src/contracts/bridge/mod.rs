@@ -50,6 +50,11 @@ impl BridgeContract {
         &self.config.account
     }
 
+    /// Logical time and timestamp of the latest block, seen by the subscription
+    pub async fn current_time(&self) -> (u64, u32) {
+        self.subscription.current_time().await
+    }
+
     pub async fn get_active_event_configurations(
         &self,
     ) -> ContractResult<Vec<ActiveEventConfiguration>> {
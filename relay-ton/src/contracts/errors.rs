@@ -20,6 +20,8 @@ pub enum ContractError {
     TransportError(#[from] TransportError),
     #[error("invalid eth address")]
     InvalidEthAddress,
+    #[error("failed to sign message. {reason}")]
+    SigningFailed { reason: String },
 }
 
 pub type ContractResult<T> = Result<T, ContractError>;
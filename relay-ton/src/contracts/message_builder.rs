@@ -17,13 +17,36 @@ impl From<ExternalMessageHeader> for HashMap<String, TokenValue> {
     }
 }
 
-pub fn make_external_header(timeout_sec: u32, keypair: Option<&Keypair>) -> ExternalMessageHeader {
+pub fn make_external_header(
+    timeout_sec: u32,
+    pubkey: Option<ed25519_dalek::PublicKey>,
+) -> ExternalMessageHeader {
     let time = Utc::now().timestamp_millis() as u64;
     let expire = ((time / 1000) + timeout_sec as u64) as u32;
     ExternalMessageHeader {
         time,
         expire,
-        pubkey: keypair.map(|pair| pair.public),
+        pubkey,
+    }
+}
+
+/// Signs external messages of the relay contract
+#[async_trait]
+pub trait MessageSigner: Send + Sync {
+    fn public_key(&self) -> ed25519_dalek::PublicKey;
+
+    /// Signs the hash of the unsigned message body
+    async fn sign(&self, data: &[u8]) -> anyhow::Result<[u8; 64]>;
+}
+
+#[async_trait]
+impl MessageSigner for Keypair {
+    fn public_key(&self) -> ed25519_dalek::PublicKey {
+        self.public
+    }
+
+    async fn sign(&self, data: &[u8]) -> anyhow::Result<[u8; 64]> {
+        Ok(ed25519_dalek::Signer::sign(self, data).to_bytes())
     }
 }
 
@@ -83,18 +106,21 @@ impl<'a> MessageBuilder<'a> {
     }
 }
 
-pub struct SignedMessageBuilder<'a>(&'a Keypair, MessageBuilderImpl<'a, dyn AccountSubscription>);
+pub struct SignedMessageBuilder<'a>(
+    &'a dyn MessageSigner,
+    MessageBuilderImpl<'a, dyn AccountSubscription>,
+);
 
 impl<'a> SignedMessageBuilder<'a> {
     pub fn new(
         config: Cow<'a, ContractConfig>,
         contract: &'a Contract,
         transport: &'a dyn AccountSubscription,
-        keypair: &'a Keypair,
+        signer: &'a dyn MessageSigner,
         name: &str,
     ) -> ContractResult<Self> {
         Ok(Self(
-            keypair,
+            signer,
             MessageBuilderImpl::new(config, contract, transport, name)?,
         ))
     }
@@ -120,16 +146,19 @@ impl<'a> SignedMessageBuilder<'a> {
         Self(self.0, self.1.mark_local())
     }
 
-    pub fn build(self, with_signature: bool) -> ContractResult<ExternalMessage> {
-        self.1
-            .build(if with_signature { Some(self.0) } else { None })
+    pub async fn build(self, with_signature: bool) -> ContractResult<ExternalMessage> {
+        if with_signature {
+            self.1.build_signed(self.0).await
+        } else {
+            self.1.build(None)
+        }
     }
 
     #[allow(dead_code)]
     pub async fn run_local(self) -> ContractResult<ContractOutput> {
         let transport = self.1.transport;
         let output = transport
-            .run_local(self.1.function, self.build(false)?)
+            .run_local(self.1.function, self.build(false).await?)
             .await?;
         Ok(output)
     }
@@ -139,7 +168,7 @@ impl<'a> SignedMessageBuilder<'a> {
         let output = self
             .1
             .transport
-            .send_message(function, self.build(true)?)
+            .send_message(function, self.build(true).await?)
             .await?;
         Ok(output)
     }
@@ -212,7 +241,7 @@ where
     }
 
     pub fn build(self, keypair: Option<&Keypair>) -> ContractResult<ExternalMessage> {
-        let header = make_external_header(self.config.timeout_sec, keypair);
+        let header = make_external_header(self.config.timeout_sec, keypair.map(|pair| pair.public));
         let encoded_input = self
             .function
             .encode_input(&header.clone().into(), &self.input, false, keypair)
@@ -227,6 +256,38 @@ where
         })
     }
 
+    /// Only the hash of the unsigned body is passed to the signer,
+    /// so the private key may live outside of the relay
+    pub async fn build_signed(self, signer: &dyn MessageSigner) -> ContractResult<ExternalMessage> {
+        let public_key = signer.public_key();
+        let header = make_external_header(self.config.timeout_sec, Some(public_key));
+        let (unsigned, hash) = self
+            .function
+            .create_unsigned_call(&header.clone().into(), &self.input, false, true)
+            .map_err(|_| ContractError::InvalidInput)?;
+
+        let signature = signer
+            .sign(&hash)
+            .await
+            .map_err(|e| ContractError::SigningFailed {
+                reason: e.to_string(),
+            })?;
+        let encoded_input = Function::add_sign_to_encoded_input(
+            &signature,
+            Some(&public_key.as_bytes()[..]),
+            unsigned.into(),
+        )
+        .map_err(|_| ContractError::InvalidInput)?;
+
+        Ok(ExternalMessage {
+            dest: self.config.account.clone(),
+            init: None,
+            body: Some(encoded_input.into()),
+            header,
+            run_local: self.run_local,
+        })
+    }
+
     pub fn build_internal(self, src: MsgAddressInt, value: u64) -> ContractResult<InternalMessage> {
         let header = InternalMessageHeader { src, value };
         let body = self.build_internal_body()?;
@@ -4,7 +4,8 @@ pub use ton_abi::{Token, TokenValue};
 pub use super::contract::*;
 use super::errors::*;
 pub use super::message_builder::{
-    BigUint128, BigUint256, FunctionArg, FunctionArgsGroup, MessageBuilder, SignedMessageBuilder,
+    BigUint128, BigUint256, FunctionArg, FunctionArgsGroup, MessageBuilder, MessageSigner,
+    SignedMessageBuilder,
 };
 use crate::models::*;
 use crate::prelude::*;
@@ -365,6 +366,7 @@ impl StandaloneToken for u64 {}
 impl StandaloneToken for bool {}
 impl StandaloneToken for Vec<u8> {}
 impl StandaloneToken for TokenValue {}
+impl StandaloneToken for Cell {}
 
 pub trait ReadMethodId {
     type Error;
@@ -10,7 +10,7 @@ use super::BridgeContract;
 pub async fn make_relay_contract(
     transport: Arc<dyn Transport>,
     account: MsgAddrStd,
-    keypair: Arc<Keypair>,
+    signer: Arc<dyn MessageSigner>,
     bridge_contract: Arc<BridgeContract>,
 ) -> ContractResult<Arc<RelayContract>> {
     let subscription = transport
@@ -26,7 +26,7 @@ pub async fn make_relay_contract(
     Ok(Arc::new(RelayContract {
         transport,
         subscription,
-        keypair,
+        signer,
         account,
         config,
         contract,
@@ -37,7 +37,7 @@ pub async fn make_relay_contract(
 pub struct RelayContract {
     transport: Arc<dyn Transport>,
     subscription: Arc<dyn AccountSubscription>,
-    keypair: Arc<Keypair>,
+    signer: Arc<dyn MessageSigner>,
     account: MsgAddrStd,
     config: ContractConfig,
     contract: Arc<AbiContract>,
@@ -82,6 +82,14 @@ impl RelayContract {
     }
 
     pub async fn confirm_ethereum_event(&self, vote: EthEventVoteData) -> ContractResult<()> {
+        self.send_payloads(vec![self.confirm_ethereum_event_payload(vote)?])
+            .await
+    }
+
+    pub fn confirm_ethereum_event_payload(
+        &self,
+        vote: EthEventVoteData,
+    ) -> ContractResult<BuilderData> {
         log::info!(
             "CONFIRMING ETH EVENT: {:?}, {}, {}",
             hex::encode(&vote.event_transaction),
@@ -91,15 +99,21 @@ impl RelayContract {
 
         let configuration_id = vote.configuration_id;
 
-        self.send(
-            self.message("confirmEthereumEvent")?
-                .arg(vote)
-                .arg(configuration_id),
-        )
-        .await
+        self.message("confirmEthereumEvent")?
+            .arg(vote)
+            .arg(configuration_id)
+            .build_internal_body()
     }
 
     pub async fn reject_ethereum_event(&self, vote: EthEventVoteData) -> ContractResult<()> {
+        self.send_payloads(vec![self.reject_ethereum_event_payload(vote)?])
+            .await
+    }
+
+    pub fn reject_ethereum_event_payload(
+        &self,
+        vote: EthEventVoteData,
+    ) -> ContractResult<BuilderData> {
         log::info!(
             "REJECTING ETH EVENT: {:?}, {}, {}",
             hex::encode(&vote.event_transaction),
@@ -109,12 +123,10 @@ impl RelayContract {
 
         let configuration_id = vote.configuration_id;
 
-        self.send(
-            self.message("rejectEthereumEvent")?
-                .arg(vote)
-                .arg(configuration_id),
-        )
-        .await
+        self.message("rejectEthereumEvent")?
+            .arg(vote)
+            .arg(configuration_id)
+            .build_internal_body()
     }
 
     pub async fn confirm_ton_event(
@@ -122,6 +134,17 @@ impl RelayContract {
         vote: TonEventVoteData,
         event_data_signature: Vec<u8>,
     ) -> ContractResult<()> {
+        self.send_payloads(vec![
+            self.confirm_ton_event_payload(vote, event_data_signature)?
+        ])
+        .await
+    }
+
+    pub fn confirm_ton_event_payload(
+        &self,
+        vote: TonEventVoteData,
+        event_data_signature: Vec<u8>,
+    ) -> ContractResult<BuilderData> {
         log::info!(
             "CONFIRMING TON EVENT: {}, {}, {}",
             hex::encode(&vote.event_transaction),
@@ -131,16 +154,19 @@ impl RelayContract {
 
         let configuration_id = vote.configuration_id;
 
-        self.send(
-            self.message("confirmTonEvent")?
-                .arg(vote)
-                .arg(event_data_signature)
-                .arg(configuration_id),
-        )
-        .await
+        self.message("confirmTonEvent")?
+            .arg(vote)
+            .arg(event_data_signature)
+            .arg(configuration_id)
+            .build_internal_body()
     }
 
     pub async fn reject_ton_event(&self, vote: TonEventVoteData) -> ContractResult<()> {
+        self.send_payloads(vec![self.reject_ton_event_payload(vote)?])
+            .await
+    }
+
+    pub fn reject_ton_event_payload(&self, vote: TonEventVoteData) -> ContractResult<BuilderData> {
         log::info!(
             "REJECTING TON EVENT: {}, {}, {}",
             hex::encode(&vote.event_transaction),
@@ -150,12 +176,10 @@ impl RelayContract {
 
         let configuration_id = vote.configuration_id;
 
-        self.send(
-            self.message("rejectTonEvent")?
-                .arg(vote)
-                .arg(configuration_id),
-        )
-        .await
+        self.message("rejectTonEvent")?
+            .arg(vote)
+            .arg(configuration_id)
+            .build_internal_body()
     }
 
     pub async fn update_bridge_configuration(
@@ -171,28 +195,68 @@ impl RelayContract {
         .await
     }
 
-    #[inline]
-    async fn send(&self, message: MessageBuilder<'_>) -> ContractResult<()> {
-        const ONE_TON: u64 = 1_000_000_000;
-        const FLAGS: u8 = 3;
+    /// Sends bridge method calls, prepared with `*_payload` methods.
+    /// Several payloads are sent within one external message using `sendTransactions`
+    pub async fn send_payloads(&self, payloads: Vec<BuilderData>) -> ContractResult<()> {
+        let (function, payloads) = match payloads.len() {
+            0 => return Ok(()),
+            1 => (
+                "sendTransaction",
+                payloads.into_iter().next().unwrap().token_value(),
+            ),
+            _ => (
+                "sendTransactions",
+                payloads
+                    .into_iter()
+                    .map(Cell::from)
+                    .collect::<Vec<_>>()
+                    .token_value(),
+            ),
+        };
 
         SignedMessageBuilder::new(
             Cow::Borrowed(&self.config),
             &self.contract,
             self.subscription.as_ref(),
-            self.keypair.as_ref(),
-            "sendTransaction",
+            self.signer.as_ref(),
+            function,
         )?
         .arg(self.bridge_contract.address())
         .arg(BigUint128(ONE_TON.into()))
         .arg(true)
         .arg(FLAGS)
-        .arg(message.build_internal_body()?)
+        .arg(payloads)
         .send()
         .await?
         .ignore_output()
     }
 
+    /// Runs `sendTransactions` without payloads locally to make sure,
+    /// that the deployed contract has this method
+    pub async fn check_batches_support(&self) -> ContractResult<()> {
+        SignedMessageBuilder::new(
+            Cow::Borrowed(&self.config),
+            &self.contract,
+            self.subscription.as_ref(),
+            self.signer.as_ref(),
+            "sendTransactions",
+        )?
+        .arg(self.bridge_contract.address())
+        .arg(BigUint128(ONE_TON.into()))
+        .arg(true)
+        .arg(FLAGS)
+        .arg(Vec::<Cell>::new().token_value())
+        .run_local()
+        .await?;
+        Ok(())
+    }
+
+    #[inline]
+    async fn send(&self, message: MessageBuilder<'_>) -> ContractResult<()> {
+        self.send_payloads(vec![message.build_internal_body()?])
+            .await
+    }
+
     #[inline]
     fn message(&self, name: &str) -> ContractResult<MessageBuilder> {
         MessageBuilder::new(
@@ -211,6 +275,9 @@ impl Contract for RelayContract {
     }
 }
 
+const ONE_TON: u64 = 1_000_000_000;
+const FLAGS: u8 = 3;
+
 static ABI: OnceCell<Arc<AbiContract>> = OnceCell::new();
 const JSON_ABI: &str = include_str!("../../../abi/Relay.abi.json");
 
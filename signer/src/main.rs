@@ -0,0 +1,158 @@
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Clap;
+use dialoguer::Password;
+use relay::crypto::key_managment::KeyData;
+use relay::crypto::signer::*;
+use secstr::SecStr;
+use tokio::net::UnixListener;
+use tokio::sync::Semaphore;
+use url::Url;
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::Filter;
+
+/// Max time to read the request and write the response over the unix socket
+const UNIX_CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// Max number of simultaneously served unix socket connections
+const MAX_UNIX_CONNECTIONS: usize = 64;
+
+/// Reference signer daemon. Keeps relay keys outside of the relay process
+#[derive(Clap)]
+struct Arguments {
+    /// Path to the relay keystore
+    #[clap(short, long)]
+    keys: PathBuf,
+
+    /// Listen address. `unix:///path/to/socket` or `https://host:port`
+    #[clap(short, long, default_value = "unix:///run/relay-signer.sock")]
+    listen: Url,
+
+    /// File with the bearer token, which https clients must present
+    #[clap(long)]
+    token_file: Option<PathBuf>,
+
+    /// PEM encoded certificate chain of the https server
+    #[clap(long)]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM encoded private key of the https server
+    #[clap(long)]
+    tls_key: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Arguments::parse();
+
+    let password: SecStr = Password::new()
+        .with_prompt("Keystore password")
+        .interact()?
+        .into();
+    let keys = KeyData::from_file(&args.keys, &password).context("Failed unlocking keystore")?;
+    let signer: Arc<dyn Signer> = Arc::new(LocalSigner::new(args.keys.clone(), keys));
+    println!("ETH address: 0x{}", hex::encode(signer.eth_address()));
+    println!(
+        "TON public key: {}",
+        hex::encode(signer.ton_public_key().as_bytes())
+    );
+
+    match args.listen.scheme() {
+        "unix" => serve_unix(args.listen.path(), signer).await,
+        "https" => serve_https(&args, signer).await,
+        "http" => Err(anyhow!("Plain http is not supported, use https")),
+        scheme => Err(anyhow!("Unsupported listen scheme: {}", scheme)),
+    }
+}
+
+async fn serve_unix(path: &str, signer: Arc<dyn Signer>) -> Result<()> {
+    // Socket file is left after the previous run
+    if std::fs::metadata(path).is_ok() {
+        std::fs::remove_file(path)?;
+    }
+
+    // Only the owner of the daemon is allowed to request signatures. Socket is created
+    // with the restrictive umask, so that there is no moment when others can connect
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    let listener = listener.with_context(|| format!("Failed binding socket {}", path))?;
+    println!("Listening on {}", path);
+
+    // Stalled clients are disconnected, and new ones wait for free slots
+    let connections = Arc::new(Semaphore::new(MAX_UNIX_CONNECTIONS));
+    loop {
+        let permit = connections.clone().acquire_owned().await?;
+        let (stream, _) = listener.accept().await?;
+        let signer = signer.clone();
+        tokio::spawn(async move {
+            let connection = serve_unix_connection(stream, signer.as_ref());
+            match tokio::time::timeout(UNIX_CONNECTION_TIMEOUT, connection).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("Failed serving request: {}", e),
+                Err(_) => eprintln!("Connection timed out"),
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn serve_https(args: &Arguments, signer: Arc<dyn Signer>) -> Result<()> {
+    let (token_file, tls_cert, tls_key) = match (&args.token_file, &args.tls_cert, &args.tls_key) {
+        (Some(token_file), Some(tls_cert), Some(tls_key)) => (token_file, tls_cert, tls_key),
+        _ => {
+            return Err(anyhow!(
+                "--token-file, --tls-cert and --tls-key are required for https"
+            ))
+        }
+    };
+    let token = Arc::new(read_token(token_file)?);
+
+    let address = args
+        .listen
+        .socket_addrs(|| None)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Invalid listen address"))?;
+
+    let route = warp::post()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(MAX_MESSAGE_SIZE))
+        .and(warp::body::bytes())
+        .and_then(move |authorization: Option<String>, body: Bytes| {
+            let signer = signer.clone();
+            let token = token.clone();
+            async move {
+                // Body is not parsed for unauthorized clients
+                if !is_authorized(authorization.as_deref(), &token) {
+                    let response = SignerResponse::Error("Unauthorized".to_owned());
+                    return Ok::<_, Infallible>(warp::reply::with_status(
+                        warp::reply::json(&response),
+                        StatusCode::UNAUTHORIZED,
+                    ));
+                }
+
+                let response = match serde_json::from_slice(&body) {
+                    Ok(request) => handle_signer_request(signer.as_ref(), request).await,
+                    Err(e) => SignerResponse::Error(format!("Invalid request: {}", e)),
+                };
+                Ok(warp::reply::with_status(
+                    warp::reply::json(&response),
+                    StatusCode::OK,
+                ))
+            }
+        });
+
+    println!("Listening on {}", address);
+    warp::serve(route)
+        .tls()
+        .cert_path(tls_cert)
+        .key_path(tls_key)
+        .run(address)
+        .await;
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -22,12 +23,50 @@ pub struct RelayConfig {
     /// Listen address of relay. Used by the client to perform all maintenance actions.
     pub listen_address: SocketAddr,
 
+    /// Authentication of the control server requests. Requests are not authenticated if not specified
+    #[serde(default)]
+    pub api_auth: Option<ApiAuthSettings>,
+
+    /// TLS of the control server. Plain http is used if not specified
+    #[serde(default)]
+    pub api_tls: Option<ApiTlsSettings>,
+
+    /// File, where audit entries of the administrative api requests are appended as JSON lines.
+    /// Entries are only stored in the db if not specified
+    #[serde(default)]
+    pub audit_log_path: Option<PathBuf>,
+
+    /// Listen address of the gRPC api. Requires the `grpc` feature. Uses the same auth and TLS
+    #[serde(default)]
+    pub grpc_listen_address: Option<SocketAddr>,
+
     /// Path to json, where ton and eth private keys will be stored in encrypted way.
     pub keys_path: PathBuf,
 
     /// Path to Sled database.
     pub storage_path: PathBuf,
 
+    /// Database backend
+    #[serde(default)]
+    pub storage_engine: StorageEngine,
+
+    /// Value-level encryption of the database. Disabled if not specified
+    #[serde(default)]
+    pub storage_encryption: Option<StorageEncryptionSettings>,
+
+    /// Unlock the relay on start with the password from the file or environment.
+    /// `/unlock` request is required if not specified
+    #[serde(default)]
+    pub auto_unlock: Option<AutoUnlockSettings>,
+
+    /// External signer, which holds ETH and TON keys. Keys from `keys_path` are used if not specified
+    #[serde(default)]
+    pub signer: Option<RemoteSignerSettings>,
+
+    /// Directory for db snapshots, made before migrations. `<storage_path>_snapshots` by default
+    #[serde(default)]
+    pub migration_snapshots_path: Option<PathBuf>,
+
     /// Logger settings
     #[serde(default = "default_logger_settings")]
     pub logger_settings: serde_yaml::Value,
@@ -36,6 +75,10 @@ pub struct RelayConfig {
     #[serde(default)]
     pub metrics_settings: Option<MetricsSettings>,
 
+    /// Thresholds of the readiness check
+    #[serde(default)]
+    pub health: HealthSettings,
+
     /// ETH specific settings
     pub eth_settings: EthSettings,
 
@@ -43,6 +86,129 @@ pub struct RelayConfig {
     pub ton_settings: TonSettings,
 }
 
+impl RelayConfig {
+    pub fn snapshots_path(&self) -> PathBuf {
+        match &self.migration_snapshots_path {
+            Some(path) => path.clone(),
+            None => {
+                let mut path = self.storage_path.clone().into_os_string();
+                path.push("_snapshots");
+                path.into()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageEngine {
+    /// Persistent storage at `storage_path`
+    Sled,
+    /// Volatile storage. All state is lost on restart
+    Memory,
+}
+
+impl Default for StorageEngine {
+    fn default() -> Self {
+        Self::Sled
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct StorageEncryptionSettings {
+    /// File with the hex encoded 32 byte data key.
+    /// The key is derived from the unlock password if not specified
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+    /// Whether `db/backup` and `db/export` may write decrypted values
+    #[serde(default)]
+    pub allow_plaintext_backups: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiAuthSettings {
+    /// Tokens, accepted in the `Authorization: Bearer <token>` header
+    pub tokens: Vec<ApiTokenSettings>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiTokenSettings {
+    /// Name of the token owner. Used in logs
+    pub name: String,
+
+    /// Hex encoded SHA-256 hash of the token
+    pub hash: String,
+
+    /// Allowed requests
+    pub role: ApiRole,
+}
+
+#[derive(Deserialize, Serialize, Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiRole {
+    /// Read-only requests: status, queues, journals and stats
+    Monitor,
+    /// All requests, including init, unlock, votes and db backups
+    Admin,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ApiTlsSettings {
+    /// PEM encoded certificate chain of the server
+    pub cert_path: PathBuf,
+
+    /// PEM encoded private key of the server
+    pub key_path: PathBuf,
+
+    /// PEM encoded CA certificates. Clients must present a certificate, signed by one of them,
+    /// if specified
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AutoUnlockSettings {
+    /// Where the unlock password is read from
+    pub password: PasswordSource,
+
+    /// Refuse password files, which are accessible by group or other users
+    #[serde(default = "default_true")]
+    pub check_permissions: bool,
+
+    /// Wipe the read buffer and remove the environment variable after the password is read
+    #[serde(default = "default_true")]
+    pub zeroize: bool,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PasswordSource {
+    /// File with the password. Trailing newline is ignored
+    File { path: PathBuf },
+    /// Credential, passed with `LoadCredential=` or `SetCredential=` in the systemd unit
+    SystemdCredential { name: String },
+    /// Environment variable
+    Env { name: String },
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RemoteSignerSettings {
+    /// Signer address. `unix:///path/to/socket` or `https://host:port/path`
+    pub endpoint: String,
+
+    /// File with the bearer token of the signer. Required for https
+    #[serde(default)]
+    pub token_path: Option<PathBuf>,
+
+    /// PEM encoded CA certificate of the signer. System roots are used if not specified
+    #[serde(default)]
+    pub ca_path: Option<PathBuf>,
+
+    /// Timeout of a single signing request
+    #[serde(with = "relay_utils::serde_time", default = "default_signer_timeout")]
+    pub timeout: Duration,
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct MetricsSettings {
     /// Listen address of metrics. Used by the client to gather prometheus metrics
@@ -57,6 +223,31 @@ pub struct MetricsSettings {
     pub collection_interval: Duration,
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct HealthSettings {
+    /// Relay is not ready if the latest scanned ETH block is further behind the node
+    pub max_eth_lag: u64,
+
+    /// Relay is not ready if the latest TON block, seen by the bridge subscription, is older
+    #[serde(with = "relay_utils::serde_time")]
+    pub max_ton_lag: Duration,
+
+    /// Timeout of each component check
+    #[serde(with = "relay_utils::serde_time")]
+    pub check_timeout: Duration,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            max_eth_lag: 100,
+            max_ton_lag: Duration::from_secs(300),
+            check_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct EthSettings {
     /// Address of ethereum node. Only http is supported right now
@@ -82,6 +273,10 @@ pub struct EthSettings {
     /// Offset in blocks for checking suspicious transactions
     pub suspicious_blocks_offset: u64,
 
+    /// Number of blocks after which relay keys ownership changes are considered final
+    #[serde(default = "default_ownership_blocks_to_confirm")]
+    pub ownership_blocks_to_confirm: u64,
+
     /// Bridge address in ethereum
     pub bridge_address: Address,
 }
@@ -96,6 +291,7 @@ impl Default for EthSettings {
             eth_poll_interval: Duration::from_secs(10),
             eth_poll_attempts: 86400 / 10,
             suspicious_blocks_offset: 10,
+            ownership_blocks_to_confirm: default_ownership_blocks_to_confirm(),
             bridge_address: Default::default(),
         }
     }
@@ -133,7 +329,13 @@ pub struct TonSettings {
     pub message_retry_interval_multiplier: f64,
 
     /// Amount of parallel sent messages in ton
-    pub parallel_spawned_contracts_limit: usize,
+    pub parallel_spawned_contracts_limit: NonZeroUsize,
+    /// Order and per-configuration limits of sent messages
+    #[serde(default)]
+    pub votes_scheduler: VotesSchedulerSettings,
+    /// Sending several votes within one message
+    #[serde(default)]
+    pub votes_batching: VotesBatchingSettings,
 
     /// TON events verification interval
     #[serde(with = "relay_utils::serde_time")]
@@ -149,6 +351,14 @@ pub struct TonSettings {
     /// Duration between attempts to create events handler
     #[serde(with = "relay_utils::serde_time")]
     pub events_handler_interval: Duration,
+
+    /// Automatic retry of the failed votes queue
+    #[serde(default)]
+    pub failed_votes_retry: FailedVotesRetrySettings,
+
+    /// Cleanup of the old voting statistics
+    #[serde(default)]
+    pub voting_stats_retention: VotingStatsRetentionSettings,
 }
 
 impl Default for TonSettings {
@@ -164,28 +374,203 @@ impl Default for TonSettings {
             message_retry_interval: Duration::from_secs(60),
             message_retry_count: 10,
             message_retry_interval_multiplier: 1.5,
-            parallel_spawned_contracts_limit: 10,
+            parallel_spawned_contracts_limit: NonZeroUsize::new(10).unwrap(),
+            votes_scheduler: VotesSchedulerSettings::default(),
+            votes_batching: VotesBatchingSettings::default(),
             ton_events_verification_interval: Duration::from_secs(1),
             ton_events_verification_queue_lt_offset: 10,
             ton_events_allowed_time_diff: 10,
             events_handler_retry_count: 50,
             events_handler_interval: Duration::from_secs(10),
+            failed_votes_retry: FailedVotesRetrySettings::default(),
+            voting_stats_retention: VotingStatsRetentionSettings::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct FailedVotesRetrySettings {
+    /// Whether failed votes are retried automatically
+    pub enabled: bool,
+
+    /// Interval between failed votes queue scans
+    #[serde(with = "relay_utils::serde_time")]
+    pub scan_interval: Duration,
+
+    /// Delay before the first automatic retry
+    #[serde(with = "relay_utils::serde_time")]
+    pub initial_delay: Duration,
+    /// Coefficient, on which the delay will be multiplied after each failed attempt
+    pub delay_multiplier: f64,
+    /// Upper bound for the delay between retries
+    #[serde(with = "relay_utils::serde_time")]
+    pub max_delay: Duration,
+
+    /// Votes failed earlier than this are moved to the dead-letter queue
+    #[serde(with = "relay_utils::serde_time")]
+    pub max_age: Duration,
+    /// Amount of automatic retries before the vote is moved to the dead-letter queue
+    pub max_attempts: u32,
+}
+
+impl Default for FailedVotesRetrySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            scan_interval: Duration::from_secs(60),
+            initial_delay: Duration::from_secs(300),
+            delay_multiplier: 2.0,
+            max_delay: Duration::from_secs(6 * 3600),
+            max_age: Duration::from_secs(3 * 86400),
+            max_attempts: 10,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct VotesSchedulerSettings {
+    /// Amount of parallel sent messages for each configuration.
+    /// `parallel_spawned_contracts_limit` is used if not specified
+    pub default_configuration_limit: Option<NonZeroUsize>,
+    /// Share of the sending slots for each configuration
+    pub default_configuration_weight: u32,
+    /// Overrides for specific configurations
+    pub configurations: Vec<ConfigurationSchedulingSettings>,
+    /// Messages waiting longer than this are sent before all others
+    #[serde(with = "relay_utils::serde_time")]
+    pub starvation_timeout: Duration,
+}
+
+impl Default for VotesSchedulerSettings {
+    fn default() -> Self {
+        Self {
+            default_configuration_limit: None,
+            default_configuration_weight: 1,
+            configurations: Vec::new(),
+            starvation_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct VotesBatchingSettings {
+    /// Whether votes are sent in batches. Requires relay contract with `sendTransactions` method
+    pub enabled: bool,
+    /// Time to gather votes into the batch
+    #[serde(with = "relay_utils::serde_time")]
+    pub window: Duration,
+    /// Batch is sent immediately when this amount of votes is gathered
+    pub max_batch_size: usize,
+}
+
+impl Default for VotesBatchingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: Duration::from_secs(1),
+            max_batch_size: 8,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct VotingStatsRetentionSettings {
+    /// Interval between voting stats compactions
+    #[serde(with = "relay_utils::serde_time")]
+    pub compaction_interval: Duration,
+    /// Votes received earlier than this are removed. Kept forever if not specified
+    #[serde(with = "relay_utils::optional_serde_time")]
+    pub default_max_age: Option<Duration>,
+    /// Amount of the latest votes kept for each configuration. Unlimited if not specified
+    pub default_max_count: Option<usize>,
+    /// Overrides for specific configurations
+    pub configurations: Vec<ConfigurationRetentionSettings>,
+}
+
+impl VotingStatsRetentionSettings {
+    /// Whether any votes can be removed
+    pub fn is_enabled(&self) -> bool {
+        self.default_max_age.is_some()
+            || self.default_max_count.is_some()
+            || self
+                .configurations
+                .iter()
+                .any(|item| item.max_age.is_some() || item.max_count.is_some())
+    }
+
+    /// Max age and max count of the votes for the configuration
+    pub fn configuration_rules(&self, configuration_id: u32) -> (Option<Duration>, Option<usize>) {
+        let item = self
+            .configurations
+            .iter()
+            .find(|item| item.configuration_id == configuration_id);
+        (
+            item.and_then(|item| item.max_age).or(self.default_max_age),
+            item.and_then(|item| item.max_count)
+                .or(self.default_max_count),
+        )
+    }
+}
+
+impl Default for VotingStatsRetentionSettings {
+    fn default() -> Self {
+        Self {
+            compaction_interval: Duration::from_secs(3600),
+            default_max_age: None,
+            default_max_count: None,
+            configurations: Vec::new(),
         }
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ConfigurationRetentionSettings {
+    pub configuration_id: u32,
+    /// Votes received earlier than this are removed
+    #[serde(default, with = "relay_utils::optional_serde_time")]
+    pub max_age: Option<Duration>,
+    /// Amount of the latest votes kept for this configuration
+    #[serde(default)]
+    pub max_count: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ConfigurationSchedulingSettings {
+    pub configuration_id: u32,
+    /// Amount of parallel sent messages for this configuration
+    #[serde(default)]
+    pub limit: Option<NonZeroUsize>,
+    /// Share of the sending slots for this configuration
+    #[serde(default)]
+    pub weight: Option<u32>,
+}
+
 impl Default for RelayConfig {
     fn default() -> Self {
         Self {
             keys_path: PathBuf::from("/var/lib/relay/keys.json"),
             listen_address: "127.0.0.1:12345".parse().unwrap(),
+            api_auth: None,
+            api_tls: None,
+            audit_log_path: None,
+            grpc_listen_address: None,
             storage_path: PathBuf::from("/var/lib/relay/persistent_storage"),
+            storage_engine: StorageEngine::default(),
+            storage_encryption: None,
+            auto_unlock: None,
+            signer: None,
+            migration_snapshots_path: None,
             logger_settings: default_logger_settings(),
             metrics_settings: Some(MetricsSettings {
                 listen_address: "127.0.0.1:10000".parse().unwrap(),
                 metrics_path: default_metrics_path(),
                 collection_interval: Duration::from_secs(10),
             }),
+            health: HealthSettings::default(),
             eth_settings: EthSettings::default(),
             ton_settings: TonSettings::default(),
         }
@@ -227,6 +612,18 @@ fn default_metrics_path() -> PathAndQuery {
     PathAndQuery::from_static("/")
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_signer_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_ownership_blocks_to_confirm() -> u64 {
+    12
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum TonTransportConfig {
@@ -272,6 +669,51 @@ pub struct Arguments {
     /// Generate default config
     #[clap(long)]
     pub gen_config: Option<PathBuf>,
+
+    /// Restore empty database from the backup before start
+    #[clap(long, requires = "config")]
+    pub restore: Option<PathBuf>,
+
+    /// Check database integrity before start and move corrupted entries to quarantine
+    #[clap(long, requires = "config")]
+    pub check_db: bool,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Clap)]
+pub enum Command {
+    /// Database maintenance
+    #[clap(subcommand)]
+    Db(DbCommand),
+    /// Generate a random API token and print it with its hash for `api_auth`
+    GenApiToken,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Clap)]
+pub enum DbCommand {
+    /// Validate keys and values of all trees without starting the relay
+    Check {
+        /// Move corrupted entries to the quarantine tree
+        #[clap(long)]
+        quarantine: bool,
+    },
+    /// Apply migrations without starting the relay
+    Migrate {
+        /// Only print affected trees and record counts
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Revert migrations, so that the db can be used by the older relay
+    Downgrade {
+        /// Version of the relay, which will use the db
+        #[clap(long)]
+        to: String,
+        /// Only print affected trees and record counts
+        #[clap(long)]
+        dry_run: bool,
+    },
 }
 
 pub fn generate_config<T>(path: T) -> Result<(), Error>
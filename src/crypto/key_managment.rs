@@ -2,15 +2,17 @@ use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::fs::File;
 use std::num::NonZeroU32;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use ed25519_dalek::{ed25519, Keypair, Signer};
+use ed25519_dalek::{ed25519, ExpandedSecretKey, Keypair};
 use rand::prelude::*;
 use ring::{digest, pbkdf2};
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 use secp256k1::{Message, PublicKey, SecretKey};
 use secstr::{SecStr, SecVec};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::{from_reader, to_writer_pretty};
+use sodiumoxide::crypto::pwhash::argon2id13;
 use sodiumoxide::crypto::secretbox;
 use sodiumoxide::crypto::secretbox::{Key, Nonce};
 
@@ -26,37 +28,150 @@ const N_ITER: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(1) };
 #[cfg(not(debug_assertions))]
 const N_ITER: NonZeroU32 = unsafe { NonZeroU32::new_unchecked(5_000_000) };
 
+#[cfg(debug_assertions)]
+const ARGON2_LIMITS: (argon2id13::OpsLimit, argon2id13::MemLimit) = (
+    argon2id13::OPSLIMIT_INTERACTIVE,
+    argon2id13::MEMLIMIT_INTERACTIVE,
+);
+
+/// Argon2id parameters for the new keys. Moderate limits take about a second and 256 MB of RAM
+#[cfg(not(debug_assertions))]
+const ARGON2_LIMITS: (argon2id13::OpsLimit, argon2id13::MemLimit) =
+    (argon2id13::OPSLIMIT_MODERATE, argon2id13::MEMLIMIT_MODERATE);
+
+/// Upper bound of pbkdf2 iterations accepted from the keystore file
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// Current version of the keystore format. Legacy files without version are treated as `0`
+const KEYSTORE_VERSION: u32 = 1;
+
+/// Password based key derivation function with its parameters
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum Kdf {
+    Pbkdf2 { iterations: NonZeroU32 },
+    Argon2id { ops_limit: usize, mem_limit: usize },
+}
+
+impl Kdf {
+    /// Kdf of the keystore files without `kdf` section
+    pub fn legacy() -> Self {
+        Self::Pbkdf2 { iterations: N_ITER }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pbkdf2 { .. } => "pbkdf2",
+            Self::Argon2id { .. } => "argon2id",
+        }
+    }
+
+    pub fn salt_len(&self) -> usize {
+        match self {
+            Self::Pbkdf2 { .. } => CREDENTIAL_LEN,
+            Self::Argon2id { .. } => argon2id13::SALTBYTES,
+        }
+    }
+
+    pub fn gen_salt(&self) -> Vec<u8> {
+        let mut rng = rand::rngs::OsRng::new().expect("OsRng fail");
+        let mut salt = vec![0u8; self.salt_len()];
+        rng.fill(salt.as_mut_slice());
+        salt
+    }
+
+    /// Checks parameters, which are read from the keystore or db metadata,
+    /// so that a tampered file can't stall the relay or exhaust its memory
+    pub fn validate(&self) -> Result<(), Error> {
+        match *self {
+            Self::Pbkdf2 { iterations } if iterations.get() > MAX_PBKDF2_ITERATIONS => {
+                Err(anyhow!("Too many pbkdf2 iterations: {}", iterations))
+            }
+            Self::Argon2id {
+                ops_limit,
+                mem_limit,
+            } if !(argon2id13::OPSLIMIT_INTERACTIVE.0..=argon2id13::OPSLIMIT_SENSITIVE.0)
+                .contains(&ops_limit)
+                || !(argon2id13::MEMLIMIT_INTERACTIVE.0..=argon2id13::MEMLIMIT_SENSITIVE.0)
+                    .contains(&mem_limit) =>
+            {
+                Err(anyhow!(
+                    "Invalid argon2id limits: ops {}, mem {}",
+                    ops_limit,
+                    mem_limit
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Calculates symmetric key from user password
+    pub fn derive_key(&self, password: &SecStr, salt: &[u8]) -> Result<Key, Error> {
+        self.validate()?;
+        let mut hash = SecVec::new(vec![0; secretbox::KEYBYTES]);
+        match *self {
+            Self::Pbkdf2 { iterations } => pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                iterations,
+                salt,
+                password.unsecure(),
+                &mut hash.unsecure_mut(),
+            ),
+            Self::Argon2id {
+                ops_limit,
+                mem_limit,
+            } => {
+                let salt = argon2id13::Salt::from_slice(salt)
+                    .ok_or_else(|| anyhow!("Invalid argon2id salt length"))?;
+                argon2id13::derive_key(
+                    hash.unsecure_mut(),
+                    password.unsecure(),
+                    &salt,
+                    argon2id13::OpsLimit(ops_limit),
+                    argon2id13::MemLimit(mem_limit),
+                )
+                .map_err(|_| anyhow!("Failed deriving key with argon2id"))?;
+            }
+        }
+        Ok(Key::from_slice(hash.unsecure()).expect("Shouldn't panic"))
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        let (ops_limit, mem_limit) = ARGON2_LIMITS;
+        Self::Argon2id {
+            ops_limit: ops_limit.0,
+            mem_limit: mem_limit.0,
+        }
+    }
+}
+
 #[derive(Eq, PartialEq, Debug)]
 pub struct KeyData {
     pub eth: EthSigner,
+    /// ETH key, which replaces `eth` when the rotation is complete
+    pub eth_next: Option<EthSigner>,
     pub ton: TonSigner,
 }
 
-#[derive(Eq, PartialEq, Clone)]
+/// Secret keys are kept in the locked memory, which is wiped on drop.
+/// Signers are not cloned, share them with `Arc<dyn Signer>` instead
+#[derive(Eq, PartialEq)]
 pub struct EthSigner {
     pubkey: PublicKey,
-    private_key: SecretKey,
+    private_key: SecVec<u8>,
 }
 
-#[derive(Clone)]
+#[derive(Eq, PartialEq)]
 pub struct TonSigner {
-    inner: Arc<Keypair>,
-}
-
-impl Eq for TonSigner {}
-
-impl PartialEq for TonSigner {
-    fn eq(&self, other: &Self) -> bool {
-        self.inner
-            .secret
-            .as_bytes()
-            .eq(other.inner.secret.as_bytes())
-    }
+    public: ed25519_dalek::PublicKey,
+    secret: SecVec<u8>,
 }
 
 impl Debug for TonSigner {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.inner.public)
+        write!(f, "{:?}", self.public)
     }
 }
 
@@ -69,6 +184,11 @@ impl Debug for EthSigner {
 ///Data, stored on disk in `encrypted_data` filed of config.
 #[derive(Serialize, Deserialize)]
 struct CryptoData {
+    #[serde(default)]
+    version: u32,
+    #[serde(default = "Kdf::legacy")]
+    kdf: Kdf,
+
     #[serde(serialize_with = "buffer_to_hex", deserialize_with = "hex_to_buffer")]
     salt: Vec<u8>,
 
@@ -92,6 +212,69 @@ struct CryptoData {
         deserialize_with = "deserialize_nonce"
     )]
     ton_nonce: Nonce,
+
+    /// Rotated ETH key, encrypted with the same key as the current one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    eth_next: Option<EncryptedEthKey>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedEthKey {
+    #[serde(
+        serialize_with = "serialize_pubkey",
+        deserialize_with = "deserialize_pubkey"
+    )]
+    pubkey: PublicKey,
+    #[serde(serialize_with = "buffer_to_hex", deserialize_with = "hex_to_buffer")]
+    encrypted_private_key: Vec<u8>,
+    #[serde(
+        serialize_with = "serialize_nonce",
+        deserialize_with = "deserialize_nonce"
+    )]
+    nonce: Nonce,
+}
+
+impl CryptoData {
+    fn read<T>(path: T) -> Result<Self, Error>
+    where
+        T: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        let crypto_data: Self = from_reader(&file)?;
+        if crypto_data.version > KEYSTORE_VERSION {
+            return Err(anyhow!(
+                "Unsupported keystore version: {}",
+                crypto_data.version
+            ));
+        }
+        Ok(crypto_data)
+    }
+
+    /// Atomically replaces the file with the new data.
+    /// Until the rename, the previous file stays untouched
+    fn write<T>(&self, path: T) -> Result<(), Error>
+    where
+        T: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+
+        let file = File::create(&temp_path)?;
+        to_writer_pretty(&file, self)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, path)?;
+
+        // Persist the rename itself
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
 }
 
 /// Serializes `buffer` to a lowercase hex string.
@@ -146,24 +329,28 @@ where
 }
 
 impl EthSigner {
+    /// Fails if the key is not a valid secp256k1 secret key
+    pub fn new(private_key: SecVec<u8>) -> Result<Self, Error> {
+        Ok(Self {
+            pubkey: eth_pubkey_from_secret(private_key.unsecure())?,
+            private_key,
+        })
+    }
+
     /// signs data according to https://eips.ethereum.org/EIPS/eip-191
     pub fn sign(&self, data: &[u8]) -> Vec<u8> {
-        // 1. Calculate prefixed hash
-        let data_hash = Keccak256::digest(data);
-        let mut eth_data: Vec<u8> = "\x19Ethereum Signed Message:\n32".into();
-        eth_data.extend_from_slice(data_hash.as_slice());
-
-        // 2. Calculate hash of prefixed hash
-        let hash = Keccak256::digest(&eth_data);
-        let message = Message::from_slice(&*hash).expect("Shouldn't fail");
+        // 1. Calculate hash of prefixed hash
+        let message = eth_message_hash(data);
 
-        // 3. Sign
+        // 2. Sign
+        let private_key =
+            SecretKey::from_slice(self.private_key.unsecure()).expect("Shouldn't fail");
         let secp = secp256k1::Secp256k1::new();
         let (id, sign) = secp
-            .sign_recoverable(&message, &self.private_key)
+            .sign_recoverable(&message, &private_key)
             .serialize_compact();
 
-        // 4. Prepare for ETH
+        // 3. Prepare for ETH
         let mut ex_sign = Vec::with_capacity(65);
         ex_sign.extend_from_slice(&sign);
         ex_sign.push(id.to_i32() as u8 + 27); //recovery id with eth specific offset
@@ -176,35 +363,82 @@ impl EthSigner {
         self.pubkey
     }
 
-    ///getting address according to https://github.com/ethereumbook/ethereumbook/blob/develop/04keys-addresses.asciidoc#public-keys
     pub fn address(&self) -> Address {
-        let pub_key = &self.pubkey.serialize_uncompressed()[1..];
-        Address::from_slice(&sha3::Keccak256::digest(&pub_key).as_slice()[32 - 20..])
+        eth_address_from_pubkey(&self.pubkey)
+    }
+}
+
+///getting address according to https://github.com/ethereumbook/ethereumbook/blob/develop/04keys-addresses.asciidoc#public-keys
+pub fn eth_address_from_pubkey(pubkey: &PublicKey) -> Address {
+    let pub_key = &pubkey.serialize_uncompressed()[1..];
+    Address::from_slice(&sha3::Keccak256::digest(&pub_key).as_slice()[32 - 20..])
+}
+
+/// Calculates the hash, which is signed according to https://eips.ethereum.org/EIPS/eip-191
+fn eth_message_hash(data: &[u8]) -> Message {
+    let data_hash = Keccak256::digest(data);
+    let mut eth_data: Vec<u8> = "\x19Ethereum Signed Message:\n32".into();
+    eth_data.extend_from_slice(data_hash.as_slice());
+
+    let hash = Keccak256::digest(&eth_data);
+    Message::from_slice(&*hash).expect("Shouldn't fail")
+}
+
+/// Recovers address of the signer from the signature, produced by `EthSigner::sign`
+pub fn recover_eth_address(data: &[u8], signature: &[u8]) -> Result<Address, Error> {
+    if signature.len() != 65 {
+        return Err(anyhow!("Invalid ETH signature length"));
     }
+    let id = RecoveryId::from_i32(signature[64] as i32 - 27)?;
+    let signature = RecoverableSignature::from_compact(&signature[..64], id)?;
+
+    let secp = secp256k1::Secp256k1::verification_only();
+    let pubkey = secp.recover(&eth_message_hash(data), &signature)?;
+    Ok(eth_address_from_pubkey(&pubkey))
+}
+
+/// Validates ETH private key and computes its public key.
+///
+/// secp256k1 `SecretKey` is `Copy` and can't be wiped, so the key material is passed
+/// around in `SecVec` and `SecretKey` only exists for the duration of the single operation
+pub fn eth_pubkey_from_secret(private_key: &[u8]) -> Result<PublicKey, Error> {
+    let private_key =
+        SecretKey::from_slice(private_key).map_err(|_| anyhow!("Invalid ETH private key"))?;
+    Ok(PublicKey::from_secret_key(
+        &secp256k1::Secp256k1::signing_only(),
+        &private_key,
+    ))
 }
 
 impl TonSigner {
-    pub fn public_key(&self) -> &[u8; 32] {
-        self.inner.public.as_bytes()
+    /// Moves the secret key into the locked memory. Passed keypair is wiped on drop
+    pub fn new(keypair: Keypair) -> Self {
+        Self {
+            public: keypair.public,
+            secret: SecVec::new(keypair.secret.as_bytes().to_vec()),
+        }
     }
 
-    pub fn sign(&self, data: &[u8]) -> [u8; ed25519::SIGNATURE_LENGTH] {
-        self.inner.sign(data).to_bytes()
+    pub fn public_key(&self) -> ed25519_dalek::PublicKey {
+        self.public
     }
 
-    pub fn keypair(&self) -> Arc<ed25519_dalek::Keypair> {
-        self.inner.clone()
+    pub fn sign(&self, data: &[u8]) -> [u8; ed25519::SIGNATURE_LENGTH] {
+        // Both keys are wiped on drop
+        let secret =
+            ed25519_dalek::SecretKey::from_bytes(self.secret.unsecure()).expect("Shouldn't fail");
+        let expanded = ExpandedSecretKey::from(&secret);
+        expanded.sign(data, &self.public).to_bytes()
     }
 }
 
 impl KeyData {
-    pub fn from_file<T>(path: T, password: SecStr) -> Result<Self, Error>
+    pub fn from_file<T>(path: T, password: &SecStr) -> Result<Self, Error>
     where
         T: AsRef<Path>,
     {
-        let file = File::open(&path)?;
-        let crypto_data: CryptoData = from_reader(&file)?;
-        let sym_key = Self::symmetric_key_from_password(password, &*crypto_data.salt);
+        let crypto_data = CryptoData::read(path)?;
+        let sym_key = crypto_data.kdf.derive_key(password, &crypto_data.salt)?;
 
         let eth_private_key = Self::eth_private_key_from_encrypted(
             &crypto_data.eth_encrypted_private_key,
@@ -218,96 +452,179 @@ impl KeyData {
             &crypto_data.ton_nonce,
         )?;
 
+        let eth_next = match &crypto_data.eth_next {
+            Some(next) => Some(EthSigner {
+                pubkey: next.pubkey,
+                private_key: Self::eth_private_key_from_encrypted(
+                    &next.encrypted_private_key,
+                    &sym_key,
+                    &next.nonce,
+                )?,
+            }),
+            None => None,
+        };
+
         Ok(Self {
             eth: EthSigner {
                 pubkey: crypto_data.eth_pubkey,
                 private_key: eth_private_key,
             },
-            ton: TonSigner {
-                inner: Arc::new(ton_data),
-            },
+            eth_next,
+            ton: TonSigner::new(ton_data),
         })
     }
 
     pub fn init<T>(
         pem_file_path: T,
-        password: SecStr,
-        eth_private_key: SecretKey,
+        password: &SecStr,
+        eth_private_key: SecVec<u8>,
         ton_key_pair: ed25519_dalek::Keypair,
     ) -> Result<Self, Error>
     where
         T: AsRef<Path>,
     {
-        sodiumoxide::init().expect("Failed initializing libsodium");
+        let key_data = Self {
+            eth: EthSigner::new(eth_private_key)?,
+            eth_next: None,
+            ton: TonSigner::new(ton_key_pair),
+        };
 
-        let mut rng = rand::rngs::OsRng::new().expect("OsRng fail");
-        let mut salt = vec![0u8; CREDENTIAL_LEN];
-        rng.fill(salt.as_mut_slice());
-        let key = Self::symmetric_key_from_password(password, &salt);
+        key_data
+            .encrypt(password, Kdf::default())?
+            .write(pem_file_path)?;
+        Ok(key_data)
+    }
 
-        // ETH
-        let (eth_pubkey, eth_encrypted_private_key, eth_nonce) = {
-            let curve = secp256k1::Secp256k1::new();
+    /// Whether the keystore was written by the older relay and can be re-encrypted
+    pub fn is_legacy_file<T>(path: T) -> Result<bool, Error>
+    where
+        T: AsRef<Path>,
+    {
+        let crypto_data = CryptoData::read(path)?;
+        Ok(crypto_data.version < KEYSTORE_VERSION || crypto_data.kdf != Kdf::default())
+    }
 
-            let public = PublicKey::from_secret_key(&curve, &eth_private_key);
-            let nonce = secretbox::gen_nonce();
-            let private_key = secretbox::seal(&eth_private_key[..], &nonce, &key);
-            (public, private_key, nonce)
+    /// Rewrites the keystore in the current format with the default kdf, fresh salt and nonces.
+    /// Returns the previous kdf
+    pub fn reencrypt_file<T>(path: T, password: &SecStr) -> Result<Kdf, Error>
+    where
+        T: AsRef<Path>,
+    {
+        let old_kdf = CryptoData::read(&path)?.kdf;
+        let key_data = Self::from_file(&path, password)?;
+        key_data.encrypt(password, Kdf::default())?.write(&path)?;
+        Ok(old_kdf)
+    }
+
+    /// Re-encrypts the keystore with the new password, fresh salt and nonces
+    pub fn change_password<T>(
+        path: T,
+        old_password: &SecStr,
+        new_password: &SecStr,
+    ) -> Result<(), Error>
+    where
+        T: AsRef<Path>,
+    {
+        let key_data = Self::from_file(&path, old_password)?;
+        key_data.encrypt(new_password, Kdf::default())?.write(&path)
+    }
+
+    /// Stores the new ETH key next to the current one. Signing continues with the current key
+    /// until the rotation is complete. Previously started rotation is replaced
+    pub fn start_eth_key_rotation<T>(
+        path: T,
+        password: &SecStr,
+        private_key: SecVec<u8>,
+    ) -> Result<Self, Error>
+    where
+        T: AsRef<Path>,
+    {
+        let mut key_data = Self::from_file(&path, password)?;
+        let next = EthSigner::new(private_key)?;
+        if next.pubkey == key_data.eth.pubkey {
+            return Err(anyhow!("New ETH key is the same as the current one"));
+        }
+
+        key_data.eth_next = Some(next);
+        key_data.encrypt(password, Kdf::default())?.write(&path)?;
+        Ok(key_data)
+    }
+
+    /// Replaces the current ETH key with the rotated one.
+    /// Password is not needed, because both keys are encrypted with the same key
+    pub fn complete_eth_key_rotation<T>(&mut self, path: T) -> Result<(), Error>
+    where
+        T: AsRef<Path>,
+    {
+        let next = match &self.eth_next {
+            Some(next) => next,
+            None => return Err(anyhow!("ETH key rotation is not started")),
         };
 
-        // TON
-        let (ton_encrypted_private_key, ton_nonce) = {
-            let nonce = secretbox::gen_nonce();
-            let private_key = secretbox::seal(ton_key_pair.secret.as_bytes(), &nonce, &key);
-            (private_key, nonce)
+        let mut crypto_data = CryptoData::read(&path)?;
+        let encrypted = match crypto_data.eth_next.take() {
+            Some(encrypted) if encrypted.pubkey == next.pubkey => encrypted,
+            _ => return Err(anyhow!("Keystore doesn't contain the rotated ETH key")),
         };
+        crypto_data.eth_pubkey = encrypted.pubkey;
+        crypto_data.eth_encrypted_private_key = encrypted.encrypted_private_key;
+        crypto_data.eth_nonce = encrypted.nonce;
+        crypto_data.write(&path)?;
+
+        self.eth = self.eth_next.take().expect("Shouldn't fail");
+        Ok(())
+    }
 
-        //
-        let data = CryptoData {
+    fn encrypt(&self, password: &SecStr, kdf: Kdf) -> Result<CryptoData, Error> {
+        sodiumoxide::init().expect("Failed initializing libsodium");
+
+        let salt = kdf.gen_salt();
+        let key = kdf.derive_key(password, &salt)?;
+
+        // ETH
+        let eth_nonce = secretbox::gen_nonce();
+        let eth_encrypted_private_key =
+            secretbox::seal(self.eth.private_key.unsecure(), &eth_nonce, &key);
+
+        // TON
+        let ton_nonce = secretbox::gen_nonce();
+        let ton_encrypted_private_key =
+            secretbox::seal(self.ton.secret.unsecure(), &ton_nonce, &key);
+
+        let eth_next = self.eth_next.as_ref().map(|next| {
+            let nonce = secretbox::gen_nonce();
+            EncryptedEthKey {
+                pubkey: next.pubkey,
+                encrypted_private_key: secretbox::seal(next.private_key.unsecure(), &nonce, &key),
+                nonce,
+            }
+        });
+
+        Ok(CryptoData {
+            version: KEYSTORE_VERSION,
+            kdf,
             salt,
-            eth_pubkey,
+            eth_pubkey: self.eth.pubkey,
             eth_encrypted_private_key,
             eth_nonce,
             ton_encrypted_private_key,
             ton_nonce,
-        };
-
-        let crypto_config = File::create(pem_file_path)?;
-        to_writer_pretty(crypto_config, &data)?;
-        Ok(Self {
-            eth: EthSigner {
-                private_key: eth_private_key,
-                pubkey: eth_pubkey,
-            },
-            ton: TonSigner {
-                inner: Arc::new(ton_key_pair),
-            },
+            eth_next,
         })
     }
 
-    ///Calculates symmetric key from user password, using pbkdf2
-    fn symmetric_key_from_password(password: SecStr, salt: &[u8]) -> Key {
-        let mut pbkdf2_hash = SecVec::new(vec![0; CREDENTIAL_LEN]);
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            N_ITER,
-            salt,
-            password.unsecure(),
-            &mut pbkdf2_hash.unsecure_mut(),
-        );
-        secretbox::Key::from_slice(&pbkdf2_hash.unsecure()).expect("Shouldn't panic")
-    }
-
     fn eth_private_key_from_encrypted(
         encrypted_key: &[u8],
         key: &Key,
         nonce: &Nonce,
-    ) -> Result<SecretKey, Error> {
-        SecretKey::from_slice(
-            &secretbox::open(encrypted_key, nonce, key)
+    ) -> Result<SecVec<u8>, Error> {
+        let data = SecVec::new(
+            secretbox::open(encrypted_key, nonce, key)
                 .map_err(|_| anyhow!("Failed decrypting eth SecretKey"))?,
-        )
-        .map_err(|_| anyhow!("Failed constructing SecretKey from decrypted data"))
+        );
+        eth_pubkey_from_secret(data.unsecure())
+            .map_err(|_| anyhow!("Failed constructing SecretKey from decrypted data"))?;
+        Ok(data)
     }
 
     fn ton_private_key_from_encrypted(
@@ -316,9 +633,10 @@ impl KeyData {
         nonce: &Nonce,
     ) -> Result<ed25519_dalek::Keypair, Error> {
         secretbox::open(encrypted_key, nonce, key)
+            .map(SecVec::new)
             .map_err(|_| anyhow!("Failed decrypting with provided password"))
             .and_then(|data| {
-                let secret = ed25519_dalek::SecretKey::from_bytes(&data)
+                let secret = ed25519_dalek::SecretKey::from_bytes(data.unsecure())
                     .map_err(|e| anyhow!("failed to load ton key. {}", e.to_string()))?;
                 let public = ed25519_dalek::PublicKey::from(&secret);
                 Ok(Keypair { secret, public })
@@ -332,18 +650,16 @@ mod test {
 
     use bip39::Language;
     use pretty_assertions::assert_eq;
-    use secp256k1::{PublicKey, SecretKey};
-    use secstr::SecStr;
+    use secstr::{SecStr, SecVec};
 
-    use crate::crypto::key_managment::{EthSigner, KeyData};
+    use crate::crypto::key_managment::{EthSigner, Kdf, KeyData, TonSigner};
     use crate::prelude::*;
 
-    fn default_keys() -> (SecretKey, ed25519_dalek::Keypair) {
-        let eth_private_key = SecretKey::from_slice(
-            &hex::decode("416ddb82736d0ddf80cc50eda0639a2dd9f104aef121fb9c8af647ad8944a8b1")
+    fn default_keys() -> (SecVec<u8>, ed25519_dalek::Keypair) {
+        let eth_private_key = SecVec::new(
+            hex::decode("416ddb82736d0ddf80cc50eda0639a2dd9f104aef121fb9c8af647ad8944a8b1")
                 .unwrap(),
-        )
-        .unwrap();
+        );
 
         let ton_private_key = ed25519_dalek::SecretKey::from_bytes(
             &hex::decode("e371ef1d7266fc47b30d49dc886861598f09e2e6294d7f0520fe9aa460114e51")
@@ -361,26 +677,33 @@ mod test {
 
     #[test]
     fn test_sign() {
-        use secp256k1::PublicKey;
         let message_text = b"hello_world1";
 
         let (private_key, _) = default_keys();
-        let curve = secp256k1::Secp256k1::new();
-        let signer = EthSigner {
-            pubkey: PublicKey::from_secret_key(&curve, &private_key),
-            private_key,
-        };
+        let signer = EthSigner::new(private_key).unwrap();
         let res = signer.sign(message_text);
         let expected = hex::decode("ff244ad5573d02bc6ead270d5ff48c490b0113225dd61617791ba6610ed1e56a007ec790f8fca53243907b888e6b33ad15c52fed3bc6a7ee5da2fa287ea4f8211b").unwrap();
         assert_eq!(expected.len(), res.len());
         assert_eq!(res, expected.as_slice());
     }
 
+    #[test]
+    fn test_recover_address() {
+        let (private_key, _) = default_keys();
+        let signer = EthSigner::new(private_key).unwrap();
+        let signature = signer.sign(b"hello_world1");
+
+        let address = recover_eth_address(b"hello_world1", &signature).unwrap();
+        assert_eq!(address, signer.address());
+        let address = recover_eth_address(b"hello_world2", &signature).unwrap();
+        assert_ne!(address, signer.address());
+        assert!(recover_eth_address(b"hello_world1", &signature[..64]).is_err());
+    }
+
     #[test]
     fn test_signing_bytes() {
         let tokens = [ethabi::Token::String("lol".into())];
         let data = ethabi::encode(&tokens);
-        use secp256k1::PublicKey;
 
         let private_key = crate::crypto::recovery::derive_from_words_eth(
             Language::English,
@@ -388,11 +711,7 @@ mod test {
             None,
         )
         .unwrap();
-        let curve = secp256k1::Secp256k1::new();
-        let signer = EthSigner {
-            pubkey: PublicKey::from_secret_key(&curve, &private_key),
-            private_key,
-        };
+        let signer = EthSigner::new(private_key).unwrap();
         let res = signer.sign(&*data);
         println!("{}\n{}", hex::encode(&data), hex::encode(&res));
     }
@@ -404,8 +723,8 @@ mod test {
 
         let (eth_private_key, ton_key_pair) = default_keys();
 
-        let signer = KeyData::init(&path, password.clone(), eth_private_key, ton_key_pair).unwrap();
-        let read_signer = KeyData::from_file(&path, password).unwrap();
+        let signer = KeyData::init(&path, &password, eth_private_key, ton_key_pair).unwrap();
+        let read_signer = KeyData::from_file(&path, &password).unwrap();
         std::fs::remove_file(path).unwrap();
         assert_eq!(read_signer, signer);
     }
@@ -417,12 +736,110 @@ mod test {
 
         let (eth_private_key, ton_key_pair) = default_keys();
 
-        KeyData::init(&path, password, eth_private_key, ton_key_pair).unwrap();
-        let result = KeyData::from_file(&path, SecStr::new("lol".into()));
+        KeyData::init(&path, &password, eth_private_key, ton_key_pair).unwrap();
+        let result = KeyData::from_file(&path, &SecStr::new("lol".into()));
         std::fs::remove_file(path).unwrap();
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_reencrypt_legacy() {
+        let password = SecStr::new("123".into());
+        let path = "./test/test_legacy.key";
+
+        let (eth_private_key, ton_key_pair) = default_keys();
+        let signer = KeyData::init(&path, &password, eth_private_key, ton_key_pair).unwrap();
+        assert!(!KeyData::is_legacy_file(&path).unwrap());
+
+        // Legacy files have neither version nor kdf section
+        let mut data =
+            serde_json::to_value(signer.encrypt(&password, Kdf::legacy()).unwrap()).unwrap();
+        let fields = data.as_object_mut().unwrap();
+        fields.remove("version");
+        fields.remove("kdf");
+        std::fs::write(path, serde_json::to_vec(&data).unwrap()).unwrap();
+
+        assert!(KeyData::is_legacy_file(&path).unwrap());
+        assert_eq!(KeyData::from_file(&path, &password).unwrap(), signer);
+
+        let old_kdf = KeyData::reencrypt_file(&path, &password).unwrap();
+        assert_eq!(old_kdf, Kdf::legacy());
+        assert!(!KeyData::is_legacy_file(&path).unwrap());
+        let read_signer = KeyData::from_file(&path, &password).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(read_signer, signer);
+    }
+
+    #[test]
+    fn test_change_password() {
+        let password = SecStr::new("123".into());
+        let new_password = SecStr::new("456".into());
+        let path = "./test/test_change_password.key";
+
+        let (eth_private_key, ton_key_pair) = default_keys();
+        let signer = KeyData::init(&path, &password, eth_private_key, ton_key_pair).unwrap();
+
+        assert!(KeyData::change_password(&path, &new_password, &password).is_err());
+        KeyData::change_password(&path, &password, &new_password).unwrap();
+
+        let old_result = KeyData::from_file(&path, &password);
+        let read_signer = KeyData::from_file(&path, &new_password).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(old_result.is_err());
+        assert_eq!(read_signer, signer);
+    }
+
+    #[test]
+    fn test_secrets_are_hidden() {
+        let (eth_private_key, ton_key_pair) = default_keys();
+        let eth_secret = hex::encode(eth_private_key.unsecure());
+        let ton_secret = hex::encode(ton_key_pair.secret.as_bytes());
+
+        let key_data = KeyData {
+            eth: EthSigner::new(eth_private_key).unwrap(),
+            eth_next: None,
+            ton: TonSigner::new(ton_key_pair),
+        };
+        let debug = format!("{:?}", key_data);
+        assert!(!debug.contains(&eth_secret));
+        assert!(!debug.contains(&ton_secret));
+    }
+
+    #[test]
+    fn test_eth_key_rotation() {
+        let password = SecStr::new("123".into());
+        let path = "./test/test_eth_key_rotation.key";
+
+        let (eth_private_key, ton_key_pair) = default_keys();
+        let mut signer =
+            KeyData::init(&path, &password, eth_private_key.clone(), ton_key_pair).unwrap();
+        let old_pubkey = signer.eth.pubkey();
+
+        let next_private_key = SecVec::new(vec![1; 32]);
+        let next_pubkey = EthSigner::new(next_private_key.clone()).unwrap().pubkey();
+
+        assert!(KeyData::start_eth_key_rotation(&path, &password, eth_private_key).is_err());
+        assert!(signer.complete_eth_key_rotation(&path).is_err());
+
+        let mut rotating =
+            KeyData::start_eth_key_rotation(&path, &password, next_private_key).unwrap();
+        assert_eq!(rotating.eth.pubkey(), old_pubkey);
+        assert_eq!(rotating.eth_next.as_ref().unwrap().pubkey(), next_pubkey);
+
+        // Rotated key survives the password change
+        let new_password = SecStr::new("456".into());
+        KeyData::change_password(&path, &password, &new_password).unwrap();
+        assert_eq!(KeyData::from_file(&path, &new_password).unwrap(), rotating);
+
+        rotating.complete_eth_key_rotation(&path).unwrap();
+        assert_eq!(rotating.eth.pubkey(), next_pubkey);
+        assert!(rotating.eth_next.is_none());
+
+        let read_signer = KeyData::from_file(&path, &new_password).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(read_signer, rotating);
+    }
+
     #[test]
     fn test_size() {
         assert_eq!(
@@ -431,14 +848,27 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_kdf_limits() {
+        assert!(Kdf::default().validate().is_ok());
+        assert!(Kdf::legacy().validate().is_ok());
+
+        let password: SecStr = "123".into();
+        let kdf = Kdf::Argon2id {
+            ops_limit: usize::MAX,
+            mem_limit: usize::MAX,
+        };
+        assert!(kdf.derive_key(&password, &kdf.gen_salt()).is_err());
+        let kdf = Kdf::Pbkdf2 {
+            iterations: std::num::NonZeroU32::new(u32::MAX).unwrap(),
+        };
+        assert!(kdf.derive_key(&password, &kdf.gen_salt()).is_err());
+    }
+
     #[test]
     fn address_from_pubkey() {
         let (key, _) = default_keys();
-        let curve = secp256k1::Secp256k1::new();
-        let signer = EthSigner {
-            pubkey: PublicKey::from_secret_key(&curve, &key),
-            private_key: key,
-        };
+        let signer = EthSigner::new(key).unwrap();
         let address = signer.address();
         let expected = EthAddress::from_str("9c5a095ae311cad1b09bc36ac8635f4ed4765dcf").unwrap();
         assert_eq!(address, expected);
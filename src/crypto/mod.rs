@@ -4,3 +4,4 @@
 /// For the password deriving we use pbkdf2 function with a huge amount of rounds.
 pub mod key_managment;
 pub mod recovery;
+pub mod signer;
@@ -1,13 +1,23 @@
-use anyhow::Error;
+use std::num::NonZeroU32;
+
+use aes::cipher::{NewCipher, StreamCipher};
+use aes::Aes128Ctr;
+use anyhow::{anyhow, Error};
 use bip39::{Language, Seed};
-use secp256k1::SecretKey;
+use ring::{constant_time, pbkdf2};
+use secstr::{SecStr, SecVec};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
 use tiny_hderive::bip32::ExtendedPrivKey;
+use zeroize::Zeroize;
+
+use super::key_managment::{eth_address_from_pubkey, eth_pubkey_from_secret};
 
 pub fn derive_from_words_eth(
     lang: Language,
     phrase: &str,
     derivation_path: Option<&str>,
-) -> Result<SecretKey, Error> {
+) -> Result<SecVec<u8>, Error> {
     let mnemonic = bip39::Mnemonic::from_phrase(phrase, lang)?;
     let hd = Seed::new(&mnemonic, "");
     let seed_bytes = hd.as_bytes();
@@ -15,7 +25,13 @@ pub fn derive_from_words_eth(
     let path = derivation_path.unwrap_or("m/44'/60'/0'/0/0");
     let derived =
         ExtendedPrivKey::derive(seed_bytes, path).map_err(|e| Error::msg(format!("{:#?}", e)))?;
-    Ok(SecretKey::from_slice(&derived.secret())?)
+
+    let mut secret = derived.secret();
+    let private_key = SecVec::new(secret.to_vec());
+    secret.zeroize();
+
+    eth_pubkey_from_secret(private_key.unsecure())?;
+    Ok(private_key)
 }
 
 pub fn derive_from_words_ton(
@@ -31,7 +47,177 @@ pub fn derive_from_words_ton(
     let derived =
         ExtendedPrivKey::derive(seed_bytes, path).map_err(|e| Error::msg(format!("{:#?}", e)))?;
 
-    ed25519_keys_from_secret_bytes(&derived.secret())
+    let mut secret = derived.secret();
+    let keypair = ed25519_keys_from_secret_bytes(&secret);
+    secret.zeroize();
+    keypair
+}
+
+/// Memory limit of the scrypt (`128 * n * r` bytes) accepted from the keystore
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+/// Length of the derived key: 16 bytes of the AES key and 16 bytes of the MAC key
+const DERIVED_KEY_LEN: usize = 32;
+
+/// Decrypts ETH private key from the keystore v3 JSON.
+/// See https://github.com/ethereum/wiki/wiki/Web3-Secret-Storage-Definition
+pub fn import_eth_keystore(json: &str, password: &SecStr) -> Result<SecVec<u8>, Error> {
+    let keystore: EthKeystore = serde_json::from_str(json)?;
+    if keystore.version != 3 {
+        return Err(anyhow!(
+            "Unsupported keystore version: {}",
+            keystore.version
+        ));
+    }
+    let crypto = keystore.crypto;
+    if crypto.cipher != "aes-128-ctr" {
+        return Err(anyhow!("Unsupported keystore cipher: {}", crypto.cipher));
+    }
+
+    let derived_key = match crypto.kdf.as_str() {
+        "scrypt" => {
+            let params: EthKeystoreScryptParams = serde_json::from_value(crypto.kdfparams)?;
+            if params.n < 2 || !params.n.is_power_of_two() {
+                return Err(anyhow!("Invalid scrypt parameter n: {}", params.n));
+            }
+            let memory = params.n.saturating_mul(128 * params.r as u64);
+            if memory > MAX_SCRYPT_MEMORY || params.p > MAX_SCRYPT_P {
+                return Err(anyhow!(
+                    "Scrypt parameters are too large: n {}, r {}, p {}",
+                    params.n,
+                    params.r,
+                    params.p
+                ));
+            }
+            check_dklen(params.dklen)?;
+            let scrypt_params =
+                scrypt::Params::new(params.n.trailing_zeros() as u8, params.r, params.p)
+                    .map_err(|_| anyhow!("Invalid scrypt parameters"))?;
+
+            let mut key = SecVec::new(vec![0; params.dklen]);
+            scrypt::scrypt(
+                password.unsecure(),
+                &hex::decode(params.salt)?,
+                &scrypt_params,
+                key.unsecure_mut(),
+            )
+            .map_err(|_| anyhow!("Invalid scrypt key length"))?;
+            key
+        }
+        "pbkdf2" => {
+            let params: EthKeystorePbkdf2Params = serde_json::from_value(crypto.kdfparams)?;
+            if params.prf != "hmac-sha256" {
+                return Err(anyhow!("Unsupported pbkdf2 prf: {}", params.prf));
+            }
+            let iterations = NonZeroU32::new(params.c)
+                .filter(|c| c.get() <= MAX_PBKDF2_ITERATIONS)
+                .ok_or_else(|| anyhow!("Invalid pbkdf2 iteration count: {}", params.c))?;
+            check_dklen(params.dklen)?;
+
+            let mut key = SecVec::new(vec![0; params.dklen]);
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                iterations,
+                &hex::decode(params.salt)?,
+                password.unsecure(),
+                key.unsecure_mut(),
+            );
+            key
+        }
+        kdf => return Err(anyhow!("Unsupported keystore kdf: {}", kdf)),
+    };
+    let derived_key = derived_key.unsecure();
+
+    let mut data = SecVec::new(hex::decode(crypto.ciphertext)?);
+    let mac = Keccak256::new()
+        .chain(&derived_key[16..32])
+        .chain(data.unsecure())
+        .finalize();
+    constant_time::verify_slices_are_equal(mac.as_slice(), &hex::decode(crypto.mac)?)
+        .map_err(|_| anyhow!("Invalid keystore password"))?;
+
+    Aes128Ctr::new_from_slices(&derived_key[..16], &hex::decode(crypto.cipherparams.iv)?)
+        .map_err(|_| anyhow!("Invalid keystore iv"))?
+        .apply_keystream(data.unsecure_mut());
+    let pubkey = eth_pubkey_from_secret(data.unsecure())?;
+
+    if let Some(address) = keystore.address {
+        let expected = eth_address_from_pubkey(&pubkey);
+        if hex::decode(address.trim_start_matches("0x"))? != expected.as_bytes() {
+            return Err(anyhow!("Keystore address doesn't match the private key"));
+        }
+    }
+
+    Ok(data)
+}
+
+fn check_dklen(dklen: usize) -> Result<(), Error> {
+    if dklen != DERIVED_KEY_LEN {
+        return Err(anyhow!("Invalid derived key length: {}", dklen));
+    }
+    Ok(())
+}
+
+/// Reads ed25519 keypair JSON, as produced by tonos-cli
+pub fn import_ton_keypair(json: &str) -> Result<ed25519_dalek::Keypair, Error> {
+    let mut keys: TonKeypairFile = serde_json::from_str(json)?;
+    let secret = hex::decode(&keys.secret);
+    keys.secret.zeroize();
+
+    let keypair = ed25519_keys_from_secret_bytes(SecVec::new(secret?).unsecure())?;
+    if hex::decode(keys.public)? != keypair.public.as_bytes() {
+        return Err(anyhow!("TON public key doesn't match the secret key"));
+    }
+    Ok(keypair)
+}
+
+#[derive(Deserialize)]
+struct EthKeystore {
+    version: u32,
+    /// Written by the older geth versions as `Crypto`
+    #[serde(alias = "Crypto")]
+    crypto: EthKeystoreCrypto,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EthKeystoreCrypto {
+    cipher: String,
+    cipherparams: EthKeystoreCipherParams,
+    ciphertext: String,
+    kdf: String,
+    kdfparams: serde_json::Value,
+    mac: String,
+}
+
+#[derive(Deserialize)]
+struct EthKeystoreCipherParams {
+    iv: String,
+}
+
+#[derive(Deserialize)]
+struct EthKeystoreScryptParams {
+    dklen: usize,
+    n: u64,
+    r: u32,
+    p: u32,
+    salt: String,
+}
+
+#[derive(Deserialize)]
+struct EthKeystorePbkdf2Params {
+    dklen: usize,
+    c: u32,
+    prf: String,
+    salt: String,
+}
+
+#[derive(Deserialize)]
+struct TonKeypairFile {
+    public: String,
+    secret: String,
 }
 
 fn ed25519_keys_from_secret_bytes(bytes: &[u8]) -> Result<ed25519_dalek::Keypair, Error> {
@@ -50,7 +236,7 @@ fn ed25519_keys_from_secret_bytes(bytes: &[u8]) -> Result<ed25519_dalek::Keypair
 #[cfg(test)]
 mod test {
     use bip39::Language;
-    use secp256k1::SecretKey;
+    use secstr::{SecStr, SecVec};
 
     use crate::crypto::recovery::*;
 
@@ -59,11 +245,10 @@ mod test {
         let key = derive_from_words_eth(Language::English, "talk pave choice void clever tired humor marble clutch ankle fish type deliver witness picnic thumb away offer legend keep trouble island earn pet", None);
         assert_eq!(
             key.unwrap(),
-            SecretKey::from_slice(
-                &hex::decode("e3c08a91583ecf88e361436878bcc58324f42695546cd00c92d301887775fb26")
+            SecVec::new(
+                hex::decode("e3c08a91583ecf88e361436878bcc58324f42695546cd00c92d301887775fb26")
                     .unwrap(),
-            )
-            .unwrap(),
+            ),
         );
     }
 
@@ -73,6 +258,84 @@ mod test {
         assert!(key.is_err());
     }
 
+    #[test]
+    fn eth_keystore_import() {
+        let expected = SecVec::new(
+            hex::decode("7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d")
+                .unwrap(),
+        );
+        let password = SecStr::new("testpassword".into());
+
+        let pbkdf2 = r#"{
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+                "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+                "kdf": "pbkdf2",
+                "kdfparams": {
+                    "c": 262144,
+                    "dklen": 32,
+                    "prf": "hmac-sha256",
+                    "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+                },
+                "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+            },
+            "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+            "version": 3
+        }"#;
+        assert_eq!(import_eth_keystore(pbkdf2, &password).unwrap(), expected);
+        assert!(import_eth_keystore(pbkdf2, &SecStr::new("lol".into())).is_err());
+
+        let scrypt = r#"{
+            "Crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+                "ciphertext": "01a05c7f05b697274227d8bd0825a6caa89967e24643426c0fcfa2fb663052d7",
+                "kdf": "scrypt",
+                "kdfparams": {
+                    "dklen": 32,
+                    "n": 1024,
+                    "r": 8,
+                    "p": 1,
+                    "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+                },
+                "mac": "d60a6540bbdeaa746e4c7b4359c74e4bb0b679bedce5b4d129ad96150d200274"
+            },
+            "version": 3
+        }"#;
+        assert_eq!(import_eth_keystore(scrypt, &password).unwrap(), expected);
+
+        let huge_scrypt = scrypt.replace(r#""n": 1024"#, r#""n": 1099511627776"#);
+        assert!(import_eth_keystore(&huge_scrypt, &password).is_err());
+        let huge_pbkdf2 = pbkdf2.replace(r#""c": 262144"#, r#""c": 4294967295"#);
+        assert!(import_eth_keystore(&huge_pbkdf2, &password).is_err());
+        let long_key = pbkdf2.replace(r#""dklen": 32"#, r#""dklen": 4294967295"#);
+        assert!(import_eth_keystore(&long_key, &password).is_err());
+    }
+
+    #[test]
+    fn ton_keypair_import() {
+        let keypair = import_ton_keypair(
+            r#"{
+                "public": "5f7cd6c8b6a3e7f4a6e79b1c4cfb56b67e7a4dd4bd7e4e1d0b20e9d0d3bd0f6c",
+                "secret": "e371ef1d7266fc47b30d49dc886861598f09e2e6294d7f0520fe9aa460114e51"
+            }"#,
+        );
+        assert!(keypair.is_err());
+
+        let secret = "e371ef1d7266fc47b30d49dc886861598f09e2e6294d7f0520fe9aa460114e51";
+        let public = ed25519_dalek::PublicKey::from(
+            &ed25519_dalek::SecretKey::from_bytes(&hex::decode(secret).unwrap()).unwrap(),
+        );
+        let keypair = import_ton_keypair(&format!(
+            r#"{{"public": "{}", "secret": "{}"}}"#,
+            hex::encode(public.as_bytes()),
+            secret
+        ))
+        .unwrap();
+        assert_eq!(keypair.public, public);
+    }
+
     #[test]
     fn ton_recovery() {
         let key = derive_from_words_ton(
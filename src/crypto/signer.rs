@@ -0,0 +1,496 @@
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{ed25519, Verifier};
+use relay_ton::contracts::message_builder::MessageSigner;
+use secp256k1::PublicKey;
+use secstr::{SecStr, SecVec};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use super::key_managment::{eth_address_from_pubkey, recover_eth_address, KeyData};
+use crate::config::RemoteSignerSettings;
+use crate::prelude::*;
+
+/// Holder of the relay ETH and TON keys
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn eth_pubkey(&self) -> PublicKey;
+
+    fn ton_public_key(&self) -> ed25519_dalek::PublicKey;
+
+    /// Signs data according to https://eips.ethereum.org/EIPS/eip-191
+    async fn sign_eth(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
+
+    async fn sign_ton(&self, data: &[u8]) -> Result<[u8; ed25519::SIGNATURE_LENGTH], Error>;
+
+    fn eth_address(&self) -> Address {
+        eth_address_from_pubkey(&self.eth_pubkey())
+    }
+
+    /// ETH key, which replaces the current one when the bridge contracts acknowledge it
+    fn next_eth_pubkey(&self) -> Option<PublicKey> {
+        None
+    }
+
+    /// Stores the new ETH key. Signing continues with the current key
+    async fn start_eth_key_rotation(
+        &self,
+        _password: &SecStr,
+        _private_key: SecVec<u8>,
+    ) -> Result<(), Error> {
+        Err(anyhow!("ETH key rotation is not supported by the signer"))
+    }
+
+    /// Switches signing to the new ETH key
+    async fn complete_eth_key_rotation(&self) -> Result<(), Error> {
+        Err(anyhow!("ETH key rotation is not supported by the signer"))
+    }
+}
+
+/// Local keystore
+pub struct LocalSigner {
+    keys_path: PathBuf,
+    keys: std::sync::RwLock<KeyData>,
+    /// Serializes keystore file modifications
+    keystore_lock: Mutex<()>,
+}
+
+impl LocalSigner {
+    pub fn new(keys_path: PathBuf, keys: KeyData) -> Self {
+        Self {
+            keys_path,
+            keys: std::sync::RwLock::new(keys),
+            keystore_lock: Mutex::new(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn eth_pubkey(&self) -> PublicKey {
+        self.keys.read().unwrap().eth.pubkey()
+    }
+
+    fn ton_public_key(&self) -> ed25519_dalek::PublicKey {
+        self.keys.read().unwrap().ton.public_key()
+    }
+
+    async fn sign_eth(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(self.keys.read().unwrap().eth.sign(data))
+    }
+
+    async fn sign_ton(&self, data: &[u8]) -> Result<[u8; ed25519::SIGNATURE_LENGTH], Error> {
+        Ok(self.keys.read().unwrap().ton.sign(data))
+    }
+
+    fn next_eth_pubkey(&self) -> Option<PublicKey> {
+        self.keys
+            .read()
+            .unwrap()
+            .eth_next
+            .as_ref()
+            .map(|next| next.pubkey())
+    }
+
+    async fn start_eth_key_rotation(
+        &self,
+        password: &SecStr,
+        private_key: SecVec<u8>,
+    ) -> Result<(), Error> {
+        // Keystore is not changed while the rotation is started. Key derivation is slow,
+        // so signing continues with the current keys until the new ones are written
+        let _keystore_lock = self.keystore_lock.lock().await;
+
+        let keys_path = self.keys_path.clone();
+        let password = password.clone();
+        let keys = tokio::task::spawn_blocking(move || {
+            KeyData::start_eth_key_rotation(&keys_path, &password, private_key)
+        })
+        .await??;
+
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    async fn complete_eth_key_rotation(&self) -> Result<(), Error> {
+        let _keystore_lock = self.keystore_lock.lock().await;
+        self.keys
+            .write()
+            .unwrap()
+            .complete_eth_key_rotation(&self.keys_path)
+    }
+}
+
+/// Signs relay contract messages with the TON key of the signer
+pub struct TonMessageSigner(pub Arc<dyn Signer>);
+
+#[async_trait]
+impl MessageSigner for TonMessageSigner {
+    fn public_key(&self) -> ed25519_dalek::PublicKey {
+        self.0.ton_public_key()
+    }
+
+    async fn sign(&self, data: &[u8]) -> Result<[u8; ed25519::SIGNATURE_LENGTH], Error> {
+        self.0.sign_ton(data).await
+    }
+}
+
+/// Max size of the remote signer protocol request or response
+pub const MAX_MESSAGE_SIZE: u64 = 1024 * 1024;
+
+/// Request of the remote signer protocol.
+///
+/// Sent as a single line of JSON over the Unix socket or as a body of the HTTP POST request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SignerRequest {
+    PublicKeys,
+    /// Hex encoded data, which is signed according to EIP-191
+    SignEth {
+        data: String,
+    },
+    /// Hex encoded data, which is signed with ed25519 as is
+    SignTon {
+        data: String,
+    },
+}
+
+/// Response of the remote signer protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse {
+    PublicKeys {
+        /// Hex encoded compressed secp256k1 public key
+        eth_pubkey: String,
+        /// Hex encoded ed25519 public key
+        ton_public_key: String,
+    },
+    /// Hex encoded signature
+    Signature(String),
+    Error(String),
+}
+
+/// Serves the remote signer protocol request with the given signer
+pub async fn handle_signer_request(signer: &dyn Signer, request: SignerRequest) -> SignerResponse {
+    let result = match request {
+        SignerRequest::PublicKeys => {
+            return SignerResponse::PublicKeys {
+                eth_pubkey: hex::encode(signer.eth_pubkey().serialize()),
+                ton_public_key: hex::encode(signer.ton_public_key().as_bytes()),
+            }
+        }
+        SignerRequest::SignEth { data } => match hex::decode(data) {
+            Ok(data) => signer.sign_eth(&data).await,
+            Err(e) => Err(e.into()),
+        },
+        SignerRequest::SignTon { data } => match hex::decode(data) {
+            Ok(data) => signer
+                .sign_ton(&data)
+                .await
+                .map(|signature| signature.to_vec()),
+            Err(e) => Err(e.into()),
+        },
+    };
+
+    match result {
+        Ok(signature) => SignerResponse::Signature(hex::encode(signature)),
+        Err(e) => SignerResponse::Error(e.to_string()),
+    }
+}
+
+/// Signer daemon, reached over the Unix socket or HTTP
+pub struct RemoteSigner {
+    connection: Connection,
+    eth_pubkey: PublicKey,
+    ton_public_key: ed25519_dalek::PublicKey,
+}
+
+impl RemoteSigner {
+    /// Fetches public keys of the signer
+    pub async fn connect(settings: &RemoteSignerSettings) -> Result<Self, Error> {
+        let connection = Connection::new(settings)?;
+
+        let (eth_pubkey, ton_public_key) =
+            match connection.request(&SignerRequest::PublicKeys).await? {
+                SignerResponse::PublicKeys {
+                    eth_pubkey,
+                    ton_public_key,
+                } => (eth_pubkey, ton_public_key),
+                SignerResponse::Error(e) => return Err(anyhow!("Remote signer error: {}", e)),
+                _ => return Err(anyhow!("Unexpected remote signer response")),
+            };
+
+        let eth_pubkey = PublicKey::from_slice(&hex::decode(eth_pubkey)?)?;
+        let ton_public_key = ed25519_dalek::PublicKey::from_bytes(&hex::decode(ton_public_key)?)
+            .map_err(|e| anyhow!("Invalid TON public key: {}", e))?;
+
+        Ok(Self {
+            connection,
+            eth_pubkey,
+            ton_public_key,
+        })
+    }
+
+    async fn sign(&self, request: SignerRequest) -> Result<Vec<u8>, Error> {
+        match self.connection.request(&request).await? {
+            SignerResponse::Signature(signature) => Ok(hex::decode(signature)?),
+            SignerResponse::Error(e) => Err(anyhow!("Remote signer error: {}", e)),
+            _ => Err(anyhow!("Unexpected remote signer response")),
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn eth_pubkey(&self) -> PublicKey {
+        self.eth_pubkey
+    }
+
+    fn ton_public_key(&self) -> ed25519_dalek::PublicKey {
+        self.ton_public_key
+    }
+
+    async fn sign_eth(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let signature = self
+            .sign(SignerRequest::SignEth {
+                data: hex::encode(data),
+            })
+            .await?;
+
+        // Don't send transactions, which will be rejected by the contract
+        match recover_eth_address(data, &signature) {
+            Ok(address) if address == self.eth_address() => Ok(signature),
+            _ => Err(anyhow!("Remote signer returned invalid ETH signature")),
+        }
+    }
+
+    async fn sign_ton(&self, data: &[u8]) -> Result<[u8; ed25519::SIGNATURE_LENGTH], Error> {
+        let signature = self
+            .sign(SignerRequest::SignTon {
+                data: hex::encode(data),
+            })
+            .await?;
+        let signature = ed25519::Signature::try_from(signature.as_slice())
+            .map_err(|_| anyhow!("Invalid TON signature length"))?;
+
+        // Don't send messages, which will be rejected by the contract
+        self.ton_public_key
+            .verify(data, &signature)
+            .map_err(|_| anyhow!("Remote signer returned invalid TON signature"))?;
+        Ok(signature.to_bytes())
+    }
+}
+
+/// Reads single request from the Unix socket connection and writes the response
+pub async fn serve_unix_connection(
+    mut stream: UnixStream,
+    signer: &dyn Signer,
+) -> Result<(), Error> {
+    let response = match read_message(&mut stream).await? {
+        Some(line) => match serde_json::from_slice(&line) {
+            Ok(request) => handle_signer_request(signer, request).await,
+            Err(e) => SignerResponse::Error(format!("Invalid request: {}", e)),
+        },
+        None => SignerResponse::Error("Request is too long".to_owned()),
+    };
+
+    let mut data = serde_json::to_vec(&response)?;
+    data.push(b'\n');
+    stream.write_all(&data).await?;
+    Ok(())
+}
+
+/// Reads single line of the Unix socket protocol. Returns `None` if it exceeds
+/// `MAX_MESSAGE_SIZE`
+async fn read_message(stream: &mut UnixStream) -> Result<Option<Vec<u8>>, Error> {
+    let mut line = Vec::new();
+    BufReader::new(stream.take(MAX_MESSAGE_SIZE))
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if line.len() as u64 == MAX_MESSAGE_SIZE && line.last() != Some(&b'\n') {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+enum Connection {
+    /// Newline delimited JSON, one request per connection
+    Unix { path: PathBuf, timeout: Duration },
+    Http {
+        client: reqwest::Client,
+        url: Url,
+        token: SecStr,
+    },
+}
+
+impl Connection {
+    fn new(settings: &RemoteSignerSettings) -> Result<Self, Error> {
+        let url = Url::parse(&settings.endpoint)
+            .map_err(|e| anyhow!("Invalid remote signer endpoint: {}", e))?;
+
+        match url.scheme() {
+            "unix" => Ok(Self::Unix {
+                path: PathBuf::from(url.path()),
+                timeout: settings.timeout,
+            }),
+            "https" => {
+                let token_path = settings
+                    .token_path
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("Remote signer token is required for https"))?;
+                let token = read_token(token_path)?;
+
+                let mut client = reqwest::Client::builder().timeout(settings.timeout);
+                if let Some(ca_path) = &settings.ca_path {
+                    let ca = std::fs::read(ca_path)
+                        .map_err(|e| anyhow!("Failed reading {}: {}", ca_path.display(), e))?;
+                    client = client.add_root_certificate(reqwest::Certificate::from_pem(&ca)?);
+                }
+
+                Ok(Self::Http {
+                    client: client.build()?,
+                    url,
+                    token,
+                })
+            }
+            "http" => Err(anyhow!(
+                "Plain http can't be used for the remote signer, use https"
+            )),
+            scheme => Err(anyhow!("Unsupported remote signer scheme: {}", scheme)),
+        }
+    }
+
+    async fn request(&self, request: &SignerRequest) -> Result<SignerResponse, Error> {
+        match self {
+            Self::Unix { path, timeout } => {
+                tokio::time::timeout(*timeout, Self::unix_request(path, request))
+                    .await
+                    .map_err(|_| anyhow!("Remote signer request timeout"))?
+            }
+            Self::Http { client, url, token } => Ok(client
+                .post(url.clone())
+                .header(
+                    reqwest::header::AUTHORIZATION,
+                    format!("Bearer {}", std::str::from_utf8(token.unsecure())?),
+                )
+                .json(request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?),
+        }
+    }
+
+    async fn unix_request(path: &Path, request: &SignerRequest) -> Result<SignerResponse, Error> {
+        let mut stream = UnixStream::connect(path).await?;
+
+        let mut data = serde_json::to_vec(request)?;
+        data.push(b'\n');
+        stream.write_all(&data).await?;
+
+        let line = read_message(&mut stream)
+            .await?
+            .ok_or_else(|| anyhow!("Remote signer response is too long"))?;
+        Ok(serde_json::from_slice(&line)?)
+    }
+}
+
+/// Reads the bearer token of the remote signer. Trailing newline is ignored
+pub fn read_token(path: &Path) -> Result<SecStr, Error> {
+    let mut token = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed reading token {}: {}", path.display(), e))?;
+    while matches!(token.last(), Some(b'\n') | Some(b'\r')) {
+        token.pop();
+    }
+    let token = SecStr::new(token);
+    if token.unsecure().is_empty() {
+        return Err(anyhow!("Token {} is empty", path.display()));
+    }
+    Ok(token)
+}
+
+/// Checks the `Authorization: Bearer <token>` header in constant time
+pub fn is_authorized(header: Option<&str>, token: &SecStr) -> bool {
+    match header.and_then(|header| header.strip_prefix("Bearer ")) {
+        Some(presented) => {
+            ring::constant_time::verify_slices_are_equal(presented.as_bytes(), token.unsecure())
+                .is_ok()
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    fn make_signer(path: &std::path::Path) -> LocalSigner {
+        let ton_secret = ed25519_dalek::SecretKey::from_bytes(&[2; 32]).unwrap();
+        let ton_keypair = ed25519_dalek::Keypair {
+            public: ed25519_dalek::PublicKey::from(&ton_secret),
+            secret: ton_secret,
+        };
+        let keys = KeyData::init(
+            path,
+            &SecStr::new("123".into()),
+            SecVec::new(vec![1; 32]),
+            ton_keypair,
+        )
+        .unwrap();
+        LocalSigner::new(path.to_path_buf(), keys)
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer() {
+        let dir = std::env::temp_dir().join(format!("relay_signer_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("signer.sock");
+
+        let local: Arc<dyn Signer> = Arc::new(make_signer(&dir.join("keys.json")));
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        tokio::spawn({
+            let local = local.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    serve_unix_connection(stream, local.as_ref()).await.unwrap();
+                }
+            }
+        });
+
+        let remote = RemoteSigner::connect(&RemoteSignerSettings {
+            endpoint: format!("unix://{}", socket_path.display()),
+            token_path: None,
+            ca_path: None,
+            timeout: Duration::from_secs(10),
+        })
+        .await
+        .unwrap();
+        assert_eq!(remote.eth_pubkey(), local.eth_pubkey());
+        assert_eq!(remote.ton_public_key(), local.ton_public_key());
+
+        let data = b"hello world";
+        assert_eq!(
+            remote.sign_eth(data).await.unwrap(),
+            local.sign_eth(data).await.unwrap()
+        );
+        assert_eq!(
+            remote.sign_ton(data).await.unwrap().to_vec(),
+            local.sign_ton(data).await.unwrap().to_vec()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let token = SecStr::from("secret");
+        assert!(is_authorized(Some("Bearer secret"), &token));
+        assert!(!is_authorized(Some("Bearer secret2"), &token));
+        assert!(!is_authorized(Some("secret"), &token));
+        assert!(!is_authorized(None, &token));
+    }
+}
@@ -0,0 +1,153 @@
+use relay_models::models::AuditEntryView;
+
+use super::constants::*;
+use crate::prelude::*;
+
+/// Append-only history of the administrative api requests
+#[derive(Clone)]
+pub struct AuditLog {
+    tree: Tree,
+}
+
+impl AuditLog {
+    pub fn new(db: &Db) -> Result<Self, Error> {
+        Ok(Self {
+            tree: db.open_tree(AUDIT_LOG)?,
+        })
+    }
+
+    /// Appends new entry and returns its id. Ids grow with time
+    pub fn append(&self, entry: &AuditEntry) -> Result<u64, Error> {
+        let value = entry.try_to_vec().expect("Shouldn't fail");
+
+        let mut id = chrono::Utc::now().timestamp_nanos() as u64;
+        loop {
+            // Entries are never overwritten
+            let mut inserted = false;
+            self.tree
+                .fetch_and_update(id.to_be_bytes(), |old| match old {
+                    Some(old) => {
+                        inserted = false;
+                        Some(old.to_vec())
+                    }
+                    None => {
+                        inserted = true;
+                        Some(value.clone())
+                    }
+                })?;
+
+            if inserted {
+                break;
+            }
+            id += 1;
+        }
+
+        #[cfg(feature = "paranoid")]
+        self.tree.flush()?;
+        Ok(id)
+    }
+
+    /// Returns at most `limit` latest entries, which satisfy the filter. Newest first
+    pub fn get<F>(&self, limit: usize, filter: F) -> Result<Vec<(u64, AuditEntry)>, Error>
+    where
+        F: Fn(&AuditEntry) -> bool,
+    {
+        let mut entries = Vec::new();
+        if limit == 0 {
+            return Ok(entries);
+        }
+
+        for item in self.tree.range_rev::<Vec<u8>, _>(..) {
+            let (key, value) = item?;
+            let entry = AuditEntry::try_from_slice(&value)?;
+            if !filter(&entry) {
+                continue;
+            }
+
+            entries.push((parse_id(&key)?, entry));
+            if entries.len() == limit {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn parse_id(key: &[u8]) -> Result<u64, Error> {
+    let key: [u8; 8] = key
+        .try_into()
+        .map_err(|_| anyhow!("Invalid key length: {}", key.len()))?;
+    Ok(u64::from_be_bytes(key))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct AuditEntry {
+    /// Timestamp in seconds
+    pub timestamp: i64,
+    /// Name of the api token and the remote address
+    pub caller: String,
+    /// Api path of the request
+    pub action: String,
+    /// JSON encoded request parameters without secrets
+    pub params: String,
+    /// HTTP status of the response
+    pub status: u16,
+    /// Response body of the failed requests
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn into_view(self, id: u64) -> AuditEntryView {
+        AuditEntryView {
+            id,
+            timestamp: self.timestamp,
+            caller: self.caller,
+            action: self.action,
+            params: self.params,
+            status: self.status,
+            error: self.error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(action: &str, status: u16) -> AuditEntry {
+        AuditEntry {
+            timestamp: 1,
+            caller: "operator@127.0.0.1:50000".to_string(),
+            action: action.to_string(),
+            params: "{}".to_string(),
+            status,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_append_and_get() {
+        let db = Db::in_memory();
+        let audit_log = AuditLog::new(&db).unwrap();
+
+        let first = audit_log.append(&entry("retry-failed", 200)).unwrap();
+        let second = audit_log.append(&entry("rescan-eth", 400)).unwrap();
+        let third = audit_log.append(&entry("retry-failed", 200)).unwrap();
+        assert!(first < second && second < third);
+
+        let ids: Vec<_> = audit_log
+            .get(10, |_| true)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, [third, second, first]);
+
+        let latest = audit_log
+            .get(1, |entry| entry.action == "retry-failed")
+            .unwrap();
+        assert_eq!(latest, [(third, entry("retry-failed", 200))]);
+
+        assert!(audit_log.get(0, |_| true).unwrap().is_empty());
+    }
+}
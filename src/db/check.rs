@@ -0,0 +1,198 @@
+use relay_storage::transaction;
+
+use super::constants::*;
+use super::*;
+use crate::prelude::*;
+
+type Validator = fn(&[u8], &[u8]) -> Result<(), Error>;
+
+/// Entry which doesn't match the layout of its tree
+#[derive(Debug, Clone)]
+pub struct CorruptedEntry {
+    pub tree: Vec<u8>,
+    pub key: Vec<u8>,
+    pub reason: String,
+}
+
+impl std::fmt::Display for CorruptedEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}: {}",
+            display_tree_name(&self.tree),
+            hex::encode(&self.key),
+            self.reason
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheckReport {
+    pub checked_trees: usize,
+    pub checked_entries: usize,
+    /// Trees with unknown layout
+    pub skipped_trees: Vec<Vec<u8>>,
+    pub corrupted: Vec<CorruptedEntry>,
+}
+
+impl std::fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Checked trees: {}, entries: {}, corrupted: {}",
+            self.checked_trees,
+            self.checked_entries,
+            self.corrupted.len()
+        )?;
+        for name in &self.skipped_trees {
+            write!(f, "\nSkipped unknown tree: {}", display_tree_name(name))?;
+        }
+        for entry in &self.corrupted {
+            write!(f, "\nCorrupted entry in {}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// Walks all trees and validates their keys and values
+pub fn check_database(db: &Db) -> Result<CheckReport, Error> {
+    let mut report = CheckReport::default();
+
+    for name in db.tree_names() {
+        // Audit log is append-only and must never be moved to the quarantine
+        if name == QUARANTINE.as_bytes() || name == AUDIT_LOG.as_bytes() {
+            continue;
+        }
+
+        let validator = match find_validator(&name) {
+            Some(validator) => validator,
+            None => {
+                report.skipped_trees.push(name);
+                continue;
+            }
+        };
+
+        for item in db.open_tree(&name)?.iter() {
+            let (key, value) = item?;
+            if let Err(e) = validator(&key, &value) {
+                report.corrupted.push(CorruptedEntry {
+                    tree: name.clone(),
+                    key,
+                    reason: e.to_string(),
+                });
+            }
+            report.checked_entries += 1;
+        }
+        report.checked_trees += 1;
+    }
+
+    Ok(report)
+}
+
+/// Moves entries to the quarantine tree. Returns the amount of moved entries
+pub fn quarantine_entries(db: &Db, entries: &[CorruptedEntry]) -> Result<usize, Error> {
+    let quarantine = db.open_tree(QUARANTINE)?;
+
+    let mut moved = 0;
+    for entry in entries {
+        let tree = db.open_tree(&entry.tree)?;
+
+        // Original tree name is kept to be able to restore the entry manually
+        let mut quarantine_key = Vec::with_capacity(4 + entry.tree.len() + entry.key.len());
+        quarantine_key.extend_from_slice(&(entry.tree.len() as u32).to_be_bytes());
+        quarantine_key.extend_from_slice(&entry.tree);
+        quarantine_key.extend_from_slice(&entry.key);
+
+        let found = transaction(&[&tree, &quarantine], |trees| {
+            let (tree, quarantine) = (trees[0], trees[1]);
+            match tree.remove(&entry.key)? {
+                Some(value) => {
+                    quarantine.insert(&quarantine_key, &value)?;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        })?;
+
+        if found {
+            moved += 1;
+        }
+    }
+
+    Ok(moved)
+}
+
+fn find_validator(name: &[u8]) -> Option<Validator> {
+    if TonVerificationQueue::parse_tree_name(name).is_some() {
+        return Some(TonVerificationQueue::check_entry);
+    }
+
+    let name = std::str::from_utf8(name).ok()?;
+    let validator: Validator = match name {
+        ETH_EVENT_VOTES => EthVotingStats::check_entry,
+        TON_EVENT_VOTES => TonVotingStats::check_entry,
+        ETH_EVENT_VOTES_COUNTERS => EthVotingStats::check_counter_entry,
+        TON_EVENT_VOTES_COUNTERS => TonVotingStats::check_counter_entry,
+        ETH_QUEUE => EthVerificationQueue::check_entry,
+        TON_EVENTS_QUEUE_PENDING | TON_EVENTS_QUEUE_FAILED | TON_EVENTS_QUEUE_DEAD => {
+            TonEventVotesQueue::check_entry
+        }
+        ETH_EVENTS_QUEUE_PENDING | ETH_EVENTS_QUEUE_FAILED | ETH_EVENTS_QUEUE_DEAD => {
+            EthEventVotesQueue::check_entry
+        }
+        TON_EVENTS_QUEUE_HISTORY | ETH_EVENTS_QUEUE_HISTORY => check_history_entry,
+        ETH_EVENTS_JOURNAL => EventsJournal::check_eth_entry,
+        TON_EVENTS_JOURNAL => EventsJournal::check_ton_entry,
+        TON_LATEST_SCANNED_LT => ScanningState::check_entry,
+        STORAGE_PROBE => StorageProbe::check_entry,
+        _ if name == Migrator::versions_tree_name() => Migrator::check_entry,
+        _ => return None,
+    };
+    Some(validator)
+}
+
+fn display_tree_name(name: &[u8]) -> String {
+    match TonVerificationQueue::parse_tree_name(name) {
+        Some(configuration_id) => format!("{}/{}", TON_QUEUE, configuration_id),
+        None => String::from_utf8_lossy(name).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_and_quarantine() {
+        let db = Db::in_memory();
+
+        let history = db.open_tree(ETH_EVENTS_QUEUE_HISTORY).unwrap();
+        let valid = VoteRetryHistory {
+            first_failed_at: 1,
+            attempts: Vec::new(),
+        };
+        history
+            .insert([1; 32], valid.try_to_vec().unwrap())
+            .unwrap();
+        history.insert([2; 32], [1, 2, 3]).unwrap();
+        history
+            .insert([3; 16], valid.try_to_vec().unwrap())
+            .unwrap();
+
+        db.open_tree("unknown").unwrap().insert([0], [0]).unwrap();
+        // Audit log is never checked
+        db.open_tree(AUDIT_LOG).unwrap().insert([0], [0]).unwrap();
+
+        let report = check_database(&db).unwrap();
+        assert_eq!(report.checked_entries, 3);
+        assert_eq!(report.skipped_trees, [b"unknown".to_vec()]);
+        assert_eq!(report.corrupted.len(), 2);
+
+        assert_eq!(quarantine_entries(&db, &report.corrupted).unwrap(), 2);
+        assert_eq!(history.len(), 1);
+        assert_eq!(db.open_tree(QUARANTINE).unwrap().len(), 2);
+
+        let report = check_database(&db).unwrap();
+        assert!(report.corrupted.is_empty());
+    }
+}
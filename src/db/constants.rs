@@ -1,15 +1,32 @@
 pub const ETH_EVENT_VOTES: &str = "eth_event_votes";
 pub const TON_EVENT_VOTES: &str = "ton_event_votes";
+pub const ETH_EVENT_VOTES_COUNTERS: &str = "eth_event_votes_counters";
+pub const TON_EVENT_VOTES_COUNTERS: &str = "ton_event_votes_counters";
 
 pub const ETH_QUEUE: &str = "eth_queue";
 pub const TON_QUEUE: &str = "ton_queue";
 
 pub const TON_EVENTS_QUEUE_PENDING: &str = "ton_events_queue_pending";
 pub const TON_EVENTS_QUEUE_FAILED: &str = "ton_events_queue_failed";
+pub const TON_EVENTS_QUEUE_DEAD: &str = "ton_events_queue_dead";
+pub const TON_EVENTS_QUEUE_HISTORY: &str = "ton_events_queue_history";
 
 pub const ETH_EVENTS_QUEUE_PENDING: &str = "eth_events_queue_pending";
 pub const ETH_EVENTS_QUEUE_FAILED: &str = "eth_events_queue_failed";
+pub const ETH_EVENTS_QUEUE_DEAD: &str = "eth_events_queue_dead";
+pub const ETH_EVENTS_QUEUE_HISTORY: &str = "eth_events_queue_history";
+
+pub const ETH_EVENTS_JOURNAL: &str = "eth_events_journal";
+pub const TON_EVENTS_JOURNAL: &str = "ton_events_journal";
+
+pub const AUDIT_LOG: &str = "audit_log";
 
 pub const TON_LATEST_SCANNED_LT: &str = "ton_latest_scanned_lt";
 
 pub const SYSTEM_DATA: &str = "system_data";
+
+pub const QUARANTINE: &str = "quarantine";
+
+pub const STORAGE_ENCRYPTION: &str = "storage_encryption";
+
+pub const STORAGE_PROBE: &str = "storage_probe";
@@ -0,0 +1,227 @@
+use std::path::Path;
+
+use relay_storage::{
+    transaction, CipherSlot, EncryptedStorage, Storage, StorageResult, ValueCipher,
+};
+use secstr::SecStr;
+use sodiumoxide::crypto::secretbox;
+
+use super::constants::STORAGE_ENCRYPTION;
+use crate::crypto::key_managment::Kdf;
+use crate::prelude::*;
+
+const KDF: &[u8] = b"kdf";
+const SALT: &[u8] = b"salt";
+/// Data key, sealed with the key derived from the unlock password
+const WRAPPED_KEY: &[u8] = b"wrapped_key";
+const KEY_CHECK: &[u8] = b"key_check";
+/// Encrypted with the data key to detect wrong keys on unlock
+const KEY_CHECK_DATA: &[u8] = b"relay storage key";
+
+/// Value-level encryption of the database.
+///
+/// All values are sealed with xsalsa20poly1305. The data key is either
+/// loaded from the key file or generated and wrapped with the unlock password
+#[derive(Clone)]
+pub struct StorageEncryption {
+    db: Db,
+    slot: CipherSlot,
+}
+
+impl StorageEncryption {
+    /// Wraps storage backend. Returned db stays locked until one of the `unlock_*` methods succeeds
+    pub fn new<S>(storage: S) -> (Db, Self)
+    where
+        S: Storage + 'static,
+    {
+        sodiumoxide::init().expect("Failed initializing libsodium");
+
+        let slot = CipherSlot::default();
+        let db = Db::new(EncryptedStorage::new(
+            storage,
+            slot.clone(),
+            &[STORAGE_ENCRYPTION.as_bytes()],
+        ));
+        (db.clone(), Self { db, slot })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.slot.is_locked()
+    }
+
+    /// Loads hex encoded 32 byte data key from the file
+    pub fn unlock_with_key_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let data = std::fs::read_to_string(&path)?;
+        let data = hex::decode(data.trim()).map_err(|_| anyhow!("Invalid storage key file"))?;
+        let key = secretbox::Key::from_slice(&data)
+            .ok_or_else(|| anyhow!("Storage key must be {} bytes", secretbox::KEYBYTES))?;
+        self.unlock(key)
+    }
+
+    /// Unwraps the data key with the key, derived from the unlock password.
+    /// New data key is generated for the empty storage
+    pub fn unlock_with_password(&self, password: &SecStr) -> Result<(), Error> {
+        let metadata = self.db.open_tree(STORAGE_ENCRYPTION)?;
+        let key = match unwrap_data_key(&metadata, password)? {
+            Some(key) => key,
+            None => {
+                let key = secretbox::gen_key();
+                wrap_data_key(&metadata, &key, password)?;
+                key
+            }
+        };
+
+        self.unlock(key)
+    }
+
+    /// Wraps the data key with the new password. Values are not re-encrypted
+    pub fn change_password(
+        &self,
+        old_password: &SecStr,
+        new_password: &SecStr,
+    ) -> Result<(), Error> {
+        let metadata = self.db.open_tree(STORAGE_ENCRYPTION)?;
+        match unwrap_data_key(&metadata, old_password)? {
+            Some(key) => wrap_data_key(&metadata, &key, new_password),
+            // Data key will be generated on the first unlock
+            None => Ok(()),
+        }
+    }
+
+    fn unlock(&self, key: secretbox::Key) -> Result<(), Error> {
+        let metadata = self.db.open_tree(STORAGE_ENCRYPTION)?;
+        let cipher = SecretBoxCipher(key);
+
+        match metadata.get(KEY_CHECK)? {
+            Some(check) => match cipher.decrypt(&check) {
+                Ok(check) if check == KEY_CHECK_DATA => {}
+                _ => return Err(anyhow!("Storage is encrypted with another key")),
+            },
+            None => {
+                // Values written before the encryption was enabled can't be read
+                for name in self.db.tree_names() {
+                    if !self.db.open_tree(&name)?.is_empty() {
+                        return Err(anyhow!(
+                            "Storage contains unencrypted data in tree {}. \
+                            Restore the backup into the empty storage to encrypt it",
+                            String::from_utf8_lossy(&name)
+                        ));
+                    }
+                }
+                metadata.insert(KEY_CHECK, cipher.encrypt(KEY_CHECK_DATA))?;
+                metadata.flush()?;
+            }
+        }
+
+        self.slot.set(Arc::new(cipher));
+        Ok(())
+    }
+}
+
+fn unwrap_data_key(metadata: &Tree, password: &SecStr) -> Result<Option<secretbox::Key>, Error> {
+    let (kdf, salt) = match (metadata.get(KDF)?, metadata.get(SALT)?) {
+        (Some(kdf), Some(salt)) => (serde_json::from_slice::<Kdf>(&kdf)?, salt),
+        (None, Some(salt)) => (Kdf::legacy(), salt),
+        _ => return Ok(None),
+    };
+    let password_key = kdf.derive_key(password, &salt)?;
+
+    match metadata.get(WRAPPED_KEY)? {
+        Some(wrapped) => {
+            let key = SecretBoxCipher(password_key)
+                .decrypt(&wrapped)
+                .map_err(|_| anyhow!("Invalid password"))?;
+            secretbox::Key::from_slice(&key)
+                .map(Some)
+                .ok_or_else(|| anyhow!("Invalid data key length"))
+        }
+        // Storages, where the data key was derived from the password directly
+        None => Ok(Some(password_key)),
+    }
+}
+
+fn wrap_data_key(metadata: &Tree, key: &secretbox::Key, password: &SecStr) -> Result<(), Error> {
+    let kdf = Kdf::default();
+    let salt = kdf.gen_salt();
+    let wrapped = SecretBoxCipher(kdf.derive_key(password, &salt)?).encrypt(&key.0);
+    let kdf = serde_json::to_vec(&kdf)?;
+
+    transaction(&[metadata], |trees| {
+        trees[0].insert(KDF, &kdf)?;
+        trees[0].insert(SALT, &salt)?;
+        trees[0].insert(WRAPPED_KEY, &wrapped)?;
+        Ok(())
+    })?;
+    metadata.flush()?;
+    Ok(())
+}
+
+/// Prepends random nonce to the sealed value
+struct SecretBoxCipher(secretbox::Key);
+
+impl ValueCipher for SecretBoxCipher {
+    fn encrypt(&self, value: &[u8]) -> Vec<u8> {
+        let nonce = secretbox::gen_nonce();
+        let mut result = nonce.as_ref().to_vec();
+        result.extend_from_slice(&secretbox::seal(value, &nonce, &self.0));
+        result
+    }
+
+    fn decrypt(&self, value: &[u8]) -> StorageResult<Vec<u8>> {
+        if value.len() < secretbox::NONCEBYTES {
+            return Err(anyhow!("Encrypted value is too short"));
+        }
+        let (nonce, data) = value.split_at(secretbox::NONCEBYTES);
+        let nonce = secretbox::Nonce::from_slice(nonce).expect("Shouldn't fail");
+        secretbox::open(data, &nonce, &self.0).map_err(|_| anyhow!("Failed decrypting value"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use relay_storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn test_unlock() {
+        let (db, encryption) = StorageEncryption::new(MemoryStorage::default());
+        assert!(encryption.is_locked());
+        assert!(db.open_tree("tree").unwrap().insert([1], [2]).is_err());
+
+        let password = SecStr::new("123".into());
+        encryption.unlock_with_password(&password).unwrap();
+        assert!(!encryption.is_locked());
+
+        let tree = db.open_tree("tree").unwrap();
+        tree.insert([1], [2]).unwrap();
+        assert_eq!(tree.get([1]).unwrap(), Some(vec![2]));
+        // Metadata tree is not exposed
+        assert_eq!(db.tree_names(), vec![b"tree".to_vec()]);
+
+        // Wrong password is rejected
+        let other = StorageEncryption {
+            db: db.clone(),
+            slot: CipherSlot::default(),
+        };
+        assert!(other
+            .unlock_with_password(&SecStr::new("lol".into()))
+            .is_err());
+        assert!(other.is_locked());
+        assert!(other.unlock_with_password(&password).is_ok());
+
+        // Data key stays the same after the password change
+        let new_password = SecStr::new("456".into());
+        assert!(other.change_password(&new_password, &password).is_err());
+        other.change_password(&password, &new_password).unwrap();
+        let other = StorageEncryption {
+            db: db.clone(),
+            slot: CipherSlot::default(),
+        };
+        assert!(other.unlock_with_password(&password).is_err());
+        other.unlock_with_password(&new_password).unwrap();
+    }
+}
@@ -0,0 +1,248 @@
+use relay_models::models::{EventDirection, EventJournalEntryView, EventStateView};
+
+use super::constants::*;
+use crate::models::*;
+use crate::prelude::*;
+
+/// Max amount of the entries in the history of one event.
+/// Retries can append the same states over and over again
+const MAX_EVENT_ENTRIES: usize = 32;
+
+/// Persistent history of event state transitions
+#[derive(Clone)]
+pub struct EventsJournal {
+    eth: Tree,
+    ton: Tree,
+}
+
+impl EventsJournal {
+    pub fn new(db: &Db) -> Result<Self, Error> {
+        Ok(Self {
+            eth: db.open_tree(ETH_EVENTS_JOURNAL)?,
+            ton: db.open_tree(TON_EVENTS_JOURNAL)?,
+        })
+    }
+
+    /// Appends new state to the event history
+    pub fn record(
+        &self,
+        key: &EventJournalKey,
+        state: EventState,
+        details: Option<String>,
+    ) -> Result<(), Error> {
+        self.append(key, state, details, false)
+    }
+
+    /// Appends new state to the event history if it wasn't reached before
+    pub fn record_once(
+        &self,
+        key: &EventJournalKey,
+        state: EventState,
+        details: Option<String>,
+    ) -> Result<(), Error> {
+        self.append(key, state, details, true)
+    }
+
+    pub fn get(&self, key: &EventJournalKey) -> Result<Vec<EventJournalEntry>, Error> {
+        let (tree, key) = self.locate(key);
+        Ok(match tree.get(key)? {
+            Some(value) => Vec::<EventJournalEntry>::try_from_slice(&value)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Validates raw entry of the ETH events journal
+    pub fn check_eth_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() != 36 {
+            return Err(anyhow!("Invalid key length: {}", key.len()));
+        }
+        Vec::<EventJournalEntry>::try_from_slice(value)?;
+        Ok(())
+    }
+
+    /// Validates raw entry of the TON events journal
+    pub fn check_ton_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() != 32 {
+            return Err(anyhow!("Invalid key length: {}", key.len()));
+        }
+        Vec::<EventJournalEntry>::try_from_slice(value)?;
+        Ok(())
+    }
+
+    fn append(
+        &self,
+        key: &EventJournalKey,
+        state: EventState,
+        details: Option<String>,
+        once: bool,
+    ) -> Result<(), Error> {
+        let (tree, key) = self.locate(key);
+        let entry = EventJournalEntry {
+            state,
+            timestamp: chrono::Utc::now().timestamp(),
+            details,
+        };
+
+        tree.fetch_and_update(key, |value| {
+            let mut entries = value
+                .and_then(|value| Vec::<EventJournalEntry>::try_from_slice(value).ok())
+                .unwrap_or_default();
+
+            if !once || entries.iter().all(|item| item.state != state) {
+                entries.push(entry.clone());
+            }
+            truncate_entries(&mut entries);
+
+            Some(entries.try_to_vec().expect("Shouldn't fail"))
+        })?;
+
+        #[cfg(feature = "paranoid")]
+        tree.flush()?;
+        Ok(())
+    }
+
+    fn locate(&self, key: &EventJournalKey) -> (&Tree, Vec<u8>) {
+        match key {
+            EventJournalKey::Eth {
+                tx_hash,
+                event_index,
+            } => {
+                let mut key = tx_hash.as_bytes().to_vec();
+                key.extend_from_slice(&event_index.to_be_bytes());
+                (&self.eth, key)
+            }
+            EventJournalKey::Ton(event_address) => {
+                (&self.ton, event_address.address.get_bytestring(0))
+            }
+        }
+    }
+}
+
+/// Removes the oldest entries except the first one, which shows when the event was seen
+fn truncate_entries(entries: &mut Vec<EventJournalEntry>) {
+    if entries.len() > MAX_EVENT_ENTRIES {
+        entries.drain(1..=entries.len() - MAX_EVENT_ENTRIES);
+    }
+}
+
+/// ETH events are identified by their transaction, TON events by the event contract
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EventJournalKey {
+    Eth { tx_hash: H256, event_index: u32 },
+    Ton(MsgAddrStd),
+}
+
+impl EventJournalKey {
+    pub fn direction(&self) -> EventDirection {
+        match self {
+            Self::Eth { .. } => EventDirection::EthToTon,
+            Self::Ton(_) => EventDirection::TonToEth,
+        }
+    }
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct EventJournalEntry {
+    pub state: EventState,
+    /// Timestamp in seconds
+    pub timestamp: i64,
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, BorshSerialize, BorshDeserialize)]
+pub enum EventState {
+    /// Event was found in the source network
+    Seen,
+    /// Event waits for confirmation depth in the verification queue
+    Queued,
+    /// Event was verified and the vote was enqueued
+    Verified,
+    /// Vote was delivered to the relay contract
+    VoteSent,
+    /// Our vote was found in the event contract
+    VoteObserved,
+    /// Event got enough confirmations or rejections
+    Finalized,
+    /// Event was dropped or the vote ran out of retries
+    Failed,
+}
+
+impl IntoView for EventJournalEntry {
+    type View = EventJournalEntryView;
+
+    fn into_view(self) -> Self::View {
+        EventJournalEntryView {
+            state: match self.state {
+                EventState::Seen => EventStateView::Seen,
+                EventState::Queued => EventStateView::Queued,
+                EventState::Verified => EventStateView::Verified,
+                EventState::VoteSent => EventStateView::VoteSent,
+                EventState::VoteObserved => EventStateView::VoteObserved,
+                EventState::Finalized => EventStateView::Finalized,
+                EventState::Failed => EventStateView::Failed,
+            },
+            timestamp: self.timestamp,
+            details: self.details,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record_once() {
+        let db = Db::in_memory();
+        let journal = EventsJournal::new(&db).unwrap();
+        let key = EventJournalKey::Eth {
+            tx_hash: H256::repeat_byte(1),
+            event_index: 2,
+        };
+
+        journal.record_once(&key, EventState::Seen, None).unwrap();
+        journal.record(&key, EventState::VoteSent, None).unwrap();
+        journal.record(&key, EventState::VoteSent, None).unwrap();
+        journal.record_once(&key, EventState::Seen, None).unwrap();
+
+        let states: Vec<_> = journal
+            .get(&key)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.state)
+            .collect();
+        assert_eq!(
+            states,
+            [EventState::Seen, EventState::VoteSent, EventState::VoteSent]
+        );
+
+        let other = EventJournalKey::Ton(MsgAddrStd::default());
+        assert!(journal.get(&other).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_entries_limit() {
+        let db = Db::in_memory();
+        let journal = EventsJournal::new(&db).unwrap();
+        let key = EventJournalKey::Ton(MsgAddrStd::default());
+
+        journal.record(&key, EventState::Seen, None).unwrap();
+        for i in 0..MAX_EVENT_ENTRIES * 2 {
+            journal
+                .record(&key, EventState::Failed, Some(i.to_string()))
+                .unwrap();
+        }
+
+        let entries = journal.get(&key).unwrap();
+        assert_eq!(entries.len(), MAX_EVENT_ENTRIES);
+        assert_eq!(entries[0].state, EventState::Seen);
+        assert_eq!(
+            entries[1].details,
+            Some((MAX_EVENT_ENTRIES + 1).to_string())
+        );
+        assert_eq!(
+            entries.last().unwrap().details,
+            Some((MAX_EVENT_ENTRIES * 2 - 1).to_string())
+        );
+    }
+}
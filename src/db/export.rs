@@ -0,0 +1,30 @@
+use relay_models::models::{DatabaseExportView, EthToTonExportView, TonToEthExportView};
+
+use super::*;
+use crate::models::*;
+use crate::prelude::*;
+
+/// Collects queues, voting stats and scan cursors into the human-readable form
+pub fn export_database(db: &Db) -> Result<DatabaseExportView, Error> {
+    let mut ton_queued = HashMap::new();
+    for configuration_id in TonVerificationQueue::configuration_ids(db) {
+        let queue = TonVerificationQueue::new(db, configuration_id)?;
+        ton_queued.insert(configuration_id, queue.dump_elements());
+    }
+
+    Ok(DatabaseExportView {
+        created_at: chrono::Utc::now().timestamp(),
+        eth_to_ton: EthToTonExportView {
+            last_scanned_block: relay_eth::get_last_met_height(db)?,
+            queued: EthVerificationQueue::new(db)?.dump_elements(),
+            votes: EthEventTransaction::new(db)?.dump_elements(),
+            stats: EthVotingStats::new(db)?.dump_elements(),
+        },
+        ton_to_eth: TonToEthExportView {
+            latest_scanned_lt: ScanningState::new(db)?.dump_elements(),
+            queued: ton_queued,
+            votes: TonEventTransaction::new(db)?.dump_elements(),
+            stats: TonVotingStats::new(db)?.dump_elements(),
+        },
+    })
+}
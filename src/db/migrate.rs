@@ -1,31 +1,79 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::{anyhow, Error};
-use sled::{Db, Tree};
+use relay_storage::{Db, Tree};
 
-use super::{constants::SYSTEM_DATA, verification_queue};
+use super::{constants::SYSTEM_DATA, stats_db, verification_queue};
 
 const VERSION_FIELD: &str = "version";
 
 pub struct Migrator {
     versions: Tree,
     db: Db,
+    snapshots_path: Option<PathBuf>,
 }
 
 impl Migrator {
     pub fn init(db: &Db) -> Result<Self, Error> {
         Ok(Self {
-            versions: db.open_tree(&format!("{}{}", SYSTEM_DATA, VERSION_FIELD))?,
+            versions: db.open_tree(Self::versions_tree_name())?,
             db: db.clone(),
+            snapshots_path: None,
         })
     }
 
+    /// Directory, where the snapshot of the whole db is saved before migrations
+    pub fn with_snapshots_path(mut self, path: PathBuf) -> Self {
+        self.snapshots_path = Some(path);
+        self
+    }
+
+    pub fn versions_tree_name() -> String {
+        format!("{}{}", SYSTEM_DATA, VERSION_FIELD)
+    }
+
+    /// Validates raw entry of the versions tree
+    pub fn check_entry(_: &[u8], value: &[u8]) -> Result<(), Error> {
+        match value.len() {
+            24 => Ok(()),
+            len => Err(anyhow!("Invalid version length: {}", len)),
+        }
+    }
+
     pub fn run_migrations(&self) -> Result<(), Error> {
         log::warn!("Applying migrations");
-        self.run_migration::<verification_queue::EthVerificationQueue>()?;
-        self.run_migration::<verification_queue::TonVerificationQueue>()?;
-        Ok(())
+        let plan = self.plan_migrations()?;
+        self.apply(&plan)
+    }
+
+    /// Returns steps, which will be applied by `run_migrations`
+    pub fn plan_migrations(&self) -> Result<MigrationPlan, Error> {
+        let mut plan = MigrationPlan::default();
+        self.plan_migration::<verification_queue::EthVerificationQueue>(&mut plan)?;
+        self.plan_migration::<verification_queue::TonVerificationQueue>(&mut plan)?;
+        self.plan_migration::<stats_db::EthVotingStats>(&mut plan)?;
+        self.plan_migration::<stats_db::TonVotingStats>(&mut plan)?;
+        Ok(plan)
+    }
+
+    /// Reverts db schema to the one, used by the relay of `target` version
+    pub fn downgrade(&self, target: &semver::Version) -> Result<(), Error> {
+        log::warn!("Downgrading db to {}", target);
+        let plan = self.plan_downgrade(target)?;
+        self.apply(&plan)
+    }
+
+    /// Returns steps, which will be applied by `downgrade`
+    pub fn plan_downgrade(&self, target: &semver::Version) -> Result<MigrationPlan, Error> {
+        let mut plan = MigrationPlan::default();
+        self.plan_downgrade_for::<stats_db::TonVotingStats>(target, &mut plan)?;
+        self.plan_downgrade_for::<stats_db::EthVotingStats>(target, &mut plan)?;
+        self.plan_downgrade_for::<verification_queue::TonVerificationQueue>(target, &mut plan)?;
+        self.plan_downgrade_for::<verification_queue::EthVerificationQueue>(target, &mut plan)?;
+        Ok(plan)
     }
 
-    fn run_migration<T>(&self) -> Result<(), Error>
+    fn plan_migration<T>(&self, plan: &mut MigrationPlan) -> Result<(), Error>
     where
         T: Migration,
     {
@@ -40,14 +88,13 @@ impl Migrator {
 
         match versions.last() {
             Some(last) if last != &previous_version => match versions.len() {
-                1 => {
-                    migrate::<T>(&self.db, &previous_version, last)?;
-                    self.update_version(T::NAME, last)?;
-                }
+                1 => plan
+                    .steps
+                    .push(self.make_step::<T>(&previous_version, last, T::update)),
                 _ => {
                     for x in versions.windows(2).skip_while(|x| x[0] < previous_version) {
-                        migrate::<T>(&self.db, &x[0], &x[1])?;
-                        self.update_version(T::NAME, &x[1])?;
+                        plan.steps
+                            .push(self.make_step::<T>(&x[0], &x[1], T::update));
                     }
                 }
             },
@@ -56,6 +103,97 @@ impl Migrator {
         Ok(())
     }
 
+    fn plan_downgrade_for<T>(
+        &self,
+        target: &semver::Version,
+        plan: &mut MigrationPlan,
+    ) -> Result<(), Error>
+    where
+        T: Migration,
+    {
+        let previous_version = semver::Version::from_tuple(self.get_previous_version(T::NAME)?);
+
+        // Each breaking version is reverted to the preceding one
+        let mut versions = vec![semver::Version::from_tuple(Version::default())];
+        versions.extend(T::get_breaking_versions().0);
+
+        for x in versions.windows(2).rev() {
+            if x[1] <= previous_version && &x[1] > target {
+                plan.steps
+                    .push(self.make_step::<T>(&x[1], &x[0], T::downgrade));
+            }
+        }
+        Ok(())
+    }
+
+    fn make_step<T>(
+        &self,
+        from: &semver::Version,
+        to: &semver::Version,
+        apply: MigrationFn,
+    ) -> MigrationStep
+    where
+        T: Migration,
+    {
+        let existing = self.db.tree_names();
+        let trees = T::trees(&self.db)
+            .into_iter()
+            .map(|(name, tree_name)| {
+                let records = if existing.contains(&tree_name) {
+                    self.db
+                        .open_tree(tree_name)
+                        .map(|tree| tree.len())
+                        .unwrap_or_default()
+                } else {
+                    0
+                };
+                (name, records)
+            })
+            .collect();
+
+        MigrationStep {
+            entity: T::NAME,
+            from: from.clone(),
+            to: to.clone(),
+            trees,
+            apply,
+        }
+    }
+
+    fn apply(&self, plan: &MigrationPlan) -> Result<(), Error> {
+        // There is nothing to lose if all affected trees are empty
+        if plan.records() > 0 {
+            if let Some(path) = &self.snapshots_path {
+                self.make_snapshot(path)?;
+            }
+        }
+
+        for step in &plan.steps {
+            migrate(&self.db, step)?;
+            self.update_version(step.entity, &step.to)?;
+        }
+        Ok(())
+    }
+
+    fn make_snapshot(&self, path: &Path) -> Result<(), Error> {
+        std::fs::create_dir_all(path)?;
+
+        let path = path.join(format!(
+            "before_migration_{}.bin",
+            chrono::Utc::now().format("%Y%m%d_%H%M%S")
+        ));
+        let file = std::fs::File::create(&path)?;
+        let stats = self.db.backup(std::io::BufWriter::new(file))?;
+
+        log::warn!(
+            "Saved db snapshot to {}. Trees: {}, entries: {}",
+            path.display(),
+            stats.trees,
+            stats.entries
+        );
+        Ok(())
+    }
+
     fn get_previous_version(&self, entity: &str) -> Result<Version, Error> {
         let value = match self.versions.get(&entity)? {
             Some(value) if value.len() == 24 => value,
@@ -77,6 +215,12 @@ impl Migrator {
     }
 
     fn update_version(&self, entity: &str, version: &semver::Version) -> Result<(), Error> {
+        // Entity is reverted to the state before the first migration
+        if version.as_tuple() == Version::default() {
+            self.versions.remove(entity.as_bytes())?;
+            return Ok(());
+        }
+
         let mut value = [0; 24];
         value[0..8].copy_from_slice(&version.major.to_le_bytes());
         value[8..16].copy_from_slice(&version.minor.to_le_bytes());
@@ -107,10 +251,73 @@ impl VersionExt for semver::Version {
 pub trait Migration: Sized {
     const NAME: &'static str;
 
+    /// Trees, which are read or modified by the migrations, with their display names
+    fn trees(db: &Db) -> Vec<(String, Vec<u8>)>;
+
     fn get_breaking_versions() -> VersionIterator;
 
     /// update from `old` to `version2`
     fn update(db: &Db, old: Version, new: Version) -> Result<(), Error>;
+
+    /// revert update to `from` version, so that data is readable by the relay of `to` version
+    fn downgrade(_db: &Db, _from: Version, _to: Version) -> Result<(), Error> {
+        Err(anyhow!("Downgrade of {} is not supported", Self::NAME))
+    }
+}
+
+/// Display names of the trees with the fixed names, see `Migration::trees`
+pub fn named_trees(names: &[&str]) -> Vec<(String, Vec<u8>)> {
+    names
+        .iter()
+        .map(|name| (name.to_string(), name.as_bytes().to_vec()))
+        .collect()
+}
+
+type MigrationFn = fn(&Db, Version, Version) -> Result<(), Error>;
+
+/// Ordered list of the migration steps
+#[derive(Debug, Clone, Default)]
+pub struct MigrationPlan {
+    pub steps: Vec<MigrationStep>,
+}
+
+impl MigrationPlan {
+    /// Total amount of records in the affected trees
+    pub fn records(&self) -> usize {
+        self.steps
+            .iter()
+            .flat_map(|step| step.trees.iter().map(|(_, records)| records))
+            .sum()
+    }
+}
+
+impl std::fmt::Display for MigrationPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.steps.is_empty() {
+            return f.write_str("No migrations required");
+        }
+
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{}: {} -> {}", step.entity, step.from, step.to)?;
+            for (name, records) in &step.trees {
+                write!(f, "\n  {}: {} records", name, records)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStep {
+    pub entity: &'static str,
+    pub from: semver::Version,
+    pub to: semver::Version,
+    /// Affected trees with the amount of records in them
+    pub trees: Vec<(String, usize)>,
+    apply: MigrationFn,
 }
 
 pub struct VersionIterator(Vec<semver::Version>);
@@ -130,23 +337,94 @@ where
     }
 }
 
-fn migrate<T: Migration>(
-    db: &Db,
-    old: &semver::Version,
-    new: &semver::Version,
-) -> Result<(), Error> {
-    assert_ne!(old, new);
+fn migrate(db: &Db, step: &MigrationStep) -> Result<(), Error> {
+    assert_ne!(step.from, step.to);
 
-    log::warn!("Applying migration for {} from {} to {}", T::NAME, old, new);
+    log::warn!(
+        "Applying migration for {} from {} to {}",
+        step.entity,
+        step.from,
+        step.to
+    );
 
-    match T::update(db, old.as_tuple(), new.as_tuple()) {
+    match (step.apply)(db, step.from.as_tuple(), step.to.as_tuple()) {
         Ok(_) => {
-            log::warn!("Successfully migrated {} from {} to {}", T::NAME, old, new);
+            log::warn!(
+                "Successfully migrated {} from {} to {}",
+                step.entity,
+                step.from,
+                step.to
+            );
             Ok(())
         }
         Err(e) => {
-            log::warn!("Error while migrating {} from {} to {}", T::NAME, old, new);
+            log::warn!(
+                "Error while migrating {} from {} to {}",
+                step.entity,
+                step.from,
+                step.to
+            );
             Err(e)
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::constants::*;
+
+    #[test]
+    fn test_downgrade() {
+        let db = Db::in_memory();
+        let migrator = Migrator::init(&db).unwrap();
+
+        let plan = migrator.plan_migrations().unwrap();
+        assert_eq!(plan.steps.len(), 4);
+        migrator.run_migrations().unwrap();
+        assert!(migrator.plan_migrations().unwrap().steps.is_empty());
+
+        let target = semver::Version::new(1, 0, 1);
+        let plan = migrator.plan_downgrade(&target).unwrap();
+        let entities: Vec<_> = plan.steps.iter().map(|step| step.entity).collect();
+        assert_eq!(entities, ["TonVotingStats", "EthVotingStats"]);
+
+        migrator.downgrade(&target).unwrap();
+        assert!(!db
+            .tree_names()
+            .iter()
+            .any(|name| name == ETH_EVENT_VOTES_COUNTERS.as_bytes()));
+        assert!(migrator.plan_downgrade(&target).unwrap().steps.is_empty());
+
+        // Counters are rebuilt on the next upgrade
+        let plan = migrator.plan_migrations().unwrap();
+        assert_eq!(plan.steps.len(), 2);
+    }
+
+    #[test]
+    fn test_ton_queue_trees() {
+        let db = Db::in_memory();
+        let migrator = Migrator::init(&db).unwrap();
+
+        let tree = db
+            .open_tree(verification_queue::TonVerificationQueue::tree_name(5))
+            .unwrap();
+        tree.insert([[0u8; 8], [7u8; 8]].concat(), &[]).unwrap();
+
+        let plan = migrator.plan_migrations().unwrap();
+        let step = plan
+            .steps
+            .iter()
+            .find(|step| step.entity == "TonVerificationQueue")
+            .unwrap();
+        assert_eq!(step.trees, vec![("ton_queue[5]".to_owned(), 1)]);
+        assert_eq!(plan.records(), 1);
+
+        migrator.run_migrations().unwrap();
+        let keys: Vec<_> = tree.iter().keys().map(Result::unwrap).collect();
+        let mut expected = vec![0u8; 8];
+        expected.push(1);
+        expected.extend_from_slice(&[7; 8]);
+        assert_eq!(keys, vec![expected]);
+    }
+}
@@ -1,4 +1,11 @@
+mod audit_log;
+mod check;
+mod encryption;
+mod events_journal;
+mod export;
+mod page;
 mod stats_db;
+mod storage_probe;
 mod verification_queue;
 mod votes_queues;
 
@@ -7,13 +14,20 @@ pub mod migrate;
 
 use std::collections::HashMap;
 
+pub use self::audit_log::*;
+pub use self::check::*;
+pub use self::encryption::*;
+pub use self::events_journal::*;
+pub use self::export::*;
 pub use self::migrate::*;
+pub use self::page::*;
 pub use self::stats_db::*;
+pub use self::storage_probe::*;
 pub use self::verification_queue::*;
 pub use self::votes_queues::*;
 
 /// This module contains all db related operations.
-/// We are using `sled` as kv storage and `borsh` for encoding in the binary format.
+/// We are using `relay_storage` as kv storage and `borsh` for encoding in the binary format.
 pub trait Table {
     type Key;
     type Value;
@@ -0,0 +1,140 @@
+use std::ops::Bound;
+
+use crate::prelude::*;
+
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+
+/// Max number of items in one page
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+/// Position and bounds of the requested page
+#[derive(Debug, Clone)]
+pub struct PageRequest {
+    /// Key of the last entry of the previous page
+    pub cursor: Option<Vec<u8>>,
+    pub limit: usize,
+    pub descending: bool,
+    /// Inclusive lower bound of the keys
+    pub from: Option<Vec<u8>>,
+    /// Exclusive upper bound of the keys
+    pub to: Option<Vec<u8>>,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            limit: DEFAULT_PAGE_LIMIT,
+            descending: false,
+            from: None,
+            to: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Key of the last item if the page is full
+    pub next_cursor: Option<Vec<u8>>,
+}
+
+/// Reads entries of the tree in the key order, skipping those for which `f` returns `None`
+pub fn read_page<T, F>(tree: &Tree, request: &PageRequest, mut f: F) -> Result<Page<T>, Error>
+where
+    F: FnMut(&[u8], &[u8]) -> Result<Option<T>, Error>,
+{
+    let limit = request.limit.min(MAX_PAGE_LIMIT);
+
+    let mut from = match &request.from {
+        Some(from) => Bound::Included(from.clone()),
+        None => Bound::Unbounded,
+    };
+    let mut to = match &request.to {
+        Some(to) => Bound::Excluded(to.clone()),
+        None => Bound::Unbounded,
+    };
+    if let Some(cursor) = &request.cursor {
+        if request.descending {
+            to = Bound::Excluded(cursor.clone());
+        } else {
+            from = Bound::Excluded(cursor.clone());
+        }
+    }
+
+    let iter = if request.descending {
+        tree.range_rev::<Vec<u8>, _>((from, to))
+    } else {
+        tree.range::<Vec<u8>, _>((from, to))
+    };
+
+    let mut items = Vec::new();
+    let mut last_key = None;
+    if limit > 0 {
+        for item in iter {
+            let (key, value) = item?;
+            if let Some(item) = f(&key, &value)? {
+                items.push(item);
+                if items.len() == limit {
+                    last_key = Some(key);
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(Page {
+        items,
+        next_cursor: last_key,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_page() {
+        let db = Db::in_memory();
+        let tree = db.open_tree("test").unwrap();
+        for i in 0u8..10 {
+            tree.insert([i], [i % 2]).unwrap();
+        }
+        let even = |key: &[u8], value: &[u8]| Ok((value[0] == 0).then(|| key[0]));
+
+        let mut request = PageRequest {
+            limit: 2,
+            ..Default::default()
+        };
+        let first = read_page(&tree, &request, even).unwrap();
+        assert_eq!(first.items, [0, 2]);
+        assert_eq!(first.next_cursor, Some(vec![2]));
+
+        request.cursor = first.next_cursor;
+        let second = read_page(&tree, &request, even).unwrap();
+        assert_eq!(second.items, [4, 6]);
+
+        request.cursor = second.next_cursor;
+        let last = read_page(&tree, &request, even).unwrap();
+        assert_eq!(last.items, [8]);
+        assert_eq!(last.next_cursor, None);
+
+        let request = PageRequest {
+            limit: 2,
+            descending: true,
+            from: Some(vec![3]),
+            to: Some(vec![8]),
+            ..Default::default()
+        };
+        let page = read_page(&tree, &request, even).unwrap();
+        assert_eq!(page.items, [6, 4]);
+
+        let request = PageRequest {
+            cursor: page.next_cursor,
+            ..request
+        };
+        let page = read_page(&tree, &request, even).unwrap();
+        assert!(page.items.is_empty());
+        assert_eq!(page.next_cursor, None);
+    }
+}
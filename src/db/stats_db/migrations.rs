@@ -0,0 +1,64 @@
+use crate::db::{constants::*, named_trees, Migration, Version, VersionIterator};
+use crate::prelude::*;
+
+use super::{EthVotingStats, TonVotingStats};
+
+impl Migration for EthVotingStats {
+    const NAME: &'static str = "EthVotingStats";
+
+    fn get_breaking_versions() -> VersionIterator {
+        [(1, 2, 0)].into()
+    }
+
+    fn trees(_: &Db) -> Vec<(String, Vec<u8>)> {
+        named_trees(&[ETH_EVENT_VOTES, ETH_EVENT_VOTES_COUNTERS])
+    }
+
+    fn update(db: &Db, _: Version, new: Version) -> Result<(), Error> {
+        match new {
+            // votes counters were introduced
+            (1, 2, 0) => Self::new(db)?.rebuild_counters(),
+            _ => Ok(()),
+        }
+    }
+
+    fn downgrade(db: &Db, from: Version, _: Version) -> Result<(), Error> {
+        match from {
+            (1, 2, 0) => {
+                db.drop_tree(ETH_EVENT_VOTES_COUNTERS)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Migration for TonVotingStats {
+    const NAME: &'static str = "TonVotingStats";
+
+    fn get_breaking_versions() -> VersionIterator {
+        [(1, 2, 0)].into()
+    }
+
+    fn trees(_: &Db) -> Vec<(String, Vec<u8>)> {
+        named_trees(&[TON_EVENT_VOTES, TON_EVENT_VOTES_COUNTERS])
+    }
+
+    fn update(db: &Db, _: Version, new: Version) -> Result<(), Error> {
+        match new {
+            // votes counters were introduced
+            (1, 2, 0) => Self::new(db)?.rebuild_counters(),
+            _ => Ok(()),
+        }
+    }
+
+    fn downgrade(db: &Db, from: Version, _: Version) -> Result<(), Error> {
+        match from {
+            (1, 2, 0) => {
+                db.drop_tree(TON_EVENT_VOTES_COUNTERS)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
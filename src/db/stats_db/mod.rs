@@ -1,11 +1,15 @@
 use relay_models::models::{EthTxStatView, EventVote, TonTxStatView};
+use relay_storage::transaction;
 use relay_ton::contracts::Voting;
 
+use crate::config::VotingStatsRetentionSettings;
 use crate::models::*;
 use crate::prelude::*;
 
 use super::constants::*;
-use super::Table;
+use super::{read_page, Page, PageRequest, Table, VotesQueue};
+
+mod migrations;
 
 #[derive(Clone)]
 pub struct ScanningState {
@@ -40,12 +44,56 @@ impl ScanningState {
     }
 }
 
+impl ScanningState {
+    /// Validates raw entry of the scanning state
+    pub fn check_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() != 32 {
+            return Err(anyhow!("Invalid address length: {}", key.len()));
+        }
+        if value.len() != 8 {
+            return Err(anyhow!("Invalid lt length: {}", value.len()));
+        }
+        Ok(())
+    }
+}
+
+impl Table for ScanningState {
+    type Key = String;
+    type Value = u64;
+
+    fn dump_elements(&self) -> HashMap<Self::Key, Self::Value> {
+        self.latest_scanned_lt
+            .iter()
+            .filter_map(|x| match x {
+                Ok(a) => Some(a),
+                Err(e) => {
+                    log::error!("Failed getting scan state from db. Db corruption?: {}", e);
+                    None
+                }
+            })
+            .filter(|(_, value)| value.len() == 8)
+            .map(|(key, value)| {
+                let address = MsgAddrStd {
+                    anycast: None,
+                    workchain_id: 0,
+                    address: UInt256::from(key.as_slice()).into(),
+                };
+
+                let mut lt = [0; 8];
+                lt.copy_from_slice(&value);
+                (address.to_string(), u64::from_be_bytes(lt))
+            })
+            .collect()
+    }
+}
+
 pub type TonVotingStats = VotingStats<TonEventReceivedVoteWithData>;
 
 impl TonVotingStats {
     pub fn new(db: &Db) -> Result<Self, Error> {
         Ok(Self {
             tree: db.open_tree(TON_EVENT_VOTES)?,
+            counters: db.open_tree(TON_EVENT_VOTES_COUNTERS)?,
             _marker: Default::default(),
         })
     }
@@ -57,6 +105,7 @@ impl EthVotingStats {
     pub fn new(db: &Db) -> Result<Self, Error> {
         Ok(Self {
             tree: db.open_tree(ETH_EVENT_VOTES)?,
+            counters: db.open_tree(ETH_EVENT_VOTES_COUNTERS)?,
             _marker: Default::default(),
         })
     }
@@ -65,6 +114,8 @@ impl EthVotingStats {
 #[derive(Clone)]
 pub struct VotingStats<T> {
     tree: Tree,
+    /// Amount of votes for each relay and configuration. Not decreased on compaction
+    counters: Tree,
     _marker: std::marker::PhantomData<T>,
 }
 
@@ -74,7 +125,30 @@ where
 {
     pub async fn insert_vote(&self, event: &T) -> Result<(), Error> {
         log::debug!("Inserting stats");
+        self.insert_vote_and_remove(event, None)
+    }
+
+    /// Inserts our own vote and removes its event from the votes queue in one transaction
+    pub async fn insert_own_vote<E>(
+        &self,
+        event: &T,
+        votes_queue: &VotesQueue<E>,
+    ) -> Result<(), Error>
+    where
+        E: BorshSerialize + BorshDeserialize,
+    {
+        log::debug!("Inserting own vote stats");
+        self.insert_vote_and_remove(
+            event,
+            Some(votes_queue.locate(event.info().event_address())),
+        )
+    }
 
+    fn insert_vote_and_remove(
+        &self,
+        event: &T,
+        queued: Option<([&Tree; 4], Vec<u8>)>,
+    ) -> Result<(), Error> {
         let info = event.info();
 
         let event_addr = info.event_address().address.get_bytestring(0);
@@ -86,11 +160,36 @@ where
         key[64] = (info.kind() == Voting::Confirm) as u8;
 
         let stored = event.get_stored_data();
-        self.tree
-            .insert(key, stored.try_to_vec().expect("Fatal db error"))?;
+        let value = stored.try_to_vec().expect("Fatal db error");
+        let counter_key = make_counter_key(&relay_addr, stored.configuration_id());
+
+        let mut trees = vec![&self.tree, &self.counters];
+        if let Some((queue_trees, _)) = &queued {
+            trees.extend_from_slice(queue_trees);
+        }
+
+        transaction(&trees, |trees| {
+            let (tree, counters) = (trees[0], trees[1]);
+            if tree.insert(&key, &value)?.is_none() {
+                let count = counters
+                    .get(&counter_key)?
+                    .map(parse_counter)
+                    .unwrap_or_default();
+                counters.insert(&counter_key, &(count + 1).to_le_bytes())?;
+            }
+            if let Some((_, queue_key)) = &queued {
+                for tree in &trees[2..] {
+                    tree.remove(queue_key)?;
+                }
+            }
+            Ok(())
+        })?;
 
         #[cfg(feature = "paranoid")]
-        self.tree.flush()?;
+        {
+            self.tree.flush()?;
+            self.counters.flush()?;
+        }
         Ok(())
     }
 
@@ -106,23 +205,163 @@ where
     }
 
     pub fn count_votes(&self, configuration_id: u32, relay_addr: &MsgAddrStd) -> usize {
-        let relay_addr = relay_addr.address.get_bytestring(0);
-        self.tree
-            .iter()
-            .filter_map(|item| {
-                let (key, value) = item.ok()?;
-                if relay_addr != key[32..64] {
-                    return None;
+        let key = make_counter_key(&relay_addr.address.get_bytestring(0), configuration_id);
+        match self.counters.get(key) {
+            Ok(value) => value.map(parse_counter).unwrap_or_default() as usize,
+            Err(e) => {
+                log::error!("Failed getting votes counter. Db corruption?: {}", e);
+                0
+            }
+        }
+    }
+
+    /// Removes votes which are out of retention limits of their configuration.
+    /// Votes of `own_relay` are kept, because they are used by `has_already_voted`.
+    /// Returns the amount of removed votes
+    pub fn compact(
+        &self,
+        rules: &VotingStatsRetentionSettings,
+        own_relay: &MsgAddrStd,
+        now: i64,
+    ) -> Result<usize, Error> {
+        let own_relay = own_relay.address.get_bytestring(0);
+
+        let mut configurations: HashMap<u32, Vec<(i64, Vec<u8>)>> = HashMap::new();
+        for item in self.tree.iter() {
+            let (key, value) = item?;
+            if key.len() != 65 {
+                log::error!(
+                    "Invalid vote stats key length: {}. Db corruption?",
+                    key.len()
+                );
+                continue;
+            }
+            if key[32..64] == own_relay[..] {
+                continue;
+            }
+            let stored =
+                match <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(&value) {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        log::error!("Failed to decode vote stats. Db corruption?: {}", e);
+                        continue;
+                    }
                 };
+            configurations
+                .entry(stored.configuration_id())
+                .or_insert_with(Vec::new)
+                .push((stored.met(), key));
+        }
 
-                let stored =
-                    <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(&value)
-                        .ok()?;
+        let mut batch = relay_storage::Batch::default();
+        let mut removed = 0;
+        for (configuration_id, mut votes) in configurations {
+            let (max_age, max_count) = rules.configuration_rules(configuration_id);
+
+            // Newest first
+            votes.sort_unstable_by(|(left, _), (right, _)| right.cmp(left));
+
+            let kept = match max_age {
+                Some(max_age) => {
+                    let oldest = now - max_age.as_secs() as i64;
+                    votes.iter().take_while(|(met, _)| *met >= oldest).count()
+                }
+                None => votes.len(),
+            };
+            let kept = max_count.map(|count| kept.min(count)).unwrap_or(kept);
+
+            for (_, key) in votes.drain(kept..) {
+                batch.remove(key);
+                removed += 1;
+            }
+        }
+
+        if removed > 0 {
+            self.tree.apply_batch(batch)?;
+        }
+        Ok(removed)
+    }
+
+    /// Recalculates votes counters from the stored votes
+    fn rebuild_counters(&self) -> Result<(), Error> {
+        let mut counters: HashMap<Vec<u8>, u64> = HashMap::new();
+        for item in self.tree.iter() {
+            let (key, value) = item?;
+            let stored =
+                <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(&value)?;
+            *counters
+                .entry(make_counter_key(&key[32..64], stored.configuration_id()))
+                .or_default() += 1;
+        }
+
+        let mut batch = relay_storage::Batch::default();
+        for (key, count) in counters {
+            batch.insert(key, &count.to_le_bytes());
+        }
 
-                ((&stored as &dyn StoredStat).configuration_id() == configuration_id).then(|| ())
+        self.counters.clear()?;
+        self.counters.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+impl<T> VotingStats<T>
+where
+    T: GetStoredData,
+{
+    /// Returns votes ordered by the event address
+    pub fn get_page<F>(&self, request: &PageRequest, mut filter: F) -> Result<Page<T::View>, Error>
+    where
+        F: FnMut(&MsgAddrStd, Voting, &T::Stored) -> bool,
+    {
+        read_page(&self.tree, request, |key, value| {
+            let (event_addr, relay, vote) = parse_vote_key(key);
+            let stored = <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(value)?;
+            Ok(if filter(&relay, vote, &stored) {
+                Some(T::create_view(&event_addr, &relay, vote, stored))
+            } else {
+                None
             })
-            .count()
+        })
     }
+
+    /// Validates raw entry of the votes tree
+    pub fn check_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() != 65 {
+            return Err(anyhow!("Invalid key length: {}", key.len()));
+        }
+        if key[64] > 1 {
+            return Err(anyhow!("Invalid vote: {}", key[64]));
+        }
+        <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(value)?;
+        Ok(())
+    }
+
+    /// Validates raw entry of the votes counters tree
+    pub fn check_counter_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() != 36 {
+            return Err(anyhow!("Invalid key length: {}", key.len()));
+        }
+        if value.len() != 8 {
+            return Err(anyhow!("Invalid counter length: {}", value.len()));
+        }
+        Ok(())
+    }
+}
+
+fn make_counter_key(relay_addr: &[u8], configuration_id: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(36);
+    key.extend_from_slice(relay_addr);
+    key.extend_from_slice(&configuration_id.to_be_bytes());
+    key
+}
+
+fn parse_counter(value: Vec<u8>) -> u64 {
+    let mut buffer = [0; 8];
+    if value.len() == 8 {
+        buffer.copy_from_slice(&value);
+    }
+    u64::from_le_bytes(buffer)
 }
 
 impl<T> Table for VotingStats<T>
@@ -143,23 +382,7 @@ where
                 }
             })
             .fold(HashMap::new(), |mut result, (key, value)| {
-                let event_addr = MsgAddrStd {
-                    anycast: None,
-                    workchain_id: 0,
-                    address: UInt256::from(&key[0..32]).into(),
-                };
-
-                let relay = MsgAddrStd {
-                    anycast: None,
-                    workchain_id: 0,
-                    address: UInt256::from(&key[32..64]).into(),
-                };
-
-                let vote = if key[64] == 0 {
-                    Voting::Reject
-                } else {
-                    Voting::Confirm
-                };
+                let (event_addr, relay, vote) = parse_vote_key(&key);
 
                 let stored =
                     <<T as GetStoredData>::Stored as BorshDeserialize>::try_from_slice(&value)
@@ -168,15 +391,45 @@ where
                 result
                     .entry(relay.to_string())
                     .or_insert_with(Vec::new)
-                    .push(<T as GetStoredData>::create_view(&event_addr, vote, stored));
+                    .push(<T as GetStoredData>::create_view(
+                        &event_addr,
+                        &relay,
+                        vote,
+                        stored,
+                    ));
 
                 result
             })
     }
 }
 
+fn parse_vote_key(key: &[u8]) -> (MsgAddrStd, MsgAddrStd, Voting) {
+    let event_addr = MsgAddrStd {
+        anycast: None,
+        workchain_id: 0,
+        address: UInt256::from(&key[0..32]).into(),
+    };
+
+    let relay = MsgAddrStd {
+        anycast: None,
+        workchain_id: 0,
+        address: UInt256::from(&key[32..64]).into(),
+    };
+
+    let vote = if key[64] == 0 {
+        Voting::Reject
+    } else {
+        Voting::Confirm
+    };
+
+    (event_addr, relay, vote)
+}
+
 pub trait StoredStat {
     fn configuration_id(&self) -> u32;
+
+    /// Timestamp when the vote was received
+    fn met(&self) -> i64;
 }
 
 pub trait GetStoredData {
@@ -185,7 +438,12 @@ pub trait GetStoredData {
 
     fn get_stored_data(&self) -> Self::Stored;
 
-    fn create_view(event_addr: &MsgAddrStd, vote: Voting, stored: Self::Stored) -> Self::View;
+    fn create_view(
+        event_addr: &MsgAddrStd,
+        relay_addr: &MsgAddrStd,
+        vote: Voting,
+        stored: Self::Stored,
+    ) -> Self::View;
 }
 
 impl GetStoredData for EthEventReceivedVoteWithData {
@@ -202,12 +460,18 @@ impl GetStoredData for EthEventReceivedVoteWithData {
     }
 
     #[inline]
-    fn create_view(event_addr: &MsgAddrStd, vote: Voting, stored: Self::Stored) -> Self::View {
+    fn create_view(
+        event_addr: &MsgAddrStd,
+        relay_addr: &MsgAddrStd,
+        vote: Voting,
+        stored: Self::Stored,
+    ) -> Self::View {
         EthTxStatView {
             configuration_id: stored.configuration_id(),
             tx_hash: hex::encode(stored.tx_hash),
             met: stored.met.to_string(),
             event_addr: event_addr.to_string(),
+            relay_addr: relay_addr.to_string(),
             vote: vote.into_view(),
         }
     }
@@ -228,13 +492,19 @@ impl GetStoredData for TonEventReceivedVoteWithData {
     }
 
     #[inline]
-    fn create_view(event_addr: &MsgAddrStd, vote: Voting, stored: Self::Stored) -> Self::View {
+    fn create_view(
+        event_addr: &MsgAddrStd,
+        relay_addr: &MsgAddrStd,
+        vote: Voting,
+        stored: Self::Stored,
+    ) -> Self::View {
         TonTxStatView {
             configuration_id: stored.configuration_id(),
             tx_hash: hex::encode(stored.tx_hash.as_slice()),
             tx_lt: stored.tx_lt.to_string(),
             met: stored.met.to_string(),
             event_addr: event_addr.to_string(),
+            relay_addr: relay_addr.to_string(),
             vote: vote.into_view(),
         }
     }
@@ -251,6 +521,10 @@ impl StoredStat for EthStoredTxStat {
     fn configuration_id(&self) -> u32 {
         self.configuration_id
     }
+
+    fn met(&self) -> i64 {
+        self.met
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -265,6 +539,10 @@ impl StoredStat for TonStoredTxStat {
     fn configuration_id(&self) -> u32 {
         self.configuration_id
     }
+
+    fn met(&self) -> i64 {
+        self.met
+    }
 }
 
 impl IntoView for Voting {
@@ -277,3 +555,72 @@ impl IntoView for Voting {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigurationRetentionSettings;
+
+    fn address(byte: u8) -> MsgAddrStd {
+        MsgAddrStd::with_address(None, 0, UInt256::from([byte; 32]).into())
+    }
+
+    fn insert_stat(stats: &EthVotingStats, relay: u8, event: u8, configuration_id: u32, met: i64) {
+        let mut key = [0; 65];
+        key[0..32].copy_from_slice(&[event; 32]);
+        key[32..64].copy_from_slice(&[relay; 32]);
+        let stored = EthStoredTxStat {
+            configuration_id,
+            tx_hash: Vec::new(),
+            met,
+        };
+        stats
+            .tree
+            .insert(key, stored.try_to_vec().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_compact() {
+        let db = Db::in_memory();
+        let stats = EthVotingStats::new(&db).unwrap();
+        for event in 0..5 {
+            insert_stat(&stats, 1, event, 1, 100 + event as i64);
+            insert_stat(&stats, 1, 10 + event, 2, 100 + event as i64);
+        }
+        stats.rebuild_counters().unwrap();
+
+        let rules = VotingStatsRetentionSettings {
+            default_max_count: Some(3),
+            configurations: vec![ConfigurationRetentionSettings {
+                configuration_id: 2,
+                max_age: Some(Duration::from_secs(3)),
+                max_count: None,
+            }],
+            ..Default::default()
+        };
+
+        // Own votes are never removed
+        insert_stat(&stats, 0, 20, 1, 0);
+        // Corrupted entries are skipped
+        stats.tree.insert([30; 16], []).unwrap();
+
+        // config 1 keeps 3 newest, config 2 keeps votes met at 102..=104, limited to 3
+        assert_eq!(stats.compact(&rules, &address(0), 105).unwrap(), 4);
+        let mut remaining: Vec<_> = stats
+            .tree
+            .iter()
+            .keys()
+            .map(|key| key.unwrap()[0])
+            .collect();
+        remaining.sort_unstable();
+        assert_eq!(remaining, [2, 3, 4, 12, 13, 14, 20, 30]);
+        assert!(stats.has_already_voted(&address(20), &address(0)).unwrap());
+
+        // Counters are not affected by compaction
+        let relay = address(1);
+        assert_eq!(stats.count_votes(1, &relay), 5);
+        assert_eq!(stats.count_votes(2, &relay), 5);
+        assert_eq!(stats.count_votes(3, &relay), 0);
+    }
+}
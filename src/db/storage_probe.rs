@@ -0,0 +1,55 @@
+use super::constants::*;
+use crate::prelude::*;
+
+const PROBE_KEY: &[u8] = b"probe";
+
+/// Scratch tree, which is written to check, that the storage accepts writes
+#[derive(Clone)]
+pub struct StorageProbe {
+    tree: Tree,
+}
+
+impl StorageProbe {
+    pub fn new(db: &Db) -> Result<Self, Error> {
+        Ok(Self {
+            tree: db.open_tree(STORAGE_PROBE)?,
+        })
+    }
+
+    /// Writes the probe entry, reads it back and removes it. Changes are flushed to the disk
+    pub fn check(&self) -> Result<(), Error> {
+        let value = chrono::Utc::now().timestamp_nanos().to_be_bytes();
+        self.tree.insert(PROBE_KEY, value)?;
+        let stored = self.tree.get(PROBE_KEY)?;
+        self.tree.remove(PROBE_KEY)?;
+        self.tree.flush()?;
+
+        match stored {
+            Some(stored) if stored == value => Ok(()),
+            _ => Err(anyhow!("Probe entry was not stored")),
+        }
+    }
+
+    /// Validates raw entry of the probe tree. It can only remain after the interrupted check
+    pub fn check_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        match (key, value.len()) {
+            (PROBE_KEY, 8) => Ok(()),
+            _ => Err(anyhow!("Invalid probe entry")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let db = Db::in_memory();
+        let probe = StorageProbe::new(&db).unwrap();
+
+        probe.check().unwrap();
+        probe.check().unwrap();
+        assert!(db.open_tree(STORAGE_PROBE).unwrap().is_empty());
+    }
+}
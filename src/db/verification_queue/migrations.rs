@@ -1,11 +1,18 @@
-use crate::db::{constants::*, Migration, Version, VersionIterator};
+use crate::db::{constants::*, named_trees, Migration, Version, VersionIterator};
 use crate::prelude::*;
 
 use super::{EthVerificationQueue, TonVerificationQueue};
 
+/// Length of the big-endian target key, which is followed by the external flag
+const TARGET_KEY_LEN: usize = 8;
+
 impl Migration for EthVerificationQueue {
     const NAME: &'static str = "EthVerificationQueue";
 
+    fn trees(_: &Db) -> Vec<(String, Vec<u8>)> {
+        named_trees(&[ETH_QUEUE])
+    }
+
     fn get_breaking_versions() -> VersionIterator {
         [(1, 0, 1)].into()
     }
@@ -15,21 +22,19 @@ impl Migration for EthVerificationQueue {
 
         match new {
             (1, 0, 1) => {
-                let mut batch = sled::Batch::default();
-
-                for item in queue.db.iter() {
-                    let (key, _) = item?;
-
-                    let mut new_key = Vec::with_capacity(key.len() + 1);
-                    new_key.extend(&key[0..8]);
-                    new_key.push(1); // mark all known transactions in queue as external on migration
-                    new_key.extend(&key[8..]);
+                queue.db.apply_batch(add_external_flag(&queue.db)?)?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-                    batch.remove(key);
-                    batch.insert(new_key, &[]);
-                }
+    fn downgrade(db: &Db, from: Version, _: Version) -> Result<(), Error> {
+        let queue = Self::new(db)?;
 
-                queue.db.apply_batch(batch)?;
+        match from {
+            (1, 0, 1) => {
+                queue.db.apply_batch(remove_external_flag(&queue.db)?)?;
                 Ok(())
             }
             _ => Ok(()),
@@ -37,36 +42,87 @@ impl Migration for EthVerificationQueue {
     }
 }
 
+/// Each configuration has its own queue tree
 impl Migration for TonVerificationQueue {
     const NAME: &'static str = "TonVerificationQueue";
 
+    fn trees(db: &Db) -> Vec<(String, Vec<u8>)> {
+        Self::configuration_ids(db)
+            .into_iter()
+            .map(|id| (format!("{}[{}]", TON_QUEUE, id), Self::tree_name(id)))
+            .collect()
+    }
+
     fn get_breaking_versions() -> VersionIterator {
         [(1, 0, 1)].into()
     }
 
     fn update(db: &Db, _: Version, new: Version) -> Result<(), Error> {
-        let tree = db.open_tree(TON_QUEUE)?;
-
         match new {
             (1, 0, 1) => {
-                let mut batch = sled::Batch::default();
-
-                for item in tree.iter() {
-                    let (key, _) = item?;
-
-                    let mut new_key = Vec::with_capacity(key.len() + 1);
-                    new_key.extend(&key[0..12]);
-                    new_key.push(1); // mark all known transactions in queue as external on migration
-                    new_key.extend(&key[12..]);
-
-                    batch.remove(key);
-                    batch.insert(new_key, &[]);
+                for configuration_id in Self::configuration_ids(db) {
+                    let queue = Self::new(db, configuration_id)?;
+                    queue.db.apply_batch(add_external_flag(&queue.db)?)?;
                 }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-                tree.apply_batch(batch)?;
+    fn downgrade(db: &Db, from: Version, _: Version) -> Result<(), Error> {
+        match from {
+            (1, 0, 1) => {
+                for configuration_id in Self::configuration_ids(db) {
+                    let queue = Self::new(db, configuration_id)?;
+                    queue.db.apply_batch(remove_external_flag(&queue.db)?)?;
+                }
                 Ok(())
             }
             _ => Ok(()),
         }
     }
 }
+
+/// Inserts the external flag after the target key
+fn add_external_flag(tree: &Tree) -> Result<relay_storage::Batch, Error> {
+    let mut batch = relay_storage::Batch::default();
+
+    for item in tree.iter() {
+        let (key, _) = item?;
+        if key.len() < TARGET_KEY_LEN {
+            continue;
+        }
+
+        let mut new_key = Vec::with_capacity(key.len() + 1);
+        new_key.extend(&key[0..TARGET_KEY_LEN]);
+        new_key.push(1); // mark all known transactions in queue as external on migration
+        new_key.extend(&key[TARGET_KEY_LEN..]);
+
+        batch.remove(key);
+        batch.insert(new_key, &[]);
+    }
+
+    Ok(batch)
+}
+
+/// Restores keys layout before the external flag was introduced
+fn remove_external_flag(tree: &Tree) -> Result<relay_storage::Batch, Error> {
+    let mut batch = relay_storage::Batch::default();
+
+    for item in tree.iter() {
+        let (key, _) = item?;
+        if key.len() <= TARGET_KEY_LEN {
+            continue;
+        }
+
+        let mut old_key = Vec::with_capacity(key.len() - 1);
+        old_key.extend(&key[0..TARGET_KEY_LEN]);
+        old_key.extend(&key[TARGET_KEY_LEN + 1..]);
+
+        batch.remove(key);
+        batch.insert(old_key, &[]);
+    }
+
+    Ok(batch)
+}
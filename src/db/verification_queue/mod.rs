@@ -1,14 +1,18 @@
 mod migrations;
 
+use relay_storage::{Entry, StorageResult};
 use relay_ton::contracts::EthEventVoteData;
 
 use super::constants::*;
-use crate::db::Table;
+use crate::db::{read_page, Page, PageRequest, Table};
 use crate::models::*;
 use crate::prelude::*;
 
 const RANGE_LOWER_BOUND: [u8; 8] = [0; 8];
 
+/// Value of the entry, which is being processed
+const CLAIMED: [u8; 1] = [1];
+
 pub type EthVerificationQueue = VerificationQueue<EthEventVoteData>;
 pub type TonVerificationQueue = VerificationQueue<SignedTonEventVoteData>;
 
@@ -24,18 +28,43 @@ impl EthVerificationQueue {
 
 impl TonVerificationQueue {
     pub fn new(db: &Db, configuration_id: u32) -> Result<Self, Error> {
-        const COMMON_PREFIX_LEN: usize = TON_QUEUE.as_bytes().len();
-
-        let mut prefix = [0u8; COMMON_PREFIX_LEN + std::mem::size_of::<u32>()];
-        prefix[..COMMON_PREFIX_LEN].copy_from_slice(&TON_QUEUE.as_bytes());
-        prefix[COMMON_PREFIX_LEN..].copy_from_slice(&configuration_id.to_le_bytes());
-
         Ok(Self {
-            db: db.open_tree(prefix)?,
+            db: db.open_tree(Self::tree_name(configuration_id))?,
             guard: Arc::new(Default::default()),
             _marker: Default::default(),
         })
     }
+
+    /// Name of the queue tree of the configuration
+    pub fn tree_name(configuration_id: u32) -> Vec<u8> {
+        let mut name = Vec::with_capacity(TON_QUEUE.len() + std::mem::size_of::<u32>());
+        name.extend_from_slice(TON_QUEUE.as_bytes());
+        name.extend_from_slice(&configuration_id.to_le_bytes());
+        name
+    }
+
+    /// Returns ids of configurations which have their queue in db
+    pub fn configuration_ids(db: &Db) -> Vec<u32> {
+        db.tree_names()
+            .into_iter()
+            .filter_map(|name| Self::parse_tree_name(&name))
+            .collect()
+    }
+
+    /// Returns configuration id if the tree belongs to the queue
+    pub fn parse_tree_name(name: &[u8]) -> Option<u32> {
+        const COMMON_PREFIX_LEN: usize = TON_QUEUE.as_bytes().len();
+
+        if name.len() != COMMON_PREFIX_LEN + std::mem::size_of::<u32>()
+            || !name.starts_with(TON_QUEUE.as_bytes())
+        {
+            return None;
+        }
+
+        let mut configuration_id = [0; 4];
+        configuration_id.copy_from_slice(&name[COMMON_PREFIX_LEN..]);
+        Some(u32::from_le_bytes(configuration_id))
+    }
 }
 
 #[derive(Clone)]
@@ -52,17 +81,12 @@ where
     pub async fn range_before(
         &self,
         key: u64,
-    ) -> VerificationQueueLock<'_, impl Iterator<Item = (sled::IVec, T)>, T> {
+    ) -> VerificationQueueLock<'_, impl Iterator<Item = (Vec<u8>, T)>, T> {
         let guard = self.guard.lock().await;
         let results = self
             .db
             .range(RANGE_LOWER_BOUND..=((key + 1).to_be_bytes()))
-            .keys()
-            .filter_map(|key| {
-                let key = key.ok()?;
-                let value: T = BorshDeserialize::deserialize(&mut &key[9..]).ok()?;
-                Some((key, value))
-            });
+            .filter_map(parse_waiting_entry);
 
         VerificationQueueLock {
             results,
@@ -74,13 +98,12 @@ where
     pub async fn range_after(
         &self,
         key: u64,
-    ) -> VerificationQueueLock<'_, impl Iterator<Item = (sled::IVec, T)>, T> {
+    ) -> VerificationQueueLock<'_, impl Iterator<Item = (Vec<u8>, T)>, T> {
         let guard = self.guard.lock().await;
-        let results = self.db.range(key.to_be_bytes()..).keys().filter_map(|key| {
-            let key = key.ok()?;
-            let value: T = BorshDeserialize::deserialize(&mut &key[9..]).ok()?;
-            Some((key, value))
-        });
+        let results = self
+            .db
+            .range(key.to_be_bytes()..)
+            .filter_map(parse_waiting_entry);
 
         VerificationQueueLock {
             results,
@@ -97,13 +120,85 @@ where
         Ok(())
     }
 
+    /// Returns claimed entries back to the queue. Must be called on start,
+    /// because their processing was interrupted
+    pub fn release_claims(&self) -> Result<usize, Error> {
+        let mut batch = relay_storage::Batch::default();
+        let mut released = 0;
+        for item in self.db.iter() {
+            let (key, value) = item?;
+            if !value.is_empty() {
+                batch.insert(key, &[]);
+                released += 1;
+            }
+        }
+        self.db.apply_batch(batch)?;
+        Ok(released)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.db.is_empty()
     }
 
+    /// Validates raw entry. The whole item is stored in the key, see `make_key`
+    pub fn check_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if key.len() < 9 {
+            return Err(anyhow!("Key is too short: {}", key.len()));
+        }
+        if key[8] > 1 {
+            return Err(anyhow!("Invalid external flag: {}", key[8]));
+        }
+        if !value.is_empty() && value != CLAIMED {
+            return Err(anyhow!("Unexpected value"));
+        }
+        T::try_from_slice(&key[9..])?;
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         self.db.len()
     }
+
+    /// Returns entries ordered by the target key with their external flag.
+    /// Request bounds are the big-endian target keys
+    pub fn get_page<F>(
+        &self,
+        request: &PageRequest,
+        mut filter: F,
+    ) -> Result<Page<(u64, bool, T)>, Error>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        read_page(&self.db, request, |key, _| {
+            let (target_key, external, value) = parse_key::<T>(key)?;
+            Ok(filter(&value).then(|| (target_key, external, value)))
+        })
+    }
+}
+
+fn parse_key<T>(key: &[u8]) -> Result<(u64, bool, T), Error>
+where
+    T: BorshDeserialize,
+{
+    if key.len() < 9 {
+        return Err(anyhow!("Key is too short: {}", key.len()));
+    }
+    let mut target_key = [0; 8];
+    target_key.copy_from_slice(&key[0..8]);
+    let value = T::try_from_slice(&key[9..])?;
+    Ok((u64::from_be_bytes(target_key), key[8] != 0, value))
+}
+
+fn parse_waiting_entry<T>(item: StorageResult<Entry>) -> Option<(Vec<u8>, T)>
+where
+    T: BorshDeserialize,
+{
+    let (key, claimed) = item.ok()?;
+    if !claimed.is_empty() {
+        return None;
+    }
+    let value: T = BorshDeserialize::deserialize(&mut &key[9..]).ok()?;
+    Some((key, value))
 }
 
 #[inline]
@@ -162,7 +257,7 @@ pub struct VerificationQueueLock<'a, I, T> {
 
 impl<'a, I, T> Iterator for VerificationQueueLock<'a, I, T>
 where
-    I: Iterator<Item = (sled::IVec, T)>,
+    I: Iterator<Item = (Vec<u8>, T)>,
     T: BorshSerialize + BorshDeserialize,
 {
     type Item = (VerificationQueueLockEntry<'a, T>, T);
@@ -185,7 +280,7 @@ where
 }
 
 pub struct VerificationQueueLockEntry<'a, T> {
-    key: sled::IVec,
+    key: Vec<u8>,
     queue: &'a VerificationQueue<T>,
 }
 
@@ -206,4 +301,100 @@ impl<'a, T> VerificationQueueLockEntry<'a, T> {
         self.queue.db.flush()?;
         Ok(())
     }
+
+    /// Marks the entry as being processed. It is skipped by the queue, but stays in db
+    /// until the claim is completed, so that it will be processed again after restart
+    pub fn claim(self) -> Result<VerificationClaim, Error> {
+        self.queue.db.insert(&self.key, &CLAIMED)?;
+        #[cfg(feature = "paranoid")]
+        self.queue.db.flush()?;
+        Ok(VerificationClaim {
+            tree: self.queue.db.clone(),
+            key: self.key,
+        })
+    }
+}
+
+/// Verification queue entry, which is being processed
+pub struct VerificationClaim {
+    tree: Tree,
+    key: Vec<u8>,
+}
+
+impl VerificationClaim {
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Removes the entry, when the event requires no further processing
+    pub fn complete(self) -> Result<(), Error> {
+        self.tree.remove(&self.key)?;
+        #[cfg(feature = "paranoid")]
+        self.tree.flush()?;
+        Ok(())
+    }
+
+    /// Returns the entry back to the queue, when its processing has failed
+    pub fn release(self) -> Result<(), Error> {
+        self.tree.insert(&self.key, &[])?;
+        #[cfg(feature = "paranoid")]
+        self.tree.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(queue: &VerificationQueue<u32>) -> Vec<u64> {
+        futures::executor::block_on(queue.range_before(100))
+            .map(|(entry, _)| entry.key())
+            .collect()
+    }
+
+    #[test]
+    fn test_claims() {
+        let db = Db::in_memory();
+        let queue = VerificationQueue::<u32> {
+            db: db.open_tree("test").unwrap(),
+            guard: Arc::new(Default::default()),
+            _marker: Default::default(),
+        };
+
+        futures::executor::block_on(async {
+            queue.insert(1, true, &10).await.unwrap();
+            queue.insert(2, false, &20).await.unwrap();
+        });
+
+        let (entry, value) = futures::executor::block_on(queue.range_before(100))
+            .next()
+            .unwrap();
+        assert_eq!(value, 10);
+        let claim = entry.claim().unwrap();
+
+        // Claimed entry is skipped, but stays in db
+        assert_eq!(keys(&queue), [2]);
+        assert_eq!(queue.len(), 2);
+
+        // Interrupted processing is restarted
+        assert_eq!(queue.release_claims().unwrap(), 1);
+        assert_eq!(keys(&queue), [1, 2]);
+
+        claim.complete().unwrap();
+        assert_eq!(keys(&queue), [2]);
+
+        // Failed processing is retried without restart
+        let (entry, _) = futures::executor::block_on(queue.range_before(100))
+            .next()
+            .unwrap();
+        let claim = entry.claim().unwrap();
+        assert!(keys(&queue).is_empty());
+        claim.release().unwrap();
+        assert_eq!(keys(&queue), [2]);
+    }
 }
@@ -1,7 +1,9 @@
-use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult};
-use sled::Transactional;
+use relay_models::models::{VoteAttemptView, VoteRetryHistoryView};
+use relay_storage::{transaction, TransactionError};
 
 use super::constants::*;
+use super::{read_page, Page, PageRequest, Table, VerificationClaim};
+use crate::config::FailedVotesRetrySettings;
 use crate::models::*;
 use crate::prelude::*;
 
@@ -28,6 +30,8 @@ impl TonEventVotesQueue {
         Ok(Self {
             pending: db.open_tree(TON_EVENTS_QUEUE_PENDING)?,
             failed: db.open_tree(TON_EVENTS_QUEUE_FAILED)?,
+            dead: db.open_tree(TON_EVENTS_QUEUE_DEAD)?,
+            history: db.open_tree(TON_EVENTS_QUEUE_HISTORY)?,
             _marker: Default::default(),
         })
     }
@@ -40,6 +44,8 @@ impl EthEventVotesQueue {
         Ok(Self {
             pending: db.open_tree(ETH_EVENTS_QUEUE_PENDING)?,
             failed: db.open_tree(ETH_EVENTS_QUEUE_FAILED)?,
+            dead: db.open_tree(ETH_EVENTS_QUEUE_DEAD)?,
+            history: db.open_tree(ETH_EVENTS_QUEUE_HISTORY)?,
             _marker: Default::default(),
         })
     }
@@ -49,6 +55,8 @@ impl EthEventVotesQueue {
 pub struct VotesQueue<T> {
     pending: Tree,
     failed: Tree,
+    dead: Tree,
+    history: Tree,
     _marker: std::marker::PhantomData<T>,
 }
 
@@ -56,94 +64,189 @@ impl<T> VotesQueue<T>
 where
     T: BorshSerialize + BorshDeserialize,
 {
-    pub fn insert_pending(&self, event_address: &MsgAddrStd, data: &T) -> Result<(), Error> {
+    /// Inserts the transaction into the pending queue. Claimed verification queue entry
+    /// is removed in the same transaction. Retry history of the dead transaction is reset,
+    /// so that it gets the full set of attempts again
+    pub fn insert_pending(
+        &self,
+        event_address: &MsgAddrStd,
+        data: &T,
+        claim: Option<VerificationClaim>,
+    ) -> Result<(), Error> {
         let key = make_key(event_address);
 
-        (&self.pending, &self.failed).transaction(|(pending, failed)| {
-            failed.remove(key.clone())?;
-            pending.insert(key.clone(), data.try_to_vec().expect("Shouldn't fail"))?;
-            ConflictableTransactionResult::<(), std::io::Error>::Ok(())
+        let data = data.try_to_vec().expect("Shouldn't fail");
+
+        let mut trees = vec![&self.pending, &self.failed, &self.dead, &self.history];
+        if let Some(claim) = &claim {
+            trees.push(claim.tree());
+        }
+
+        transaction(&trees, |trees| {
+            let (pending, failed, dead, history) = (trees[0], trees[1], trees[2], trees[3]);
+            failed.remove(&key)?;
+            if dead.remove(&key)?.is_some() {
+                history.remove(&key)?;
+            }
+            pending.insert(&key, &data)?;
+            if let Some(claim) = &claim {
+                trees[4].remove(claim.key())?;
+            }
+            Ok(())
         })?;
 
         #[cfg(feature = "paranoid")]
         {
             self.failed.flush()?;
+            self.dead.flush()?;
             self.pending.flush()?;
         }
 
         Ok(())
     }
 
+    /// Trees, which may contain the transaction, and its key in them.
+    /// Used to remove the transaction together with changes in other tables
+    pub fn locate(&self, event_address: &MsgAddrStd) -> ([&Tree; 4], Vec<u8>) {
+        (
+            [&self.pending, &self.failed, &self.dead, &self.history],
+            make_key(event_address),
+        )
+    }
+
     pub fn mark_complete(&self, event_address: &MsgAddrStd) -> Result<(), Error> {
         let key = make_key(event_address);
 
-        (&self.pending, &self.failed).transaction(|(pending, failed)| {
-            pending.remove(key.clone())?;
-            failed.remove(key.clone())?;
-            ConflictableTransactionResult::<(), std::io::Error>::Ok(())
-        })?;
+        transaction(
+            &[&self.pending, &self.failed, &self.dead, &self.history],
+            |trees| {
+                for tree in trees {
+                    tree.remove(&key)?;
+                }
+                Ok(())
+            },
+        )?;
 
         Ok(())
     }
 
-    pub fn mark_failed(&self, event_address: &MsgAddrStd) -> Result<(), Error> {
+    /// Moves transaction from pending to failed queue and records the attempt
+    pub fn mark_failed(&self, event_address: &MsgAddrStd, error: &str) -> Result<(), Error> {
         let key = make_key(event_address);
+        let now = chrono::Utc::now().timestamp();
 
-        (&self.pending, &self.failed)
-            .transaction(|(pending, failed)| match pending.remove(key.clone())? {
+        transaction(&[&self.pending, &self.failed, &self.history], |trees| {
+            let (pending, failed, history) = (trees[0], trees[1], trees[2]);
+            match pending.remove(&key)? {
                 Some(transaction) => {
-                    failed.insert(key.clone(), transaction)?;
+                    failed.insert(&key, &transaction)?;
+
+                    let mut entry = history
+                        .get(&key)?
+                        .and_then(|value| VoteRetryHistory::try_from_slice(&value).ok())
+                        .unwrap_or_else(|| VoteRetryHistory::new(now));
+                    entry.attempts.push(VoteAttempt {
+                        failed_at: now,
+                        error: error.to_owned(),
+                    });
+                    let entry = entry.try_to_vec().expect("Shouldn't fail");
+                    history.insert(&key, &entry)?;
+
                     Ok(())
                 }
-                None => Err(ConflictableTransactionError::Abort(
-                    TransactionNotFoundError,
-                )),
-            })
-            .map_err(Error::from)
+                None => Err(TransactionError::abort(TransactionNotFoundError)),
+            }
+        })
+        .map_err(Error::from)
+    }
+
+    /// Moves transaction from failed to dead-letter queue. History is preserved
+    pub fn mark_dead(&self, event_address: &MsgAddrStd) -> Result<(), Error> {
+        let key = make_key(event_address);
+
+        transaction(&[&self.failed, &self.dead], |trees| {
+            let (failed, dead) = (trees[0], trees[1]);
+            match failed.remove(&key)? {
+                Some(transaction) => {
+                    dead.insert(&key, &transaction)?;
+                    Ok(())
+                }
+                None => Err(TransactionError::abort(TransactionNotFoundError)),
+            }
+        })
+        .map_err(Error::from)
     }
 
     pub fn has_event(&self, event_address: &MsgAddrStd) -> Result<bool, Error> {
         let key = make_key(event_address);
 
-        Ok(self.pending.contains_key(&key)? || self.failed.contains_key(&key)?)
+        Ok(self.pending.contains_key(&key)?
+            || self.failed.contains_key(&key)?
+            || self.dead.contains_key(&key)?)
+    }
+
+    /// Validates raw entry of the pending, failed or dead queue
+    pub fn check_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        check_key(key)?;
+        T::try_from_slice(value)?;
+        Ok(())
+    }
+
+    pub fn get_history(
+        &self,
+        event_address: &MsgAddrStd,
+    ) -> Result<Option<VoteRetryHistory>, Error> {
+        let key = make_key(event_address);
+
+        Ok(match self.history.get(&key)? {
+            Some(value) => Some(VoteRetryHistory::try_from_slice(&value)?),
+            None => None,
+        })
     }
 
     pub fn get_all_pending(&self) -> impl Iterator<Item = (MsgAddrStd, T)> {
-        self.pending
-            .iter()
-            .filter_map(|x| match x {
-                Ok(a) => Some(a),
-                Err(e) => {
-                    log::error!("Failed getting pending from db. Db corruption?: {}", e);
-                    None
-                }
-            })
-            .map(|(key, value)| {
-                (
-                    parse_key(&key),
-                    <T as BorshDeserialize>::deserialize(&mut value.as_ref())
-                        .expect("Shouldn't fail"),
-                )
-            })
+        iterate_queue(&self.pending, "pending")
     }
 
     pub fn get_all_failed(&self) -> impl Iterator<Item = (MsgAddrStd, T)> {
-        self.failed
-            .iter()
-            .filter_map(|x| match x {
-                Ok(a) => Some(a),
-                Err(e) => {
-                    log::error!("Failed getting failed from db. Db corruption?: {}", e);
-                    None
-                }
-            })
-            .map(|(key, value)| {
-                (
-                    parse_key(&key),
-                    <T as BorshDeserialize>::deserialize(&mut value.as_ref())
-                        .expect("Shouldn't fail"),
-                )
-            })
+        iterate_queue(&self.failed, "failed")
+    }
+
+    pub fn get_all_dead(&self) -> impl Iterator<Item = (MsgAddrStd, T)> {
+        iterate_queue(&self.dead, "dead")
+    }
+
+    pub fn get_pending_page<F>(
+        &self,
+        request: &PageRequest,
+        filter: F,
+    ) -> Result<Page<(MsgAddrStd, T)>, Error>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        read_queue_page(&self.pending, request, filter)
+    }
+
+    pub fn get_failed_page<F>(
+        &self,
+        request: &PageRequest,
+        filter: F,
+    ) -> Result<Page<(MsgAddrStd, T)>, Error>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        read_queue_page(&self.failed, request, filter)
+    }
+
+    pub fn get_dead_page<F>(
+        &self,
+        request: &PageRequest,
+        filter: F,
+    ) -> Result<Page<(MsgAddrStd, T)>, Error>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        read_queue_page(&self.dead, request, filter)
     }
 
     pub fn pending_len(&self) -> usize {
@@ -153,16 +256,181 @@ where
     pub fn failed_len(&self) -> usize {
         self.failed.len()
     }
+
+    pub fn dead_len(&self) -> usize {
+        self.dead.len()
+    }
+}
+
+impl<T> Table for VotesQueue<T>
+where
+    T: BorshSerialize + BorshDeserialize + IntoView,
+{
+    type Key = String;
+    type Value = Vec<T::View>;
+
+    fn dump_elements(&self) -> HashMap<Self::Key, Self::Value> {
+        let mut result = HashMap::with_capacity(3);
+        for &(name, tree) in [
+            ("pending", &self.pending),
+            ("failed", &self.failed),
+            ("dead", &self.dead),
+        ]
+        .iter()
+        {
+            let views = iterate_queue::<T>(tree, name)
+                .map(|(_, transaction)| transaction.into_view())
+                .collect();
+            result.insert(name.to_string(), views);
+        }
+        result
+    }
+}
+
+fn iterate_queue<T>(tree: &Tree, name: &'static str) -> impl Iterator<Item = (MsgAddrStd, T)>
+where
+    T: BorshDeserialize,
+{
+    tree.iter()
+        .filter_map(move |x| match x {
+            Ok(a) => Some(a),
+            Err(e) => {
+                log::error!("Failed getting {} from db. Db corruption?: {}", name, e);
+                None
+            }
+        })
+        .map(|(key, value)| {
+            (
+                parse_key(&key),
+                <T as BorshDeserialize>::deserialize(&mut value.as_ref()).expect("Shouldn't fail"),
+            )
+        })
+}
+
+/// Reads transactions ordered by the event address
+fn read_queue_page<T, F>(
+    tree: &Tree,
+    request: &PageRequest,
+    mut filter: F,
+) -> Result<Page<(MsgAddrStd, T)>, Error>
+where
+    T: BorshDeserialize,
+    F: FnMut(&T) -> bool,
+{
+    read_page(tree, request, |key, value| {
+        let transaction = T::try_from_slice(value)?;
+        Ok(filter(&transaction).then(|| (parse_key(key), transaction)))
+    })
+}
+
+/// Failed attempts to deliver the vote for a single event
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct VoteRetryHistory {
+    /// Timestamp of the first failure in seconds
+    pub first_failed_at: i64,
+    pub attempts: Vec<VoteAttempt>,
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct VoteAttempt {
+    /// Timestamp in seconds
+    pub failed_at: i64,
+    pub error: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FailedVoteAction {
+    /// Backoff delay has not passed yet
+    Wait,
+    /// Vote should be sent again
+    Retry,
+    /// Vote is abandoned and should be moved to the dead-letter queue
+    Abandon,
+}
+
+impl VoteRetryHistory {
+    fn new(now: i64) -> Self {
+        Self {
+            first_failed_at: now,
+            attempts: Vec::new(),
+        }
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.attempts.last().map(|attempt| attempt.error.as_str())
+    }
+
+    /// Decides what to do with the failed vote according to the backoff rules
+    pub fn next_action(&self, settings: &FailedVotesRetrySettings, now: i64) -> FailedVoteAction {
+        let last_failed_at = match self.attempts.last() {
+            Some(attempt) => attempt.failed_at,
+            None => return FailedVoteAction::Retry,
+        };
+
+        // First attempt is the original sending, all others are automatic retries
+        let retries_count = self.attempts.len() - 1;
+        if retries_count >= settings.max_attempts as usize {
+            return FailedVoteAction::Abandon;
+        }
+
+        let age = now.saturating_sub(self.first_failed_at);
+        if age > settings.max_age.as_secs() as i64 {
+            return FailedVoteAction::Abandon;
+        }
+
+        let delay = (settings.initial_delay.as_secs_f64()
+            * settings.delay_multiplier.powi(retries_count as i32))
+        .min(settings.max_delay.as_secs_f64());
+
+        if now.saturating_sub(last_failed_at) as f64 >= delay {
+            FailedVoteAction::Retry
+        } else {
+            FailedVoteAction::Wait
+        }
+    }
+}
+
+impl IntoView for VoteRetryHistory {
+    type View = VoteRetryHistoryView;
+
+    fn into_view(self) -> Self::View {
+        VoteRetryHistoryView {
+            first_failed_at: self.first_failed_at,
+            last_error: self.last_error().map(ToOwned::to_owned),
+            attempts: self
+                .attempts
+                .into_iter()
+                .map(|attempt| VoteAttemptView {
+                    failed_at: attempt.failed_at,
+                    error: attempt.error,
+                })
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
-#[error("cannot mark transaction as failed when it is not pending")]
+#[error("cannot move transaction, because it is not in the source queue")]
 struct TransactionNotFoundError;
 
 fn make_key(event_address: &MsgAddrStd) -> Vec<u8> {
     event_address.address.get_bytestring(0)
 }
 
+/// Validates raw entry of the retry history
+pub fn check_history_entry(key: &[u8], value: &[u8]) -> Result<(), Error> {
+    check_key(key)?;
+    VoteRetryHistory::try_from_slice(value)?;
+    Ok(())
+}
+
+fn check_key(key: &[u8]) -> Result<(), Error> {
+    match key.len() {
+        32 => Ok(()),
+        len => Err(anyhow!("Invalid event address length: {}", len)),
+    }
+}
+
 fn parse_key(key: &[u8]) -> MsgAddrStd {
     MsgAddrStd {
         anycast: None,
@@ -170,3 +438,80 @@ fn parse_key(key: &[u8]) -> MsgAddrStd {
         address: UInt256::from_be_bytes(key).into(),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn history(first_failed_at: i64, attempts: &[i64]) -> VoteRetryHistory {
+        VoteRetryHistory {
+            first_failed_at,
+            attempts: attempts
+                .iter()
+                .map(|&failed_at| VoteAttempt {
+                    failed_at,
+                    error: String::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let settings = FailedVotesRetrySettings {
+            initial_delay: Duration::from_secs(10),
+            delay_multiplier: 2.0,
+            max_delay: Duration::from_secs(30),
+            max_age: Duration::from_secs(1000),
+            max_attempts: 3,
+            ..Default::default()
+        };
+
+        let one = history(0, &[0]);
+        assert_eq!(one.next_action(&settings, 9), FailedVoteAction::Wait);
+        assert_eq!(one.next_action(&settings, 10), FailedVoteAction::Retry);
+
+        let two = history(0, &[0, 100]);
+        assert_eq!(two.next_action(&settings, 119), FailedVoteAction::Wait);
+        assert_eq!(two.next_action(&settings, 120), FailedVoteAction::Retry);
+
+        // Delay is limited by `max_delay`
+        let three = history(0, &[0, 100, 200]);
+        assert_eq!(three.next_action(&settings, 230), FailedVoteAction::Retry);
+
+        let four = history(0, &[0, 100, 200, 300]);
+        assert_eq!(four.next_action(&settings, 400), FailedVoteAction::Abandon);
+
+        assert_eq!(one.next_action(&settings, 1001), FailedVoteAction::Abandon);
+    }
+
+    #[test]
+    fn test_retry_dead() {
+        let db = Db::in_memory();
+        let queue = VotesQueue::<u32> {
+            pending: db.open_tree("pending").unwrap(),
+            failed: db.open_tree("failed").unwrap(),
+            dead: db.open_tree("dead").unwrap(),
+            history: db.open_tree("history").unwrap(),
+            _marker: Default::default(),
+        };
+        let event = MsgAddrStd::with_address(None, 0, UInt256::from([1; 32]).into());
+
+        queue.insert_pending(&event, &1, None).unwrap();
+        queue.mark_failed(&event, "first").unwrap();
+
+        // Automatic retry keeps the history
+        queue.insert_pending(&event, &1, None).unwrap();
+        queue.mark_failed(&event, "second").unwrap();
+        assert_eq!(
+            queue.get_history(&event).unwrap().unwrap().attempts.len(),
+            2
+        );
+
+        // Manual retry of the dead transaction starts over
+        queue.mark_dead(&event).unwrap();
+        queue.insert_pending(&event, &1, None).unwrap();
+        assert!(queue.get_history(&event).unwrap().is_none());
+        assert_eq!(queue.pending_len(), 1);
+    }
+}
@@ -0,0 +1,224 @@
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+
+use relay_models::models::{
+    ActivityEventView, ActivityQuery, ActivityView, EventConfigurationType, EventDirection,
+    LaggedActivityView,
+};
+
+use crate::prelude::*;
+
+/// Max number of events, which are kept for the slow subscribers
+const ACTIVITY_CHANNEL_CAPACITY: usize = 1024;
+
+const ACTIVITY_TYPES: [&str; 8] = [
+    "eth_event",
+    "ton_event",
+    "vote_sent",
+    "vote_confirmed",
+    "vote_failed",
+    "configuration_changed",
+    "state_changed",
+    "lagged",
+];
+
+/// Broadcasts relay activity to the api subscribers
+#[derive(Clone)]
+pub struct ActivityNotifier {
+    tx: broadcast::Sender<ActivityView>,
+}
+
+impl Default for ActivityNotifier {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(ACTIVITY_CHANNEL_CAPACITY);
+        Self { tx }
+    }
+}
+
+impl ActivityNotifier {
+    pub fn notify(&self, event: ActivityEventView) {
+        // Fails only if there are no subscribers
+        let _ = self.tx.send(ActivityView {
+            timestamp: chrono::Utc::now().timestamp(),
+            event,
+        });
+    }
+
+    /// Returns new events, which satisfy the filter. Missed events are reported with `lagged`
+    pub fn subscribe(&self, filter: ActivityFilter) -> impl Stream<Item = ActivityView> {
+        BroadcastStream::new(self.tx.subscribe()).filter_map(move |item| {
+            let activity = match item {
+                Ok(activity) => activity,
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => ActivityView {
+                    timestamp: chrono::Utc::now().timestamp(),
+                    event: ActivityEventView::Lagged(LaggedActivityView { skipped }),
+                },
+            };
+            if filter.matches(&activity) {
+                Some(activity)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+pub struct ActivityFilter {
+    types: Option<Vec<&'static str>>,
+    configuration_id: Option<u32>,
+    direction: Option<EventDirection>,
+}
+
+impl ActivityFilter {
+    pub fn new(query: &ActivityQuery) -> Result<Self, Error> {
+        let types = query
+            .types
+            .as_deref()
+            .map(|types| {
+                types
+                    .split(',')
+                    .map(|name| {
+                        let name = name.trim();
+                        ACTIVITY_TYPES
+                            .iter()
+                            .find(|&&item| item == name)
+                            .copied()
+                            .ok_or_else(|| anyhow!("Unknown activity type: {}", name))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()
+            })
+            .transpose()?;
+
+        Ok(Self {
+            types,
+            configuration_id: query.configuration_id,
+            direction: query.direction,
+        })
+    }
+
+    /// Activity without configuration or direction is not filtered by them.
+    /// Lagging is always reported
+    pub fn matches(&self, activity: &ActivityView) -> bool {
+        let event = &activity.event;
+        if let ActivityEventView::Lagged(_) = event {
+            return true;
+        }
+
+        self.types
+            .as_ref()
+            .map_or(true, |types| types.contains(&activity_type(event)))
+            && match (self.configuration_id, configuration_id(event)) {
+                (Some(expected), Some(id)) => expected == id,
+                _ => true,
+            }
+            && match (self.direction, direction(event)) {
+                (Some(expected), Some(direction)) => expected == direction,
+                _ => true,
+            }
+    }
+}
+
+pub fn activity_type(event: &ActivityEventView) -> &'static str {
+    match event {
+        ActivityEventView::EthEvent(_) => "eth_event",
+        ActivityEventView::TonEvent(_) => "ton_event",
+        ActivityEventView::VoteSent(_) => "vote_sent",
+        ActivityEventView::VoteConfirmed(_) => "vote_confirmed",
+        ActivityEventView::VoteFailed(_) => "vote_failed",
+        ActivityEventView::ConfigurationChanged(_) => "configuration_changed",
+        ActivityEventView::StateChanged(_) => "state_changed",
+        ActivityEventView::Lagged(_) => "lagged",
+    }
+}
+
+fn configuration_id(event: &ActivityEventView) -> Option<u32> {
+    match event {
+        ActivityEventView::EthEvent(event) => Some(event.configuration_id),
+        ActivityEventView::TonEvent(event) => Some(event.configuration_id),
+        ActivityEventView::VoteSent(vote)
+        | ActivityEventView::VoteConfirmed(vote)
+        | ActivityEventView::VoteFailed(vote) => Some(vote.configuration_id),
+        ActivityEventView::ConfigurationChanged(configuration) => {
+            Some(configuration.configuration_id)
+        }
+        ActivityEventView::StateChanged(_) | ActivityEventView::Lagged(_) => None,
+    }
+}
+
+fn direction(event: &ActivityEventView) -> Option<EventDirection> {
+    match event {
+        ActivityEventView::EthEvent(_) => Some(EventDirection::EthToTon),
+        ActivityEventView::TonEvent(_) => Some(EventDirection::TonToEth),
+        ActivityEventView::VoteSent(vote)
+        | ActivityEventView::VoteConfirmed(vote)
+        | ActivityEventView::VoteFailed(vote) => Some(vote.direction),
+        ActivityEventView::ConfigurationChanged(configuration) => {
+            Some(match configuration.configuration_type {
+                EventConfigurationType::Eth => EventDirection::EthToTon,
+                EventConfigurationType::Ton => EventDirection::TonToEth,
+            })
+        }
+        ActivityEventView::StateChanged(_) | ActivityEventView::Lagged(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use relay_models::models::{RelayStateView, StateChangeView, VoteActivityView};
+
+    fn vote(configuration_id: u32, direction: EventDirection) -> ActivityView {
+        ActivityView {
+            timestamp: 0,
+            event: ActivityEventView::VoteFailed(VoteActivityView {
+                direction,
+                configuration_id,
+                event_address: String::new(),
+                vote: relay_models::models::EventVote::Confirm,
+                error: None,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_filter() {
+        let state_changed = ActivityView {
+            timestamp: 0,
+            event: ActivityEventView::StateChanged(StateChangeView {
+                from: RelayStateView::Locked,
+                to: RelayStateView::Running,
+            }),
+        };
+        let lagged = ActivityView {
+            timestamp: 0,
+            event: ActivityEventView::Lagged(LaggedActivityView { skipped: 1 }),
+        };
+
+        let filter = ActivityFilter::new(&ActivityQuery {
+            types: Some("vote_failed, state_changed".to_string()),
+            configuration_id: Some(1),
+            direction: Some(EventDirection::TonToEth),
+        })
+        .unwrap();
+        assert!(filter.matches(&vote(1, EventDirection::TonToEth)));
+        assert!(!filter.matches(&vote(2, EventDirection::TonToEth)));
+        assert!(!filter.matches(&vote(1, EventDirection::EthToTon)));
+        assert!(filter.matches(&state_changed));
+        assert!(filter.matches(&lagged));
+
+        let filter = ActivityFilter::new(&ActivityQuery {
+            types: Some("vote_sent".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(!filter.matches(&vote(1, EventDirection::TonToEth)));
+        assert!(!filter.matches(&state_changed));
+
+        assert!(ActivityFilter::new(&ActivityQuery {
+            types: Some("vote".to_string()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::{SinkExt, Stream, StreamExt};
+use tokio::sync::RwLock;
+use warp::http::StatusCode;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::{reply, Reply};
+
+use relay_models::models::{ActivityQuery, ActivityView};
+
+use crate::engine::activity::{activity_type, ActivityFilter};
+use crate::engine::models::*;
+
+/// Streams relay activity as server-sent events named by the activity type
+pub async fn sse(
+    state: Arc<RwLock<State>>,
+    query: ActivityQuery,
+) -> Result<reply::Response, Infallible> {
+    let filter = match ActivityFilter::new(&query) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    let stream = state
+        .read()
+        .await
+        .activity
+        .subscribe(filter)
+        .map(|activity| {
+            warp::sse::Event::default()
+                .event(activity_type(&activity.event))
+                .json_data(&activity)
+        });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)).into_response())
+}
+
+/// Streams relay activity as websocket text messages with json
+pub async fn websocket(
+    ws: Ws,
+    state: Arc<RwLock<State>>,
+    query: ActivityQuery,
+) -> Result<reply::Response, Infallible> {
+    let filter = match ActivityFilter::new(&query) {
+        Ok(filter) => filter,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    let stream = state.read().await.activity.subscribe(filter);
+    Ok(ws
+        .on_upgrade(move |socket| forward_activity(socket, stream))
+        .into_response())
+}
+
+async fn forward_activity<S>(socket: WebSocket, activity: S)
+where
+    S: Stream<Item = ActivityView>,
+{
+    let (mut tx, mut rx) = socket.split();
+    tokio::pin!(activity);
+
+    loop {
+        tokio::select! {
+            activity = activity.next() => {
+                let activity = match activity {
+                    Some(activity) => activity,
+                    None => break,
+                };
+                let message = match serde_json::to_string(&activity) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        log::error!("Failed to serialize activity: {}", e);
+                        continue;
+                    }
+                };
+                if tx.send(Message::text(message)).await.is_err() {
+                    break;
+                }
+            }
+            // Incoming messages are ignored, waiting only for the close
+            message = rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            }
+        }
+    }
+
+    let _ = tx.close().await;
+}
+
+fn bad_request(e: anyhow::Error) -> reply::Response {
+    reply::with_status(e.to_string(), StatusCode::BAD_REQUEST).into_response()
+}
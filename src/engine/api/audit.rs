@@ -0,0 +1,250 @@
+use std::future::Future;
+use std::path::Path;
+
+use anyhow::Context;
+use warp::filters::path::FullPath;
+use warp::http::StatusCode;
+use warp::hyper::body::{to_bytes, Body};
+use warp::reply::Response;
+use warp::{reply, Filter, Rejection, Reply};
+
+use relay_models::models::AuditQuery;
+
+use super::auth::{ApiAuth, ApiCaller, AuthError};
+use super::error::ApiResult;
+use crate::config::ApiRole;
+use crate::db::{AuditEntry, AuditLog};
+use crate::engine::models::*;
+use crate::prelude::*;
+
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+/// Writes audit entries into the db and the optional file
+pub struct Auditor {
+    audit_log: AuditLog,
+    file: Option<std::sync::Mutex<std::fs::File>>,
+}
+
+impl Auditor {
+    pub fn new(db: &Db, path: Option<&Path>) -> Result<Self, Error> {
+        let file = path
+            .map(|path| {
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed opening audit log {}", path.display()))
+            })
+            .transpose()?
+            .map(std::sync::Mutex::new);
+
+        Ok(Self {
+            audit_log: AuditLog::new(db)?,
+            file,
+        })
+    }
+
+    /// Stores the entry on the blocking pool, since the file is synced after every write
+    async fn record(self: Arc<Self>, entry: AuditEntry) {
+        if let Err(e) = tokio::task::spawn_blocking(move || self.write(entry)).await {
+            log::error!("Failed writing audit entry: {:?}", e);
+        }
+    }
+
+    /// Logs the administrative request, which was rejected by the authentication.
+    /// Such requests are not stored, so that unauthenticated callers can't flood the log
+    pub(super) fn log_rejected(&self, caller: &ApiCaller, action: &str, error: AuthError) {
+        log::warn!(
+            "Audit: {} by {} rejected with {}: {}",
+            action,
+            caller,
+            error.status().as_u16(),
+            error
+        );
+    }
+
+    fn write(&self, entry: AuditEntry) {
+        log::info!(
+            "Audit: {} by {} finished with {}",
+            entry.action,
+            entry.caller,
+            entry.status
+        );
+
+        // Entries, which could not be stored in the locked db, still get into the file
+        let id = match self.audit_log.append(&entry) {
+            Ok(id) => id,
+            Err(e) => {
+                log::error!("Failed writing audit entry to db: {:?}", e);
+                chrono::Utc::now().timestamp_nanos() as u64
+            }
+        };
+
+        if let Some(file) = &self.file {
+            let mut line = serde_json::to_string(&entry.into_view(id)).expect("Shouldn't fail");
+            line.push('\n');
+
+            let mut file = file.lock().unwrap();
+            if let Err(e) = file
+                .write_all(line.as_bytes())
+                .and_then(|_| file.sync_data())
+            {
+                log::error!("Failed writing audit entry to file: {:?}", e);
+            }
+        }
+    }
+}
+
+/// Administrative request on behalf of the caller
+pub struct Audit {
+    auditor: Arc<Auditor>,
+    caller: ApiCaller,
+}
+
+impl Audit {
+    pub fn new(auditor: Arc<Auditor>, caller: ApiCaller) -> Self {
+        Self { auditor, caller }
+    }
+
+    /// Executes the request handler and records its result
+    pub async fn record<F, R>(
+        self,
+        action: &'static str,
+        params: serde_json::Value,
+        handler: F,
+    ) -> Result<Response, Infallible>
+    where
+        F: Future<Output = Result<R, Infallible>>,
+        R: Reply,
+    {
+        let mut response = handler.await?.into_response();
+        let status = response.status();
+
+        let error = if status.is_success() {
+            None
+        } else {
+            let (parts, body) = response.into_parts();
+            let body = to_bytes(body).await.unwrap_or_default();
+            let error = String::from_utf8_lossy(&body).into_owned();
+            response = Response::from_parts(parts, Body::from(body));
+            Some(error)
+        };
+
+        self.finish(action, params, status, error).await;
+        Ok(response)
+    }
+
+    /// Executes the api operation and records its result
+    #[cfg(feature = "grpc")]
+    pub async fn record_result<F, T>(
+        self,
+        action: &'static str,
+        params: serde_json::Value,
+        operation: F,
+    ) -> ApiResult<T>
+    where
+        F: Future<Output = ApiResult<T>>,
+    {
+        let result = operation.await;
+        let (status, error) = match &result {
+            Ok(_) => (StatusCode::OK, None),
+            Err(e) => (e.status, Some(e.message.clone())),
+        };
+
+        self.finish(action, params, status, error).await;
+        result
+    }
+
+    async fn finish(
+        self,
+        action: &'static str,
+        params: serde_json::Value,
+        status: StatusCode,
+        error: Option<String>,
+    ) {
+        self.auditor
+            .record(AuditEntry {
+                timestamp: chrono::Utc::now().timestamp(),
+                caller: self.caller.to_string(),
+                action: action.to_string(),
+                params: params.to_string(),
+                status: status.as_u16(),
+                error,
+            })
+            .await;
+    }
+}
+
+/// Rejects requests without an admin token and extracts the audit of the caller.
+/// Rejected requests are logged with the path as their action
+pub fn authenticate_admin(
+    auth: Arc<ApiAuth>,
+    auditor: Arc<Auditor>,
+) -> impl Filter<Extract = (Audit,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::addr::remote())
+        .and(warp::path::full())
+        .and_then(move |header: Option<String>, address, path: FullPath| {
+            let auth = auth.clone();
+            let auditor = auditor.clone();
+            async move {
+                let caller = ApiCaller {
+                    name: None,
+                    address,
+                };
+                match auth.check(header.as_deref(), ApiRole::Admin) {
+                    Ok(name) => Ok(Audit::new(auditor, ApiCaller { name, ..caller })),
+                    Err(e) => {
+                        let action = path.as_str().trim_start_matches('/');
+                        auditor.log_rejected(&caller, action, e);
+                        Err(warp::reject::custom(e))
+                    }
+                }
+            }
+        })
+}
+
+pub async fn get_audit(
+    state: Arc<RwLock<State>>,
+    query: AuditQuery,
+) -> Result<impl Reply, Infallible> {
+    let state = state.read().await;
+    let audit_log = match AuditLog::new(&state.state_manager) {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            log::error!("Failed opening audit log: {:?}", e);
+            return Ok(reply::with_status(
+                e.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT);
+    let entries = audit_log.get(limit, |entry| {
+        query
+            .action
+            .as_ref()
+            .map_or(true, |action| &entry.action == action)
+            && query.caller.as_ref().map_or(true, |caller| {
+                entry.caller.split('@').next() == Some(caller.as_str())
+            })
+    });
+
+    Ok(match entries {
+        Ok(entries) => {
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|(id, entry)| entry.into_view(id))
+                .collect();
+            reply::with_status(
+                serde_json::to_string(&entries).expect("Shouldn't fail"),
+                StatusCode::OK,
+            )
+        }
+        Err(e) => {
+            log::error!("Failed reading audit log: {:?}", e);
+            reply::with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    })
+}
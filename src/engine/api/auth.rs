@@ -0,0 +1,273 @@
+use anyhow::Context;
+use rand::prelude::*;
+use ring::digest;
+use warp::http::StatusCode;
+use warp::{reply, Filter, Rejection, Reply};
+
+use crate::config::{ApiAuthSettings, ApiRole, ApiTlsSettings};
+use crate::prelude::*;
+
+/// Checks the `Authorization: Bearer <token>` header of the api requests
+pub struct ApiAuth {
+    /// All requests are allowed if `None`
+    tokens: Option<Vec<ApiToken>>,
+}
+
+struct ApiToken {
+    name: String,
+    hash: [u8; 32],
+    role: ApiRole,
+}
+
+impl ApiAuth {
+    pub fn new(settings: Option<&ApiAuthSettings>) -> Result<Self, Error> {
+        let settings = match settings {
+            Some(settings) => settings,
+            None => return Ok(Self { tokens: None }),
+        };
+
+        if settings.tokens.is_empty() {
+            return Err(anyhow!("No api tokens specified"));
+        }
+
+        let tokens = settings
+            .tokens
+            .iter()
+            .map(|token| {
+                let mut hash = [0; 32];
+                hex::decode_to_slice(token.hash.trim(), &mut hash).with_context(|| {
+                    format!("Invalid SHA-256 hash of the api token {}", token.name)
+                })?;
+                Ok(ApiToken {
+                    name: token.name.clone(),
+                    hash,
+                    role: token.role,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok(Self {
+            tokens: Some(tokens),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    /// Returns the name of the token owner
+    pub(super) fn check(
+        &self,
+        header: Option<&str>,
+        role: ApiRole,
+    ) -> Result<Option<String>, AuthError> {
+        let tokens = match &self.tokens {
+            Some(tokens) => tokens,
+            None => return Ok(None),
+        };
+
+        let token = header
+            .and_then(|header| {
+                let header = header.trim();
+                let (scheme, token) = header.split_at(header.find(' ')?);
+                if scheme.eq_ignore_ascii_case("bearer") {
+                    Some(token.trim())
+                } else {
+                    None
+                }
+            })
+            .ok_or(AuthError::MissingToken)?;
+
+        let hash = digest::digest(&digest::SHA256, token.as_bytes());
+        let token = tokens
+            .iter()
+            .find(|item| {
+                ring::constant_time::verify_slices_are_equal(&item.hash, hash.as_ref()).is_ok()
+            })
+            .ok_or(AuthError::InvalidToken)?;
+
+        if token.role < role {
+            log::warn!(
+                "Api token {} with role {:?} is not allowed to perform {:?} requests",
+                token.name,
+                token.role,
+                role
+            );
+            return Err(AuthError::Forbidden);
+        }
+        Ok(Some(token.name.clone()))
+    }
+}
+
+/// Identity of the api client
+#[derive(Debug, Clone)]
+pub struct ApiCaller {
+    /// Name of the api token owner. `None` if authentication is disabled
+    pub name: Option<String>,
+    pub address: Option<SocketAddr>,
+}
+
+impl std::fmt::Display for ApiCaller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name.as_deref().unwrap_or("anonymous"))?;
+        match &self.address {
+            Some(address) => write!(f, "@{}", address),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Rejects requests without a token of the required role and extracts the caller
+pub fn authenticate(
+    auth: Arc<ApiAuth>,
+    role: ApiRole,
+) -> impl Filter<Extract = (ApiCaller,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::addr::remote())
+        .and_then(move |header: Option<String>, address| {
+            let auth = auth.clone();
+            async move {
+                let name = auth
+                    .check(header.as_deref(), role)
+                    .map_err(warp::reject::custom)?;
+                Ok::<_, Rejection>(ApiCaller { name, address })
+            }
+        })
+}
+
+/// Rejects requests without a token of the required role
+pub fn authorize(
+    auth: Arc<ApiAuth>,
+    role: ApiRole,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    authenticate(auth, role).map(|_| ()).untuple_one()
+}
+
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Rejection> {
+    let error = match rejection.find::<AuthError>() {
+        Some(error) => error,
+        None => return Err(rejection),
+    };
+
+    let status = error.status();
+    let mut response = reply::with_status(error.to_string(), status).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(
+            warp::http::header::WWW_AUTHENTICATE,
+            warp::http::HeaderValue::from_static("Bearer"),
+        );
+    }
+    Ok(response)
+}
+
+/// Certificates and keys of the api server
+#[derive(Clone)]
+pub struct ApiTls {
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+    pub client_ca: Option<Vec<u8>>,
+}
+
+impl ApiTls {
+    pub fn read(settings: &ApiTlsSettings) -> Result<Self, Error> {
+        let read = |path: &std::path::Path| {
+            std::fs::read(path).with_context(|| format!("Failed reading {}", path.display()))
+        };
+
+        Ok(Self {
+            cert: read(&settings.cert_path)?,
+            key: read(&settings.key_path)?,
+            client_ca: settings.client_ca_path.as_deref().map(read).transpose()?,
+        })
+    }
+}
+
+/// Returns random token and its hex encoded SHA-256 hash
+pub fn generate_token() -> (String, String) {
+    let mut rng = rand::rngs::OsRng::new().expect("OsRng fail");
+    let token = hex::encode(rng.gen::<[u8; 32]>());
+    let hash = hex::encode(digest::digest(&digest::SHA256, token.as_bytes()));
+    (token, hash)
+}
+
+#[derive(Debug, Copy, Clone, thiserror::Error)]
+pub(super) enum AuthError {
+    #[error("Api token is required")]
+    MissingToken,
+    #[error("Invalid api token")]
+    InvalidToken,
+    #[error("Api token is not allowed to perform this request")]
+    Forbidden,
+}
+
+impl AuthError {
+    pub(super) fn status(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken | AuthError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AuthError::Forbidden => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl warp::reject::Reject for AuthError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ApiTokenSettings;
+
+    #[test]
+    fn test_check_token() {
+        let (monitor_token, monitor_hash) = generate_token();
+        let (admin_token, admin_hash) = generate_token();
+
+        let auth = ApiAuth::new(Some(&ApiAuthSettings {
+            tokens: vec![
+                ApiTokenSettings {
+                    name: "monitoring".to_string(),
+                    hash: monitor_hash,
+                    role: ApiRole::Monitor,
+                },
+                ApiTokenSettings {
+                    name: "operator".to_string(),
+                    hash: admin_hash,
+                    role: ApiRole::Admin,
+                },
+            ],
+        }))
+        .unwrap();
+
+        let monitor = format!("Bearer {}", monitor_token);
+        let admin = format!("bearer {}", admin_token);
+
+        assert_eq!(
+            auth.check(Some(&monitor), ApiRole::Monitor).unwrap(),
+            Some("monitoring".to_string())
+        );
+        assert!(matches!(
+            auth.check(Some(&monitor), ApiRole::Admin),
+            Err(AuthError::Forbidden)
+        ));
+        assert!(auth.check(Some(&admin), ApiRole::Monitor).is_ok());
+        assert_eq!(
+            auth.check(Some(&admin), ApiRole::Admin).unwrap(),
+            Some("operator".to_string())
+        );
+
+        assert!(matches!(
+            auth.check(None, ApiRole::Monitor),
+            Err(AuthError::MissingToken)
+        ));
+        assert!(matches!(
+            auth.check(Some(&monitor_token), ApiRole::Monitor),
+            Err(AuthError::MissingToken)
+        ));
+        assert!(matches!(
+            auth.check(Some("Bearer invalid"), ApiRole::Monitor),
+            Err(AuthError::InvalidToken)
+        ));
+
+        let disabled = ApiAuth::new(None).unwrap();
+        assert_eq!(disabled.check(None, ApiRole::Admin).unwrap(), None);
+    }
+}
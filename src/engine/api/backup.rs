@@ -0,0 +1,132 @@
+use std::convert::Infallible;
+use std::io::{Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::Stream;
+use tokio::io::AsyncReadExt;
+use tokio::sync::RwLock;
+use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::{reply, Reply};
+
+use crate::config::RelayConfig;
+use crate::db::export_database;
+use crate::engine::models::*;
+
+/// Returns consistent binary snapshot of the whole database.
+///
+/// Snapshot is written to the unlinked temporary file, so that writes are suspended
+/// only while the snapshot is being saved, and then streamed from it
+pub async fn backup(
+    state: Arc<RwLock<State>>,
+    config: RelayConfig,
+) -> Result<reply::Response, Infallible> {
+    if let Some(response) = check_plaintext_allowed(&config) {
+        return Ok(response);
+    }
+
+    let db = state.read().await.state_manager.clone();
+
+    let result = tokio::task::spawn_blocking(move || -> Result<_, anyhow::Error> {
+        let mut file = temporary_file()?;
+        let stats = db.backup(std::io::BufWriter::new(&mut file))?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok((file, stats))
+    })
+    .await;
+
+    Ok(match result {
+        Ok(Ok((file, stats))) => {
+            log::info!(
+                "Created db backup. Trees: {}, entries: {}",
+                stats.trees,
+                stats.entries
+            );
+            let body = file_stream(tokio::fs::File::from_std(file));
+            reply::Response::new(Body::wrap_stream(body))
+        }
+        Ok(Err(e)) => internal_error(format!("Failed creating db backup: {}", e)),
+        Err(e) => internal_error(format!("Failed creating db backup: {}", e)),
+    })
+}
+
+/// Returns queues, voting stats and scan cursors as JSON
+pub async fn export(
+    state: Arc<RwLock<State>>,
+    config: RelayConfig,
+) -> Result<reply::Response, Infallible> {
+    if let Some(response) = check_plaintext_allowed(&config) {
+        return Ok(response);
+    }
+
+    let db = state.read().await.state_manager.clone();
+
+    let result = tokio::task::spawn_blocking(move || export_database(&db)).await;
+
+    Ok(match result {
+        Ok(Ok(export)) => serde_json::to_string(&export)
+            .expect("Shouldn't fail")
+            .into_response(),
+        Ok(Err(e)) => internal_error(format!("Failed exporting db: {}", e)),
+        Err(e) => internal_error(format!("Failed exporting db: {}", e)),
+    })
+}
+
+/// Backups and exports contain decrypted values, so they are only made
+/// for the encrypted storage if explicitly allowed
+fn check_plaintext_allowed(config: &RelayConfig) -> Option<reply::Response> {
+    match &config.storage_encryption {
+        Some(settings) if !settings.allow_plaintext_backups => Some(
+            reply::with_status(
+                "Storage is encrypted. Set `storage_encryption.allow_plaintext_backups` \
+                 to save decrypted values",
+                StatusCode::FORBIDDEN,
+            )
+            .into_response(),
+        ),
+        _ => None,
+    }
+}
+
+fn internal_error(message: String) -> reply::Response {
+    log::error!("{}", message);
+    reply::with_status(message, StatusCode::INTERNAL_SERVER_ERROR).into_response()
+}
+
+/// Creates file which is only accessible through the returned handle
+fn temporary_file() -> std::io::Result<std::fs::File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let path = std::env::temp_dir().join(format!(
+        "relay_backup_{}_{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    std::fs::remove_file(&path)?;
+    Ok(file)
+}
+
+fn file_stream(file: tokio::fs::File) -> impl Stream<Item = std::io::Result<Vec<u8>>> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    futures::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(len) => {
+                chunk.truncate(len);
+                Some((Ok(chunk), Some(file)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
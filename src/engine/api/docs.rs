@@ -4,6 +4,30 @@ use relay_models::models::*;
 
 use super::*;
 
+/// Concrete `PageView` types for the documentation
+macro_rules! page_models {
+    ($($name:ident => $item:ty),*$(,)?) => {$(
+        #[allow(dead_code)]
+        #[derive(OpgModel)]
+        struct $name {
+            items: Vec<$item>,
+            #[opg("Cursor of the next page. Absent on the last page")]
+            next_cursor: Option<String>,
+        }
+    )*};
+}
+
+page_models! {
+    EthTonVotesPage => EthTonVoteView,
+    EthTonDeadVotesPage => EthTonDeadVoteView,
+    EthQueuedEventsPage => EthQueuedEventView,
+    EthTxStatsPage => EthTxStatView,
+    TonEthVotesPage => TonEthVoteView,
+    TonEthDeadVotesPage => TonEthDeadVoteView,
+    TonQueuedEventsPage => TonQueuedEventView,
+    TonTxStatsPage => TonTxStatView,
+}
+
 pub fn swagger() -> String {
     let api = describe_api! {
         info: {
@@ -15,6 +39,11 @@ pub fn swagger() -> String {
             event_configurations("Methods for managing event configurations"),
             eth_to_ton("Statistics for events emitted from ETH"),
             ton_to_eth("Statistics for events emitted from TON"),
+            events("History of event state transitions"),
+            activity("Live stream of the relay activity"),
+            health("Probes for orchestrators. Don't require authentication"),
+            database("Methods for moving and inspecting relay database"),
+            audit("History of administrative requests"),
         },
         paths: {
             ("init"): {
@@ -35,10 +64,49 @@ pub fn swagger() -> String {
                     200: String,
                 }
             },
+            ("change-password"): {
+                POST: {
+                    tags: { stuff },
+                    summary: "Re-encrypt keys with the new password",
+                    body: ChangePassword,
+                    200: String,
+                    400: String,
+                    405: String,
+                    500: String,
+                }
+            },
+            ("keystore" / "reencrypt"): {
+                POST: {
+                    tags: { stuff },
+                    summary: "Rewrite keystore in the current format with argon2id key derivation",
+                    body: Password,
+                    200: String,
+                    400: String,
+                    405: String,
+                }
+            },
+            ("keystore" / "rotate-eth-key"): {
+                POST: {
+                    tags: { stuff },
+                    summary: "Store the new ETH key. The relay switches to it when bridge contracts acknowledge it",
+                    body: RotateEthKey,
+                    200: String,
+                    400: String,
+                    405: String,
+                }
+            },
+            ("keystore" / "public-keys"): {
+                GET: {
+                    tags: { stuff },
+                    summary: "Get ETH address and public keys of the relay",
+                    200: PublicKeysView,
+                    400: String,
+                }
+            },
             ("retry-failed"): {
                 POST: {
                     tags: { stuff },
-                    summary: "Retry failed vote transactions",
+                    summary: "Retry failed and abandoned vote transactions",
                     200: Vec<EthTonTransactionView>,
                 }
             },
@@ -89,56 +157,377 @@ pub fn swagger() -> String {
                 GET: {
                     tags: { eth_to_ton },
                     summary: "Pending votes for ETH-to-TON events",
-                    200: Vec<EthTonTransactionView>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: EthTonVotesPage,
+                    400: String,
                 }
             },
             ("eth-to-ton" / "failed"): {
                 GET: {
                     tags: { eth_to_ton },
                     summary: "Failed votes for ETH-to-TON events",
-                    200: Vec<EthTonTransactionView>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: EthTonVotesPage,
+                    400: String,
+                }
+            },
+            ("eth-to-ton" / "dead"): {
+                GET: {
+                    tags: { eth_to_ton },
+                    summary: "Abandoned votes for ETH-to-TON events with their retry history",
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: EthTonDeadVotesPage,
+                    400: String,
                 }
             },
             ("eth-to-ton" / "queued"): {
                 GET: {
                     tags: { eth_to_ton },
                     summary: "Verification queue",
-                    200: HashMap<u64, EthEventVoteDataView>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the target blocks",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query from: u64): {
+                            description: "Inclusive lower bound of the target blocks",
+                        },
+                        (query to: u64): {
+                            description: "Exclusive upper bound of the target blocks",
+                        },
+                    },
+                    200: EthQueuedEventsPage,
+                    400: String,
                 }
             },
             ("eth-to-ton" / "stats"): {
                 GET: {
                     tags: { eth_to_ton },
                     summary: "Known votes for all relays",
-                    200: HashMap<String, Vec<EthTxStatView>>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query relay: String): {
+                            description: "TON address of the relay",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                        (query from: u64): {
+                            description: "Inclusive lower bound of the timestamp, when the vote was received",
+                        },
+                        (query to: u64): {
+                            description: "Exclusive upper bound of the timestamp, when the vote was received",
+                        },
+                    },
+                    200: EthTxStatsPage,
+                    400: String,
                 }
             },
             ("ton-to-eth" / "pending"): {
                 GET: {
                     tags: { ton_to_eth },
                     summary: "Pending votes for TON-to-ETH events",
-                    200: Vec<TonEthTransactionView>,
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: TonEthVotesPage,
+                    400: String,
                 }
             },
             ("ton-to-eth" / "failed"): {
                 GET: {
                     tags: { ton_to_eth },
                     summary: "Failed votes for TON-to-ETH events",
-                    200: Vec<TonEthTransactionView>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: TonEthVotesPage,
+                    400: String,
+                }
+            },
+            ("ton-to-eth" / "dead"): {
+                GET: {
+                    tags: { ton_to_eth },
+                    summary: "Abandoned votes for TON-to-ETH events with their retry history",
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                    },
+                    200: TonEthDeadVotesPage,
+                    400: String,
                 }
             },
             ("ton-to-eth" / "queued" / { configuration_id: u64 }): {
                 GET: {
                     tags: { ton_to_eth },
                     summary: "Verification queue",
-                    200: HashMap<u64, TonEventVoteDataView>,
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event transaction logical times",
+                        },
+                        (query from: u64): {
+                            description: "Inclusive lower bound of the event transaction logical times",
+                        },
+                        (query to: u64): {
+                            description: "Exclusive upper bound of the event transaction logical times",
+                        },
+                    },
+                    200: TonQueuedEventsPage,
+                    400: String,
                 }
             },
             ("ton-to-eth" / "stats"): {
                 GET: {
                     tags: { ton_to_eth },
                     summary: "Known votes for all relays",
-                    200: HashMap<String, Vec<TonTxStatView>>
+                    parameters: {
+                        (query cursor: String): {
+                            description: "`next_cursor` of the previous page",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of items. 100 by default, at most 1000",
+                        },
+                        (query order: String): {
+                            description: "`asc` or `desc` order of the event addresses",
+                        },
+                        (query relay: String): {
+                            description: "TON address of the relay",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query vote: String): {
+                            description: "`confirm` or `reject`",
+                        },
+                        (query from: u64): {
+                            description: "Inclusive lower bound of the timestamp, when the vote was received",
+                        },
+                        (query to: u64): {
+                            description: "Exclusive upper bound of the timestamp, when the vote was received",
+                        },
+                    },
+                    200: TonTxStatsPage,
+                    400: String,
+                }
+            },
+            ("events" / "eth" / { tx_hash: String } / { event_index: u32 }): {
+                GET: {
+                    tags: { events },
+                    summary: "State transitions of the ETH event",
+                    200: Vec<EventJournalEntryView>,
+                    400: String,
+                    404: String,
+                }
+            },
+            ("events" / "ton" / { address: String }): {
+                GET: {
+                    tags: { events },
+                    summary: "State transitions of the TON event by its contract address",
+                    200: Vec<EventJournalEntryView>,
+                    400: String,
+                    404: String,
+                }
+            },
+            ("activity"): {
+                GET: {
+                    tags: { activity },
+                    summary: "Server-sent events with the relay activity. Event name is the activity type",
+                    parameters: {
+                        (query types: String): {
+                            description: "Comma separated activity types: `eth_event`, `ton_event`, `vote_sent`, `vote_confirmed`, `vote_failed`, `configuration_changed`, `state_changed`, `lagged`",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query direction: String): {
+                            description: "`eth_to_ton` or `ton_to_eth`",
+                        },
+                    },
+                    200: ActivityView,
+                    400: String,
+                }
+            },
+            ("activity" / "ws"): {
+                GET: {
+                    tags: { activity },
+                    summary: "Websocket with the relay activity as json text messages",
+                    parameters: {
+                        (query types: String): {
+                            description: "Comma separated activity types: `eth_event`, `ton_event`, `vote_sent`, `vote_confirmed`, `vote_failed`, `configuration_changed`, `state_changed`, `lagged`",
+                        },
+                        (query configuration_id: u32): {
+                            description: "Event configuration id",
+                        },
+                        (query direction: String): {
+                            description: "`eth_to_ton` or `ton_to_eth`",
+                        },
+                    },
+                    101: ActivityView,
+                    400: String,
+                }
+            },
+            ("health" / "live"): {
+                GET: {
+                    tags: { health },
+                    summary: "Responds while the relay process is running",
+                    200: ComponentHealthView,
+                }
+            },
+            ("health" / "ready"): {
+                GET: {
+                    tags: { health },
+                    summary: "Checks ETH node, TON transport, storage, keystore and event configurations subscriptions",
+                    200: ReadinessView,
+                    503: ReadinessView,
+                }
+            },
+            ("db" / "backup"): {
+                POST: {
+                    tags: { database },
+                    summary: "Consistent binary snapshot of all trees. Can be restored with `relay --restore`",
+                    200: String,
+                    500: String,
+                }
+            },
+            ("db" / "export"): {
+                GET: {
+                    tags: { database },
+                    summary: "Queues, voting stats and scan cursors in human-readable form",
+                    200: DatabaseExportView,
+                    500: String,
+                }
+            },
+            ("audit"): {
+                GET: {
+                    tags: { audit },
+                    summary: "Latest administrative requests with their callers and results. Newest first",
+                    parameters: {
+                        (query action: String): {
+                            description: "Api path of the request, e.g. `rescan-eth`",
+                        },
+                        (query caller: String): {
+                            description: "Name of the api token",
+                        },
+                        (query limit: u32): {
+                            description: "Max number of entries. 100 by default",
+                        },
+                    },
+                    200: Vec<AuditEntryView>,
+                    500: String,
                 }
             }
         }
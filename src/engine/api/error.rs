@@ -0,0 +1,62 @@
+use std::convert::Infallible;
+use std::future::Future;
+
+use serde::Serialize;
+use warp::http::StatusCode;
+use warp::reply;
+
+/// Failed api operation with the matching HTTP status.
+/// Operations are shared by the REST and gRPC apis, each maps the error on its own
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
+
+impl ApiError {
+    pub fn new<T>(status: StatusCode, message: T) -> Self
+    where
+        T: Into<String>,
+    {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn into_reply(self) -> reply::WithStatus<String> {
+        reply::with_status(self.message, self.status)
+    }
+}
+
+/// Replies with the JSON encoded result of the api operation
+pub async fn json_reply<F, T>(operation: F) -> Result<reply::WithStatus<String>, Infallible>
+where
+    F: Future<Output = ApiResult<T>>,
+    T: Serialize,
+{
+    Ok(match operation.await {
+        Ok(data) => reply::with_status(
+            serde_json::to_string(&data).expect("Shouldn't fail"),
+            StatusCode::OK,
+        ),
+        Err(e) => e.into_reply(),
+    })
+}
+
+/// Replies with the fixed message if the api operation succeeds
+pub async fn message_reply<F>(
+    operation: F,
+    message: &'static str,
+    status: StatusCode,
+) -> Result<reply::WithStatus<String>, Infallible>
+where
+    F: Future<Output = ApiResult<()>>,
+{
+    Ok(match operation.await {
+        Ok(()) => reply::with_status(message.to_string(), status),
+        Err(e) => e.into_reply(),
+    })
+}
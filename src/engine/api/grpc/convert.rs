@@ -0,0 +1,569 @@
+use std::convert::TryFrom;
+
+use tonic::Status;
+
+use relay_models::models;
+
+use super::proto;
+
+impl From<models::Status> for proto::Status {
+    fn from(status: models::Status) -> Self {
+        Self {
+            password_needed: status.password_needed,
+            init_data_needed: status.init_data_needed,
+            is_working: status.is_working,
+            ton_relay_address: status.ton_relay_address,
+            eth_pubkey: status.eth_pubkey,
+            eth_key_rotation: status.eth_key_rotation.map(From::from),
+        }
+    }
+}
+
+impl From<models::EthKeyRotationView> for proto::EthKeyRotation {
+    fn from(rotation: models::EthKeyRotationView) -> Self {
+        Self {
+            next_eth_address: rotation.next_eth_address,
+            ton_acknowledged: rotation.ton_acknowledged,
+            eth_acknowledged: rotation.eth_acknowledged,
+            checked_at: rotation.checked_at,
+        }
+    }
+}
+
+impl From<proto::InitData> for models::InitData {
+    fn from(data: proto::InitData) -> Self {
+        Self {
+            ton_seed: data.ton_seed,
+            eth_seed: data.eth_seed,
+            password: data.password,
+            language: data.language,
+            ton_derivation_path: data.ton_derivation_path,
+            eth_derivation_path: data.eth_derivation_path,
+            eth_keystore: data.eth_keystore.map(|keystore| models::EthKeystoreData {
+                keystore: keystore.keystore,
+                password: keystore.password,
+            }),
+            ton_keypair: data.ton_keypair,
+        }
+    }
+}
+
+impl From<proto::Password> for models::Password {
+    fn from(data: proto::Password) -> Self {
+        Self {
+            password: data.password,
+        }
+    }
+}
+
+impl From<models::EventConfigurationView> for proto::EventConfiguration {
+    fn from(configuration: models::EventConfigurationView) -> Self {
+        use proto::event_configuration::Data;
+
+        match configuration {
+            models::EventConfigurationView::Eth { id, address, data } => Self {
+                id,
+                address,
+                data: Some(Data::Eth(proto::EthEventConfiguration {
+                    common: Some(data.common.into()),
+                    event_address: data.event_address,
+                    event_blocks_to_confirm: data.event_blocks_to_confirm as u32,
+                    proxy_address: data.proxy_address,
+                    start_block_number: data.start_block_number,
+                })),
+            },
+            models::EventConfigurationView::Ton { id, address, data } => Self {
+                id,
+                address,
+                data: Some(Data::Ton(proto::TonEventConfiguration {
+                    common: Some(data.common.into()),
+                    event_address: data.event_address,
+                    proxy_address: data.proxy_address,
+                    start_timestamp: data.start_timestamp,
+                })),
+            },
+        }
+    }
+}
+
+impl From<models::CommonEventConfigurationParamsView> for proto::CommonEventConfigurationParams {
+    fn from(common: models::CommonEventConfigurationParamsView) -> Self {
+        Self {
+            event_abi: common.event_abi,
+            event_required_confirmations: common.event_required_confirmations as u32,
+            event_required_rejects: common.event_required_rejects as u32,
+            event_code: common.event_code,
+            bridge_address: common.bridge_address,
+            event_initial_balance: common.event_initial_balance,
+            meta: common.meta,
+        }
+    }
+}
+
+impl TryFrom<proto::NewEventConfiguration> for models::NewEventConfiguration {
+    type Error = Status;
+
+    fn try_from(data: proto::NewEventConfiguration) -> Result<Self, Self::Error> {
+        let configuration_type =
+            match proto::EventConfigurationType::from_i32(data.configuration_type) {
+                Some(proto::EventConfigurationType::Eth) => models::EventConfigurationType::Eth,
+                Some(proto::EventConfigurationType::Ton) => models::EventConfigurationType::Ton,
+                _ => return Err(Status::invalid_argument("Unknown configuration type")),
+            };
+
+        Ok(Self {
+            configuration_id: data.configuration_id,
+            address: data.address,
+            configuration_type,
+        })
+    }
+}
+
+impl TryFrom<proto::EventConfigurationVote> for models::Voting {
+    type Error = Status;
+
+    fn try_from(data: proto::EventConfigurationVote) -> Result<Self, Self::Error> {
+        match event_vote(data.vote)? {
+            Some(models::EventVote::Confirm) => Ok(Self::Confirm(data.configuration_id)),
+            Some(models::EventVote::Reject) => Ok(Self::Reject(data.configuration_id)),
+            None => Err(Status::invalid_argument("Vote is required")),
+        }
+    }
+}
+
+impl From<proto::RescanEthRequest> for models::RescanEthData {
+    fn from(data: proto::RescanEthRequest) -> Self {
+        Self { block: data.block }
+    }
+}
+
+impl TryFrom<proto::BridgeConfiguration> for models::BridgeConfigurationView {
+    type Error = Status;
+
+    fn try_from(data: proto::BridgeConfiguration) -> Result<Self, Self::Error> {
+        let to_u16 = |value: u32, name: &str| {
+            u16::try_from(value).map_err(|_| Status::invalid_argument(format!("Too big {}", name)))
+        };
+
+        Ok(Self {
+            nonce: to_u16(data.nonce, "nonce")?,
+            bridge_update_required_confirmations: to_u16(
+                data.bridge_update_required_confirmations,
+                "bridge_update_required_confirmations",
+            )?,
+            bridge_update_required_rejections: to_u16(
+                data.bridge_update_required_rejections,
+                "bridge_update_required_rejections",
+            )?,
+            active: data.active,
+        })
+    }
+}
+
+impl TryFrom<&proto::VotesRequest> for models::PageQuery {
+    type Error = Status;
+
+    fn try_from(request: &proto::VotesRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            configuration_id: request.configuration_id,
+            vote: event_vote(request.vote)?,
+            ..page_query(request.page_size, request.order)?
+        })
+    }
+}
+
+impl TryFrom<&proto::EthQueueRequest> for models::PageQuery {
+    type Error = Status;
+
+    fn try_from(request: &proto::EthQueueRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            configuration_id: request.configuration_id,
+            from: request.from,
+            to: request.to,
+            ..page_query(request.page_size, request.order)?
+        })
+    }
+}
+
+impl TryFrom<&proto::TonQueueRequest> for models::PageQuery {
+    type Error = Status;
+
+    fn try_from(request: &proto::TonQueueRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            from: request.from,
+            to: request.to,
+            ..page_query(request.page_size, request.order)?
+        })
+    }
+}
+
+impl TryFrom<&proto::StatsRequest> for models::PageQuery {
+    type Error = Status;
+
+    fn try_from(request: &proto::StatsRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            relay: request.relay.clone(),
+            configuration_id: request.configuration_id,
+            vote: event_vote(request.vote)?,
+            from: request.from,
+            to: request.to,
+            ..page_query(request.page_size, request.order)?
+        })
+    }
+}
+
+impl TryFrom<proto::ActivityRequest> for models::ActivityQuery {
+    type Error = Status;
+
+    fn try_from(request: proto::ActivityRequest) -> Result<Self, Self::Error> {
+        let direction = match proto::EventDirection::from_i32(request.direction) {
+            Some(proto::EventDirection::Unspecified) => None,
+            Some(proto::EventDirection::EthToTon) => Some(models::EventDirection::EthToTon),
+            Some(proto::EventDirection::TonToEth) => Some(models::EventDirection::TonToEth),
+            None => return Err(Status::invalid_argument("Unknown direction")),
+        };
+
+        Ok(Self {
+            types: (!request.types.is_empty()).then(|| request.types.join(",")),
+            configuration_id: request.configuration_id,
+            direction,
+        })
+    }
+}
+
+/// Page size of zero means the default one
+fn page_query(page_size: u32, order: i32) -> Result<models::PageQuery, Status> {
+    let order = match proto::SortOrder::from_i32(order) {
+        Some(proto::SortOrder::Asc) => models::SortOrder::Asc,
+        Some(proto::SortOrder::Desc) => models::SortOrder::Desc,
+        None => return Err(Status::invalid_argument("Unknown sort order")),
+    };
+
+    Ok(models::PageQuery {
+        limit: (page_size > 0).then(|| page_size as usize),
+        order: Some(order),
+        ..Default::default()
+    })
+}
+
+pub fn vote_queue(queue: i32) -> Result<proto::VoteQueue, Status> {
+    proto::VoteQueue::from_i32(queue).ok_or_else(|| Status::invalid_argument("Unknown queue"))
+}
+
+/// Unspecified vote means any vote
+fn event_vote(vote: i32) -> Result<Option<models::EventVote>, Status> {
+    match proto::EventVote::from_i32(vote) {
+        Some(proto::EventVote::Unspecified) => Ok(None),
+        Some(proto::EventVote::Confirm) => Ok(Some(models::EventVote::Confirm)),
+        Some(proto::EventVote::Reject) => Ok(Some(models::EventVote::Reject)),
+        None => Err(Status::invalid_argument("Unknown vote")),
+    }
+}
+
+impl From<models::EventVote> for proto::EventVote {
+    fn from(vote: models::EventVote) -> Self {
+        match vote {
+            models::EventVote::Confirm => Self::Confirm,
+            models::EventVote::Reject => Self::Reject,
+        }
+    }
+}
+
+impl From<models::EventDirection> for proto::EventDirection {
+    fn from(direction: models::EventDirection) -> Self {
+        match direction {
+            models::EventDirection::EthToTon => Self::EthToTon,
+            models::EventDirection::TonToEth => Self::TonToEth,
+        }
+    }
+}
+
+impl From<models::EventConfigurationType> for proto::EventConfigurationType {
+    fn from(configuration_type: models::EventConfigurationType) -> Self {
+        match configuration_type {
+            models::EventConfigurationType::Eth => Self::Eth,
+            models::EventConfigurationType::Ton => Self::Ton,
+        }
+    }
+}
+
+impl From<models::RelayStateView> for proto::RelayState {
+    fn from(state: models::RelayStateView) -> Self {
+        match state {
+            models::RelayStateView::Uninitialized => Self::Uninitialized,
+            models::RelayStateView::Locked => Self::Locked,
+            models::RelayStateView::Running => Self::Running,
+        }
+    }
+}
+
+impl From<models::EthEventVoteDataView> for proto::EthEventVoteData {
+    fn from(data: models::EthEventVoteDataView) -> Self {
+        Self {
+            event_transaction: data.event_transaction,
+            event_index: data.event_index,
+            event_data: data.event_data,
+            event_block_number: data.event_block_number,
+            event_block: data.event_block,
+            configuration_id: data.configuration_id,
+        }
+    }
+}
+
+impl From<models::TonEventVoteDataView> for proto::TonEventVoteData {
+    fn from(data: models::TonEventVoteDataView) -> Self {
+        Self {
+            configuration_id: data.configuration_id,
+            event_transaction: data.event_transaction,
+            event_transaction_lt: data.event_transaction_lt,
+            event_index: data.event_index,
+        }
+    }
+}
+
+impl From<models::VoteRetryHistoryView> for proto::VoteRetryHistory {
+    fn from(history: models::VoteRetryHistoryView) -> Self {
+        Self {
+            first_failed_at: history.first_failed_at,
+            last_error: history.last_error,
+            attempts: history
+                .attempts
+                .into_iter()
+                .map(|attempt| proto::VoteAttempt {
+                    failed_at: attempt.failed_at,
+                    error: attempt.error,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn eth_ton_vote(
+    event_address: String,
+    transaction: models::EthTonTransactionView,
+    history: Option<models::VoteRetryHistoryView>,
+) -> proto::EthTonVote {
+    let (vote, data) = match transaction {
+        models::EthTonTransactionView::Confirm(data) => (proto::EventVote::Confirm, data),
+        models::EthTonTransactionView::Reject(data) => (proto::EventVote::Reject, data),
+    };
+
+    proto::EthTonVote {
+        event_address,
+        vote: vote as i32,
+        data: Some(data.into()),
+        history: history.map(From::from),
+    }
+}
+
+fn ton_eth_vote(
+    event_address: String,
+    transaction: models::TonEthTransactionView,
+    history: Option<models::VoteRetryHistoryView>,
+) -> proto::TonEthVote {
+    let (vote, data, signature) = match transaction {
+        models::TonEthTransactionView::Confirm(signed) => {
+            (proto::EventVote::Confirm, signed.data, signed.signature)
+        }
+        models::TonEthTransactionView::Reject(data) => {
+            (proto::EventVote::Reject, data, String::new())
+        }
+    };
+
+    proto::TonEthVote {
+        event_address,
+        vote: vote as i32,
+        data: Some(data.into()),
+        signature,
+        history: history.map(From::from),
+    }
+}
+
+impl From<models::EthTonVoteView> for proto::EthTonVote {
+    fn from(vote: models::EthTonVoteView) -> Self {
+        eth_ton_vote(vote.event_address, vote.transaction, None)
+    }
+}
+
+impl From<models::EthTonDeadVoteView> for proto::EthTonVote {
+    fn from(vote: models::EthTonDeadVoteView) -> Self {
+        eth_ton_vote(vote.event_address, vote.transaction, Some(vote.history))
+    }
+}
+
+impl From<models::TonEthVoteView> for proto::TonEthVote {
+    fn from(vote: models::TonEthVoteView) -> Self {
+        ton_eth_vote(vote.event_address, vote.transaction, None)
+    }
+}
+
+impl From<models::TonEthDeadVoteView> for proto::TonEthVote {
+    fn from(vote: models::TonEthDeadVoteView) -> Self {
+        ton_eth_vote(vote.event_address, vote.transaction, Some(vote.history))
+    }
+}
+
+impl From<models::EthQueuedEventView> for proto::EthQueuedEvent {
+    fn from(event: models::EthQueuedEventView) -> Self {
+        Self {
+            target_block: event.target_block,
+            external: event.external,
+            event: Some(event.event.into()),
+        }
+    }
+}
+
+impl From<models::TonQueuedEventView> for proto::TonQueuedEvent {
+    fn from(event: models::TonQueuedEventView) -> Self {
+        Self {
+            target_lt: event.target_lt,
+            external: event.external,
+            event: Some(event.event.into()),
+        }
+    }
+}
+
+impl From<models::EthTxStatView> for proto::EthTxStat {
+    fn from(stat: models::EthTxStatView) -> Self {
+        Self {
+            configuration_id: stat.configuration_id,
+            tx_hash: stat.tx_hash,
+            met: stat.met,
+            event_addr: stat.event_addr,
+            relay_addr: stat.relay_addr,
+            vote: proto::EventVote::from(stat.vote) as i32,
+        }
+    }
+}
+
+impl From<models::TonTxStatView> for proto::TonTxStat {
+    fn from(stat: models::TonTxStatView) -> Self {
+        Self {
+            configuration_id: stat.configuration_id,
+            tx_hash: stat.tx_hash,
+            tx_lt: stat.tx_lt,
+            met: stat.met,
+            event_addr: stat.event_addr,
+            relay_addr: stat.relay_addr,
+            vote: proto::EventVote::from(stat.vote) as i32,
+        }
+    }
+}
+
+impl From<models::VoteActivityView> for proto::VoteActivity {
+    fn from(vote: models::VoteActivityView) -> Self {
+        Self {
+            direction: proto::EventDirection::from(vote.direction) as i32,
+            configuration_id: vote.configuration_id,
+            event_address: vote.event_address,
+            vote: proto::EventVote::from(vote.vote) as i32,
+            error: vote.error,
+        }
+    }
+}
+
+impl From<models::ActivityView> for proto::Activity {
+    fn from(activity: models::ActivityView) -> Self {
+        use proto::activity::Event;
+
+        let event = match activity.event {
+            models::ActivityEventView::EthEvent(data) => Event::EthEvent(data.into()),
+            models::ActivityEventView::TonEvent(data) => Event::TonEvent(data.into()),
+            models::ActivityEventView::VoteSent(vote) => Event::VoteSent(vote.into()),
+            models::ActivityEventView::VoteConfirmed(vote) => Event::VoteConfirmed(vote.into()),
+            models::ActivityEventView::VoteFailed(vote) => Event::VoteFailed(vote.into()),
+            models::ActivityEventView::ConfigurationChanged(configuration) => {
+                Event::ConfigurationChanged(proto::ConfigurationActivity {
+                    configuration_id: configuration.configuration_id,
+                    address: configuration.address,
+                    configuration_type: proto::EventConfigurationType::from(
+                        configuration.configuration_type,
+                    ) as i32,
+                    active: configuration.active,
+                })
+            }
+            models::ActivityEventView::StateChanged(change) => {
+                Event::StateChanged(proto::StateChange {
+                    from: proto::RelayState::from(change.from) as i32,
+                    to: proto::RelayState::from(change.to) as i32,
+                })
+            }
+            models::ActivityEventView::Lagged(lagged) => Event::Lagged(proto::Lagged {
+                skipped: lagged.skipped,
+            }),
+        };
+
+        Self {
+            timestamp: activity.timestamp,
+            event: Some(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tonic::Code;
+
+    use super::*;
+
+    #[test]
+    fn test_vote_queue() {
+        assert_eq!(vote_queue(0).unwrap(), proto::VoteQueue::Pending);
+        assert_eq!(vote_queue(1).unwrap(), proto::VoteQueue::Failed);
+        assert_eq!(vote_queue(2).unwrap(), proto::VoteQueue::Dead);
+        assert_eq!(vote_queue(3).unwrap_err().code(), Code::InvalidArgument);
+        assert_eq!(vote_queue(-1).unwrap_err().code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn test_page_query() {
+        let request = proto::VotesRequest {
+            queue: proto::VoteQueue::Failed as i32,
+            configuration_id: Some(1),
+            vote: proto::EventVote::Reject as i32,
+            page_size: 0,
+            order: proto::SortOrder::Desc as i32,
+        };
+        let query = models::PageQuery::try_from(&request).unwrap();
+        assert_eq!(query.configuration_id, Some(1));
+        assert_eq!(query.vote, Some(models::EventVote::Reject));
+        assert_eq!(query.order, Some(models::SortOrder::Desc));
+        // Default page size
+        assert_eq!(query.limit, None);
+
+        let request = proto::VotesRequest {
+            vote: proto::EventVote::Unspecified as i32,
+            page_size: 10,
+            ..request
+        };
+        let query = models::PageQuery::try_from(&request).unwrap();
+        assert_eq!(query.vote, None);
+        assert_eq!(query.limit, Some(10));
+
+        let request = proto::VotesRequest {
+            vote: 10,
+            ..request
+        };
+        assert!(models::PageQuery::try_from(&request).is_err());
+    }
+
+    #[test]
+    fn test_bridge_configuration() {
+        let data = proto::BridgeConfiguration {
+            nonce: 1,
+            bridge_update_required_confirmations: 2,
+            bridge_update_required_rejections: 3,
+            active: true,
+        };
+        let view = models::BridgeConfigurationView::try_from(data.clone()).unwrap();
+        assert_eq!(view.nonce, 1);
+        assert_eq!(view.bridge_update_required_confirmations, 2);
+        assert_eq!(view.bridge_update_required_rejections, 3);
+
+        let data = proto::BridgeConfiguration {
+            nonce: u32::from(u16::MAX) + 1,
+            ..data
+        };
+        assert!(models::BridgeConfigurationView::try_from(data).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use std::convert::TryFrom;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::json;
+use tokio::sync::oneshot::Receiver;
+use tokio::sync::{mpsc, RwLock};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+use warp::http::StatusCode;
+
+use relay_models::models;
+use relay_ton::contracts::{EthEventVoteData, TonEventVoteData};
+
+use self::proto::relay_server::{Relay, RelayServer};
+use super::audit::{Audit, Auditor};
+use super::auth::{ApiAuth, ApiCaller, ApiTls, AuthError};
+use super::error::{ApiError, ApiResult};
+use super::status;
+use crate::config::{ApiRole, RelayConfig};
+use crate::engine::activity::ActivityFilter;
+use crate::engine::models::State;
+use crate::models::SignedTonEventVoteData;
+
+mod convert;
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("relay");
+}
+
+/// Max number of items, which are sent to the client in advance
+const STREAM_BUFFER: usize = 100;
+
+type ItemStream<T> = ReceiverStream<Result<T, Status>>;
+
+pub async fn serve(
+    listen_address: SocketAddr,
+    config: RelayConfig,
+    state: Arc<RwLock<State>>,
+    auth: Arc<ApiAuth>,
+    tls: Option<ApiTls>,
+    auditor: Arc<Auditor>,
+    shutdown_signal: Receiver<()>,
+) {
+    log::info!("Starting gRPC server");
+
+    let service = RelayService {
+        config,
+        state,
+        auth,
+        auditor,
+    };
+
+    let mut server = Server::builder();
+    if let Some(tls) = tls {
+        let mut tls_config = ServerTlsConfig::new().identity(Identity::from_pem(tls.cert, tls.key));
+        if let Some(client_ca) = tls.client_ca {
+            tls_config = tls_config.client_ca_root(Certificate::from_pem(client_ca));
+        }
+        server = match server.tls_config(tls_config) {
+            Ok(server) => server,
+            Err(e) => {
+                log::error!("Invalid gRPC TLS config: {}", e);
+                return;
+            }
+        };
+    }
+
+    let shutdown_signal = async {
+        shutdown_signal.await.ok();
+    };
+    if let Err(e) = server
+        .add_service(RelayServer::new(service))
+        .serve_with_shutdown(listen_address, shutdown_signal)
+        .await
+    {
+        log::error!("gRPC server failed: {}", e);
+    }
+}
+
+/// Handles requests with the api operations shared with the REST api, so both apis behave the same
+struct RelayService {
+    config: RelayConfig,
+    state: Arc<RwLock<State>>,
+    auth: Arc<ApiAuth>,
+    auditor: Arc<Auditor>,
+}
+
+impl RelayService {
+    fn authenticate<T>(&self, request: &Request<T>, role: ApiRole) -> Result<ApiCaller, Status> {
+        let name = self
+            .auth
+            .check(authorization(request)?, role)
+            .map_err(auth_status)?;
+
+        Ok(ApiCaller {
+            name,
+            address: request.remote_addr(),
+        })
+    }
+
+    /// Authenticates the administrative request. Rejected attempts are logged
+    async fn audit<T>(&self, request: &Request<T>, action: &str) -> Result<Audit, Status> {
+        let caller = ApiCaller {
+            name: None,
+            address: request.remote_addr(),
+        };
+        match self.auth.check(authorization(request)?, ApiRole::Admin) {
+            Ok(name) => Ok(Audit::new(
+                self.auditor.clone(),
+                ApiCaller { name, ..caller },
+            )),
+            Err(e) => {
+                self.auditor.log_rejected(&caller, action, e);
+                Err(auth_status(e))
+            }
+        }
+    }
+}
+
+fn authorization<T>(request: &Request<T>) -> Result<Option<&str>, Status> {
+    request
+        .metadata()
+        .get("authorization")
+        .map(|header| header.to_str())
+        .transpose()
+        .map_err(|_| Status::unauthenticated("Invalid authorization metadata"))
+}
+
+fn auth_status(error: AuthError) -> Status {
+    match error {
+        AuthError::MissingToken | AuthError::InvalidToken => {
+            Status::unauthenticated(error.to_string())
+        }
+        AuthError::Forbidden => Status::permission_denied(error.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl Relay for RelayService {
+    async fn get_status(&self, request: Request<()>) -> Result<Response<proto::Status>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let status = status::get_status(self.state.clone()).await?;
+        Ok(Response::new(status.into()))
+    }
+
+    async fn init(&self, request: Request<proto::InitData>) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "init").await?;
+        let data = models::InitData::from(request.into_inner());
+        let params = super::init_audit_params(&data);
+        audit
+            .record_result(
+                "init",
+                params,
+                super::wait_for_init(data, self.config.clone(), self.state.clone()),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    async fn unlock(&self, request: Request<proto::Password>) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "unlock").await?;
+        let data = models::Password::from(request.into_inner());
+        audit
+            .record_result(
+                "unlock",
+                json!({}),
+                super::wait_for_password(data, self.config.clone(), self.state.clone()),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    async fn get_event_configurations(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<proto::EventConfigurations>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let configurations = super::get_event_configurations(self.state.clone()).await?;
+        Ok(Response::new(proto::EventConfigurations {
+            configurations: configurations.into_iter().map(From::from).collect(),
+        }))
+    }
+
+    async fn create_event_configuration(
+        &self,
+        request: Request<proto::NewEventConfiguration>,
+    ) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "event-configurations").await?;
+        let data = models::NewEventConfiguration::try_from(request.into_inner())?;
+        let params = json!(data);
+        audit
+            .record_result(
+                "event-configurations",
+                params,
+                super::create_event_configuration(self.state.clone(), data),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    async fn vote_for_event_configuration(
+        &self,
+        request: Request<proto::EventConfigurationVote>,
+    ) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "event-configurations/vote").await?;
+        let data = models::Voting::try_from(request.into_inner())?;
+        let params = json!(data);
+        audit
+            .record_result(
+                "event-configurations/vote",
+                params,
+                super::vote_for_event_configuration(self.state.clone(), data),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    async fn rescan_eth(
+        &self,
+        request: Request<proto::RescanEthRequest>,
+    ) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "rescan-eth").await?;
+        let data = models::RescanEthData::from(request.into_inner());
+        let params = json!(data);
+        audit
+            .record_result(
+                "rescan-eth",
+                params,
+                super::set_eth_block_height(self.state.clone(), data),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    async fn update_bridge_configuration(
+        &self,
+        request: Request<proto::BridgeConfiguration>,
+    ) -> Result<Response<()>, Status> {
+        let audit = self.audit(&request, "update-bridge-configuration").await?;
+        let data = models::BridgeConfigurationView::try_from(request.into_inner())?;
+        let params = json!(data);
+        audit
+            .record_result(
+                "update-bridge-configuration",
+                params,
+                super::update_bridge_configuration(self.state.clone(), data),
+            )
+            .await?;
+        Ok(Response::new(()))
+    }
+
+    type GetEthToTonVotesStream = ItemStream<proto::EthTonVote>;
+
+    async fn get_eth_to_ton_votes(
+        &self,
+        request: Request<proto::VotesRequest>,
+    ) -> Result<Response<Self::GetEthToTonVotesStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let request = request.into_inner();
+        let query = models::PageQuery::try_from(&request)?;
+
+        let state = self.state.clone();
+        let stream = match convert::vote_queue(request.queue)? {
+            proto::VoteQueue::Pending => stream_pages(query, move |query| {
+                status::pending::<EthEventVoteData, EthEventVoteData>(state.clone(), query)
+            }),
+            proto::VoteQueue::Failed => stream_pages(query, move |query| {
+                status::failed::<EthEventVoteData, EthEventVoteData>(state.clone(), query)
+            }),
+            proto::VoteQueue::Dead => stream_pages(query, move |query| {
+                status::dead::<EthEventVoteData, EthEventVoteData>(state.clone(), query)
+            }),
+        };
+        Ok(Response::new(stream))
+    }
+
+    type GetTonToEthVotesStream = ItemStream<proto::TonEthVote>;
+
+    async fn get_ton_to_eth_votes(
+        &self,
+        request: Request<proto::VotesRequest>,
+    ) -> Result<Response<Self::GetTonToEthVotesStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let request = request.into_inner();
+        let query = models::PageQuery::try_from(&request)?;
+
+        let state = self.state.clone();
+        let stream = match convert::vote_queue(request.queue)? {
+            proto::VoteQueue::Pending => stream_pages(query, move |query| {
+                status::pending::<SignedTonEventVoteData, TonEventVoteData>(state.clone(), query)
+            }),
+            proto::VoteQueue::Failed => stream_pages(query, move |query| {
+                status::failed::<SignedTonEventVoteData, TonEventVoteData>(state.clone(), query)
+            }),
+            proto::VoteQueue::Dead => stream_pages(query, move |query| {
+                status::dead::<SignedTonEventVoteData, TonEventVoteData>(state.clone(), query)
+            }),
+        };
+        Ok(Response::new(stream))
+    }
+
+    type GetEthToTonQueueStream = ItemStream<proto::EthQueuedEvent>;
+
+    async fn get_eth_to_ton_queue(
+        &self,
+        request: Request<proto::EthQueueRequest>,
+    ) -> Result<Response<Self::GetEthToTonQueueStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let query = models::PageQuery::try_from(request.get_ref())?;
+
+        let state = self.state.clone();
+        let stream = stream_pages(query, move |query| status::eth_queue(state.clone(), query));
+        Ok(Response::new(stream))
+    }
+
+    type GetTonToEthQueueStream = ItemStream<proto::TonQueuedEvent>;
+
+    async fn get_ton_to_eth_queue(
+        &self,
+        request: Request<proto::TonQueueRequest>,
+    ) -> Result<Response<Self::GetTonToEthQueueStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let configuration_id = request.get_ref().configuration_id;
+        let query = models::PageQuery::try_from(request.get_ref())?;
+
+        let state = self.state.clone();
+        let stream = stream_pages(query, move |query| {
+            status::ton_queue(state.clone(), configuration_id, query)
+        });
+        Ok(Response::new(stream))
+    }
+
+    type GetEthToTonStatsStream = ItemStream<proto::EthTxStat>;
+
+    async fn get_eth_to_ton_stats(
+        &self,
+        request: Request<proto::StatsRequest>,
+    ) -> Result<Response<Self::GetEthToTonStatsStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let query = models::PageQuery::try_from(request.get_ref())?;
+
+        let state = self.state.clone();
+        let stream = stream_pages(query, move |query| {
+            status::eth_relay_stats(state.clone(), query)
+        });
+        Ok(Response::new(stream))
+    }
+
+    type GetTonToEthStatsStream = ItemStream<proto::TonTxStat>;
+
+    async fn get_ton_to_eth_stats(
+        &self,
+        request: Request<proto::StatsRequest>,
+    ) -> Result<Response<Self::GetTonToEthStatsStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let query = models::PageQuery::try_from(request.get_ref())?;
+
+        let state = self.state.clone();
+        let stream = stream_pages(query, move |query| {
+            status::ton_relay_stats(state.clone(), query)
+        });
+        Ok(Response::new(stream))
+    }
+
+    type WatchActivityStream = ItemStream<proto::Activity>;
+
+    async fn watch_activity(
+        &self,
+        request: Request<proto::ActivityRequest>,
+    ) -> Result<Response<Self::WatchActivityStream>, Status> {
+        self.authenticate(&request, ApiRole::Monitor)?;
+        let query = models::ActivityQuery::try_from(request.into_inner())?;
+        let filter =
+            ActivityFilter::new(&query).map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let activity = self.state.read().await.activity.subscribe(filter);
+
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            tokio::pin!(activity);
+            loop {
+                tokio::select! {
+                    activity = activity.next() => match activity {
+                        Some(activity) => {
+                            if tx.send(Ok(activity.into())).await.is_err() {
+                                break;
+                            }
+                        }
+                        None => break,
+                    },
+                    // Stop waiting for the activity after the client disconnects
+                    _ = tx.closed() => break,
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Reads pages until the last one, sending their items to the client
+fn stream_pages<V, T, F, Fut>(mut query: models::PageQuery, fetch: F) -> ItemStream<T>
+where
+    V: Send + 'static,
+    T: From<V> + Send + 'static,
+    F: Fn(models::PageQuery) -> Fut + Send + 'static,
+    Fut: Future<Output = ApiResult<models::PageView<V>>> + Send,
+{
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+
+    tokio::spawn(async move {
+        loop {
+            let page = match fetch(query.clone()).await {
+                Ok(page) => page,
+                Err(e) => {
+                    let _ = tx.send(Err(e.into())).await;
+                    return;
+                }
+            };
+
+            for item in page.items {
+                // Fails only if the client disconnected
+                if tx.send(Ok(T::from(item))).await.is_err() {
+                    return;
+                }
+            }
+
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return,
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        match error.status {
+            StatusCode::BAD_REQUEST => Status::invalid_argument(error.message),
+            StatusCode::NOT_FOUND => Status::not_found(error.message),
+            // Used for the requests, which are not allowed in the current relay state
+            StatusCode::FORBIDDEN | StatusCode::METHOD_NOT_ALLOWED => {
+                Status::failed_precondition(error.message)
+            }
+            StatusCode::LOCKED | StatusCode::SERVICE_UNAVAILABLE => {
+                Status::unavailable(error.message)
+            }
+            _ => Status::internal(error.message),
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use futures::future;
+use relay_eth::SyncedHeight;
+use tokio::sync::{Mutex, RwLock};
+use warp::http::StatusCode;
+use warp::{reply, Reply};
+
+use relay_models::models::*;
+use relay_storage::Db;
+
+use crate::config::{HealthSettings, RelayConfig};
+use crate::db::StorageProbe;
+use crate::engine::bridge::Bridge;
+use crate::engine::models::*;
+
+/// Minimal interval between the storage probes
+const STORAGE_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Responds while the api server is running
+pub async fn live() -> Result<impl Reply, Infallible> {
+    let view = ComponentHealthView {
+        healthy: true,
+        error: None,
+    };
+    Ok(reply::with_status(
+        serde_json::to_string(&view).expect("Shouldn't fail"),
+        StatusCode::OK,
+    ))
+}
+
+/// Checks all components. Responds with `503` if any of them is unhealthy.
+/// Errors and details are only shown to the `detailed` callers
+pub async fn ready(
+    state: Arc<RwLock<State>>,
+    config: RelayConfig,
+    storage_cache: Arc<StorageHealthCache>,
+    detailed: bool,
+) -> Result<impl Reply, Infallible> {
+    let settings = &config.health;
+
+    // State is locked for writing while the relay is being unlocked.
+    // It is only held to copy the handles, so checks don't block the unlock
+    let (db, keystore, bridge) =
+        match tokio::time::timeout(settings.check_timeout, state.read()).await {
+            Ok(state) => {
+                let bridge = match &state.bridge_state {
+                    BridgeState::Running(bridge) => Ok(bridge.clone()),
+                    _ => Err("Relay is not running".to_owned()),
+                };
+                (
+                    Some(state.state_manager.clone()),
+                    keystore_health(&state.bridge_state),
+                    bridge,
+                )
+            }
+            Err(_) => {
+                let error = "Relay state is busy".to_owned();
+                let keystore = KeystoreHealthView {
+                    error: Some(error.clone()),
+                    ..Default::default()
+                };
+                (None, keystore, Err(error))
+            }
+        };
+
+    let storage = async {
+        match db {
+            Some(db) => storage_cache.check(db, settings.check_timeout).await,
+            None => ComponentHealthView {
+                healthy: false,
+                error: Some("Relay state is busy".to_owned()),
+            },
+        }
+    };
+    let components = async {
+        match bridge {
+            Ok(bridge) => {
+                future::join3(
+                    eth_health(&bridge, settings),
+                    ton_health(&bridge, settings),
+                    configurations_health(&bridge, settings),
+                )
+                .await
+            }
+            Err(error) => (
+                EthHealthView {
+                    error: Some(error.clone()),
+                    ..Default::default()
+                },
+                TonHealthView {
+                    error: Some(error.clone()),
+                    ..Default::default()
+                },
+                ConfigurationsHealthView {
+                    error: Some(error),
+                    ..Default::default()
+                },
+            ),
+        }
+    };
+    let (storage, (eth, ton, configurations)) = future::join(storage, components).await;
+
+    let mut view = ReadinessView {
+        ready: eth.healthy
+            && ton.healthy
+            && storage.healthy
+            && keystore.healthy
+            && configurations.healthy,
+        eth,
+        ton,
+        storage,
+        keystore,
+        configurations,
+    };
+    let status = readiness_status(&view);
+    if !detailed {
+        view = without_details(&view);
+    }
+    Ok(reply::with_status(
+        serde_json::to_string(&view).expect("Shouldn't fail"),
+        status,
+    ))
+}
+
+/// Latest result of the storage probe. The probe writes and flushes the db,
+/// so it runs at most once per [`STORAGE_PROBE_INTERVAL`]
+#[derive(Default)]
+pub struct StorageHealthCache {
+    latest: Mutex<Option<(Instant, ComponentHealthView)>>,
+}
+
+impl StorageHealthCache {
+    async fn check(&self, db: Db, timeout: Duration) -> ComponentHealthView {
+        let check = async {
+            // Concurrent requests wait for the running probe instead of starting their own
+            let mut latest = self.latest.lock().await;
+            if let Some((checked_at, health)) = &*latest {
+                if checked_at.elapsed() < STORAGE_PROBE_INTERVAL {
+                    return Ok(health.clone());
+                }
+            }
+
+            let health = tokio::task::spawn_blocking(move || storage_health(&db)).await?;
+            *latest = Some((Instant::now(), health.clone()));
+            Ok(health)
+        };
+
+        match with_timeout(timeout, check).await {
+            Ok(health) => health,
+            Err(e) => ComponentHealthView {
+                healthy: false,
+                error: Some(format!("Storage probe failed: {}", e)),
+            },
+        }
+    }
+}
+
+fn keystore_health(bridge_state: &BridgeState) -> KeystoreHealthView {
+    let error = match bridge_state {
+        BridgeState::Uninitialized => Some("Relay is not initialized"),
+        BridgeState::Locked => Some("Relay is locked"),
+        BridgeState::Running(_) => None,
+    };
+    KeystoreHealthView {
+        healthy: error.is_none(),
+        error: error.map(str::to_owned),
+        state: Some(bridge_state.view()),
+    }
+}
+
+fn storage_health(db: &Db) -> ComponentHealthView {
+    match StorageProbe::new(db).and_then(|probe| probe.check()) {
+        Ok(()) => ComponentHealthView {
+            healthy: true,
+            error: None,
+        },
+        Err(e) => ComponentHealthView {
+            healthy: false,
+            error: Some(format!("Storage is not writable: {}", e)),
+        },
+    }
+}
+
+async fn eth_health(bridge: &Bridge, settings: &HealthSettings) -> EthHealthView {
+    let (synced_height, scanned_height) =
+        match with_timeout(settings.check_timeout, bridge.eth_heights()).await {
+            Ok(heights) => heights,
+            Err(e) => {
+                return EthHealthView {
+                    error: Some(format!("ETH node is unreachable: {}", e)),
+                    ..Default::default()
+                }
+            }
+        };
+
+    let node_height = synced_height.as_u64();
+    let (lag, error) = eth_lag(&synced_height, scanned_height, settings.max_eth_lag);
+
+    EthHealthView {
+        healthy: error.is_none(),
+        error,
+        node_height: Some(node_height),
+        scanned_height: Some(scanned_height),
+        lag: Some(lag),
+    }
+}
+
+async fn ton_health(bridge: &Bridge, settings: &HealthSettings) -> TonHealthView {
+    let block_utime = match with_timeout(settings.check_timeout, async {
+        Ok(bridge.ton_block_utime().await)
+    })
+    .await
+    {
+        Ok(block_utime) => block_utime,
+        Err(e) => {
+            return TonHealthView {
+                error: Some(format!("TON transport is unavailable: {}", e)),
+                ..Default::default()
+            }
+        }
+    };
+
+    let (lag, error) = ton_lag(
+        chrono::Utc::now().timestamp(),
+        block_utime,
+        settings.max_ton_lag,
+    );
+
+    TonHealthView {
+        healthy: error.is_none(),
+        error,
+        block_utime: Some(block_utime),
+        lag: Some(lag),
+    }
+}
+
+async fn configurations_health(
+    bridge: &Bridge,
+    settings: &HealthSettings,
+) -> ConfigurationsHealthView {
+    let (active, missing) =
+        match with_timeout(settings.check_timeout, bridge.unsubscribed_configurations()).await {
+            Ok(configurations) => configurations,
+            Err(e) => {
+                return ConfigurationsHealthView {
+                    error: Some(format!("Failed to get active configurations: {}", e)),
+                    ..Default::default()
+                }
+            }
+        };
+
+    let error = if missing.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "Not subscribed to {} of {} active configurations",
+            missing.len(),
+            active
+        ))
+    };
+
+    ConfigurationsHealthView {
+        healthy: error.is_none(),
+        error,
+        active: Some(active),
+        missing,
+    }
+}
+
+/// Returns the number of not scanned blocks and the reason, why ETH is unhealthy
+fn eth_lag(
+    synced_height: &SyncedHeight,
+    scanned_height: u64,
+    max_lag: u64,
+) -> (u64, Option<String>) {
+    let lag = synced_height.as_u64().saturating_sub(scanned_height);
+    let error = match synced_height {
+        SyncedHeight::NotSynced(_) => Some("ETH node is syncing".to_owned()),
+        SyncedHeight::Synced(_) if lag > max_lag => {
+            Some(format!("Latest scanned block is {} blocks behind", lag))
+        }
+        SyncedHeight::Synced(_) => None,
+    };
+    (lag, error)
+}
+
+/// Returns the age of the latest TON block and the reason, why TON is unhealthy
+fn ton_lag(now: i64, block_utime: u32, max_lag: Duration) -> (u64, Option<String>) {
+    let lag = (now - block_utime as i64).max(0) as u64;
+    let error = if lag > max_lag.as_secs() {
+        Some(format!("Latest TON block is {} seconds old", lag))
+    } else {
+        None
+    };
+    (lag, error)
+}
+
+fn readiness_status(view: &ReadinessView) -> StatusCode {
+    if view.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// Only health flags of the components are shown to the unauthenticated callers
+fn without_details(view: &ReadinessView) -> ReadinessView {
+    ReadinessView {
+        ready: view.ready,
+        eth: EthHealthView {
+            healthy: view.eth.healthy,
+            ..Default::default()
+        },
+        ton: TonHealthView {
+            healthy: view.ton.healthy,
+            ..Default::default()
+        },
+        storage: ComponentHealthView {
+            healthy: view.storage.healthy,
+            error: None,
+        },
+        keystore: KeystoreHealthView {
+            healthy: view.keystore.healthy,
+            ..Default::default()
+        },
+        configurations: ConfigurationsHealthView {
+            healthy: view.configurations.healthy,
+            ..Default::default()
+        },
+    }
+}
+
+async fn with_timeout<T, F>(timeout: Duration, future: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("Timed out after {:?}", timeout)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_eth_lag() {
+        let (lag, error) = eth_lag(&SyncedHeight::Synced(110), 100, 10);
+        assert_eq!(lag, 10);
+        assert!(error.is_none());
+
+        let (lag, error) = eth_lag(&SyncedHeight::Synced(111), 100, 10);
+        assert_eq!(lag, 11);
+        assert!(error.is_some());
+
+        // Scanned height can be ahead of the node after the switch to another one
+        let (lag, error) = eth_lag(&SyncedHeight::Synced(90), 100, 10);
+        assert_eq!(lag, 0);
+        assert!(error.is_none());
+
+        let (_, error) = eth_lag(&SyncedHeight::NotSynced(100), 100, 10);
+        assert!(error.is_some());
+    }
+
+    #[test]
+    fn test_ton_lag() {
+        let max_lag = Duration::from_secs(60);
+
+        let (lag, error) = ton_lag(1060, 1000, max_lag);
+        assert_eq!(lag, 60);
+        assert!(error.is_none());
+
+        let (lag, error) = ton_lag(1061, 1000, max_lag);
+        assert_eq!(lag, 61);
+        assert!(error.is_some());
+
+        // Clock of the relay can be behind
+        let (lag, error) = ton_lag(990, 1000, max_lag);
+        assert_eq!(lag, 0);
+        assert!(error.is_none());
+    }
+
+    #[test]
+    fn test_readiness() {
+        let healthy = ReadinessView {
+            ready: true,
+            eth: EthHealthView {
+                healthy: true,
+                lag: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(readiness_status(&healthy), StatusCode::OK);
+
+        let unhealthy = ReadinessView {
+            ready: false,
+            configurations: ConfigurationsHealthView {
+                error: Some("Not subscribed".to_owned()),
+                missing: vec![1],
+                ..Default::default()
+            },
+            ..healthy
+        };
+        assert_eq!(
+            readiness_status(&unhealthy),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let view = without_details(&unhealthy);
+        assert!(!view.ready);
+        assert!(view.eth.healthy);
+        assert_eq!(view.eth.lag, None);
+        assert_eq!(view.configurations.error, None);
+        assert!(view.configurations.missing.is_empty());
+    }
+}
@@ -1,24 +1,49 @@
 use bip39::Language;
+use secstr::{SecStr, SecVec};
+use serde_json::json;
 use tokio::sync::oneshot::Receiver;
 use tokio::sync::RwLock;
 use warp::http::StatusCode;
 use warp::{reply, Filter, Reply};
+use zeroize::Zeroizing;
 
 use relay_models::models::*;
 use relay_ton::contracts::{BridgeConfiguration, EthEventVoteData, TonEventVoteData, VoteData};
 use relay_ton::transport::Transport;
 
-use crate::config::{RelayConfig, TonTransportConfig};
-use crate::crypto::key_managment::KeyData;
+use self::audit::Audit;
+use crate::config::{ApiRole, RelayConfig, TonTransportConfig};
+use crate::crypto::key_managment::{Kdf, KeyData};
 use crate::crypto::recovery::*;
+use crate::crypto::signer::LocalSigner;
 use crate::engine::models::*;
 use crate::models::SignedTonEventVoteData;
 use crate::prelude::*;
 
+use self::error::*;
+
+pub use audit::Auditor;
+pub use auth::{generate_token, ApiAuth, ApiTls};
+
+mod activity;
+mod audit;
+mod auth;
+mod backup;
 mod docs;
+mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod health;
 mod status;
 
-pub async fn serve(config: RelayConfig, state: Arc<RwLock<State>>, shutdown_signal: Receiver<()>) {
+pub async fn serve(
+    config: RelayConfig,
+    state: Arc<RwLock<State>>,
+    auth: Arc<ApiAuth>,
+    tls: Option<ApiTls>,
+    auditor: Arc<Auditor>,
+    shutdown_signal: Receiver<()>,
+) {
     log::info!("Starting server");
     let serve_address = config.listen_address;
     fn json_data<T>() -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone
@@ -30,134 +55,391 @@ pub async fn serve(config: RelayConfig, state: Arc<RwLock<State>>, shutdown_sign
 
     let state = warp::any().map(move || (Arc::clone(&state), config.clone()));
 
+    let monitor = auth::authorize(auth.clone(), ApiRole::Monitor);
+    let health_auth = auth.clone();
+    let admin = audit::authenticate_admin(auth, auditor);
+
     let swagger = warp::path!("swagger.yaml")
         .and(warp::get())
+        .and(monitor.clone())
         .map(docs::swagger)
         .boxed();
 
     let init = warp::path!("init")
         .and(warp::post())
+        .and(admin.clone())
         .and(json_data::<InitData>())
         .and(state.clone())
-        .and_then(|data, (state, config)| wait_for_init(data, config, state))
+        .and_then(|audit: Audit, data: InitData, (state, config)| {
+            let params = init_audit_params(&data);
+            audit.record(
+                "init",
+                params,
+                message_reply(
+                    wait_for_init(data, config, state),
+                    "Initialized successfully",
+                    StatusCode::ACCEPTED,
+                ),
+            )
+        })
         .boxed();
 
     let unlock = warp::path!("unlock")
         .and(warp::post())
+        .and(admin.clone())
+        .and(json_data::<Password>())
+        .and(state.clone())
+        .and_then(|audit: Audit, data, (state, config)| {
+            audit.record(
+                "unlock",
+                json!({}),
+                message_reply(
+                    wait_for_password(data, config, state),
+                    "Password accepted",
+                    StatusCode::ACCEPTED,
+                ),
+            )
+        })
+        .boxed();
+
+    let change_password = warp::path!("change-password")
+        .and(warp::post())
+        .and(admin.clone())
+        .and(json_data::<ChangePassword>())
+        .and(state.clone())
+        .and_then(|audit: Audit, data, (state, config)| {
+            audit.record(
+                "change-password",
+                json!({}),
+                change_password(data, config, state),
+            )
+        })
+        .boxed();
+
+    let reencrypt_keystore = warp::path!("keystore" / "reencrypt")
+        .and(warp::post())
+        .and(admin.clone())
         .and(json_data::<Password>())
         .and(state.clone())
-        .and_then(|data, (state, config)| wait_for_password(data, config, state))
+        .and_then(|audit: Audit, data, (state, config)| {
+            audit.record(
+                "keystore/reencrypt",
+                json!({}),
+                reencrypt_keystore(data, config, state),
+            )
+        })
+        .boxed();
+
+    let rotate_eth_key = warp::path!("keystore" / "rotate-eth-key")
+        .and(warp::post())
+        .and(admin.clone())
+        .and(json_data::<RotateEthKey>())
+        .and(state.clone())
+        .and_then(|audit: Audit, data: RotateEthKey, (state, _)| {
+            let params = json!({
+                "language": data.language,
+                "eth_derivation_path": data.eth_derivation_path,
+                "eth_keystore": data.eth_keystore.is_some(),
+            });
+            audit.record(
+                "keystore/rotate-eth-key",
+                params,
+                rotate_eth_key(data, state),
+            )
+        })
+        .boxed();
+
+    let public_keys = warp::path!("keystore" / "public-keys")
+        .and(warp::get())
+        .and(monitor.clone())
+        .and(state.clone())
+        .and_then(|(state, _)| get_public_keys(state))
         .boxed();
 
     let retry_failed = warp::path!("retry-failed")
         .and(warp::post())
+        .and(admin.clone())
         .and(state.clone())
-        .and_then(|(state, _)| status::retry_failed(state))
+        .and_then(|audit: Audit, (state, _)| {
+            audit.record("retry-failed", json!({}), status::retry_failed(state))
+        })
         .boxed();
 
     let rescan_eth = warp::path!("rescan-eth")
         .and(warp::post())
+        .and(admin.clone())
         .and(json_data::<RescanEthData>())
         .and(state.clone())
-        .and_then(|data, (state, _)| set_eth_block_height(state, data))
+        .and_then(|audit: Audit, data: RescanEthData, (state, _)| {
+            let params = json!(data);
+            audit.record(
+                "rescan-eth",
+                params,
+                message_reply(set_eth_block_height(state, data), "OK", StatusCode::OK),
+            )
+        })
         .boxed();
 
     let status = warp::path!("status")
         .and(warp::get())
+        .and(monitor.clone())
         .and(state.clone())
-        .and_then(|(state, _)| status::get_status(state))
+        .and_then(|(state, _)| json_reply(status::get_status(state)))
         .boxed();
 
     let get_event_configurations = warp::path!("event-configurations")
         .and(warp::get())
+        .and(monitor.clone())
         .and(state.clone())
-        .and_then(|(state, _)| get_event_configurations(state))
+        .and_then(|(state, _)| json_reply(get_event_configurations(state)))
         .boxed();
 
     // TODO: add request for getting event configuration by id
 
     let create_event_configuration = warp::path!("event-configurations")
         .and(warp::post())
+        .and(admin.clone())
         .and(json_data::<NewEventConfiguration>())
         .and(state.clone())
-        .and_then(|data, (state, _)| create_event_configuration(state, data))
+        .and_then(|audit: Audit, data: NewEventConfiguration, (state, _)| {
+            let params = json!(data);
+            audit.record(
+                "event-configurations",
+                params,
+                message_reply(create_event_configuration(state, data), "", StatusCode::OK),
+            )
+        })
         .boxed();
 
     let vote_for_event_configuration = warp::path!("event-configurations" / "vote")
         .and(warp::post())
+        .and(admin.clone())
         .and(json_data::<Voting>())
         .and(state.clone())
-        .and_then(|data, (state, _)| vote_for_event_configuration(state, data))
+        .and_then(|audit: Audit, data: Voting, (state, _)| {
+            let params = json!(data);
+            audit.record(
+                "event-configurations/vote",
+                params,
+                message_reply(
+                    vote_for_event_configuration(state, data),
+                    "",
+                    StatusCode::OK,
+                ),
+            )
+        })
         .boxed();
 
     let pending_transactions_eth_to_ton = warp::path!("eth-to-ton" / "pending")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| {
-            status::pending::<EthEventVoteData, EthEventVoteData, EthTonTransactionView>(state)
+        .and_then(|query, (state, _)| {
+            json_reply(status::pending::<EthEventVoteData, EthEventVoteData>(
+                state, query,
+            ))
         })
         .boxed();
 
     let failed_transactions_eth_to_ton = warp::path!("eth-to-ton" / "failed")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| {
-            status::failed::<EthEventVoteData, EthEventVoteData, EthTonTransactionView>(state)
+        .and_then(|query, (state, _)| {
+            json_reply(status::failed::<EthEventVoteData, EthEventVoteData>(
+                state, query,
+            ))
+        })
+        .boxed();
+
+    let dead_transactions_eth_to_ton = warp::path!("eth-to-ton" / "dead")
+        .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
+        .and(state.clone())
+        .and_then(|query, (state, _)| {
+            json_reply(status::dead::<EthEventVoteData, EthEventVoteData>(
+                state, query,
+            ))
         })
         .boxed();
 
     let queued_transactions_eth_to_ton = warp::path!("eth-to-ton" / "queued")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| status::eth_queue(state))
+        .and_then(|query, (state, _)| json_reply(status::eth_queue(state, query)))
         .boxed();
 
     let eth_relay_stats = warp::path!("eth-to-ton" / "stats")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| status::eth_relay_stats(state))
+        .and_then(|query, (state, _)| json_reply(status::eth_relay_stats(state, query)))
         .boxed();
 
     let pending_transactions_ton_to_eth = warp::path!("ton-to-eth" / "pending")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| {
-            status::pending::<SignedTonEventVoteData, TonEventVoteData, TonEthTransactionView>(
-                state,
-            )
+        .and_then(|query, (state, _)| {
+            json_reply(status::pending::<SignedTonEventVoteData, TonEventVoteData>(
+                state, query,
+            ))
         })
         .boxed();
 
     let failed_transactions_ton_to_eth = warp::path!("ton-to-eth" / "failed")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|(state, _)| {
-            status::failed::<SignedTonEventVoteData, TonEventVoteData, TonEthTransactionView>(state)
+        .and_then(|query, (state, _)| {
+            json_reply(status::failed::<SignedTonEventVoteData, TonEventVoteData>(
+                state, query,
+            ))
+        })
+        .boxed();
+
+    let dead_transactions_ton_to_eth = warp::path!("ton-to-eth" / "dead")
+        .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
+        .and(state.clone())
+        .and_then(|query, (state, _)| {
+            json_reply(status::dead::<SignedTonEventVoteData, TonEventVoteData>(
+                state, query,
+            ))
         })
         .boxed();
 
     let queued_transactions_ton_to_eth = warp::path!("ton-to-eth" / "queued" / u32)
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
         .and(state.clone())
-        .and_then(|configuration_id, (state, _)| status::ton_queue(state, configuration_id))
+        .and_then(|configuration_id, query, (state, _)| {
+            json_reply(status::ton_queue(state, configuration_id, query))
+        })
         .boxed();
 
     let ton_relay_stats = warp::path!("ton-to-eth" / "stats")
         .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<PageQuery>())
+        .and(state.clone())
+        .and_then(|query, (state, _)| json_reply(status::ton_relay_stats(state, query)))
+        .boxed();
+
+    let eth_event_journal = warp::path!("events" / "eth" / String / u32)
+        .and(warp::get())
+        .and(monitor.clone())
+        .and(state.clone())
+        .and_then(|tx_hash, event_index, (state, _)| {
+            json_reply(status::eth_event_journal(state, tx_hash, event_index))
+        })
+        .boxed();
+
+    let ton_event_journal = warp::path!("events" / "ton" / String)
+        .and(warp::get())
+        .and(monitor.clone())
         .and(state.clone())
-        .and_then(|(state, _)| status::ton_relay_stats(state))
+        .and_then(|address, (state, _)| json_reply(status::ton_event_journal(state, address)))
+        .boxed();
+
+    let activity_events = warp::path!("activity")
+        .and(warp::get())
+        .and(monitor.clone())
+        .and(warp::query::<ActivityQuery>())
+        .and(state.clone())
+        .and_then(|query, (state, _)| activity::sse(state, query))
+        .boxed();
+
+    let activity_websocket = warp::path!("activity" / "ws")
+        .and(warp::ws())
+        .and(monitor.clone())
+        .and(warp::query::<ActivityQuery>())
+        .and(state.clone())
+        .and_then(|ws, query, (state, _)| activity::websocket(ws, state, query))
+        .boxed();
+
+    // Health probes are not authenticated to be usable by orchestrators
+    let storage_health = Arc::new(health::StorageHealthCache::default());
+    let health_live = warp::path!("health" / "live")
+        .and(warp::get())
+        .and_then(health::live)
+        .boxed();
+
+    let health_ready = warp::path!("health" / "ready")
+        .and(warp::get())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(state.clone())
+        .and_then(move |header: Option<String>, (state, config)| {
+            // Failure details are only shown to the callers with a token
+            let detailed = health_auth
+                .check(header.as_deref(), ApiRole::Monitor)
+                .is_ok();
+            health::ready(state, config, storage_health.clone(), detailed)
+        })
+        .boxed();
+
+    let db_backup = warp::path!("db" / "backup")
+        .and(warp::post())
+        .and(admin.clone())
+        .and(state.clone())
+        .and_then(|audit: Audit, (state, config)| {
+            audit.record("db/backup", json!({}), backup::backup(state, config))
+        })
+        .boxed();
+
+    let db_export = warp::path!("db" / "export")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(state.clone())
+        .and_then(|audit: Audit, (state, config)| {
+            audit.record("db/export", json!({}), backup::export(state, config))
+        })
+        .boxed();
+
+    let get_audit = warp::path!("audit")
+        .and(warp::get())
+        .and(admin.clone())
+        .and(warp::query::<AuditQuery>())
+        .and(state.clone())
+        .and_then(|_, query, (state, _)| audit::get_audit(state, query))
         .boxed();
 
     let update_bridge_configuration = warp::path!("update-bridge-configuration")
         .and(warp::post())
+        .and(admin.clone())
         .and(state.clone())
         .and(json_data::<BridgeConfigurationView>())
-        .and_then(|(state, _), data| update_bridge_configuration(state, data))
+        .and_then(|audit: Audit, (state, _), data: BridgeConfigurationView| {
+            let params = json!(data);
+            audit.record(
+                "update-bridge-configuration",
+                params,
+                message_reply(
+                    update_bridge_configuration(state, data),
+                    "ok",
+                    StatusCode::OK,
+                ),
+            )
+        })
         .boxed();
 
     let routes = swagger
         .or(init)
         .or(unlock)
+        .or(change_password)
+        .or(reencrypt_keystore)
+        .or(rotate_eth_key)
+        .or(public_keys)
         .or(retry_failed)
         .or(rescan_eth)
         .or(status)
@@ -166,25 +448,51 @@ pub async fn serve(config: RelayConfig, state: Arc<RwLock<State>>, shutdown_sign
         .or(vote_for_event_configuration)
         .or(pending_transactions_eth_to_ton)
         .or(failed_transactions_eth_to_ton)
+        .or(dead_transactions_eth_to_ton)
         .or(queued_transactions_eth_to_ton)
         .or(eth_relay_stats)
         .or(pending_transactions_ton_to_eth)
         .or(failed_transactions_ton_to_eth)
+        .or(dead_transactions_ton_to_eth)
         .or(queued_transactions_ton_to_eth)
         .or(ton_relay_stats)
-        .or(update_bridge_configuration);
+        .or(eth_event_journal)
+        .or(ton_event_journal)
+        .or(activity_events)
+        .or(activity_websocket)
+        .or(health_live)
+        .or(health_ready)
+        .or(db_backup)
+        .or(db_export)
+        .or(update_bridge_configuration)
+        .or(get_audit)
+        .recover(auth::handle_rejection);
 
-    let server = warp::serve(routes);
-    let (_, server) = server.bind_with_graceful_shutdown(serve_address, async {
+    let shutdown_signal = async {
         shutdown_signal.await.ok();
-    });
-    server.await;
+    };
+    let server = warp::serve(routes);
+    match tls {
+        Some(tls) => {
+            let server = server.tls().cert(tls.cert).key(tls.key);
+            let server = match tls.client_ca {
+                Some(client_ca) => server.client_auth_required(client_ca),
+                None => server,
+            };
+            let (_, server) = server.bind_with_graceful_shutdown(serve_address, shutdown_signal);
+            server.await;
+        }
+        None => {
+            let (_, server) = server.bind_with_graceful_shutdown(serve_address, shutdown_signal);
+            server.await;
+        }
+    }
 }
 
 pub async fn update_bridge_configuration(
     state: Arc<RwLock<State>>,
     data: BridgeConfigurationView,
-) -> Result<impl Reply, Infallible> {
+) -> ApiResult<()> {
     use ethabi::{Token, Uint};
     let state = state.read().await;
     return if let BridgeState::Running(a) = &state.bridge_state {
@@ -206,7 +514,14 @@ pub async fn update_bridge_configuration(
 
         //todo check packing and correctness
         let mut eth_bytes = ethabi::encode(&tokens);
-        let mut signature = a.sign_with_eth_key(&*eth_bytes);
+        let mut signature = match a.sign_with_eth_key(&*eth_bytes).await {
+            Ok(signature) => signature,
+            Err(e) => {
+                let message = format!("Failed signing bridge configuration: {}", e);
+                log::error!("{}", &message);
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message));
+            }
+        };
         eth_bytes.append(&mut signature);
         let vote_data = VoteData {
             signature: eth_bytes,
@@ -214,29 +529,23 @@ pub async fn update_bridge_configuration(
         if let Err(e) = a.update_bridge_configuration(bridge_conf, vote_data).await {
             let message = format!("Failed updating bridge configuration: {}", e);
             log::error!("{}", &message);
-            return Ok(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            ));
+            return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message));
         }
-        Ok(reply::with_status("ok".to_string(), StatusCode::OK))
+        Ok(())
     } else {
-        Ok(reply::with_status(
-            "Bridge is not running".to_string(),
+        Err(ApiError::new(
             StatusCode::METHOD_NOT_ALLOWED,
+            "Bridge is not running",
         ))
     };
 }
 
-async fn vote_for_event_configuration(
-    state: Arc<RwLock<State>>,
-    voting: Voting,
-) -> Result<impl Reply, Infallible> {
+async fn vote_for_event_configuration(state: Arc<RwLock<State>>, voting: Voting) -> ApiResult<()> {
     let (configuration_id, voting) = match FromRequest::<_>::try_from(voting) {
         Ok(voting) => voting,
         Err(err) => {
             log::error!("{}", err);
-            return Ok(reply::with_status(err.to_string(), StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, err.to_string()));
         }
     };
 
@@ -246,26 +555,24 @@ async fn vote_for_event_configuration(
         _ => {
             let err = "Bridge was not initialized";
             log::error!("{}", err);
-            return Ok(reply::with_status(err.to_string(), StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, err));
         }
     };
 
-    Ok(
-        match bridge
-            .vote_for_event_configuration(configuration_id, voting)
-            .await
-        {
-            Ok(_) => reply::with_status(String::new(), StatusCode::OK),
-            Err(err) => {
-                let err = format!("Failed voting for new configuration event: {}", err);
-                log::error!("{}", err);
-                reply::with_status(err, StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        },
-    )
+    match bridge
+        .vote_for_event_configuration(configuration_id, voting)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            let err = format!("Failed voting for new configuration event: {}", err);
+            log::error!("{}", err);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err))
+        }
+    }
 }
 
-async fn get_event_configurations(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
+async fn get_public_keys(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
     let state = state.read().await;
     let bridge = match &state.bridge_state {
         BridgeState::Running(bridge) => bridge,
@@ -276,23 +583,43 @@ async fn get_event_configurations(state: Arc<RwLock<State>>) -> Result<impl Repl
         }
     };
 
-    let configurations = bridge.get_event_configurations().await;
+    let public_keys = PublicKeysView {
+        eth_address: format!("0x{}", hex::encode(bridge.eth_address())),
+        eth_pubkey: hex::encode(bridge.eth_pubkey().serialize()),
+        ton_public_key: hex::encode(bridge.ton_public_key().as_bytes()),
+    };
 
     Ok(reply::with_status(
-        serde_json::to_string(&configurations).expect("shouldn't fail"),
+        serde_json::to_string(&public_keys).expect("shouldn't fail"),
         StatusCode::OK,
     ))
 }
 
+async fn get_event_configurations(
+    state: Arc<RwLock<State>>,
+) -> ApiResult<Vec<EventConfigurationView>> {
+    let state = state.read().await;
+    let bridge = match &state.bridge_state {
+        BridgeState::Running(bridge) => bridge,
+        _ => {
+            let err = "Bridge was not initialized";
+            log::error!("{}", err);
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, err));
+        }
+    };
+
+    Ok(bridge.get_event_configurations().await)
+}
+
 async fn create_event_configuration(
     state: Arc<RwLock<State>>,
     data: NewEventConfiguration,
-) -> Result<impl Reply, Infallible> {
+) -> ApiResult<()> {
     let (configuration_id, address, event_type) = match FromRequest::<_>::try_from(data) {
         Ok(data) => data,
         Err(err) => {
             log::error!("{}", err);
-            return Ok(reply::with_status(err.to_string(), StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, err.to_string()));
         }
     };
 
@@ -302,183 +629,390 @@ async fn create_event_configuration(
         _ => {
             let err = "Bridge was not initialized";
             log::error!("{}", err);
-            return Ok(reply::with_status(err.to_string(), StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, err));
         }
     };
 
-    Ok(
-        match bridge
-            .create_event_configuration(configuration_id, address, event_type)
-            .await
-        {
-            Ok(()) => reply::with_status(String::new(), StatusCode::OK),
-            Err(err) => {
-                let err = format!("Failed getting configuration events: {}", err);
-                log::error!("{}", err);
-                reply::with_status(err, StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        },
-    )
+    match bridge
+        .create_event_configuration(configuration_id, address, event_type)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let err = format!("Failed getting configuration events: {}", err);
+            log::error!("{}", err);
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err))
+        }
+    }
 }
 
-async fn set_eth_block_height(
-    state: Arc<RwLock<State>>,
-    height: RescanEthData,
-) -> Result<impl Reply, Infallible> {
+async fn set_eth_block_height(state: Arc<RwLock<State>>, height: RescanEthData) -> ApiResult<()> {
     let state = state.write().await;
-    Ok(match &state.bridge_state {
+    match &state.bridge_state {
         BridgeState::Uninitialized => {
             log::info!("Trying to change ethereum scan height on uninitialized relay");
-            reply::with_status(
-                "Trying to change ethereum scan height on uninitialized relay".to_string(),
+            Err(ApiError::new(
                 StatusCode::FORBIDDEN,
-            )
+                "Trying to change ethereum scan height on uninitialized relay",
+            ))
         }
         BridgeState::Locked => {
             log::info!("Trying to change ethereum scan height on locked relay");
-            reply::with_status(
-                "Trying to change ethereum scan height on locked relay".to_string(),
+            Err(ApiError::new(
                 StatusCode::FORBIDDEN,
-            )
+                "Trying to change ethereum scan height on locked relay",
+            ))
         }
         BridgeState::Running(bridge) => match bridge.change_eth_height(height.block).await {
             Ok(_) => {
                 log::info!("Changed  eth scan height to {}", height.block);
-                reply::with_status("OK".to_string(), StatusCode::OK)
+                Ok(())
             }
             Err(e) => {
                 let err = format!("Failed changing eth scan height: {}", e);
                 log::error!("{}", err);
-                reply::with_status(err, StatusCode::INTERNAL_SERVER_ERROR)
+                Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, err))
             }
         },
+    }
+}
+
+/// Init parameters without secrets
+fn init_audit_params(data: &InitData) -> serde_json::Value {
+    json!({
+        "language": data.language,
+        "ton_derivation_path": data.ton_derivation_path,
+        "eth_derivation_path": data.eth_derivation_path,
+        "eth_keystore": data.eth_keystore.is_some(),
+        "ton_keypair": data.ton_keypair.is_some(),
     })
 }
 
 async fn wait_for_init(
-    data: InitData,
+    mut data: InitData,
     config: RelayConfig,
     state: Arc<RwLock<State>>,
-) -> Result<impl Reply, Infallible> {
+) -> ApiResult<()> {
     log::info!("Received init data");
 
+    // Secrets are moved into the wiping containers before any return
+    let password: SecStr = std::mem::take(&mut data.password).into();
+    let eth_seed = Zeroizing::new(std::mem::take(&mut data.eth_seed));
+    let ton_seed = Zeroizing::new(std::mem::take(&mut data.ton_seed));
+    let eth_keystore = data
+        .eth_keystore
+        .take()
+        .map(|keystore| (keystore.keystore, SecStr::from(keystore.password)));
+    let ton_keypair = data.ton_keypair.take().map(Zeroizing::new);
+
     let mut state = state.write().await;
 
     if !matches!(&state.bridge_state, BridgeState::Uninitialized) {
         let err = "Already initialized".to_string();
         log::error!("{}", err);
-        return Ok(reply::with_status(err, StatusCode::METHOD_NOT_ALLOWED));
+        return Err(ApiError::new(StatusCode::METHOD_NOT_ALLOWED, err));
     }
 
-    let language = match Language::from_language_code(&data.language) {
-        Some(a) => a,
-        None => {
-            let error = format!("Bad language code provided: {}.", &data.language);
-            log::error!("{}", error);
-            return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
-        }
+    // Language is only needed for the seed phrases
+    let language = || {
+        Language::from_language_code(&data.language)
+            .ok_or_else(|| anyhow!("Bad language code provided: {}.", &data.language))
     };
 
-    let eth_private_key = match derive_from_words_eth(
-        language,
-        &data.eth_seed,
+    let eth_private_key = provided_eth_private_key(
+        &data.language,
+        &eth_seed,
         data.eth_derivation_path.as_deref(),
-    ) {
+        &eth_keystore,
+    );
+    let eth_private_key = match eth_private_key {
         Ok(a) => a,
         Err(e) => {
-            let error = format!("Failed deriving from eth seed: {}", e);
+            let error = e.to_string();
             log::error!("{}", error);
-            return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
         }
     };
 
-    let ton_key_pair = match derive_from_words_ton(
-        language,
-        &data.ton_seed,
-        data.ton_derivation_path.as_deref(),
-    ) {
+    let ton_key_pair = match &ton_keypair {
+        Some(keypair) => {
+            import_ton_keypair(keypair).map_err(|e| anyhow!("Failed importing ton keypair: {}", e))
+        }
+        None => language().and_then(|language| {
+            derive_from_words_ton(language, &ton_seed, data.ton_derivation_path.as_deref())
+                .map_err(|e| anyhow!("Failed deriving from ton seed: {}", e))
+        }),
+    };
+    let ton_key_pair = match ton_key_pair {
         Ok(a) => a,
         Err(e) => {
-            let error = format!("Failed deriving from ton seed: {}", e);
+            let error = e.to_string();
             log::error!("{}", error);
-            return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
         }
     };
 
-    let key_data = match KeyData::init(
-        &config.keys_path,
-        data.password.into(),
-        eth_private_key,
-        ton_key_pair,
-    ) {
+    // Wipe seed phrases right after derivation
+    drop((eth_seed, ton_seed, eth_keystore, ton_keypair));
+
+    // Key derivation is slow
+    let key_data = tokio::task::spawn_blocking({
+        let keys_path = config.keys_path.clone();
+        let password = password.clone();
+        move || KeyData::init(&keys_path, &password, eth_private_key, ton_key_pair)
+    })
+    .await
+    .map_err(Error::from)
+    .and_then(|result| result);
+    let key_data = match key_data {
         Ok(key_data) => key_data,
         Err(e) => {
             let error = format!("Failed initializing: {}", e);
             log::error!("{}", error);
-            return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
+            return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
         }
     };
 
-    if let Err(e) = state.finalize(config, key_data).await {
+    // Storage stays locked if the keystore wasn't written. Otherwise the keystore is
+    // removed, so that the relay stays uninitialized and init can be retried
+    if let Err(e) = state.unlock_storage(&config, &password).await {
+        if let Err(e) = std::fs::remove_file(&config.keys_path) {
+            log::error!("Failed removing keystore: {}", e);
+        }
+        let error = format!("Failed unlocking storage: {}", e);
+        log::error!("{}", error);
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
+    }
+
+    let signer = LocalSigner::new(config.keys_path.clone(), key_data);
+    if let Err(e) = state.finalize(config, Arc::new(signer)).await {
         log::error!("Failed finalize relay state: {:?}", e);
 
         let error = format!("Failed finalize relay state: {}", e);
-        return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
     };
 
-    Ok(reply::with_status(
-        "Initialized successfully".to_string(),
-        StatusCode::ACCEPTED,
-    ))
+    Ok(())
+}
+
+async fn rotate_eth_key(
+    mut data: RotateEthKey,
+    state: Arc<RwLock<State>>,
+) -> Result<impl Reply, Infallible> {
+    log::info!("Received ETH key rotation request");
+
+    // Secrets are moved into the wiping containers before any return
+    let password: SecStr = std::mem::take(&mut data.password).into();
+    let eth_seed = Zeroizing::new(std::mem::take(&mut data.eth_seed));
+    let eth_keystore = data
+        .eth_keystore
+        .take()
+        .map(|keystore| (keystore.keystore, SecStr::from(keystore.password)));
+
+    let state = state.read().await;
+    let bridge = match &state.bridge_state {
+        BridgeState::Running(bridge) => bridge,
+        _ => {
+            let err = "Bridge was not initialized".to_owned();
+            log::error!("{}", err);
+            return Ok(reply::with_status(err, StatusCode::METHOD_NOT_ALLOWED));
+        }
+    };
+
+    let eth_private_key = provided_eth_private_key(
+        &data.language,
+        &eth_seed,
+        data.eth_derivation_path.as_deref(),
+        &eth_keystore,
+    );
+    // Wipe seed phrase right after derivation
+    drop((eth_seed, eth_keystore));
+
+    let result = match eth_private_key {
+        Ok(private_key) => bridge.start_eth_key_rotation(&password, private_key).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(address) => {
+            let message = format!(
+                "ETH key rotation started. New ETH address: 0x{}",
+                hex::encode(address)
+            );
+            log::warn!("{}", message);
+            Ok(reply::with_status(message, StatusCode::OK))
+        }
+        Err(e) => {
+            let error = format!("Failed starting ETH key rotation: {}", e);
+            log::error!("{}", error);
+            Ok(reply::with_status(error, StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+/// Imports ETH key from the keystore file or derives it from the seed phrase
+fn provided_eth_private_key(
+    language: &str,
+    seed: &str,
+    derivation_path: Option<&str>,
+    keystore: &Option<(String, SecStr)>,
+) -> Result<SecVec<u8>, Error> {
+    match keystore {
+        Some((keystore, password)) => import_eth_keystore(keystore, password)
+            .map_err(|e| anyhow!("Failed importing eth keystore: {}", e)),
+        None => {
+            let language = Language::from_language_code(language)
+                .ok_or_else(|| anyhow!("Bad language code provided: {}.", language))?;
+            derive_from_words_eth(language, seed, derivation_path)
+                .map_err(|e| anyhow!("Failed deriving from eth seed: {}", e))
+        }
+    }
 }
 
 async fn wait_for_password(
     data: Password,
     config: RelayConfig,
     state: Arc<RwLock<State>>,
-) -> Result<impl Reply, Infallible> {
+) -> ApiResult<()> {
     log::info!("Received unlock request");
 
     let mut state = state.write().await;
 
     match &state.bridge_state {
         BridgeState::Uninitialized => {
-            return Ok(reply::with_status(
-                "Need to initialize first".to_string(),
+            return Err(ApiError::new(
                 StatusCode::METHOD_NOT_ALLOWED,
+                "Need to initialize first",
             ));
         }
         BridgeState::Running(_) => {
-            return Ok(reply::with_status(
-                "Already unlocked".to_string(),
+            return Err(ApiError::new(
                 StatusCode::METHOD_NOT_ALLOWED,
+                "Already unlocked",
             ));
         }
         _ => {}
     }
 
-    let key_data = match KeyData::from_file(config.keys_path.clone(), data.password.into()) {
-        Ok(key_data) => key_data,
-        Err(e) => {
-            let error = format!("Failed unlocking relay: {}", &e);
-            log::error!("{}", error);
-            return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
+    if let Err(e) = state.unlock(config, data.password.into()).await {
+        let error = format!("{:#}", e);
+        log::error!("{}", error);
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
+    }
+
+    Ok(())
+}
+
+async fn change_password(
+    data: ChangePassword,
+    config: RelayConfig,
+    state: Arc<RwLock<State>>,
+) -> Result<impl Reply, Infallible> {
+    log::info!("Received password change request");
+
+    // Prevents concurrent init or unlock
+    let state = state.write().await;
+
+    if matches!(&state.bridge_state, BridgeState::Uninitialized) {
+        return Ok(reply::with_status(
+            "Need to initialize first".to_string(),
+            StatusCode::METHOD_NOT_ALLOWED,
+        ));
+    }
+
+    let old_password: SecStr = data.old_password.into();
+    let new_password: SecStr = data.new_password.into();
+    let encryption = state.storage_encryption.clone();
+    let keys_path = config.keys_path.clone();
+
+    // Key derivation is slow, so the whole sequence runs on the blocking pool
+    let result = tokio::task::spawn_blocking(move || {
+        if let Err(e) = KeyData::from_file(&keys_path, &old_password) {
+            let error = format!("Failed verifying old password: {}", e);
+            return Err((error, StatusCode::BAD_REQUEST));
         }
-    };
 
-    if let Err(e) = state.finalize(config, key_data).await {
-        log::error!("Failed finalize relay state: {:?}", e);
+        // Storage data key can be wrapped with the unlock password
+        if let Some(encryption) = &encryption {
+            if let Err(e) = encryption.change_password(&old_password, &new_password) {
+                let error = format!("Failed changing storage password: {}", e);
+                return Err((error, StatusCode::INTERNAL_SERVER_ERROR));
+            }
+        }
 
-        let error = format!("Failed finalize relay state: {}", e);
-        return Ok(reply::with_status(error, StatusCode::BAD_REQUEST));
-    };
+        if let Err(e) = KeyData::change_password(&keys_path, &old_password, &new_password) {
+            if let Some(encryption) = &encryption {
+                if let Err(e) = encryption.change_password(&new_password, &old_password) {
+                    log::error!("Failed restoring storage password: {}", e);
+                }
+            }
+
+            let error = format!("Failed changing keystore password: {}", e);
+            return Err((error, StatusCode::INTERNAL_SERVER_ERROR));
+        }
+        Ok(())
+    })
+    .await
+    .unwrap_or_else(|e| {
+        let error = format!("Failed changing password: {}", e);
+        Err((error, StatusCode::INTERNAL_SERVER_ERROR))
+    });
 
+    if let Err((error, status)) = result {
+        log::error!("{}", error);
+        return Ok(reply::with_status(error, status));
+    }
+
+    log::info!("Password changed");
     Ok(reply::with_status(
-        "Password accepted".to_string(),
-        StatusCode::ACCEPTED,
+        "Password changed".to_string(),
+        StatusCode::OK,
     ))
 }
 
+async fn reencrypt_keystore(
+    data: Password,
+    config: RelayConfig,
+    state: Arc<RwLock<State>>,
+) -> Result<impl Reply, Infallible> {
+    log::info!("Received keystore re-encryption request");
+
+    // Prevents concurrent init or unlock
+    let state = state.write().await;
+
+    if matches!(&state.bridge_state, BridgeState::Uninitialized) {
+        return Ok(reply::with_status(
+            "Need to initialize first".to_string(),
+            StatusCode::METHOD_NOT_ALLOWED,
+        ));
+    }
+
+    let keys_path = config.keys_path.clone();
+    let password: SecStr = data.password.into();
+    // Key derivation is slow
+    let result =
+        tokio::task::spawn_blocking(move || KeyData::reencrypt_file(&keys_path, &password))
+            .await
+            .map_err(Error::from)
+            .and_then(|result| result);
+
+    match result {
+        Ok(old_kdf) => {
+            let message = format!(
+                "Keystore re-encrypted with {}. Previous kdf: {}",
+                Kdf::default().name(),
+                old_kdf.name()
+            );
+            log::info!("{}", message);
+            Ok(reply::with_status(message, StatusCode::OK))
+        }
+        Err(e) => {
+            let error = format!("Failed re-encrypting keystore: {}", e);
+            log::error!("{}", error);
+            Ok(reply::with_status(error, StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
 impl TonTransportConfig {
     pub async fn make_transport(&self) -> Result<Arc<dyn Transport>, Error> {
         #[allow(unreachable_code)]
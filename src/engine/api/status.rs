@@ -1,28 +1,32 @@
 use std::convert::Infallible;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use serde::Serialize;
 use tokio::sync::RwLock;
-use ton_block::MsgAddrStd;
+use ton_block::{MsgAddrStd, MsgAddressInt};
+use warp::http::StatusCode;
 use warp::Reply;
 
 use relay_models::models::*;
+use relay_storage::StorageLocked;
 
+use super::error::*;
 use crate::db::*;
 use crate::engine::models::*;
 use crate::models::*;
 
-pub async fn get_status(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
+pub async fn get_status(state: Arc<RwLock<State>>) -> ApiResult<Status> {
     let state = state.read().await;
 
-    let result = match &state.bridge_state {
+    Ok(match &state.bridge_state {
         BridgeState::Uninitialized => Status {
             password_needed: true,
             init_data_needed: true,
             is_working: false,
             ton_relay_address: None,
             eth_pubkey: None,
+            eth_key_rotation: None,
         },
         BridgeState::Locked => Status {
             password_needed: true,
@@ -30,6 +34,7 @@ pub async fn get_status(state: Arc<RwLock<State>>) -> Result<impl Reply, Infalli
             is_working: false,
             ton_relay_address: None,
             eth_pubkey: None,
+            eth_key_rotation: None,
         },
         BridgeState::Running(bridge) => {
             let ton_relay_address = bridge.ton_relay_address();
@@ -41,72 +46,214 @@ pub async fn get_status(state: Arc<RwLock<State>>) -> Result<impl Reply, Infalli
                 password_needed: false,
                 init_data_needed: false,
                 is_working: true,
+                eth_key_rotation: bridge.eth_key_rotation().await,
             }
         }
-    };
-    Ok(serde_json::to_string(&result).expect("Shouldn't fail"))
+    })
+}
+
+pub async fn pending<Confirm, Reject>(
+    state: Arc<RwLock<State>>,
+    query: PageQuery,
+) -> ApiResult<PageView<<EventTransaction<Confirm, Reject> as IntoVoteView>::View>>
+where
+    EventTransaction<Confirm, Reject>: VotesQueueExt,
+    EventTransaction<Confirm, Reject>: IntoVoteView + BorshSerialize + BorshDeserialize,
+    Confirm: EventVoteData,
+    Reject: EventVoteData,
+{
+    let request = page_request(&query)?;
+
+    let state = state.read().await;
+    let provider =
+        EventTransaction::<Confirm, Reject>::new(&state.state_manager).map_err(storage_error)?;
+    let page = provider.get_pending_page(&request, transaction_filter(&query));
+    page_view(page, |(event_address, transaction)| {
+        transaction.into_vote_view(&event_address)
+    })
 }
 
-pub async fn pending<Confirm, Reject, View>(
+pub async fn failed<Confirm, Reject>(
     state: Arc<RwLock<State>>,
-) -> Result<impl Reply, Infallible>
+    query: PageQuery,
+) -> ApiResult<PageView<<EventTransaction<Confirm, Reject> as IntoVoteView>::View>>
 where
     EventTransaction<Confirm, Reject>: VotesQueueExt,
-    EventTransaction<Confirm, Reject>: Into<View> + BorshSerialize + BorshDeserialize,
-    View: Serialize + From<EventTransaction<Confirm, Reject>>,
+    EventTransaction<Confirm, Reject>: IntoVoteView + BorshSerialize + BorshDeserialize,
+    Confirm: EventVoteData,
+    Reject: EventVoteData,
 {
+    let request = page_request(&query)?;
+
     let state = state.read().await;
     let provider =
-        EventTransaction::<Confirm, Reject>::new(&state.state_manager).expect("Fatal db error");
-    let pending = fold_ton_stats(provider.get_all_pending());
-    Ok(serde_json::to_string(&pending).expect("Shouldn't fail"))
+        EventTransaction::<Confirm, Reject>::new(&state.state_manager).map_err(storage_error)?;
+    let page = provider.get_failed_page(&request, transaction_filter(&query));
+    page_view(page, |(event_address, transaction)| {
+        transaction.into_vote_view(&event_address)
+    })
 }
 
-pub async fn failed<Confirm, Reject, View>(
+pub async fn dead<Confirm, Reject>(
     state: Arc<RwLock<State>>,
-) -> Result<impl Reply, Infallible>
+    query: PageQuery,
+) -> ApiResult<PageView<<EventTransaction<Confirm, Reject> as IntoDeadVoteView>::View>>
 where
     EventTransaction<Confirm, Reject>: VotesQueueExt,
-    EventTransaction<Confirm, Reject>: Into<View> + BorshSerialize + BorshDeserialize,
-    View: Serialize + From<EventTransaction<Confirm, Reject>>,
+    EventTransaction<Confirm, Reject>: IntoDeadVoteView + BorshSerialize + BorshDeserialize,
+    Confirm: EventVoteData,
+    Reject: EventVoteData,
 {
+    let request = page_request(&query)?;
+
     let state = state.read().await;
     let provider =
-        EventTransaction::<Confirm, Reject>::new(&state.state_manager).expect("Fatal db error");
-    let failed = fold_ton_stats(provider.get_all_failed());
-    Ok(serde_json::to_string(&failed).expect("Shouldn't fail"))
+        EventTransaction::<Confirm, Reject>::new(&state.state_manager).map_err(storage_error)?;
+    let page = provider.get_dead_page(&request, transaction_filter(&query));
+    page_view(page, |(event_address, transaction)| {
+        let history = provider
+            .get_history(&event_address)
+            .unwrap_or_else(|e| {
+                log::error!("Failed reading vote history: {:?}", e);
+                None
+            })
+            .map(IntoView::into_view)
+            .unwrap_or_else(|| VoteRetryHistoryView {
+                first_failed_at: 0,
+                last_error: None,
+                attempts: Vec::new(),
+            });
+        transaction.into_dead_vote_view(&event_address, history)
+    })
 }
 
-pub async fn eth_queue(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
+pub async fn eth_queue(
+    state: Arc<RwLock<State>>,
+    query: PageQuery,
+) -> ApiResult<PageView<EthQueuedEventView>> {
+    let request = queue_page_request(&query)?;
+
     let state = state.read().await;
-    let provider = EthVerificationQueue::new(&state.state_manager).expect("Fatal db error");
-    let data = provider.dump_elements();
-    Ok(serde_json::to_string(&data).expect("Shouldn't fail"))
+    let provider = EthVerificationQueue::new(&state.state_manager).map_err(storage_error)?;
+    let page = provider.get_page(&request, |event| {
+        query
+            .configuration_id
+            .map_or(true, |id| event.configuration_id == id)
+    });
+    page_view(page, |(target_block, external, event)| EthQueuedEventView {
+        target_block,
+        external,
+        event: event.into_view(),
+    })
 }
 
 pub async fn ton_queue(
     state: Arc<RwLock<State>>,
     configuration_id: u32,
-) -> Result<impl Reply, Infallible> {
+    query: PageQuery,
+) -> ApiResult<PageView<TonQueuedEventView>> {
+    let request = queue_page_request(&query)?;
+
     let state = state.read().await;
     let provider =
-        TonVerificationQueue::new(&state.state_manager, configuration_id).expect("Fatal db error");
-    let data = provider.dump_elements();
-    Ok(serde_json::to_string(&data).expect("Shouldn't fail"))
+        TonVerificationQueue::new(&state.state_manager, configuration_id).map_err(storage_error)?;
+    let page = provider.get_page(&request, |_| true);
+    page_view(page, |(target_lt, external, event)| TonQueuedEventView {
+        target_lt,
+        external,
+        event: event.into_view(),
+    })
 }
 
-pub async fn eth_relay_stats(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
+pub async fn eth_relay_stats(
+    state: Arc<RwLock<State>>,
+    query: PageQuery,
+) -> ApiResult<PageView<EthTxStatView>> {
     let state = state.read().await;
-    let provider = EthVotingStats::new(&state.state_manager).expect("Fatal db error");
-    let data = provider.dump_elements();
-    Ok(serde_json::to_string(&data).expect("Shouldn't fail"))
+    let provider = EthVotingStats::new(&state.state_manager).map_err(storage_error)?;
+    relay_stats(&provider, &query)
 }
 
-pub async fn ton_relay_stats(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
+pub async fn ton_relay_stats(
+    state: Arc<RwLock<State>>,
+    query: PageQuery,
+) -> ApiResult<PageView<TonTxStatView>> {
     let state = state.read().await;
-    let provider = TonVotingStats::new(&state.state_manager).expect("Fatal db error");
-    let data = provider.dump_elements();
-    Ok(serde_json::to_string(&data).expect("Shouldn't fail"))
+    let provider = TonVotingStats::new(&state.state_manager).map_err(storage_error)?;
+    relay_stats(&provider, &query)
+}
+
+fn relay_stats<T>(provider: &VotingStats<T>, query: &PageQuery) -> ApiResult<PageView<T::View>>
+where
+    T: GetStoredData,
+{
+    let request = page_request(query)?;
+
+    let relay = query
+        .relay
+        .as_deref()
+        .map(|relay| parse_address(relay, "relay"))
+        .transpose()?;
+
+    let page = provider.get_page(&request, |relay_addr, vote, stored| {
+        // Stored keys contain only the account id
+        relay
+            .as_ref()
+            .map_or(true, |relay| relay.address == relay_addr.address)
+            && query
+                .configuration_id
+                .map_or(true, |id| stored.configuration_id() == id)
+            && query.vote.map_or(true, |kind| vote.into_view() == kind)
+            && query.from.map_or(true, |from| stored.met() >= from as i64)
+            && query.to.map_or(true, |to| stored.met() < to as i64)
+    });
+    page_view(page, |view| view)
+}
+
+pub async fn eth_event_journal(
+    state: Arc<RwLock<State>>,
+    tx_hash: String,
+    event_index: u32,
+) -> ApiResult<Vec<EventJournalEntryView>> {
+    let tx_hash = parse_tx_hash(&tx_hash)?;
+    event_journal(
+        state,
+        EventJournalKey::Eth {
+            tx_hash,
+            event_index,
+        },
+    )
+    .await
+}
+
+pub async fn ton_event_journal(
+    state: Arc<RwLock<State>>,
+    address: String,
+) -> ApiResult<Vec<EventJournalEntryView>> {
+    let address = parse_address(&address, "event")?;
+    event_journal(state, EventJournalKey::Ton(address)).await
+}
+
+async fn event_journal(
+    state: Arc<RwLock<State>>,
+    key: EventJournalKey,
+) -> ApiResult<Vec<EventJournalEntryView>> {
+    let state = state.read().await;
+    let provider = EventsJournal::new(&state.state_manager).map_err(storage_error)?;
+    let entries = match provider.get(&key) {
+        Ok(entries) => entries,
+        Err(e) if is_storage_locked(&e) => return Err(storage_error(e)),
+        Err(e) => {
+            let error = format!("Failed reading events journal: {}", e);
+            log::error!("{}", error);
+            return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error));
+        }
+    };
+    if entries.is_empty() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Event not found"));
+    }
+
+    Ok(entries.into_iter().map(IntoView::into_view).collect())
 }
 
 pub async fn retry_failed(state: Arc<RwLock<State>>) -> Result<impl Reply, Infallible> {
@@ -121,12 +268,133 @@ pub async fn retry_failed(state: Arc<RwLock<State>>) -> Result<impl Reply, Infal
     Ok(warp::reply::with_status("", res))
 }
 
-fn fold_ton_stats<I, Confirm, Reject, View>(iter: I) -> Vec<View>
+fn parse_tx_hash(tx_hash: &str) -> ApiResult<relay_eth::H256> {
+    match hex::decode(tx_hash.trim_start_matches("0x")) {
+        Ok(tx_hash) if tx_hash.len() == 32 => Ok(relay_eth::H256::from_slice(&tx_hash)),
+        _ => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Invalid transaction hash",
+        )),
+    }
+}
+
+/// Only standard addresses are used by the bridge contracts
+fn parse_address(address: &str, name: &str) -> ApiResult<MsgAddrStd> {
+    match MsgAddressInt::from_str(address) {
+        Ok(MsgAddressInt::AddrStd(address)) => Ok(address),
+        _ => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid {} address", name),
+        )),
+    }
+}
+
+fn page_request(query: &PageQuery) -> ApiResult<PageRequest> {
+    let cursor = match query.cursor.as_deref().map(hex::decode).transpose() {
+        Ok(cursor) => cursor,
+        Err(_) => return Err(ApiError::new(StatusCode::BAD_REQUEST, "Invalid cursor")),
+    };
+
+    Ok(PageRequest {
+        cursor,
+        limit: query.limit.unwrap_or(DEFAULT_PAGE_LIMIT),
+        descending: query.order == Some(SortOrder::Desc),
+        from: None,
+        to: None,
+    })
+}
+
+/// Verification queues are ordered by the target block or logical time
+fn queue_page_request(query: &PageQuery) -> ApiResult<PageRequest> {
+    Ok(PageRequest {
+        from: query.from.map(|from| from.to_be_bytes().to_vec()),
+        to: query.to.map(|to| to.to_be_bytes().to_vec()),
+        ..page_request(query)?
+    })
+}
+
+fn transaction_filter<Confirm, Reject>(
+    query: &PageQuery,
+) -> impl Fn(&EventTransaction<Confirm, Reject>) -> bool + '_
 where
-    I: Iterator<Item = (MsgAddrStd, EventTransaction<Confirm, Reject>)>,
-    EventTransaction<Confirm, Reject>: VotesQueueExt,
-    EventTransaction<Confirm, Reject>: Into<View>,
-    View: Serialize + From<EventTransaction<Confirm, Reject>>,
+    Confirm: EventVoteData,
+    Reject: EventVoteData,
 {
-    iter.map(|(_, transaction)| transaction.into()).collect()
+    move |transaction| {
+        query
+            .configuration_id
+            .map_or(true, |id| transaction.configuration_id() == id)
+            && query
+                .vote
+                .map_or(true, |vote| transaction.kind().into_view() == vote)
+    }
+}
+
+fn page_view<T, V, F>(page: Result<Page<T>, anyhow::Error>, f: F) -> ApiResult<PageView<V>>
+where
+    F: FnMut(T) -> V,
+{
+    match page {
+        Ok(page) => Ok(PageView {
+            items: page.items.into_iter().map(f).collect(),
+            next_cursor: page.next_cursor.map(hex::encode),
+        }),
+        Err(e) if is_storage_locked(&e) => Err(storage_error(e)),
+        Err(e) => {
+            log::error!("Failed reading page: {:?}", e);
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Encrypted storage can't be read until the relay is unlocked
+fn is_storage_locked(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.is::<StorageLocked>())
+}
+
+fn storage_error(error: anyhow::Error) -> ApiError {
+    if is_storage_locked(&error) {
+        return ApiError::new(StatusCode::LOCKED, "Storage is locked");
+    }
+    log::error!("Failed opening storage: {:?}", error);
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("Storage is unavailable: {}", error),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_tx_hash() {
+        let hash = "0x8f2f1d8b6f5b2d7a4d3ec9b6e7a1f0c3b2a19d8e7f6a5b4c3d2e1f0a9b8c7d6e";
+        let expected = relay_eth::H256::from_slice(&hex::decode(&hash[2..]).unwrap());
+        assert_eq!(parse_tx_hash(hash).unwrap(), expected);
+        assert_eq!(parse_tx_hash(&hash[2..]).unwrap(), expected);
+
+        assert_eq!(
+            parse_tx_hash(&hash[..64]).unwrap_err().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(parse_tx_hash("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let address = "0:a921453472366b7feeec15323a96b5dcf17197c88dc0d4578dfa52900b8a33cb";
+        let parsed = parse_address(address, "event").unwrap();
+        assert_eq!(parsed.workchain_id, 0);
+        assert_eq!(MsgAddressInt::AddrStd(parsed).to_string(), address);
+
+        let error = parse_address("0:a921", "relay").unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.message, "Invalid relay address");
+        // Variable length addresses are not supported
+        assert!(parse_address("-1:00", "event").is_err());
+    }
 }
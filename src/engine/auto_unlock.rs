@@ -0,0 +1,131 @@
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::Context;
+use secstr::SecStr;
+
+use crate::config::{AutoUnlockSettings, PasswordSource};
+use crate::prelude::*;
+
+/// Set by systemd for the services with credentials
+const CREDENTIALS_DIRECTORY: &str = "CREDENTIALS_DIRECTORY";
+
+/// Reads the unlock password from the environment variable, if it is the configured source.
+/// The variable is removed, which is only sound while no other threads are running
+pub fn take_env_password(settings: &AutoUnlockSettings) -> Result<Option<SecStr>, Error> {
+    let name = match &settings.password {
+        PasswordSource::Env { name } => name,
+        _ => return Ok(None),
+    };
+
+    let value =
+        std::env::var(name).with_context(|| format!("Failed reading password from {}", name))?;
+    if settings.zeroize {
+        std::env::remove_var(name);
+    }
+    to_password(value.into_bytes(), settings.zeroize).map(Some)
+}
+
+/// Reads the unlock password from the configured source.
+/// `env_password` is the one taken with [`take_env_password`] on start
+pub fn read_password(
+    settings: &AutoUnlockSettings,
+    env_password: Option<SecStr>,
+) -> Result<SecStr, Error> {
+    let data = match &settings.password {
+        PasswordSource::File { path } => read_file(path, settings.check_permissions)?,
+        PasswordSource::SystemdCredential { name } => {
+            let directory = std::env::var_os(CREDENTIALS_DIRECTORY)
+                .ok_or_else(|| anyhow!("No systemd credentials are passed to the relay"))?;
+            read_file(
+                &Path::new(&directory).join(name),
+                settings.check_permissions,
+            )?
+        }
+        PasswordSource::Env { name } => {
+            return env_password
+                .ok_or_else(|| anyhow!("Password from {} wasn't taken on start", name))
+        }
+    };
+    to_password(data, settings.zeroize)
+}
+
+fn to_password(mut data: Vec<u8>, zeroize: bool) -> Result<SecStr, Error> {
+    let len = data
+        .iter()
+        .rposition(|&c| c != b'\n' && c != b'\r')
+        .map(|i| i + 1)
+        .unwrap_or_default();
+    let password = SecStr::new(data[..len].to_vec());
+    if zeroize {
+        sodiumoxide::utils::memzero(&mut data);
+    }
+
+    if password.unsecure().is_empty() {
+        return Err(anyhow!("Unlock password is empty"));
+    }
+    Ok(password)
+}
+
+fn read_file(path: &Path, check_permissions: bool) -> Result<Vec<u8>, Error> {
+    let context = || format!("Failed reading password file {}", path.display());
+    // Permissions are checked on the opened file, so it can't be swapped in between
+    let mut file = std::fs::File::open(path).with_context(context)?;
+    if check_permissions {
+        let mode = file.metadata().with_context(context)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(anyhow!(
+                "Password file {} is accessible by other users. Set its mode to 0400 or 0600",
+                path.display()
+            ));
+        }
+    }
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).with_context(context)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_password() {
+        let path = std::env::temp_dir().join(format!("relay_password_{}", std::process::id()));
+        std::fs::write(&path, "secret password\n").unwrap();
+
+        let mut settings = AutoUnlockSettings {
+            password: PasswordSource::File { path: path.clone() },
+            check_permissions: true,
+            zeroize: true,
+        };
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(read_password(&settings, None).is_err());
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(take_env_password(&settings).unwrap().is_none());
+        let password = read_password(&settings, None).unwrap();
+        assert_eq!(password.unsecure(), b"secret password");
+        std::fs::remove_file(&path).unwrap();
+
+        // Environment is left untouched, since tests run in parallel
+        settings.password = PasswordSource::Env {
+            name: "RELAY_PASSWORD".to_owned(),
+        };
+        assert!(read_password(&settings, None).is_err());
+        let password =
+            read_password(&settings, Some(SecStr::new(b"env password".to_vec()))).unwrap();
+        assert_eq!(password.unsecure(), b"env password");
+    }
+
+    #[test]
+    fn test_to_password() {
+        let password = to_password(b"env password\r\n\n".to_vec(), true).unwrap();
+        assert_eq!(password.unsecure(), b"env password");
+        assert!(to_password(b"\n".to_vec(), false).is_err());
+        assert!(to_password(Vec::new(), false).is_err());
+    }
+}
@@ -1,9 +1,15 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use relay_models::models::{ActivityEventView, VoteActivityView};
 use relay_ton::contracts::*;
 use relay_ton::transport::*;
 
 use super::utils;
+use super::votes_batcher::*;
+use super::votes_scheduler::*;
 use crate::config::TonSettings;
 use crate::db::*;
+use crate::engine::activity::ActivityNotifier;
 use crate::models::*;
 use crate::prelude::*;
 
@@ -14,10 +20,12 @@ where
     settings: TonSettings,
     relay: MsgAddrStd,
 
-    parallel_spawned_contracts_limiter: tokio::sync::Semaphore,
+    votes_scheduler: Arc<VotesScheduler>,
+    votes_batcher: Option<Arc<VotesBatcher>>,
     voting_stats:
         VotingStats<<<C as ConfigurationContract>::ReceivedVote as ReceivedVote>::VoteWithData>,
     votes_queue: VotesQueue<<C as ConfigurationContract>::EventTransaction>,
+    events_journal: EventsJournal,
 
     transport: Arc<dyn Transport>,
     scanning_state: ScanningState,
@@ -29,6 +37,10 @@ where
     rejections: Mutex<HashMap<MsgAddrStd, oneshot::Sender<()>>>,
 
     known_config_addresses: Mutex<HashSet<MsgAddressInt>>,
+
+    expired_vote_count: AtomicUsize,
+
+    activity: ActivityNotifier,
 }
 
 impl<C> EventTransport<C>
@@ -41,7 +53,7 @@ where
     <C as ConfigurationContract>::EventTransaction: EventTransactionExt<
             VoteData = <<C as ConfigurationContract>::EventContract as EventContract>::VoteData,
         > + std::fmt::Display,
-    <C::ReceivedVote as ReceivedVote>::VoteWithData: GetStoredData,
+    <C::ReceivedVote as ReceivedVote>::VoteWithData: GetStoredData + GetJournalKey,
     <<C::ReceivedVote as ReceivedVote>::VoteWithData as GetStoredData>::Stored:
         BorshSerialize + BorshDeserialize,
     for<'a> DisplayReceivedVote<'a, <C::ReceivedVote as ReceivedVote>::VoteWithData>:
@@ -53,20 +65,43 @@ where
         scanning_state: ScanningState,
         relay_contract: Arc<RelayContract>,
         settings: TonSettings,
+        activity: ActivityNotifier,
     ) -> Result<Self, Error> {
         let relay = relay_contract.address().clone();
         let event_contract = C::make_event_contract(transport.clone()).await;
         let voting_stats = C::make_voting_stats(db)?;
         let votes_queue = C::make_votes_queue(db)?;
+        let events_journal = EventsJournal::new(db)?;
+
+        let votes_batcher = if settings.votes_batching.enabled {
+            match relay_contract.check_batches_support().await {
+                Ok(()) => Some(VotesBatcher::new(
+                    relay_contract.clone(),
+                    settings.votes_batching.clone(),
+                )),
+                Err(e) => {
+                    log::error!(
+                        "Relay contract can't send votes in batches, sending them one by one: {:?}",
+                        e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
         Ok(Self {
-            parallel_spawned_contracts_limiter: tokio::sync::Semaphore::new(
+            votes_scheduler: VotesScheduler::new(
                 settings.parallel_spawned_contracts_limit,
+                settings.votes_scheduler.clone(),
             ),
+            votes_batcher,
             settings,
             relay,
             voting_stats,
             votes_queue,
+            events_journal,
             transport,
             scanning_state,
             relay_contract,
@@ -75,6 +110,8 @@ where
             confirmations: Default::default(),
             rejections: Default::default(),
             known_config_addresses: Default::default(),
+            expired_vote_count: Default::default(),
+            activity,
         })
     }
 
@@ -105,20 +142,51 @@ where
             should_check
         );
 
-        self.voting_stats
-            .insert_vote(&received_vote)
-            .await
-            .expect("Fatal db error");
-
         if vote_info.relay() == &self.relay {
             // Stop retrying after our event response was found
-            if let Err(e) = self.votes_queue.mark_complete(vote_info.event_address()) {
-                log::error!("Failed to mark transaction completed. {:?}", e);
-            }
+            self.voting_stats
+                .insert_own_vote(&received_vote, &self.votes_queue)
+                .await
+                .expect("Fatal db error");
+        } else {
+            self.voting_stats
+                .insert_vote(&received_vote)
+                .await
+                .expect("Fatal db error");
+        }
+
+        let journal_key = received_vote.journal_key();
+        if received_vote.status() != EventStatus::InProcess {
+            self.record_state_once(
+                &journal_key,
+                EventState::Finalized,
+                Some(format!("{:?}", received_vote.status())),
+            );
+        }
+
+        if vote_info.relay() == &self.relay {
+            self.record_state_once(
+                &journal_key,
+                EventState::VoteObserved,
+                Some(format!("{:?}", vote_info.kind())),
+            );
+            self.notify_vote_activity(
+                ActivityEventView::VoteConfirmed,
+                &journal_key,
+                vote_info.configuration_id(),
+                vote_info.event_address(),
+                vote_info.kind(),
+                None,
+            );
 
             self.notify_found(vote_info.event_address(), vote_info.kind())
                 .await;
         } else if should_check {
+            self.record_state_once(
+                &journal_key,
+                EventState::Queued,
+                Some(format!("Vote from {}", vote_info.relay())),
+            );
             events_verifier.enqueue(received_vote).await
         }
     }
@@ -129,6 +197,8 @@ where
         self: Arc<Self>,
         event_address: MsgAddrStd,
         data: C::EventTransaction,
+        origin: VoteOrigin,
+        claim: Option<VerificationClaim>,
     ) {
         // Skip voting for events which are already in stats db and TON queue
         if self.has_already_voted(&event_address) {
@@ -136,12 +206,21 @@ where
             self.votes_queue
                 .mark_complete(&event_address)
                 .expect("Fatal db error");
+            complete_claim(claim);
+            return;
+        }
+
+        // Skip voting for events which already have enough confirmations or rejections
+        if self.is_event_finalized(&event_address).await {
+            self.mark_expired(&event_address, &data);
+            complete_claim(claim);
             return;
         }
 
-        // Insert specified data in TON queue, replacing failed transaction if it exists
+        // Insert specified data in TON queue, replacing failed transaction if it exists.
+        // Verification queue entry is removed atomically, so the event can't be lost
         self.votes_queue
-            .insert_pending(&event_address, &data)
+            .insert_pending(&event_address, &data, claim)
             .expect("Fatal db error");
         // Start listening for cancellation
         let (rx, vote) = {
@@ -171,7 +250,12 @@ where
         let mut rx = Some(rx);
         let mut retries_count = self.settings.message_retry_count;
         let mut retries_interval = self.settings.message_retry_interval;
-        let _permit = self.parallel_spawned_contracts_limiter.acquire().await;
+        let priority = match (vote, origin) {
+            (Voting::Reject, _) => VotePriority::Rejection,
+            (Voting::Confirm, VoteOrigin::Fresh) => VotePriority::FreshConfirmation,
+            (Voting::Confirm, VoteOrigin::Retry) => VotePriority::Retry,
+        };
+        let mut permit = None;
         // Send a message with several retries on failure
         let result = loop {
             // Prepare delay future
@@ -180,8 +264,33 @@ where
                 retries_interval.as_secs_f64() * self.settings.message_retry_interval_multiplier,
             );
 
+            if permit.is_none() {
+                permit = Some(
+                    self.votes_scheduler
+                        .acquire(data.configuration_id(), priority)
+                        .await,
+                );
+            }
+
             // Try to send message
-            if let Err(e) = data.send(self.relay_contract.clone()).await {
+            let sent = self.send_vote(&data, &mut permit).await;
+            if sent.is_ok() {
+                self.record_state(
+                    &data.journal_key(&event_address),
+                    EventState::VoteSent,
+                    Some(format!("{:?}", vote)),
+                );
+                self.notify_vote_activity(
+                    ActivityEventView::VoteSent,
+                    &data.journal_key(&event_address),
+                    data.configuration_id(),
+                    &event_address,
+                    vote,
+                    None,
+                );
+            }
+
+            if let Err(e) = sent {
                 log::error!(
                     "Failed to vote for event: {:?}. Retrying ({} left)",
                     e,
@@ -240,6 +349,12 @@ where
             {
                 break Ok(());
             }
+
+            // Stop retrying if voting for the event is already finished
+            if self.is_event_finalized(&event_address).await {
+                self.mark_expired(&event_address, &data);
+                break Ok(());
+            }
         };
 
         match result {
@@ -248,7 +363,20 @@ where
             // When ran out of retries, stop waiting for transaction and mark it as failed
             Err(e) => {
                 log::error!("Stopped waiting for transaction: {}. Reason: {:?}", data, e);
-                if let Err(e) = self.votes_queue.mark_failed(&event_address) {
+                self.record_state(
+                    &data.journal_key(&event_address),
+                    EventState::Failed,
+                    Some(e.to_string()),
+                );
+                self.notify_vote_activity(
+                    ActivityEventView::VoteFailed,
+                    &data.journal_key(&event_address),
+                    data.configuration_id(),
+                    &event_address,
+                    vote,
+                    Some(e.to_string()),
+                );
+                if let Err(e) = self.votes_queue.mark_failed(&event_address, &e.to_string()) {
                     log::error!(
                         "Failed to mark transaction with hash {} as failed: {:?}",
                         data,
@@ -262,6 +390,93 @@ where
         self.cancel(&event_address, vote).await;
     }
 
+    /// Sends vote directly or as a part of the batch.
+    /// Permit is released as soon as the vote is added to the batch
+    async fn send_vote(
+        &self,
+        data: &C::EventTransaction,
+        permit: &mut Option<VotePermit>,
+    ) -> Result<(), Error> {
+        match &self.votes_batcher {
+            Some(batcher) => {
+                let sent = batcher.enqueue(data.payload(&self.relay_contract)?).await;
+                *permit = None;
+                sent.await
+            }
+            None => Ok(data.send(self.relay_contract.clone()).await?),
+        }
+    }
+
+    /// Check event contract whether voting for the event is already finished
+    async fn is_event_finalized(&self, event_address: &MsgAddrStd) -> bool {
+        match self.event_contract.get_details(event_address).await {
+            Ok(details) => details.status() != EventStatus::InProcess,
+            // Event contract is deployed by the first vote
+            Err(ContractError::TransportError(TransportError::AccountNotFound)) => false,
+            Err(e) => {
+                log::warn!("Failed to get event status for {}: {:?}", event_address, e);
+                false
+            }
+        }
+    }
+
+    /// Remove finalized event from TON queue without voting for it
+    fn mark_expired(&self, event_address: &MsgAddrStd, data: &C::EventTransaction) {
+        log::info!("Event is already finalized, skipping: {}", data);
+        self.expired_vote_count.fetch_add(1, Ordering::Relaxed);
+        self.record_state_once(
+            &data.journal_key(event_address),
+            EventState::Finalized,
+            Some("Finalized without our vote".to_owned()),
+        );
+
+        if let Err(e) = self.votes_queue.mark_complete(event_address) {
+            log::error!("Failed to mark transaction completed. {:?}", e);
+        }
+    }
+
+    /// Append event state transition to the journal
+    pub fn record_state(&self, key: &EventJournalKey, state: EventState, details: Option<String>) {
+        if let Err(e) = self.events_journal.record(key, state, details) {
+            log::error!("Failed to update events journal: {:?}", e);
+        }
+    }
+
+    /// Append event state transition to the journal if it wasn't reached before
+    pub fn record_state_once(
+        &self,
+        key: &EventJournalKey,
+        state: EventState,
+        details: Option<String>,
+    ) {
+        if let Err(e) = self.events_journal.record_once(key, state, details) {
+            log::error!("Failed to update events journal: {:?}", e);
+        }
+    }
+
+    pub fn notify_activity(&self, event: ActivityEventView) {
+        self.activity.notify(event);
+    }
+
+    /// Broadcast vote progress to the activity subscribers
+    fn notify_vote_activity(
+        &self,
+        kind: fn(VoteActivityView) -> ActivityEventView,
+        key: &EventJournalKey,
+        configuration_id: u32,
+        event_address: &MsgAddrStd,
+        vote: Voting,
+        error: Option<String>,
+    ) {
+        self.activity.notify(kind(VoteActivityView {
+            direction: key.direction(),
+            configuration_id,
+            event_address: event_address.to_string(),
+            vote: vote.into_view(),
+            error,
+        }));
+    }
+
     /// Remove transaction from TON queue and notify spawned `ensure_sent`
     async fn notify_found(&self, event_address: &MsgAddrStd, vote: Voting) {
         let mut table = match vote {
@@ -287,22 +502,107 @@ where
     /// Restart voting for pending transactions
     pub fn retry_pending(self: &Arc<Self>) {
         for (event_address, data) in self.votes_queue.get_all_pending() {
-            tokio::spawn(self.clone().ensure_sent(event_address, data));
+            tokio::spawn(
+                self.clone()
+                    .ensure_sent(event_address, data, VoteOrigin::Retry, None),
+            );
+        }
+    }
+
+    /// Remove failed transactions for events which are already finalized
+    pub async fn expire_finalized_failed(self: Arc<Self>) {
+        for (event_address, data) in self.votes_queue.get_all_failed() {
+            if self.is_event_finalized(&event_address).await {
+                self.mark_expired(&event_address, &data);
+            }
         }
     }
 
-    /// Restart voting for failed transactions
+    /// Restart voting for failed and abandoned transactions
     pub fn retry_failed(self: &Arc<Self>) {
+        let failed = self.votes_queue.get_all_failed();
+        let dead = self.votes_queue.get_all_dead();
+        for (event_address, data) in failed.chain(dead) {
+            tokio::spawn(
+                self.clone()
+                    .ensure_sent(event_address, data, VoteOrigin::Retry, None),
+            );
+        }
+    }
+
+    /// Restart voting for failed transactions which backoff delay has passed.
+    /// Transactions which ran out of attempts are moved to the dead-letter queue
+    pub fn retry_failed_with_backoff(self: &Arc<Self>) {
+        let rules = &self.settings.failed_votes_retry;
+        let now = chrono::Utc::now().timestamp();
+
         for (event_address, data) in self.votes_queue.get_all_failed() {
-            tokio::spawn(self.clone().ensure_sent(event_address, data));
+            let history = match self.votes_queue.get_history(&event_address) {
+                Ok(history) => history,
+                Err(e) => {
+                    // Corrupted history is replaced after the next failure
+                    log::error!("Failed to get retry history of {}: {:?}", data, e);
+                    None
+                }
+            };
+
+            let action = match &history {
+                Some(history) => history.next_action(rules, now),
+                // Transaction failed before the history was introduced
+                None => FailedVoteAction::Retry,
+            };
+
+            match action {
+                FailedVoteAction::Wait => {}
+                FailedVoteAction::Retry => {
+                    log::info!("Retrying failed transaction: {}", data);
+                    tokio::spawn(self.clone().ensure_sent(
+                        event_address,
+                        data,
+                        VoteOrigin::Retry,
+                        None,
+                    ));
+                }
+                FailedVoteAction::Abandon => {
+                    log::error!(
+                        "Abandoning failed transaction: {}. Last error: {}",
+                        data,
+                        history
+                            .as_ref()
+                            .and_then(|history| history.last_error())
+                            .unwrap_or_default()
+                    );
+                    if let Err(e) = self.votes_queue.mark_dead(&event_address) {
+                        log::error!("Failed to mark transaction {} as dead: {:?}", data, e);
+                    }
+                }
+            }
         }
     }
 
     /// Adds transaction to queue, starts reliable sending
-    pub async fn enqueue_vote(self: &Arc<Self>, data: C::EventTransaction) -> Result<(), Error> {
-        let event_address = self.get_event_contract_address(&data).await?;
+    pub async fn enqueue_vote(
+        self: &Arc<Self>,
+        data: C::EventTransaction,
+        claim: Option<VerificationClaim>,
+    ) -> Result<(), Error> {
+        let event_address = match self.get_event_contract_address(&data).await {
+            Ok(address) => address,
+            Err(e) => {
+                release_claim(claim);
+                return Err(e);
+            }
+        };
+        self.record_state(
+            &data.journal_key(&event_address),
+            EventState::Verified,
+            Some(format!("{:?}", data.kind())),
+        );
 
-        tokio::spawn(self.clone().ensure_sent(event_address, data));
+        tokio::spawn(
+            self.clone()
+                .ensure_sent(event_address, data, VoteOrigin::Fresh, claim),
+        );
 
         Ok(())
     }
@@ -332,6 +632,9 @@ where
         VotingQueueMetrics {
             pending_vote_count: self.votes_queue.pending_len(),
             failed_vote_count: self.votes_queue.failed_len(),
+            dead_vote_count: self.votes_queue.dead_len(),
+            expired_vote_count: self.expired_vote_count.load(Ordering::Relaxed),
+            waiting_vote_count: self.votes_scheduler.waiting_count().values().sum(),
         }
     }
 
@@ -340,6 +643,18 @@ where
         self.voting_stats.count_votes(configuration_id, &self.relay)
     }
 
+    /// Removes voting stats which are out of retention limits
+    pub fn compact_voting_stats(&self) {
+        let rules = &self.settings.voting_stats_retention;
+        let now = chrono::Utc::now().timestamp();
+
+        match tokio::task::block_in_place(|| self.voting_stats.compact(rules, &self.relay, now)) {
+            Ok(0) => {}
+            Ok(removed) => log::info!("Removed {} outdated votes from stats", removed),
+            Err(e) => log::error!("Failed to compact voting stats: {:?}", e),
+        }
+    }
+
     /// Compute event address based on its data
     async fn get_event_contract_address(
         &self,
@@ -474,10 +789,61 @@ where
     }
 }
 
+/// Removes verification queue entry of the event, which requires no vote
+fn complete_claim(claim: Option<VerificationClaim>) {
+    if let Some(claim) = claim {
+        if let Err(e) = claim.complete() {
+            log::error!("Failed to remove verified event from queue: {:?}", e);
+        }
+    }
+}
+
+/// Returns verification queue entry of the event, which failed to be enqueued
+fn release_claim(claim: Option<VerificationClaim>) {
+    if let Some(claim) = claim {
+        if let Err(e) = claim.release() {
+            log::error!("Failed to return event to verification queue: {:?}", e);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VotingQueueMetrics {
     pub pending_vote_count: usize,
     pub failed_vote_count: usize,
+    pub dead_vote_count: usize,
+    pub expired_vote_count: usize,
+    pub waiting_vote_count: usize,
+}
+
+/// Where the vote passed to `ensure_sent` came from
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VoteOrigin {
+    /// Vote for the newly received event
+    Fresh,
+    /// Vote restored from the pending or failed queue
+    Retry,
+}
+
+/// Identifies received vote in the events journal
+pub trait GetJournalKey {
+    fn journal_key(&self) -> EventJournalKey;
+}
+
+impl GetJournalKey for EthEventReceivedVoteWithData {
+    fn journal_key(&self) -> EventJournalKey {
+        let init_data = &self.data().init_data;
+        EventJournalKey::Eth {
+            tx_hash: init_data.event_transaction,
+            event_index: init_data.event_index,
+        }
+    }
+}
+
+impl GetJournalKey for TonEventReceivedVoteWithData {
+    fn journal_key(&self) -> EventJournalKey {
+        EventJournalKey::Ton(self.info().event_address().clone())
+    }
 }
 
 pub struct DisplayReceivedVote<'a, T>(&'a T);
@@ -530,7 +896,7 @@ pub trait ConfigurationContract: ContractWithEvents {
 
 #[async_trait]
 pub trait EventContract: Send + Sync {
-    type Details;
+    type Details: ReceivedVoteEventData;
     type VoteData;
 
     async fn get_details(&self, address: &MsgAddrStd) -> ContractResult<Self::Details>;
@@ -543,6 +909,8 @@ pub trait EventTransactionExt: std::fmt::Display + Clone + Send + Sync {
     fn configuration_id(&self) -> u32;
     fn kind(&self) -> Voting;
     fn vote_data(&self) -> Self::VoteData;
+    fn payload(&self, bridge: &RelayContract) -> ContractResult<BuilderData>;
+    fn journal_key(&self, event_address: &MsgAddrStd) -> EventJournalKey;
     async fn send(&self, bridge: Arc<RelayContract>) -> ContractResult<()>;
 }
 
@@ -693,6 +1061,25 @@ impl EventTransactionExt for EthEventTransaction {
         }
     }
 
+    fn journal_key(&self, _: &MsgAddrStd) -> EventJournalKey {
+        let data = match self {
+            Self::Confirm(data) => data,
+            Self::Reject(data) => data,
+        };
+        EventJournalKey::Eth {
+            tx_hash: data.event_transaction,
+            event_index: data.event_index,
+        }
+    }
+
+    fn payload(&self, bridge: &RelayContract) -> ContractResult<BuilderData> {
+        let data = self.vote_data();
+        match self.kind() {
+            Voting::Confirm => bridge.confirm_ethereum_event_payload(data),
+            Voting::Reject => bridge.reject_ethereum_event_payload(data),
+        }
+    }
+
     async fn send(&self, bridge: Arc<RelayContract>) -> ContractResult<()> {
         let data = self.vote_data();
         match self.kind() {
@@ -739,6 +1126,19 @@ impl EventTransactionExt for TonEventTransaction {
         }
     }
 
+    fn journal_key(&self, event_address: &MsgAddrStd) -> EventJournalKey {
+        EventJournalKey::Ton(event_address.clone())
+    }
+
+    fn payload(&self, bridge: &RelayContract) -> ContractResult<BuilderData> {
+        match self.clone() {
+            Self::Confirm(SignedTonEventVoteData { data, signature }) => {
+                bridge.confirm_ton_event_payload(data, signature)
+            }
+            Self::Reject(data) => bridge.reject_ton_event_payload(data),
+        }
+    }
+
     async fn send(&self, bridge: Arc<RelayContract>) -> ContractResult<()> {
         match self.clone() {
             Self::Confirm(SignedTonEventVoteData { data, signature }) => {
@@ -2,12 +2,18 @@ use std::collections::hash_map::Entry;
 use std::ops::Deref;
 
 use relay_eth::{EthListener, Event, SyncedHeight};
-use relay_models::models::EventConfigurationView;
+use relay_models::models::{
+    ActivityEventView, ConfigurationActivityView, EthKeyRotationView, EventConfigurationType,
+    EventConfigurationView,
+};
 use relay_ton::contracts::*;
+use secstr::{SecStr, SecVec};
 
 use crate::config::RelayConfig;
-use crate::crypto::key_managment::*;
+use crate::crypto::key_managment::eth_address_from_pubkey;
+use crate::crypto::signer::*;
 use crate::db::*;
+use crate::engine::activity::ActivityNotifier;
 use crate::models::*;
 use crate::prelude::*;
 
@@ -20,13 +26,19 @@ mod eth_events_handler;
 mod event_transport;
 mod semaphore;
 mod ton_events_handler;
+mod votes_batcher;
+mod votes_scheduler;
 
 mod utils;
 
+/// Interval of checking, whether bridge contracts acknowledged the rotated ETH key
+const ETH_KEY_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 pub async fn make_bridge(
     db: Db,
     configs: RelayConfig,
-    key_data: KeyData,
+    signer: Arc<dyn Signer>,
+    activity: ActivityNotifier,
 ) -> Result<Arc<Bridge>, Error> {
     let ton_transport = configs.ton_settings.transport.make_transport().await?;
 
@@ -48,7 +60,7 @@ pub async fn make_bridge(
     let relay_contract = make_relay_contract(
         ton_transport.clone(),
         relay_contract_address,
-        key_data.ton.keypair(),
+        Arc::new(TonMessageSigner(signer.clone())),
         bridge_contract,
     )
     .await?;
@@ -68,8 +80,11 @@ pub async fn make_bridge(
         .await?,
     );
 
-    let eth_signer = key_data.eth.clone();
     let eth_verification_queue = EthVerificationQueue::new(&db)?;
+    let released = eth_verification_queue.release_claims()?;
+    if released > 0 {
+        log::warn!("Restored {} interrupted ETH events verifications", released);
+    }
     let scanning_state = ScanningState::new(&db)?;
 
     let ton = Arc::new(
@@ -79,6 +94,7 @@ pub async fn make_bridge(
             scanning_state.clone(),
             relay_contract.clone(),
             configs.ton_settings.clone(),
+            activity.clone(),
         )
         .await?,
     );
@@ -89,6 +105,7 @@ pub async fn make_bridge(
             scanning_state.clone(),
             relay_contract.clone(),
             configs.ton_settings.clone(),
+            activity.clone(),
         )
         .await?,
     );
@@ -97,13 +114,15 @@ pub async fn make_bridge(
         db,
         eth_listener,
         relay_contract,
-        eth_signer,
+        signer,
         eth_verification_queue,
         configs_state: Arc::new(Default::default()),
         ton,
         eth,
         eth_event_handlers: Arc::new(Default::default()),
         ton_event_handlers: Arc::new(Default::default()),
+        eth_key_rotation: Default::default(),
+        activity,
         configs,
     });
 
@@ -128,7 +147,7 @@ pub struct Bridge {
 
     relay_contract: Arc<RelayContract>,
 
-    eth_signer: EthSigner,
+    signer: Arc<dyn Signer>,
     eth_verification_queue: EthVerificationQueue,
     configs_state: Arc<RwLock<ConfigsState>>,
 
@@ -137,10 +156,22 @@ pub struct Bridge {
 
     eth_event_handlers: Arc<EventsHandlerMap<EthEventsHandler>>,
     ton_event_handlers: Arc<EventsHandlerMap<TonEventsHandler>>,
+
+    /// Result of the last ETH key rotation check
+    eth_key_rotation: RwLock<Option<EthKeyRotationCheck>>,
+
+    activity: ActivityNotifier,
 }
 
 type EventsHandlerMap<T> = RwLock<HashMap<u32, Arc<T>>>;
 
+struct EthKeyRotationCheck {
+    next_eth_address: Address,
+    ton_acknowledged: bool,
+    eth_acknowledged: bool,
+    checked_at: i64,
+}
+
 impl Bridge {
     async fn run<T>(self: Arc<Self>, mut bridge_contract_events: T) -> Result<(), Error>
     where
@@ -164,6 +195,8 @@ impl Bridge {
                             active,
                             event_type,
                         } => {
+                            bridge.notify_configuration_changed(id, &address, event_type, active);
+
                             let bridge = bridge.clone();
                             tokio::spawn(async move {
                                 match (event_type, active) {
@@ -194,6 +227,8 @@ impl Bridge {
                             address,
                             event_type,
                         } => {
+                            bridge.notify_configuration_changed(id, &address, event_type, true);
+
                             let bridge = bridge.clone();
                             tokio::spawn(async move {
                                 match event_type {
@@ -267,6 +302,28 @@ impl Bridge {
         // Restart sending for all enqueued confirmations
         self.eth.retry_pending();
 
+        // Forget failed votes for events which were finalized without us
+        tokio::spawn(self.eth.clone().expire_finalized_failed());
+        tokio::spawn(self.ton.clone().expire_finalized_failed());
+
+        // Spawn failed votes queue processing
+        if self.configs.ton_settings.failed_votes_retry.enabled {
+            tokio::spawn(self.clone().watch_failed_votes());
+        }
+
+        // Spawn voting stats compaction
+        if self
+            .configs
+            .ton_settings
+            .voting_stats_retention
+            .is_enabled()
+        {
+            tokio::spawn(self.clone().watch_voting_stats());
+        }
+
+        // Spawn ETH key rotation checks
+        tokio::spawn(self.clone().watch_eth_key_rotation());
+
         // Subscribe for ETH blocks and events
         let mut eth_events_rx = self.eth_listener.start().await?;
 
@@ -290,6 +347,28 @@ impl Bridge {
         Ok(())
     }
 
+    fn notify_configuration_changed(
+        &self,
+        configuration_id: u32,
+        address: &MsgAddressInt,
+        event_type: EventType,
+        active: bool,
+    ) {
+        let configuration_type = match event_type {
+            EventType::ETH => EventConfigurationType::Eth,
+            EventType::TON => EventConfigurationType::Ton,
+        };
+        self.activity
+            .notify(ActivityEventView::ConfigurationChanged(
+                ConfigurationActivityView {
+                    configuration_id,
+                    address: address.to_string(),
+                    configuration_type,
+                    active,
+                },
+            ));
+    }
+
     ///Sets eth height
     pub async fn change_eth_height(&self, height: u64) -> Result<(), Error> {
         let actual_height = self.eth_listener.get_synced_height().await?.as_u64();
@@ -302,6 +381,40 @@ impl Bridge {
         Ok(())
     }
 
+    /// Height of the ETH node and the latest scanned block
+    pub async fn eth_heights(&self) -> Result<(SyncedHeight, u64), Error> {
+        let synced_height = self.eth_listener.get_synced_height().await?;
+        Ok((synced_height, self.eth_listener.scanned_height()))
+    }
+
+    /// Timestamp of the latest TON block, seen by the bridge contract subscription
+    pub async fn ton_block_utime(&self) -> u32 {
+        self.relay_contract.bridge().current_time().await.1
+    }
+
+    /// Returns the amount of active event configurations in the bridge contract
+    /// and ids of those, which the relay is not subscribed to
+    pub async fn unsubscribed_configurations(&self) -> Result<(usize, Vec<u32>), Error> {
+        let active = self
+            .relay_contract
+            .bridge()
+            .get_active_event_configurations()
+            .await?;
+
+        let eth_event_handlers = self.eth_event_handlers.read().await;
+        let ton_event_handlers = self.ton_event_handlers.read().await;
+        let missing = active
+            .iter()
+            .filter(|configuration| match configuration.event_type {
+                EventType::ETH => !eth_event_handlers.contains_key(&configuration.id),
+                EventType::TON => !ton_event_handlers.contains_key(&configuration.id),
+            })
+            .map(|configuration| configuration.id)
+            .collect();
+
+        Ok((active.len(), missing))
+    }
+
     /// Restart voting for failed transactions
     pub fn retry_failed(&self) {
         self.eth.retry_failed();
@@ -313,11 +426,60 @@ impl Bridge {
     }
 
     pub fn eth_pubkey(&self) -> secp256k1::PublicKey {
-        self.eth_signer.pubkey()
+        self.signer.eth_pubkey()
+    }
+
+    pub fn eth_address(&self) -> Address {
+        self.signer.eth_address()
+    }
+
+    pub fn ton_public_key(&self) -> ed25519_dalek::PublicKey {
+        self.signer.ton_public_key()
+    }
+
+    pub async fn sign_with_eth_key(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        self.signer.sign_eth(data).await
+    }
+
+    /// Stores the new ETH key. The relay switches to it when both bridge contracts acknowledge it.
+    /// Returns the new ETH address
+    pub async fn start_eth_key_rotation(
+        &self,
+        password: &SecStr,
+        private_key: SecVec<u8>,
+    ) -> Result<Address, Error> {
+        self.signer
+            .start_eth_key_rotation(password, private_key)
+            .await?;
+        *self.eth_key_rotation.write().await = None;
+
+        let pubkey = self
+            .signer
+            .next_eth_pubkey()
+            .ok_or_else(|| anyhow!("ETH key rotation is not started"))?;
+        Ok(eth_address_from_pubkey(&pubkey))
     }
 
-    pub fn sign_with_eth_key(&self, data: &[u8]) -> Vec<u8> {
-        self.eth_signer.sign(data)
+    /// Progress of the ETH key rotation. `None` if it is not started
+    pub async fn eth_key_rotation(&self) -> Option<EthKeyRotationView> {
+        let next_eth_address = eth_address_from_pubkey(&self.signer.next_eth_pubkey()?);
+
+        let (ton_acknowledged, eth_acknowledged, checked_at) =
+            match &*self.eth_key_rotation.read().await {
+                Some(check) if check.next_eth_address == next_eth_address => (
+                    check.ton_acknowledged,
+                    check.eth_acknowledged,
+                    Some(check.checked_at),
+                ),
+                _ => (false, false, None),
+            };
+
+        Some(EthKeyRotationView {
+            next_eth_address: format!("0x{}", hex::encode(next_eth_address)),
+            ton_acknowledged,
+            eth_acknowledged,
+            checked_at,
+        })
     }
 
     pub async fn update_bridge_configuration(
@@ -442,14 +604,25 @@ impl Bridge {
             eth_verification_queue_size: self.eth_verification_queue.len(),
             eth_pending_vote_count: eth_transport_metrics.pending_vote_count,
             eth_failed_vote_count: eth_transport_metrics.failed_vote_count,
+            eth_dead_vote_count: eth_transport_metrics.dead_vote_count,
+            eth_expired_vote_count: eth_transport_metrics.expired_vote_count,
+            eth_waiting_vote_count: eth_transport_metrics.waiting_vote_count,
             eth_event_handlers_metrics,
             ton_pending_vote_count: ton_transport_metrics.pending_vote_count,
             ton_failed_vote_count: ton_transport_metrics.failed_vote_count,
+            ton_dead_vote_count: ton_transport_metrics.dead_vote_count,
+            ton_expired_vote_count: ton_transport_metrics.expired_vote_count,
+            ton_waiting_vote_count: ton_transport_metrics.waiting_vote_count,
             ton_event_handlers_metrics,
         }
     }
 
-    async fn check_suspicious_event(self: Arc<Self>, event: EthEventVoteData, external: bool) {
+    async fn check_suspicious_event(
+        self: Arc<Self>,
+        event: EthEventVoteData,
+        external: bool,
+        claim: VerificationClaim,
+    ) {
         ///`event_from_ethereum` - fresh event from eth
         ///`event` data in our db
         async fn check_event(
@@ -510,25 +683,50 @@ impl Bridge {
                     None => {
                         log::info!("Confirming transaction. Hash: {}", event.event_transaction);
                         self.eth
-                            .enqueue_vote(EventTransaction::Confirm(event))
+                            .enqueue_vote(EventTransaction::Confirm(event), Some(claim))
                             .await
                     }
                     Some(a) => {
+                        self.eth.record_state(
+                            &EventJournalKey::Eth {
+                                tx_hash: event.event_transaction,
+                                event_index: event.event_index,
+                            },
+                            EventState::Failed,
+                            Some("Event data differs from ETH, enqueued again".to_owned()),
+                        );
                         log::error!("Found data for transaction {}. Transaction fields differs with actual eth data. Maybe blockchain was forked. Rejecting suspicious transaction and adding good to the queue.",
                                     event.event_transaction);
                         log::warn!("Rejecting: {}", hex::encode(&event.event_transaction.0));
                         log::info!("Enqueuing again");
-                        tokio::spawn(self.clone().process_eth_event(a));
+                        // Suspicious entry is removed only after the actual one is queued
+                        let bridge = self.clone();
+                        tokio::spawn(async move {
+                            bridge.process_eth_event(a).await;
+                            if let Err(e) = claim.complete() {
+                                log::error!("Failed to remove suspicious event: {:?}", e);
+                            }
+                        });
                         Ok(())
                     }
                 },
                 Err(e) if external => {
                     log::warn!("Rejection: {:?}", e);
-                    self.eth.enqueue_vote(EventTransaction::Reject(event)).await
+                    self.eth
+                        .enqueue_vote(EventTransaction::Reject(event), Some(claim))
+                        .await
                 }
                 Err(e) => {
                     log::warn!("Rejection: {:?}. Ignoring", e);
-                    Ok(())
+                    self.eth.record_state(
+                        &EventJournalKey::Eth {
+                            tx_hash: event.event_transaction,
+                            event_index: event.event_index,
+                        },
+                        EventState::Failed,
+                        Some(e.to_string()),
+                    );
+                    claim.complete()
                 }
             }
         } {
@@ -561,8 +759,9 @@ impl Bridge {
                     block_number,
                     hex::encode(&event.event_transaction)
                 );
-                tokio::spawn(self.clone().check_suspicious_event(event, entry.external()));
-                entry.remove().expect("Fatal db error");
+                let external = entry.external();
+                let claim = entry.claim().expect("Fatal db error");
+                tokio::spawn(self.clone().check_suspicious_event(event, external, claim));
             }
 
             if let SyncedHeight::Synced(a) = synced_block {
@@ -577,8 +776,9 @@ impl Bridge {
                         event.event_block_number,
                         hex::encode(&event.event_transaction)
                     );
-                    tokio::spawn(self.clone().check_suspicious_event(event, entry.external()));
-                    entry.remove().expect("Fatal db error");
+                    let external = entry.external();
+                    let claim = entry.claim().expect("Fatal db error");
+                    tokio::spawn(self.clone().check_suspicious_event(event, external, claim));
                 }
             }
 
@@ -586,6 +786,88 @@ impl Bridge {
         }
     }
 
+    // Watch failed votes queues and retry them with backoff
+    async fn watch_failed_votes(self: Arc<Self>) {
+        log::debug!("Started watch_failed_votes");
+        let interval = self.configs.ton_settings.failed_votes_retry.scan_interval;
+        loop {
+            tokio::time::sleep(interval).await;
+
+            self.eth.retry_failed_with_backoff();
+            self.ton.retry_failed_with_backoff();
+        }
+    }
+
+    // Remove outdated votes from the voting stats
+    async fn watch_voting_stats(self: Arc<Self>) {
+        log::debug!("Started watch_voting_stats");
+        let interval = self
+            .configs
+            .ton_settings
+            .voting_stats_retention
+            .compaction_interval;
+        loop {
+            tokio::time::sleep(interval).await;
+
+            self.eth.compact_voting_stats();
+            self.ton.compact_voting_stats();
+        }
+    }
+
+    // Switch to the rotated ETH key when both bridge contracts acknowledge it
+    async fn watch_eth_key_rotation(self: Arc<Self>) {
+        log::debug!("Started watch_eth_key_rotation");
+        loop {
+            tokio::time::sleep(ETH_KEY_ROTATION_CHECK_INTERVAL).await;
+
+            if let Err(e) = self.check_eth_key_rotation().await {
+                log::error!("Failed checking ETH key rotation: {:?}", e);
+            }
+        }
+    }
+
+    async fn check_eth_key_rotation(&self) -> Result<(), Error> {
+        let next_eth_address = match self.signer.next_eth_pubkey() {
+            Some(pubkey) => eth_address_from_pubkey(&pubkey),
+            None => return Ok(()),
+        };
+
+        let ton_account = self
+            .relay_contract
+            .bridge()
+            .get_ethereum_account(MsgAddressInt::AddrStd(self.ton_relay_address()))
+            .await?;
+        let (_, eth_accounts) = self
+            .eth_listener
+            .get_actual_keys(self.configs.eth_settings.ownership_blocks_to_confirm)
+            .await?;
+
+        let check = EthKeyRotationCheck {
+            next_eth_address,
+            ton_acknowledged: ton_account == next_eth_address,
+            eth_acknowledged: eth_accounts.contains(&next_eth_address),
+            checked_at: chrono::Utc::now().timestamp(),
+        };
+        log::info!(
+            "Rotating ETH key to 0x{}. TON acknowledged: {}. ETH acknowledged: {}",
+            hex::encode(next_eth_address),
+            check.ton_acknowledged,
+            check.eth_acknowledged
+        );
+
+        let acknowledged = check.ton_acknowledged && check.eth_acknowledged;
+        *self.eth_key_rotation.write().await = Some(check);
+
+        if acknowledged {
+            self.signer.complete_eth_key_rotation().await?;
+            log::warn!(
+                "Switched to the new ETH key. ETH address: 0x{}",
+                hex::encode(next_eth_address)
+            );
+        }
+        Ok(())
+    }
+
     // Validate event from ETH and vote for it
     async fn process_eth_event(self: Arc<Self>, event: relay_eth::Event) {
         log::info!(
@@ -594,6 +876,16 @@ impl Bridge {
             &event.tx_hash
         );
 
+        let journal_key = EventJournalKey::Eth {
+            tx_hash: event.tx_hash,
+            event_index: event.event_index,
+        };
+        self.eth.record_state_once(
+            &journal_key,
+            EventState::Seen,
+            Some(format!("Block {}", event.block_number)),
+        );
+
         // Extend event info
         let (configuration_id, ethereum_event_blocks_to_confirm, ton_data) = {
             let state = self.configs_state.read().await;
@@ -694,6 +986,14 @@ impl Bridge {
             .insert(target_block_number, false, &prepared_data)
             .await
             .expect("Fatal db error");
+
+        self.eth.record_state(
+            &journal_key,
+            EventState::Queued,
+            Some(format!("Until block {}", target_block_number)),
+        );
+        self.activity
+            .notify(ActivityEventView::EthEvent(prepared_data.into_view()));
     }
 
     /// Creates a listener for TON event votes in TON and its target contract
@@ -713,10 +1013,19 @@ impl Bridge {
                 return;
             }
         };
+        match verification_queue.release_claims() {
+            Ok(0) => {}
+            Ok(released) => log::warn!(
+                "Restored {} interrupted TON events verifications for configuration {}",
+                released,
+                configuration_id
+            ),
+            Err(e) => log::error!("Failed to restore interrupted verifications: {:?}", e),
+        }
 
         let handler = match TonEventsHandler::new(
             self.ton.clone(),
-            self.eth_signer.clone(),
+            self.signer.clone(),
             verification_queue,
             configuration_id,
             address,
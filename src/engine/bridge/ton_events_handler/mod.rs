@@ -1,8 +1,9 @@
+use relay_models::models::ActivityEventView;
 use relay_ton::contracts::*;
 
 use crate::config::TonSettings;
-use crate::crypto::key_managment::*;
-use crate::db::TonVerificationQueue;
+use crate::crypto::signer::Signer;
+use crate::db::{TonVerificationQueue, VerificationClaim};
 use crate::models::*;
 use crate::prelude::*;
 
@@ -18,7 +19,7 @@ pub struct TonEventsHandler {
 
 struct State {
     transport: Arc<TonEventTransport>,
-    eth_signer: EthSigner,
+    signer: Arc<dyn Signer>,
     verification_queue: TonVerificationQueue,
     address: MsgAddressInt,
 
@@ -33,7 +34,7 @@ struct State {
 impl TonEventsHandler {
     pub async fn new(
         transport: Arc<TonEventTransport>,
-        eth_signer: EthSigner,
+        signer: Arc<dyn Signer>,
         verification_queue: TonVerificationQueue,
         configuration_id: u32,
         address: MsgAddressInt,
@@ -114,7 +115,7 @@ impl TonEventsHandler {
         let handler = Arc::new(Self {
             state: Arc::new(State {
                 transport: transport.clone(),
-                eth_signer,
+                signer,
                 verification_queue,
                 address,
                 configuration_id,
@@ -282,7 +283,13 @@ impl TonEventsHandler {
 
     fn handle_swapback(&self, event: SwapBackEvent, semaphore: Option<Semaphore>) {
         async fn confirm(state: Arc<State>, event: SwapBackEvent) -> Result<(), Error> {
-            state.transport.enqueue_vote(event.confirmed(&state)?).await
+            let transaction = event.confirmed(&state).await?;
+            if let TonEventTransaction::Confirm(data) = &transaction {
+                state
+                    .transport
+                    .notify_activity(ActivityEventView::TonEvent(data.data.clone().into_view()));
+            }
+            state.transport.enqueue_vote(transaction, None).await
         }
 
         log::info!("Got swap back event: {:?}", event);
@@ -312,40 +319,47 @@ impl TonEventsHandler {
         let handler = Arc::downgrade(self);
 
         tokio::spawn(async move {
-            {
-                let handler = match handler.upgrade() {
-                    Some(handler) => handler,
-                    None => return,
-                };
-
-                let (lt, _) = handler.state.swapback_contract.current_time().await;
-
-                let prepared_votes = handler
-                    .state
-                    .verification_queue
-                    .range_before(
-                        lt.checked_sub(
-                            handler
-                                .state
-                                .transport
-                                .settings()
-                                .ton_events_verification_queue_lt_offset,
+            loop {
+                // Handler is not kept alive while sleeping
+                {
+                    let handler = match handler.upgrade() {
+                        Some(handler) => handler,
+                        None => return,
+                    };
+
+                    let (lt, _) = handler.state.swapback_contract.current_time().await;
+
+                    let prepared_votes = handler
+                        .state
+                        .verification_queue
+                        .range_before(
+                            lt.checked_sub(
+                                handler
+                                    .state
+                                    .transport
+                                    .settings()
+                                    .ton_events_verification_queue_lt_offset,
+                            )
+                            .unwrap_or_default(),
                         )
-                        .unwrap_or_default(),
-                    )
-                    .await;
+                        .await;
 
-                for (entry, event) in prepared_votes {
-                    tokio::spawn(handler.clone().handle_restored_swapback(event));
-                    entry.remove().expect("Fatal db error");
+                    for (entry, event) in prepared_votes {
+                        let claim = entry.claim().expect("Fatal db error");
+                        tokio::spawn(handler.clone().handle_restored_swapback(event, claim));
+                    }
                 }
-            }
 
-            tokio::time::sleep(interval).await;
+                tokio::time::sleep(interval).await;
+            }
         });
     }
 
-    async fn handle_restored_swapback(self: Arc<Self>, event: SignedTonEventVoteData) {
+    async fn handle_restored_swapback(
+        self: Arc<Self>,
+        event: SignedTonEventVoteData,
+        claim: VerificationClaim,
+    ) {
         let mut counter = 50;
         let event_address = loop {
             match self
@@ -358,6 +372,10 @@ impl TonEventsHandler {
                 Err(e) => {
                     if counter == 0 {
                         log::error!("Failed to compute address for restored event. 0 attempts left. Giving up to do it.");
+                        // Event will be checked again on the next pass of the queue
+                        if let Err(e) = claim.release() {
+                            log::error!("Failed to return restored event to queue: {:?}", e);
+                        }
                         return;
                     }
                     log::error!("Failed to compute address for restored event: {:?}. Retrying. {} attempts left", e, counter);
@@ -370,12 +388,14 @@ impl TonEventsHandler {
         if !self.state.transport.is_in_queue(&event_address)
             && !self.state.transport.has_already_voted(&event_address)
         {
-            tokio::spawn(
-                self.state
-                    .transport
-                    .clone()
-                    .ensure_sent(event_address, EventTransaction::Reject(event.data)),
-            );
+            tokio::spawn(self.state.transport.clone().ensure_sent(
+                event_address,
+                EventTransaction::Reject(event.data),
+                VoteOrigin::Fresh,
+                Some(claim),
+            ));
+        } else if let Err(e) = claim.complete() {
+            log::error!("Failed to remove restored event from queue: {:?}", e);
         }
     }
 
@@ -394,13 +414,13 @@ impl TonEventsHandler {
 }
 
 impl State {
-    fn calculate_signature(&self, event: &SwapBackEvent) -> Result<Vec<u8>, Error> {
+    async fn calculate_signature(&self, event: &SwapBackEvent) -> Result<Vec<u8>, Error> {
         let payload = utils::prepare_ton_event_payload(
             &self.config_contract.address(),
             &self.details,
             event,
         )?;
-        let signature = self.eth_signer.sign(&payload);
+        let signature = self.signer.sign_eth(&payload).await?;
 
         log::info!(
             "Calculated swap back event signature: {} for payload: {}",
@@ -417,7 +437,7 @@ impl EventsVerifier<TonEventReceivedVote> for State {
         let reject = |event: TonEventReceivedVoteWithData| async move {
             if let Err(e) = self
                 .transport
-                .enqueue_vote(EventTransaction::Reject(event.into_vote()))
+                .enqueue_vote(EventTransaction::Reject(event.into_vote()), None)
                 .await
             {
                 log::error!("Failed to enqueue invalid event rejection vote: {:?}", e);
@@ -457,7 +477,8 @@ impl EventsVerifier<TonEventReceivedVote> for State {
         let signature = match abi
             .decode_input(init_data.event_data.clone().into())
             .map_err(|e| anyhow!("failed decoding TON event data: {:?}", e))
-            .and_then(|tokens| {
+        {
+            Ok(tokens) => {
                 self.calculate_signature(&SwapBackEvent {
                     event_transaction: init_data.event_transaction,
                     event_transaction_lt: init_data.event_transaction_lt,
@@ -465,7 +486,11 @@ impl EventsVerifier<TonEventReceivedVote> for State {
                     event_index: init_data.event_index,
                     tokens,
                 })
-            }) {
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let signature = match signature {
             Ok(signature) => signature,
             Err(e) => {
                 log::error!("Failed to sign event from TON: {:?}", e);
@@ -487,15 +512,17 @@ impl EventsVerifier<TonEventReceivedVote> for State {
     }
 }
 
+#[async_trait]
 trait SwapBackEventExt: Sized {
-    fn confirmed(self, state: &State) -> Result<TonEventTransaction, Error>;
+    async fn confirmed(self, state: &State) -> Result<TonEventTransaction, Error>;
 }
 
+#[async_trait]
 impl SwapBackEventExt for SwapBackEvent {
-    fn confirmed(self, state: &State) -> Result<TonEventTransaction, Error> {
+    async fn confirmed(self, state: &State) -> Result<TonEventTransaction, Error> {
         let event_id = state.swapback_contract.abi().id;
 
-        let signature = state.calculate_signature(&self)?;
+        let signature = state.calculate_signature(&self).await?;
         let event_data = utils::pack_event_data_into_cell(event_id, &self.tokens)?;
 
         Ok(TonEventTransaction::Confirm(SignedTonEventVoteData {
@@ -0,0 +1,206 @@
+use std::future::Future;
+
+use relay_ton::contracts::*;
+
+use crate::config::VotesBatchingSettings;
+use crate::prelude::*;
+
+/// Destination of the gathered votes
+#[async_trait]
+pub trait PayloadsSender: Send + Sync {
+    async fn send_payloads(&self, payloads: Vec<BuilderData>) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl PayloadsSender for RelayContract {
+    async fn send_payloads(&self, payloads: Vec<BuilderData>) -> Result<(), Error> {
+        Ok(RelayContract::send_payloads(self, payloads).await?)
+    }
+}
+
+/// Gathers votes over a short window and sends them to the relay contract
+/// within a single external message
+pub struct VotesBatcher {
+    sender: Arc<dyn PayloadsSender>,
+    settings: VotesBatchingSettings,
+    state: Mutex<BatcherState>,
+}
+
+impl VotesBatcher {
+    pub fn new(sender: Arc<dyn PayloadsSender>, settings: VotesBatchingSettings) -> Arc<Self> {
+        Arc::new(Self {
+            sender,
+            settings,
+            state: Default::default(),
+        })
+    }
+
+    /// Adds payload to the current batch. Returned future resolves when the batch is sent
+    pub async fn enqueue(
+        self: &Arc<Self>,
+        payload: BuilderData,
+    ) -> impl Future<Output = Result<(), Error>> {
+        let (tx, rx) = oneshot::channel();
+
+        {
+            let mut state = self.state.lock().await;
+
+            if state.current.is_empty() {
+                state.generation += 1;
+                tokio::spawn(self.clone().flush_after_window(state.generation));
+            }
+
+            state.current.payloads.push(payload);
+            state.current.waiters.push(tx);
+
+            if state.current.payloads.len() >= self.settings.max_batch_size {
+                let batch = std::mem::take(&mut state.current);
+                tokio::spawn(self.clone().send_batch(batch));
+            }
+        }
+
+        async move {
+            rx.await
+                .unwrap_or_else(|_| Err(anyhow!("Votes batch was dropped")))
+        }
+    }
+
+    async fn flush_after_window(self: Arc<Self>, generation: u64) {
+        tokio::time::sleep(self.settings.window).await;
+
+        let batch = {
+            let mut state = self.state.lock().await;
+            // Batch was already sent because of its size
+            if state.generation != generation || state.current.is_empty() {
+                return;
+            }
+            std::mem::take(&mut state.current)
+        };
+
+        self.send_batch(batch).await;
+    }
+
+    async fn send_batch(self: Arc<Self>, batch: Batch) {
+        let count = batch.payloads.len();
+        log::info!("Sending batch of {} votes", count);
+
+        match self.sender.send_payloads(batch.payloads).await {
+            Ok(()) => {
+                for tx in batch.waiters {
+                    let _ = tx.send(Ok(()));
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to send batch of {} votes: {:?}", count, e);
+                for tx in batch.waiters {
+                    let _ = tx.send(Err(anyhow!("Failed to send votes batch: {}", e)));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct BatcherState {
+    generation: u64,
+    current: Batch,
+}
+
+#[derive(Default)]
+struct Batch {
+    payloads: Vec<BuilderData>,
+    waiters: Vec<oneshot::Sender<Result<(), Error>>>,
+}
+
+impl Batch {
+    fn is_empty(&self) -> bool {
+        self.payloads.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestSender {
+        batches: std::sync::Mutex<Vec<usize>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl PayloadsSender for TestSender {
+        async fn send_payloads(&self, payloads: Vec<BuilderData>) -> Result<(), Error> {
+            self.batches.lock().unwrap().push(payloads.len());
+            if self.fail {
+                Err(anyhow!("Rejected"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn batcher(sender: Arc<TestSender>, window: Duration) -> Arc<VotesBatcher> {
+        VotesBatcher::new(
+            sender,
+            VotesBatchingSettings {
+                enabled: true,
+                window,
+                max_batch_size: 3,
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn sends_full_batch_immediately() {
+        let sender = Arc::new(TestSender::default());
+        let batcher = batcher(sender.clone(), Duration::from_secs(600));
+
+        let mut sent = Vec::new();
+        for _ in 0..4 {
+            sent.push(batcher.enqueue(BuilderData::new()).await);
+        }
+        let mut sent = sent.into_iter();
+        for result in sent.by_ref().take(3) {
+            result.await.unwrap();
+        }
+        assert_eq!(*sender.batches.lock().unwrap(), [3]);
+
+        // The rest waits for the window
+        let rest = sent.next().unwrap();
+        tokio::pin!(rest);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut rest)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_partial_batch_after_window() {
+        let sender = Arc::new(TestSender::default());
+        let batcher = batcher(sender.clone(), Duration::from_millis(10));
+
+        let first = batcher.enqueue(BuilderData::new()).await;
+        let second = batcher.enqueue(BuilderData::new()).await;
+        first.await.unwrap();
+        second.await.unwrap();
+
+        // Next vote starts a new window
+        batcher.enqueue(BuilderData::new()).await.await.unwrap();
+        assert_eq!(*sender.batches.lock().unwrap(), [2, 1]);
+    }
+
+    #[tokio::test]
+    async fn reports_failures_to_all_votes() {
+        let sender = Arc::new(TestSender {
+            fail: true,
+            ..Default::default()
+        });
+        let batcher = batcher(sender.clone(), Duration::from_millis(10));
+
+        let first = batcher.enqueue(BuilderData::new()).await;
+        let second = batcher.enqueue(BuilderData::new()).await;
+        assert!(first.await.is_err());
+        assert!(second.await.is_err());
+        assert_eq!(*sender.batches.lock().unwrap(), [2]);
+    }
+}
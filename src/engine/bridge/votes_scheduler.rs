@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+use std::time::Instant;
+
+use crate::config::VotesSchedulerSettings;
+use crate::prelude::*;
+
+/// Order in which waiting votes are sent. Lower is sent first
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum VotePriority {
+    /// Rejection of a suspicious event
+    Rejection = 0,
+    /// Confirmation which is sent for the first time
+    FreshConfirmation = 1,
+    /// Confirmation restored from the pending or failed queue
+    Retry = 2,
+}
+
+const PRIORITY_COUNT: usize = 3;
+
+/// Limits amount of votes sent in parallel.
+///
+/// Votes are sent in order of their priority. Between configurations with
+/// the same priority, the one with the smallest amount of votes in progress
+/// relative to its weight is chosen. Votes, which wait longer than
+/// `starvation_timeout`, are sent before anything else
+pub struct VotesScheduler {
+    settings: VotesSchedulerSettings,
+    global_limit: NonZeroUsize,
+    state: std::sync::Mutex<SchedulerState>,
+}
+
+impl VotesScheduler {
+    pub fn new(global_limit: NonZeroUsize, settings: VotesSchedulerSettings) -> Arc<Self> {
+        Arc::new(Self {
+            settings,
+            global_limit,
+            state: Default::default(),
+        })
+    }
+
+    /// Waits until the vote for the specified configuration can be sent
+    pub async fn acquire(
+        self: &Arc<Self>,
+        configuration_id: u32,
+        priority: VotePriority,
+    ) -> VotePermit {
+        let (rx, mut cancel) = {
+            let mut state = self.state.lock().unwrap();
+
+            let id = state.next_waiter_id;
+            state.next_waiter_id += 1;
+
+            let (tx, rx) = oneshot::channel();
+            state
+                .configurations
+                .entry(configuration_id)
+                .or_default()
+                .waiters[priority as usize]
+                .push_back(Waiter {
+                    id,
+                    enqueued_at: Instant::now(),
+                    tx,
+                });
+
+            self.dispatch(&mut state);
+
+            let cancel = CancelWaiter {
+                scheduler: self,
+                configuration_id,
+                priority,
+                id,
+                active: true,
+            };
+            (rx, cancel)
+        };
+
+        let permit = rx
+            .await
+            .expect("Scheduler is alive while its permits exist");
+        cancel.active = false;
+        permit
+    }
+
+    /// Returns amount of waiting votes for each configuration
+    pub fn waiting_count(&self) -> HashMap<u32, usize> {
+        let state = self.state.lock().unwrap();
+        state
+            .configurations
+            .iter()
+            .map(|(id, queue)| (*id, queue.waiters.iter().map(VecDeque::len).sum()))
+            .collect()
+    }
+
+    fn release(self: &Arc<Self>, configuration_id: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        if let Some(queue) = state.configurations.get_mut(&configuration_id) {
+            queue.in_flight -= 1;
+        }
+        self.dispatch(&mut state);
+    }
+
+    fn dispatch(self: &Arc<Self>, state: &mut SchedulerState) {
+        let now = Instant::now();
+
+        while state.in_flight < self.global_limit.get() {
+            let (configuration_id, waiter) = match self.select_next(state, now) {
+                Some(next) => next,
+                None => break,
+            };
+
+            let permit = VotePermit {
+                scheduler: Some(self.clone()),
+                configuration_id,
+            };
+
+            if let Err(mut permit) = waiter.tx.send(permit) {
+                // Waiting future was dropped, permit must not be released twice
+                permit.scheduler = None;
+                continue;
+            }
+
+            state.in_flight += 1;
+            if let Some(queue) = state.configurations.get_mut(&configuration_id) {
+                queue.in_flight += 1;
+            }
+        }
+    }
+
+    fn select_next(&self, state: &mut SchedulerState, now: Instant) -> Option<(u32, Waiter)> {
+        let mut best: Option<(u32, usize, (usize, f64, Instant))> = None;
+
+        for (&configuration_id, queue) in state.configurations.iter() {
+            if queue.in_flight >= self.configuration_limit(configuration_id) {
+                continue;
+            }
+
+            let (class, enqueued_at) = match queue.head() {
+                Some(head) => head,
+                None => continue,
+            };
+
+            let effective_class =
+                if now.duration_since(enqueued_at) >= self.settings.starvation_timeout {
+                    0
+                } else {
+                    class + 1
+                };
+
+            let load = queue.in_flight as f64 / self.configuration_weight(configuration_id);
+            let key = (effective_class, load, enqueued_at);
+
+            let is_better = match &best {
+                Some((_, _, best_key)) => {
+                    key.partial_cmp(best_key) == Some(std::cmp::Ordering::Less)
+                }
+                None => true,
+            };
+            if is_better {
+                best = Some((configuration_id, class, key));
+            }
+        }
+
+        let (configuration_id, class, _) = best?;
+        let waiter = state.configurations.get_mut(&configuration_id)?.waiters[class].pop_front()?;
+        Some((configuration_id, waiter))
+    }
+
+    fn configuration_limit(&self, configuration_id: u32) -> usize {
+        self.settings
+            .configurations
+            .iter()
+            .find(|item| item.configuration_id == configuration_id)
+            .and_then(|item| item.limit)
+            .or(self.settings.default_configuration_limit)
+            .unwrap_or(self.global_limit)
+            .get()
+    }
+
+    fn configuration_weight(&self, configuration_id: u32) -> f64 {
+        let weight = self
+            .settings
+            .configurations
+            .iter()
+            .find(|item| item.configuration_id == configuration_id)
+            .and_then(|item| item.weight)
+            .unwrap_or(self.settings.default_configuration_weight);
+        weight.max(1) as f64
+    }
+}
+
+/// Releases the slot of the vote on drop
+pub struct VotePermit {
+    scheduler: Option<Arc<VotesScheduler>>,
+    configuration_id: u32,
+}
+
+impl Drop for VotePermit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release(self.configuration_id);
+        }
+    }
+}
+
+/// Removes the waiter from the queue, when `acquire` is cancelled
+struct CancelWaiter<'a> {
+    scheduler: &'a VotesScheduler,
+    configuration_id: u32,
+    priority: VotePriority,
+    id: u64,
+    active: bool,
+}
+
+impl Drop for CancelWaiter<'_> {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        let mut state = self.scheduler.state.lock().unwrap();
+        if let Some(queue) = state.configurations.get_mut(&self.configuration_id) {
+            queue.waiters[self.priority as usize].retain(|waiter| waiter.id != self.id);
+        }
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    next_waiter_id: u64,
+    in_flight: usize,
+    configurations: HashMap<u32, ConfigurationQueue>,
+}
+
+#[derive(Default)]
+struct ConfigurationQueue {
+    in_flight: usize,
+    waiters: [VecDeque<Waiter>; PRIORITY_COUNT],
+}
+
+impl ConfigurationQueue {
+    /// Returns class and enqueue time of the first vote to be sent
+    fn head(&self) -> Option<(usize, Instant)> {
+        self.waiters
+            .iter()
+            .enumerate()
+            .find_map(|(class, waiters)| waiters.front().map(|waiter| (class, waiter.enqueued_at)))
+    }
+}
+
+struct Waiter {
+    id: u64,
+    enqueued_at: Instant,
+    tx: oneshot::Sender<VotePermit>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::ConfigurationSchedulingSettings;
+
+    fn scheduler(global_limit: usize) -> Arc<VotesScheduler> {
+        VotesScheduler::new(
+            NonZeroUsize::new(global_limit).unwrap(),
+            VotesSchedulerSettings {
+                default_configuration_limit: None,
+                default_configuration_weight: 1,
+                configurations: vec![ConfigurationSchedulingSettings {
+                    configuration_id: 1,
+                    limit: NonZeroUsize::new(1),
+                    weight: None,
+                }],
+                starvation_timeout: Duration::from_secs(600),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn rejections_go_first() {
+        let scheduler = scheduler(1);
+        let first = scheduler.acquire(0, VotePriority::Retry).await;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for &priority in &[
+            VotePriority::Retry,
+            VotePriority::FreshConfirmation,
+            VotePriority::Rejection,
+        ] {
+            let scheduler = scheduler.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(0, priority).await;
+                tx.send(priority).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(scheduler.waiting_count()[&0], 3);
+
+        drop(first);
+        assert_eq!(rx.recv().await, Some(VotePriority::Rejection));
+        assert_eq!(rx.recv().await, Some(VotePriority::FreshConfirmation));
+        assert_eq!(rx.recv().await, Some(VotePriority::Retry));
+    }
+
+    #[tokio::test]
+    async fn configuration_limit() {
+        let scheduler = scheduler(10);
+        let first = scheduler.acquire(1, VotePriority::FreshConfirmation).await;
+
+        let second = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire(1, VotePriority::FreshConfirmation).await }
+        });
+        let other = scheduler.acquire(2, VotePriority::Retry).await;
+        tokio::task::yield_now().await;
+        assert_eq!(scheduler.waiting_count()[&1], 1);
+
+        drop(first);
+        second.await.unwrap();
+        drop(other);
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiters_are_removed() {
+        let scheduler = scheduler(1);
+        let first = scheduler.acquire(0, VotePriority::Retry).await;
+
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire(0, VotePriority::Retry).await }
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(scheduler.waiting_count()[&0], 1);
+
+        waiting.abort();
+        assert!(waiting.await.is_err());
+        assert_eq!(scheduler.waiting_count()[&0], 0);
+
+        // Slot is not lost
+        drop(first);
+        drop(scheduler.acquire(0, VotePriority::Retry).await);
+    }
+}
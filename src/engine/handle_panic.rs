@@ -4,8 +4,8 @@ use std::{
 };
 
 use backtrace::Backtrace;
+use relay_storage::Db;
 use serde::Serialize;
-use sled::Db;
 
 #[derive(Debug, Serialize)]
 pub struct CrashInfo {
@@ -30,7 +30,7 @@ fn handle_panic(panic_info: &PanicInfo<'_>, db: Db) {
     let details = format!("{}", panic_info);
     let backtrace = format!("{:#?}", Backtrace::new());
     match db.flush() {
-        Ok(a) => log::info!("Flushed db on panic. Written {} bytes", a),
+        Ok(()) => log::info!("Flushed db on panic"),
         Err(e) => log::error!("Failed flushing db on disk: {}", e),
     };
     let info = CrashInfo { details, backtrace };
@@ -1,59 +1,112 @@
+use std::path::{Path, PathBuf};
+
 use anyhow::Context;
+use relay_storage::{MemoryStorage, SledStorage, Storage};
+use secstr::SecStr;
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::oneshot;
 
 use models::*;
 
 use super::db::migrate::Migrator;
-use crate::config::RelayConfig;
+use super::db::{check_database, quarantine_entries, StorageEncryption};
+use crate::config::{RelayConfig, StorageEngine};
+use crate::crypto::key_managment::KeyData;
+use crate::crypto::signer::RemoteSigner;
 use crate::engine::handle_panic::setup_panic_handler;
 use crate::prelude::*;
 
+pub mod activity;
 mod api;
+mod auto_unlock;
 pub mod bridge;
 mod exporter;
 mod handle_panic;
 pub mod models;
 
-pub async fn run(config: RelayConfig) -> Result<(), Error> {
-    let db = sled::open(&config.storage_path).map_err(|e| {
-        let context = format!(
-            "Failed opening db. Db path: {}",
-            &config.storage_path.to_string_lossy()
-        );
-        Error::new(e).context(context)
-    })?;
+/// Additional actions performed on start
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Path to the backup, which will be restored into the empty database
+    pub restore_from: Option<PathBuf>,
+    /// Whether corrupted entries are moved to quarantine before start
+    pub check_db: bool,
+    /// Auto unlock password, which is taken from the environment before start
+    pub unlock_password: Option<SecStr>,
+}
 
-    Migrator::init(&db)
-        .context("Failed initializing migrator")?
-        .run_migrations()
-        .context("Failed running migrations")?;
+/// Takes the auto unlock password from the environment variable, if it is configured.
+/// Must be called before any other threads are started
+pub fn take_env_password(config: &RelayConfig) -> Result<Option<SecStr>, Error> {
+    match &config.auto_unlock {
+        Some(settings) => {
+            auto_unlock::take_env_password(settings).context("Failed reading password")
+        }
+        None => Ok(None),
+    }
+}
 
-    setup_panic_handler(db.clone());
-    let crypto_data_metadata = std::fs::File::open(&config.keys_path);
+pub async fn run(config: RelayConfig, mut options: RunOptions) -> Result<(), Error> {
+    let env_password = options.unlock_password.take();
 
-    let file_size = match crypto_data_metadata {
-        Err(e) => {
-            log::warn!("Error opening file with encrypted config: {}", e);
-            0
-        }
-        Ok(a) => a.metadata()?.len(),
-    };
+    if config.signer.is_some()
+        && matches!(&config.storage_encryption, Some(settings) if settings.key_path.is_none())
+    {
+        return Err(anyhow!(
+            "Storage encryption with the unlock password can't be used with the remote signer. \
+            Specify `key_path` of the storage encryption"
+        ));
+    }
 
-    let bridge_state = match file_size {
-        0 => {
-            log::info!("started in uninitialized state");
-            BridgeState::Uninitialized
+    if config.signer.is_some() && config.auto_unlock.is_some() {
+        return Err(anyhow!(
+            "Auto unlock can't be used with the remote signer. Remove `auto_unlock` from the config"
+        ));
+    }
+
+    let api_auth =
+        Arc::new(api::ApiAuth::new(config.api_auth.as_ref()).context("Invalid api auth settings")?);
+    if !api_auth.is_enabled() && !config.listen_address.ip().is_loopback() {
+        log::warn!(
+            "Api on {} is not protected. Specify `api_auth` in the config",
+            config.listen_address
+        );
+    }
+    let api_tls = config
+        .api_tls
+        .as_ref()
+        .map(api::ApiTls::read)
+        .transpose()
+        .context("Invalid api tls settings")?;
+
+    let (db, storage_encryption) = open_db(&config)?;
+
+    let pending_options = match &storage_encryption {
+        Some(encryption) if encryption.is_locked() => {
+            log::info!("Storage is encrypted. Db preparation is postponed until unlock");
+            Some(options)
         }
         _ => {
-            log::info!("stared in locked state");
-            BridgeState::Locked
+            prepare_db(&db, &config, &options)?;
+            None
         }
     };
 
+    setup_panic_handler(db.clone());
+
+    let auditor = Arc::new(api::Auditor::new(&db, config.audit_log_path.as_deref())?);
+
+    let bridge_state = match &config.signer {
+        // Started right away, see below
+        Some(_) => BridgeState::Locked,
+        None => keystore_bridge_state(&config.keys_path)?,
+    };
+
     let mut shutdown_notifier = ShutdownNotifier::new();
     let api_shutdown_signal = shutdown_notifier.subscribe();
     let exporter_shutdown_signal = shutdown_notifier.subscribe();
+    #[cfg(feature = "grpc")]
+    let grpc_shutdown_signal = shutdown_notifier.subscribe();
 
     {
         let db = db.clone();
@@ -71,7 +124,7 @@ pub async fn run(config: RelayConfig) -> Result<(), Error> {
 
             log::info!("Flushing db...");
             match db.flush() {
-                Ok(a) => log::info!("Flushed db before stop... Bytes written: {:?}", a),
+                Ok(()) => log::info!("Flushed db before stop"),
                 Err(e) => log::error!("Failed flushing db before panic: {}", e),
             }
 
@@ -83,12 +136,62 @@ pub async fn run(config: RelayConfig) -> Result<(), Error> {
 
     let state = Arc::new(RwLock::new(State {
         state_manager: db.clone(),
+        storage_encryption,
+        pending_options,
         bridge_state,
+        activity: Default::default(),
     }));
 
+    if let Some(settings) = &config.signer {
+        let signer = RemoteSigner::connect(settings)
+            .await
+            .context("Failed connecting to the remote signer")?;
+        log::info!("Using remote signer at {}", settings.endpoint);
+
+        state
+            .write()
+            .await
+            .finalize(config.clone(), Arc::new(signer))
+            .await?;
+    } else if let Some(settings) = &config.auto_unlock {
+        let mut state = state.write().await;
+        match &state.bridge_state {
+            BridgeState::Locked => {
+                let password = auto_unlock::read_password(settings, env_password)
+                    .context("Failed reading password")?;
+                // Relay stays locked and can still be unlocked through the api
+                match state.unlock(config.clone(), password).await {
+                    Ok(()) => log::info!("Unlocked relay on start"),
+                    Err(e) => log::error!("Failed auto unlock: {:?}", e),
+                }
+            }
+            _ => log::warn!("Relay is not initialized. Auto unlock skipped"),
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_listen_address) = config.grpc_listen_address {
+        tokio::spawn(api::grpc::serve(
+            grpc_listen_address,
+            config.clone(),
+            state.clone(),
+            api_auth.clone(),
+            api_tls.clone(),
+            auditor.clone(),
+            grpc_shutdown_signal,
+        ));
+    }
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc_listen_address.is_some() {
+        log::warn!("gRPC api is disabled. Build the relay with the `grpc` feature");
+    }
+
     tokio::spawn(api::serve(
         config.clone(),
         state.clone(),
+        api_auth,
+        api_tls,
+        auditor,
         api_shutdown_signal,
     ));
     tokio::spawn(exporter::serve(config, state, exporter_shutdown_signal));
@@ -96,6 +199,221 @@ pub async fn run(config: RelayConfig) -> Result<(), Error> {
     future::pending().await
 }
 
+fn keystore_bridge_state(keys_path: &Path) -> Result<BridgeState, Error> {
+    let crypto_data_metadata = std::fs::File::open(keys_path);
+
+    let file_size = match crypto_data_metadata {
+        Err(e) => {
+            log::warn!("Error opening file with encrypted config: {}", e);
+            0
+        }
+        Ok(a) => a.metadata()?.len(),
+    };
+
+    Ok(match file_size {
+        0 => {
+            log::info!("started in uninitialized state");
+            BridgeState::Uninitialized
+        }
+        _ => {
+            log::info!("stared in locked state");
+            BridgeState::Locked
+        }
+    })
+}
+
+/// Checks database integrity without starting the relay
+pub fn check_db(config: &RelayConfig, quarantine: bool) -> Result<(), Error> {
+    let db = open_unlocked_db(config)?;
+
+    let report = check_database(&db)?;
+    println!("{}", report);
+
+    if report.corrupted.is_empty() {
+        return Ok(());
+    }
+    if !quarantine {
+        return Err(anyhow!(
+            "Database is corrupted. Use `--quarantine` to move corrupted entries"
+        ));
+    }
+
+    let moved = quarantine_entries(&db, &report.corrupted)?;
+    db.flush()?;
+    println!("Moved {} entries to quarantine", moved);
+    Ok(())
+}
+
+/// Prints a new api token and the config entry with its hash
+pub fn generate_api_token() -> Result<(), Error> {
+    let (token, hash) = api::generate_token();
+    println!("Token: {}", token);
+    println!();
+    println!("api_auth:");
+    println!("  tokens:");
+    println!("    - name: operator");
+    println!("      hash: {}", hash);
+    println!("      role: admin");
+    Ok(())
+}
+
+/// Applies migrations without starting the relay
+pub fn migrate_db(config: &RelayConfig, dry_run: bool) -> Result<(), Error> {
+    let db = open_unlocked_db(config)?;
+    let migrator = init_migrator(&db, config)?;
+
+    println!("{}", migrator.plan_migrations()?);
+    if !dry_run {
+        migrator.run_migrations()?;
+        db.flush()?;
+    }
+    Ok(())
+}
+
+/// Reverts migrations down to the schema of the relay of `target` version
+pub fn downgrade_db(config: &RelayConfig, target: &str, dry_run: bool) -> Result<(), Error> {
+    let target = semver::Version::parse(target).context("Invalid target version")?;
+
+    let db = open_unlocked_db(config)?;
+    let migrator = init_migrator(&db, config)?;
+
+    println!("{}", migrator.plan_downgrade(&target)?);
+    if !dry_run {
+        migrator.downgrade(&target)?;
+        db.flush()?;
+    }
+    Ok(())
+}
+
+fn init_migrator(db: &Db, config: &RelayConfig) -> Result<Migrator, Error> {
+    let migrator = Migrator::init(db).context("Failed initializing migrator")?;
+    Ok(match config.storage_engine {
+        // Snapshots contain decrypted values, so they are not written for the encrypted storage
+        StorageEngine::Sled if config.storage_encryption.is_none() => {
+            migrator.with_snapshots_path(config.snapshots_path())
+        }
+        _ => migrator,
+    })
+}
+
+fn open_db(config: &RelayConfig) -> Result<(Db, Option<StorageEncryption>), Error> {
+    let (db, encryption) = match config.storage_engine {
+        StorageEngine::Sled => {
+            let storage = SledStorage::open(&config.storage_path).with_context(|| {
+                format!(
+                    "Failed opening db. Db path: {}",
+                    &config.storage_path.to_string_lossy()
+                )
+            })?;
+            wrap_storage(config, storage)
+        }
+        StorageEngine::Memory => {
+            log::warn!("Using in-memory storage. All state will be lost on restart");
+            wrap_storage(config, MemoryStorage::default())
+        }
+    }?;
+
+    // Writes from the async tasks wait for the pending backup outside of the workers
+    db.set_blocking_hook(|wait| tokio::task::block_in_place(wait));
+    Ok((db, encryption))
+}
+
+fn wrap_storage<S>(
+    config: &RelayConfig,
+    storage: S,
+) -> Result<(Db, Option<StorageEncryption>), Error>
+where
+    S: Storage + 'static,
+{
+    let settings = match &config.storage_encryption {
+        Some(settings) => settings,
+        None => return Ok((Db::new(storage), None)),
+    };
+
+    let (db, encryption) = StorageEncryption::new(storage);
+    if let Some(key_path) = &settings.key_path {
+        encryption
+            .unlock_with_key_file(key_path)
+            .context("Failed unlocking storage with the key file")?;
+    }
+    Ok((db, Some(encryption)))
+}
+
+/// Opens db for the maintenance commands, asking for the unlock password if needed
+fn open_unlocked_db(config: &RelayConfig) -> Result<Db, Error> {
+    let (db, encryption) = open_db(config)?;
+
+    if let Some(encryption) = encryption.filter(StorageEncryption::is_locked) {
+        let password: SecStr = dialoguer::Password::new()
+            .with_prompt("Unlock password")
+            .interact()?
+            .into();
+        KeyData::from_file(&config.keys_path, &password).context("Invalid password")?;
+        encryption.unlock_with_password(&password)?;
+    }
+    Ok(db)
+}
+
+/// Restores the backup, checks integrity and applies migrations. Requires unlocked storage
+fn prepare_db(db: &Db, config: &RelayConfig, options: &RunOptions) -> Result<(), Error> {
+    if let Some(path) = &options.restore_from {
+        restore_db(db, path).context("Failed restoring db")?;
+    }
+
+    let migrator = init_migrator(db, config)?;
+
+    // Db is checked before migrations, so that they don't hide or spread the corruption.
+    // Validators only know the current schema, so entries are moved to quarantine
+    // after the outdated schema is migrated
+    let pending_migrations = !migrator.plan_migrations()?.steps.is_empty();
+    if options.check_db {
+        repair_db(db, !pending_migrations).context("Failed checking db")?;
+    }
+
+    migrator
+        .run_migrations()
+        .context("Failed running migrations")?;
+
+    if options.check_db && pending_migrations {
+        repair_db(db, true).context("Failed checking db")?;
+    }
+    Ok(())
+}
+
+fn repair_db(db: &Db, quarantine: bool) -> Result<(), Error> {
+    log::info!("Checking db integrity");
+
+    let report = check_database(db)?;
+    if report.corrupted.is_empty() {
+        log::info!("{}", report);
+        return Ok(());
+    }
+
+    log::error!("{}", report);
+    if !quarantine {
+        log::warn!("Corrupted entries will be moved to quarantine after migrations");
+        return Ok(());
+    }
+
+    let moved = quarantine_entries(db, &report.corrupted)?;
+    log::warn!("Moved {} corrupted entries to quarantine", moved);
+    Ok(())
+}
+
+fn restore_db(db: &Db, path: &Path) -> Result<(), Error> {
+    log::warn!("Restoring db from {}", path.display());
+
+    let file = std::fs::File::open(path)?;
+    let stats = db.restore(std::io::BufReader::new(file))?;
+
+    log::warn!(
+        "Restored db. Trees: {}, entries: {}",
+        stats.trees,
+        stats.entries
+    );
+    Ok(())
+}
+
 async fn wait_signals(signals: &[SignalKind]) -> SignalKind {
     use future::FutureExt;
 
@@ -1,29 +1,117 @@
 use relay_models::models::{
-    CommonEventConfigurationParamsView, EthEventConfigurationView, EventConfigurationType,
-    EventConfigurationView, NewEventConfiguration, TonEventConfigurationView, Voting,
+    ActivityEventView, CommonEventConfigurationParamsView, EthEventConfigurationView,
+    EventConfigurationType, EventConfigurationView, NewEventConfiguration, RelayStateView,
+    StateChangeView, TonEventConfigurationView, Voting,
 };
 use relay_ton::contracts;
 
+use anyhow::Context;
+use secstr::SecStr;
+
 use crate::config::RelayConfig;
 use crate::crypto::key_managment::KeyData;
+use crate::crypto::signer::{LocalSigner, Signer};
+use crate::db::StorageEncryption;
+use crate::engine::activity::ActivityNotifier;
 use crate::engine::bridge::*;
+use crate::engine::RunOptions;
 use crate::prelude::*;
 
 impl State {
-    pub async fn finalize(&mut self, config: RelayConfig, key_data: KeyData) -> Result<(), Error> {
-        log::info!("ETH address: 0x{}", hex::encode(&key_data.eth.address()));
-        let bridge = make_bridge(self.state_manager.clone(), config, key_data).await?;
+    /// Unlocks encrypted storage and performs postponed db preparation.
+    /// Key derivation and migrations are slow, so they run on the blocking pool
+    pub async fn unlock_storage(
+        &mut self,
+        config: &RelayConfig,
+        password: &SecStr,
+    ) -> Result<(), Error> {
+        let encryption = self
+            .storage_encryption
+            .clone()
+            .filter(StorageEncryption::is_locked);
+        let options = self.pending_options.take();
+        let db = self.state_manager.clone();
+        let config = config.clone();
+        let password = password.clone();
+
+        let (result, options) = tokio::task::spawn_blocking(move || {
+            let result = (|| {
+                if let Some(encryption) = encryption {
+                    encryption.unlock_with_password(&password)?;
+                    log::info!("Unlocked storage");
+                }
+                match &options {
+                    Some(options) => super::prepare_db(&db, &config, options),
+                    None => Ok(()),
+                }
+            })();
+            (result, options)
+        })
+        .await?;
+
+        if result.is_err() {
+            self.pending_options = options;
+        }
+        result
+    }
+
+    /// Decrypts the local keystore, unlocks the storage and starts the bridge
+    pub async fn unlock(&mut self, config: RelayConfig, password: SecStr) -> Result<(), Error> {
+        let key_data = {
+            let keys_path = config.keys_path.clone();
+            let password = password.clone();
+            tokio::task::spawn_blocking(move || KeyData::from_file(&keys_path, &password)).await?
+        }
+        .context("Failed unlocking relay")?;
+        self.unlock_storage(&config, &password)
+            .await
+            .context("Failed unlocking storage")?;
+
+        if let Ok(true) = KeyData::is_legacy_file(&config.keys_path) {
+            log::warn!("Keystore uses outdated key derivation. Re-encrypt it with the client");
+        }
+
+        let signer = LocalSigner::new(config.keys_path.clone(), key_data);
+        self.finalize(config, Arc::new(signer))
+            .await
+            .context("Failed finalize relay state")
+    }
+
+    pub async fn finalize(
+        &mut self,
+        config: RelayConfig,
+        signer: Arc<dyn Signer>,
+    ) -> Result<(), Error> {
+        log::info!("ETH address: 0x{}", hex::encode(&signer.eth_address()));
+        let bridge = make_bridge(
+            self.state_manager.clone(),
+            config,
+            signer,
+            self.activity.clone(),
+        )
+        .await?;
 
         log::info!("Successfully initialized");
 
+        let from = self.bridge_state.view();
         self.bridge_state = BridgeState::Running(bridge);
+        self.activity
+            .notify(ActivityEventView::StateChanged(StateChangeView {
+                from,
+                to: self.bridge_state.view(),
+            }));
         Ok(())
     }
 }
 
 pub struct State {
     pub state_manager: Db,
+    /// `None` if the storage is not encrypted
+    pub storage_encryption: Option<StorageEncryption>,
+    /// Start actions, which require unlocked storage
+    pub pending_options: Option<RunOptions>,
     pub bridge_state: BridgeState,
+    pub activity: ActivityNotifier,
 }
 
 pub enum BridgeState {
@@ -32,6 +120,16 @@ pub enum BridgeState {
     Running(Arc<Bridge>),
 }
 
+impl BridgeState {
+    pub fn view(&self) -> RelayStateView {
+        match self {
+            BridgeState::Uninitialized => RelayStateView::Uninitialized,
+            BridgeState::Locked => RelayStateView::Locked,
+            BridgeState::Running(_) => RelayStateView::Running,
+        }
+    }
+}
+
 pub trait FromRequest<T>: Sized {
     fn try_from(request: T) -> Result<Self, Error>;
 }
@@ -6,35 +6,74 @@ use anyhow::Result;
 use relay::config::*;
 use relay::engine;
 
+/// Handles the commands and returns the config of the relay to start
 #[cfg(feature = "dockered")]
-async fn run() -> Result<()> {
+fn prepare() -> Result<Option<(RelayConfig, engine::RunOptions)>> {
     let config = read_env()?;
     init_logger(&config.logger_settings)?;
     log::info!("Relay ready.");
-    engine::run(config).await
+    Ok(Some((config, Default::default())))
 }
 
+/// Handles the commands and returns the config of the relay to start
 #[cfg(not(feature = "dockered"))]
-async fn run() -> Result<()> {
+fn prepare() -> Result<Option<(RelayConfig, engine::RunOptions)>> {
     let args = parse_args();
+    if let Some(Command::GenApiToken) = args.command {
+        engine::generate_api_token()?;
+        return Ok(None);
+    }
+
     match (args.config, args.gen_config) {
         (_, Some(new_config_path)) => generate_config(new_config_path)?,
         (Some(config), None) => {
             let config = read_config(config)?;
 
+            match args.command {
+                Some(Command::Db(DbCommand::Check { quarantine })) => {
+                    engine::check_db(&config, quarantine)?;
+                    return Ok(None);
+                }
+                Some(Command::Db(DbCommand::Migrate { dry_run })) => {
+                    engine::migrate_db(&config, dry_run)?;
+                    return Ok(None);
+                }
+                Some(Command::Db(DbCommand::Downgrade { to, dry_run })) => {
+                    engine::downgrade_db(&config, &to, dry_run)?;
+                    return Ok(None);
+                }
+                Some(Command::GenApiToken) => unreachable!("Handled before reading the config"),
+                None => {}
+            }
+
             init_logger(&config.logger_settings)?;
             log::info!("Relay ready.");
 
-            engine::run(config).await?;
+            let options = engine::RunOptions {
+                restore_from: args.restore,
+                check_db: args.check_db,
+                ..Default::default()
+            };
+            return Ok(Some((config, options)));
         }
         _ => Arguments::into_app().print_help().map_err(Error::from)?,
     }
-    Ok(())
+    Ok(None)
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    run().await
+fn main() -> Result<()> {
+    let (config, mut options) = match prepare()? {
+        Some(prepared) => prepared,
+        None => return Ok(()),
+    };
+    // Password variable is removed from the environment,
+    // which is only sound before the runtime starts its threads
+    options.unlock_password = engine::take_env_password(&config)?;
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(engine::run(config, options))
 }
 
 fn init_logger(config: &serde_yaml::Value) -> Result<(), Error> {
@@ -1,8 +1,9 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use relay_models::models::{
-    EthEventVoteDataView, EthTonTransactionView, SignedVoteDataView, TonEthTransactionView,
-    TonEventVoteDataView,
+    EthEventVoteDataView, EthTonDeadVoteView, EthTonTransactionView, EthTonVoteView,
+    SignedVoteDataView, TonEthDeadVoteView, TonEthTransactionView, TonEthVoteView,
+    TonEventVoteDataView, VoteRetryHistoryView,
 };
 use relay_ton::contracts::*;
 use relay_ton::prelude::*;
@@ -74,6 +75,48 @@ pub struct SignedTonEventVoteData {
 pub type EthEventTransaction = EventTransaction<EthEventVoteData, EthEventVoteData>;
 pub type TonEventTransaction = EventTransaction<SignedTonEventVoteData, TonEventVoteData>;
 
+pub trait EventVoteData {
+    fn configuration_id(&self) -> u32;
+}
+
+impl EventVoteData for EthEventVoteData {
+    fn configuration_id(&self) -> u32 {
+        self.configuration_id
+    }
+}
+
+impl EventVoteData for TonEventVoteData {
+    fn configuration_id(&self) -> u32 {
+        self.configuration_id
+    }
+}
+
+impl EventVoteData for SignedTonEventVoteData {
+    fn configuration_id(&self) -> u32 {
+        self.data.configuration_id
+    }
+}
+
+impl<C, R> EventTransaction<C, R>
+where
+    C: EventVoteData,
+    R: EventVoteData,
+{
+    pub fn configuration_id(&self) -> u32 {
+        match self {
+            EventTransaction::Confirm(data) => data.configuration_id(),
+            EventTransaction::Reject(data) => data.configuration_id(),
+        }
+    }
+
+    pub fn kind(&self) -> Voting {
+        match self {
+            EventTransaction::Confirm(_) => Voting::Confirm,
+            EventTransaction::Reject(_) => Voting::Reject,
+        }
+    }
+}
+
 impl From<EthEventTransaction> for EthTonTransactionView {
     fn from(data: EthEventTransaction) -> Self {
         match data {
@@ -95,6 +138,92 @@ impl From<TonEventTransaction> for TonEthTransactionView {
     }
 }
 
+impl IntoView for EthEventTransaction {
+    type View = EthTonTransactionView;
+
+    fn into_view(self) -> Self::View {
+        self.into()
+    }
+}
+
+impl IntoView for TonEventTransaction {
+    type View = TonEthTransactionView;
+
+    fn into_view(self) -> Self::View {
+        self.into()
+    }
+}
+
+pub trait IntoVoteView {
+    type View: Serialize;
+
+    fn into_vote_view(self, event_address: &MsgAddrStd) -> Self::View;
+}
+
+impl IntoVoteView for EthEventTransaction {
+    type View = EthTonVoteView;
+
+    fn into_vote_view(self, event_address: &MsgAddrStd) -> Self::View {
+        EthTonVoteView {
+            event_address: event_address.to_string(),
+            transaction: self.into(),
+        }
+    }
+}
+
+impl IntoVoteView for TonEventTransaction {
+    type View = TonEthVoteView;
+
+    fn into_vote_view(self, event_address: &MsgAddrStd) -> Self::View {
+        TonEthVoteView {
+            event_address: event_address.to_string(),
+            transaction: self.into(),
+        }
+    }
+}
+
+pub trait IntoDeadVoteView {
+    type View: Serialize;
+
+    fn into_dead_vote_view(
+        self,
+        event_address: &MsgAddrStd,
+        history: VoteRetryHistoryView,
+    ) -> Self::View;
+}
+
+impl IntoDeadVoteView for EthEventTransaction {
+    type View = EthTonDeadVoteView;
+
+    fn into_dead_vote_view(
+        self,
+        event_address: &MsgAddrStd,
+        history: VoteRetryHistoryView,
+    ) -> Self::View {
+        EthTonDeadVoteView {
+            event_address: event_address.to_string(),
+            transaction: self.into(),
+            history,
+        }
+    }
+}
+
+impl IntoDeadVoteView for TonEventTransaction {
+    type View = TonEthDeadVoteView;
+
+    fn into_dead_vote_view(
+        self,
+        event_address: &MsgAddrStd,
+        history: VoteRetryHistoryView,
+    ) -> Self::View {
+        TonEthDeadVoteView {
+            event_address: event_address.to_string(),
+            transaction: self.into(),
+            history,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CommonReceivedVote<T, A> {
     pub configuration_id: u32,
@@ -362,10 +491,16 @@ pub struct BridgeMetrics {
     pub eth_verification_queue_size: usize,
     pub eth_pending_vote_count: usize,
     pub eth_failed_vote_count: usize,
+    pub eth_dead_vote_count: usize,
+    pub eth_expired_vote_count: usize,
+    pub eth_waiting_vote_count: usize,
     pub eth_event_handlers_metrics: Vec<EthEventsHandlerMetrics>,
 
     pub ton_pending_vote_count: usize,
     pub ton_failed_vote_count: usize,
+    pub ton_dead_vote_count: usize,
+    pub ton_expired_vote_count: usize,
+    pub ton_waiting_vote_count: usize,
     pub ton_event_handlers_metrics: Vec<TonEventsHandlerMetrics>,
 }
 
@@ -380,6 +515,15 @@ impl std::fmt::Display for RelayMetrics<'_, &'_ BridgeMetrics> {
         self.begin_metric(f, "eth_failed_vote_count")
             .value(self.metrics.eth_failed_vote_count)?;
 
+        self.begin_metric(f, "eth_dead_vote_count")
+            .value(self.metrics.eth_dead_vote_count)?;
+
+        self.begin_metric(f, "eth_expired_vote_count")
+            .value(self.metrics.eth_expired_vote_count)?;
+
+        self.begin_metric(f, "eth_waiting_vote_count")
+            .value(self.metrics.eth_waiting_vote_count)?;
+
         for metrics in self.metrics.eth_event_handlers_metrics.iter() {
             std::fmt::Display::fmt(
                 &RelayMetrics {
@@ -396,6 +540,15 @@ impl std::fmt::Display for RelayMetrics<'_, &'_ BridgeMetrics> {
         self.begin_metric(f, "ton_failed_vote_count")
             .value(self.metrics.ton_failed_vote_count)?;
 
+        self.begin_metric(f, "ton_dead_vote_count")
+            .value(self.metrics.ton_dead_vote_count)?;
+
+        self.begin_metric(f, "ton_expired_vote_count")
+            .value(self.metrics.ton_expired_vote_count)?;
+
+        self.begin_metric(f, "ton_waiting_vote_count")
+            .value(self.metrics.ton_waiting_vote_count)?;
+
         for metrics in self.metrics.ton_event_handlers_metrics.iter() {
             std::fmt::Display::fmt(
                 &RelayMetrics {
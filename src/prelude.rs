@@ -14,10 +14,10 @@ pub use futures::future;
 pub use num_bigint::{BigInt, BigUint};
 pub use num_traits::cast::ToPrimitive;
 pub use relay_eth::{Address, H160, H256};
+pub use relay_storage::{Db, Tree};
 pub use serde::{de::DeserializeOwned, Deserialize, Serialize};
 pub use sha3::digest::Digest;
 pub use sha3::Keccak256;
-pub use sled::{Db, Tree};
 pub use tokio::sync::{mpsc, oneshot, Mutex, MutexGuard, RwLock, RwLockReadGuard};
 pub use tokio_stream::{Stream, StreamExt};
 pub use url::Url;